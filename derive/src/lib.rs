@@ -0,0 +1,254 @@
+//! Derive macros for `gemini-oxide`.
+//!
+//! `#[derive(JsonSchema)]` implements `gemini_oxide::schema::JsonSchema`; it is re-exported as
+//! `gemini_oxide::schema::JsonSchema`, so depend on `gemini-oxide` rather than on this crate.
+//!
+//! * Structs with named fields become `schema::object`s. Every field is required unless it is
+//!   an `Option` or has `#[serde(default)]`.
+//! * Newtype structs and `#[serde(transparent)]` structs use the schema of their field.
+//! * Enums whose variants are all units become `schema::string_enum`s.
+//!
+//! The serde attributes that change the JSON shape are honoured: `rename`, `rename_all`,
+//! `default`, `skip` and `skip_deserializing`. Anything else the derive cannot describe, such as
+//! `flatten` or enums with data, is a compile error; implement the trait by hand for those.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::meta::ParseNestedMeta;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, LitStr, Type};
+
+/// Implement `gemini_oxide::schema::JsonSchema` from the shape of a struct or unit enum.
+#[proc_macro_derive(JsonSchema, attributes(serde))]
+pub fn derive_json_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let container = SerdeAttrs::parse(&input.attrs)?;
+    let body = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) if !container.transparent => {
+                let mut properties = Vec::new();
+                let mut required = Vec::new();
+                for field in &fields.named {
+                    let attrs = SerdeAttrs::parse(&field.attrs)?;
+                    if attrs.skip {
+                        continue;
+                    }
+                    let ident = field.ident.as_ref().expect("named fields have names");
+                    let name = attrs.rename.unwrap_or_else(|| {
+                        rename_field(&ident.to_string(), container.rename_all.as_deref())
+                    });
+                    let ty = &field.ty;
+                    properties.push(quote! {
+                        (#name, <#ty as ::gemini_oxide::schema::JsonSchema>::json_schema())
+                    });
+                    if !(attrs.default || container.default || is_option(ty)) {
+                        required.push(name);
+                    }
+                }
+                quote! {
+                    ::gemini_oxide::schema::object([#(#properties),*], &[#(#required),*])
+                }
+            }
+            Fields::Named(fields) if fields.named.len() == 1 => {
+                let ty = &fields.named[0].ty;
+                quote! { <#ty as ::gemini_oxide::schema::JsonSchema>::json_schema() }
+            }
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let ty = &fields.unnamed[0].ty;
+                quote! { <#ty as ::gemini_oxide::schema::JsonSchema>::json_schema() }
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "JsonSchema can only be derived for structs with named fields and newtypes",
+                ))
+            }
+        },
+        Data::Enum(data) => {
+            let mut values = Vec::new();
+            for variant in &data.variants {
+                if !matches!(variant.fields, Fields::Unit) {
+                    return Err(syn::Error::new_spanned(
+                        variant,
+                        "JsonSchema can only be derived for enums whose variants are all units",
+                    ));
+                }
+                let attrs = SerdeAttrs::parse(&variant.attrs)?;
+                if attrs.skip {
+                    continue;
+                }
+                values.push(attrs.rename.unwrap_or_else(|| {
+                    rename_variant(&variant.ident.to_string(), container.rename_all.as_deref())
+                }));
+            }
+            quote! { ::gemini_oxide::schema::string_enum(&[#(#values),*]) }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "JsonSchema cannot be derived for unions",
+            ))
+        }
+    };
+
+    let name = &input.ident;
+    let mut generics = input.generics.clone();
+    for param in generics.type_params_mut() {
+        param
+            .bounds
+            .push(syn::parse_quote!(::gemini_oxide::schema::JsonSchema));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::gemini_oxide::schema::JsonSchema for #name #ty_generics #where_clause {
+            fn json_schema() -> ::gemini_oxide::schema::Value {
+                #body
+            }
+        }
+    })
+}
+
+/// The `#[serde(...)]` settings that change the JSON shape of a container, field or variant.
+#[derive(Default)]
+struct SerdeAttrs {
+    rename: Option<String>,
+    rename_all: Option<String>,
+    default: bool,
+    skip: bool,
+    transparent: bool,
+}
+
+impl SerdeAttrs {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut parsed = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    parsed.rename = deserialize_name(&meta)?.or(parsed.rename.take());
+                } else if meta.path.is_ident("rename_all") {
+                    parsed.rename_all = deserialize_name(&meta)?.or(parsed.rename_all.take());
+                } else if meta.path.is_ident("default") {
+                    parsed.default = true;
+                    skip_value(&meta)?;
+                } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_deserializing") {
+                    parsed.skip = true;
+                } else if meta.path.is_ident("transparent") {
+                    parsed.transparent = true;
+                } else if ["flatten", "tag", "untagged"]
+                    .iter()
+                    .any(|name| meta.path.is_ident(name)) {
+                    return Err(meta.error(
+                        "JsonSchema cannot be derived with this serde attribute; implement it by hand",
+                    ));
+                } else {
+                    skip_value(&meta)?;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(parsed)
+    }
+}
+
+/// The name in `rename = "..."`, or the `deserialize` one in `rename(deserialize = "...")`.
+fn deserialize_name(meta: &ParseNestedMeta<'_>) -> syn::Result<Option<String>> {
+    if meta.input.peek(syn::Token![=]) {
+        return Ok(Some(meta.value()?.parse::<LitStr>()?.value()));
+    }
+    let mut name = None;
+    meta.parse_nested_meta(|inner| {
+        let value = inner.value()?.parse::<LitStr>()?.value();
+        if inner.path.is_ident("deserialize") {
+            name = Some(value);
+        }
+        Ok(())
+    })?;
+    Ok(name)
+}
+
+/// Consume the value of an attribute this derive does not care about.
+fn skip_value(meta: &ParseNestedMeta<'_>) -> syn::Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        meta.value()?.parse::<syn::Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(|inner| skip_value(&inner))?;
+    }
+    Ok(())
+}
+
+/// Whether `ty` is an `Option`, which serde lets a reply leave out.
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
+
+/// Apply a serde `rename_all` rule to a snake_case field name.
+fn rename_field(field: &str, rule: Option<&str>) -> String {
+    let pascal = || {
+        let mut out = String::new();
+        let mut upper = true;
+        for c in field.chars() {
+            if c == '_' {
+                upper = true;
+            } else if upper {
+                out.push(c.to_ascii_uppercase());
+                upper = false;
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    };
+    match rule {
+        Some("UPPERCASE" | "SCREAMING_SNAKE_CASE") => field.to_ascii_uppercase(),
+        Some("PascalCase") => pascal(),
+        Some("camelCase") => lower_first(&pascal()),
+        Some("kebab-case") => field.replace('_', "-"),
+        Some("SCREAMING-KEBAB-CASE") => field.to_ascii_uppercase().replace('_', "-"),
+        _ => field.to_string(),
+    }
+}
+
+/// Apply a serde `rename_all` rule to a PascalCase variant name.
+fn rename_variant(variant: &str, rule: Option<&str>) -> String {
+    let snake = || {
+        let mut out = String::new();
+        for (i, c) in variant.char_indices() {
+            if i > 0 && c.is_uppercase() {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+        }
+        out
+    };
+    match rule {
+        Some("lowercase") => variant.to_ascii_lowercase(),
+        Some("UPPERCASE") => variant.to_ascii_uppercase(),
+        Some("camelCase") => lower_first(variant),
+        Some("snake_case") => snake(),
+        Some("SCREAMING_SNAKE_CASE") => snake().to_ascii_uppercase(),
+        Some("kebab-case") => snake().replace('_', "-"),
+        Some("SCREAMING-KEBAB-CASE") => snake().to_ascii_uppercase().replace('_', "-"),
+        _ => variant.to_string(),
+    }
+}
+
+fn lower_first(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,15 @@
+//! Feeds arbitrary bytes to the `stream-json` line parser: `cargo +nightly fuzz run parse_line`.
+
+#![no_main]
+
+use gemini_oxide::StreamEvent;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // `stream()` reads lines as UTF-8; anything else never reaches the parser.
+    if let Ok(text) = std::str::from_utf8(data) {
+        for line in text.lines() {
+            let _ = StreamEvent::parse_line(line);
+        }
+    }
+});
@@ -0,0 +1,460 @@
+//! Cost reports aggregated from recorded token usage.
+//!
+//! Attach a [`UsageLedger`] to requests with [`Gemini::usage_ledger`](crate::Gemini::usage_ledger)
+//! (or once for all of them with
+//! [`GeminiClient::usage_ledger`](crate::client::GeminiClient::usage_ledger)) and every run that
+//! reaches the CLI records a [`UsageEvent`] per model, carrying the request's
+//! [labels](crate::Gemini::label): `json()` from its stats, `stream()` from the stats of its
+//! `Result` event, and `text()` by asking the CLI for JSON output instead of plain text. Cache
+//! hits are free and are not recorded.
+//!
+//! An [`Aggregation`] groups the events into fixed time windows, by model and optionally by a
+//! label such as `team`, and prices them with a [`Pricing`] table. The resulting
+//! [`CostReport`] exports as CSV or JSON.
+//!
+//! ```rust
+//! use gemini_oxide::billing::{Aggregation, Pricing, UsageLedger};
+//! use gemini_oxide::client::GeminiClient;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let ledger = Arc::new(UsageLedger::new());
+//! let client = GeminiClient::new().usage_ledger(ledger.clone());
+//! // ... run requests with client.prompt(...).label("team", "payments").json() ...
+//!
+//! let report = ledger.report(
+//!     &Aggregation::new(Duration::from_secs(24 * 60 * 60))
+//!         .by_label("team")
+//!         .pricing(Pricing::new().model("gemini-2.5-pro", 1.25, 10.0)),
+//! );
+//! let csv = report.to_csv();
+//! ```
+
+use crate::storage;
+use crate::{GeminiJsonOutput, StreamEvent, StreamStats};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Tokens used by one model in one request.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct UsageEvent {
+    /// When the request finished, in Unix milliseconds.
+    pub at: u64,
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+    /// The request's [labels](crate::Gemini::label).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl UsageEvent {
+    /// One event per model in the output's stats, timestamped `at` (Unix milliseconds).
+    ///
+    /// Models are listed in name order. Outputs without stats yield no events.
+    pub fn from_output(
+        output: &GeminiJsonOutput,
+        labels: &BTreeMap<String, String>,
+        at: u64,
+    ) -> Vec<Self> {
+        let Some(stats) = &output.stats else {
+            return Vec::new();
+        };
+        let mut models: Vec<_> = stats.models.iter().collect();
+        models.sort_by(|a, b| a.0.cmp(b.0));
+        models
+            .into_iter()
+            .map(|(model, stats)| {
+                Self::from_tokens(model, |key| stats.tokens.get(key).copied(), labels, at)
+            })
+            .collect()
+    }
+
+    /// One event per model in the stats of a stream's
+    /// [`Result`](crate::StreamEvent::Result) event, in either shape, timestamped `at`.
+    ///
+    /// Models are listed in name order. Models without token counts yield no events.
+    pub fn from_stream_stats(
+        stats: &StreamStats,
+        labels: &BTreeMap<String, String>,
+        at: u64,
+    ) -> Vec<Self> {
+        let stats = stats.to_value();
+        let Some(models) = stats.get("models").and_then(|m| m.as_object()) else {
+            return Vec::new();
+        };
+        let mut models: Vec<_> = models.iter().collect();
+        models.sort_by(|a, b| a.0.cmp(b.0));
+        models
+            .into_iter()
+            .filter_map(|(model, stats)| {
+                let tokens = stats.get("tokens")?;
+                Some(Self::from_tokens(
+                    model,
+                    |key| tokens.get(key).and_then(serde_json::Value::as_u64),
+                    labels,
+                    at,
+                ))
+            })
+            .collect()
+    }
+
+    fn from_tokens(
+        model: &str,
+        count: impl Fn(&str) -> Option<u64>,
+        labels: &BTreeMap<String, String>,
+        at: u64,
+    ) -> Self {
+        let prompt = count("prompt").unwrap_or(0);
+        let output = count("candidates").unwrap_or(0);
+        Self {
+            at,
+            model: model.to_string(),
+            prompt_tokens: prompt,
+            output_tokens: output,
+            total_tokens: count("total").unwrap_or(prompt + output),
+            labels: labels.clone(),
+        }
+    }
+}
+
+/// An in-memory, thread-safe record of [`UsageEvent`]s.
+#[derive(Debug, Default)]
+pub struct UsageLedger {
+    events: Mutex<Vec<UsageEvent>>,
+}
+
+impl UsageLedger {
+    /// An empty ledger.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append an event.
+    pub fn record(&self, event: UsageEvent) {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(event);
+    }
+
+    /// Record the usage in a stream's `Result` event. Other events are ignored.
+    pub(crate) fn record_stream_event(
+        &self,
+        event: &StreamEvent,
+        labels: &BTreeMap<String, String>,
+    ) {
+        if let StreamEvent::Result { stats, .. } = event {
+            for usage in UsageEvent::from_stream_stats(stats, labels, storage::now_millis()) {
+                self.record(usage);
+            }
+        }
+    }
+
+    /// Every recorded event, in recording order.
+    pub fn events(&self) -> Vec<UsageEvent> {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Aggregate the recorded events. See [`Aggregation::aggregate`].
+    pub fn report(&self, aggregation: &Aggregation) -> CostReport {
+        aggregation.aggregate(&self.events.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// Prices per million tokens, by model.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Pricing {
+    /// `model -> (prompt price, output price)` per million tokens.
+    pub models: BTreeMap<String, (f64, f64)>,
+}
+
+impl Pricing {
+    /// An empty price table; every model is unpriced.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the prices of `model`, per million prompt and output tokens.
+    #[must_use]
+    pub fn model(mut self, model: impl Into<String>, prompt: f64, output: f64) -> Self {
+        self.models.insert(model.into(), (prompt, output));
+        self
+    }
+
+    /// The cost of the given token counts, or `None` if `model` has no price.
+    pub fn cost(&self, model: &str, prompt_tokens: u64, output_tokens: u64) -> Option<f64> {
+        let (prompt, output) = self.models.get(model)?;
+        Some((prompt_tokens as f64 * prompt + output_tokens as f64 * output) / 1_000_000.0)
+    }
+}
+
+/// How usage events are grouped into a [`CostReport`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregation {
+    /// Length of each time window; windows are aligned to the Unix epoch.
+    pub window: Duration,
+    /// Label key whose value splits each window further, e.g. `team`.
+    pub label: Option<String>,
+    /// Only events at or after this time (Unix milliseconds).
+    pub since: Option<u64>,
+    /// Only events before this time (Unix milliseconds).
+    pub until: Option<u64>,
+    pub pricing: Pricing,
+}
+
+impl Aggregation {
+    /// Group by model in windows of `window` (at least one millisecond).
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            label: None,
+            since: None,
+            until: None,
+            pricing: Pricing::new(),
+        }
+    }
+
+    /// Also group by the value of label `key`. Events without it are grouped under no value.
+    #[must_use]
+    pub fn by_label(mut self, key: impl Into<String>) -> Self {
+        self.label = Some(key.into());
+        self
+    }
+
+    /// Only include events in `[since, until)` (Unix milliseconds).
+    #[must_use]
+    pub fn between(mut self, since: u64, until: u64) -> Self {
+        self.since = Some(since);
+        self.until = Some(until);
+        self
+    }
+
+    /// Price the rows with `pricing`.
+    #[must_use]
+    pub fn pricing(mut self, pricing: Pricing) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
+    /// Aggregate `events` into rows sorted by window, label value and model.
+    pub fn aggregate(&self, events: &[UsageEvent]) -> CostReport {
+        let window = (self.window.as_millis() as u64).max(1);
+        let mut rows: BTreeMap<(u64, Option<String>, String), CostRow> = BTreeMap::new();
+        let in_range = |at: u64| {
+            self.since.is_none_or(|since| at >= since) && self.until.is_none_or(|until| at < until)
+        };
+        for event in events.iter().filter(|e| in_range(e.at)) {
+            let start = event.at - event.at % window;
+            let label = self
+                .label
+                .as_ref()
+                .and_then(|key| event.labels.get(key).cloned());
+            let row = rows
+                .entry((start, label.clone(), event.model.clone()))
+                .or_insert_with(|| CostRow {
+                    window_start: start,
+                    window_end: start + window,
+                    model: event.model.clone(),
+                    label,
+                    requests: 0,
+                    prompt_tokens: 0,
+                    output_tokens: 0,
+                    total_tokens: 0,
+                    cost: None,
+                });
+            row.requests += 1;
+            row.prompt_tokens += event.prompt_tokens;
+            row.output_tokens += event.output_tokens;
+            row.total_tokens += event.total_tokens;
+        }
+        let rows = rows
+            .into_values()
+            .map(|mut row| {
+                row.cost = self
+                    .pricing
+                    .cost(&row.model, row.prompt_tokens, row.output_tokens);
+                row
+            })
+            .collect();
+        CostReport {
+            label_key: self.label.clone(),
+            rows,
+        }
+    }
+}
+
+/// Usage of one model (and label value) in one time window.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CostRow {
+    /// Start of the window, in Unix milliseconds.
+    pub window_start: u64,
+    /// End of the window (exclusive), in Unix milliseconds.
+    pub window_end: u64,
+    pub model: String,
+    /// Value of the [grouping label](Aggregation::by_label), if the events had it.
+    pub label: Option<String>,
+    /// Usage events counted, one per model per request.
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+    /// Cost in the [`Pricing`] currency, or `None` if the model is unpriced.
+    pub cost: Option<f64>,
+}
+
+/// Aggregated usage, ready for export.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct CostReport {
+    /// The label key rows are grouped by, if any.
+    pub label_key: Option<String>,
+    pub rows: Vec<CostRow>,
+}
+
+impl CostReport {
+    /// Sum of the priced rows' costs.
+    pub fn total_cost(&self) -> f64 {
+        self.rows.iter().filter_map(|row| row.cost).sum()
+    }
+
+    /// Render as CSV with a header row. The label column is named after the label key.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("window_start,window_end,model,");
+        if let Some(key) = &self.label_key {
+            out.push_str(&csv_field(key));
+            out.push(',');
+        }
+        out.push_str("requests,prompt_tokens,output_tokens,total_tokens,cost\n");
+        for row in &self.rows {
+            out.push_str(&format!(
+                "{},{},{},",
+                row.window_start,
+                row.window_end,
+                csv_field(&row.model)
+            ));
+            if self.label_key.is_some() {
+                out.push_str(&csv_field(row.label.as_deref().unwrap_or("")));
+                out.push(',');
+            }
+            let cost = row.cost.map(|c| format!("{c:.6}")).unwrap_or_default();
+            out.push_str(&format!(
+                "{},{},{},{},{cost}\n",
+                row.requests, row.prompt_tokens, row.output_tokens, row.total_tokens
+            ));
+        }
+        out
+    }
+
+    /// Render as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(at: u64, model: &str, team: Option<&str>, prompt: u64, output: u64) -> UsageEvent {
+        UsageEvent {
+            at,
+            model: model.into(),
+            prompt_tokens: prompt,
+            output_tokens: output,
+            total_tokens: prompt + output,
+            labels: team
+                .map(|t| BTreeMap::from([("team".to_string(), t.to_string())]))
+                .unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn test_events_are_grouped_by_window_label_and_model() {
+        let ledger = UsageLedger::new();
+        ledger.record(event(1_000, "pro", Some("payments"), 1_000_000, 100_000));
+        ledger.record(event(2_000, "pro", Some("payments"), 1_000_000, 100_000));
+        ledger.record(event(3_000, "flash", Some("search"), 10, 5));
+        ledger.record(event(3_600_000, "pro", None, 5, 5));
+
+        let report = ledger.report(
+            &Aggregation::new(Duration::from_secs(3_600))
+                .by_label("team")
+                .pricing(Pricing::new().model("pro", 1.25, 10.0)),
+        );
+        assert_eq!(report.rows.len(), 3);
+        let first = &report.rows[0];
+        assert_eq!((first.window_start, first.window_end), (0, 3_600_000));
+        assert_eq!(first.label.as_deref(), Some("payments"));
+        assert_eq!(first.requests, 2);
+        assert_eq!(first.cost, Some(4.5));
+        assert_eq!(report.rows[1].cost, None);
+        assert_eq!(report.rows[2].label, None);
+    }
+
+    #[test]
+    fn test_range_filters_events() {
+        let events = [event(10, "pro", None, 1, 1), event(20, "pro", None, 1, 1)];
+        let report = Aggregation::new(Duration::from_secs(60))
+            .between(15, 30)
+            .aggregate(&events);
+        assert_eq!(report.rows.len(), 1);
+        assert_eq!(report.rows[0].requests, 1);
+    }
+
+    #[test]
+    fn test_csv_export_quotes_fields() {
+        let events = [event(0, "pro", Some("a,b"), 2, 3)];
+        let report = Aggregation::new(Duration::from_secs(60))
+            .by_label("team")
+            .pricing(Pricing::new().model("pro", 1_000_000.0, 0.0))
+            .aggregate(&events);
+        assert_eq!(
+            report.to_csv(),
+            "window_start,window_end,model,team,requests,prompt_tokens,output_tokens,total_tokens,cost\n\
+             0,60000,pro,\"a,b\",1,2,3,5,2.000000\n"
+        );
+        let parsed: CostReport = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(parsed, report);
+    }
+
+    #[tokio::test]
+    async fn test_stream_usage_is_recorded_from_untyped_stats() {
+        use crate::simulate::Simulator;
+        use crate::Gemini;
+        use futures_util::StreamExt;
+        use std::sync::Arc;
+
+        let ledger = Arc::new(UsageLedger::new());
+        let stream = Gemini::new("Hi")
+            .model("gemini-2.5-flash")
+            .simulator(Simulator::new().fallback("Hello there"))
+            .usage_ledger(ledger.clone())
+            .stream()
+            .unwrap();
+        let events: Vec<_> = stream.collect().await;
+        let Some(Ok(StreamEvent::Result { stats, .. })) = events.last() else {
+            panic!("no result event");
+        };
+        assert!(stats.typed().is_none());
+
+        let recorded = ledger.events();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].model, "gemini-2.5-flash");
+        assert!(recorded[0].output_tokens > 0);
+    }
+}
@@ -0,0 +1,617 @@
+//! Building blocks for GitHub and GitLab webhook bots.
+//!
+//! [`parse_webhook`] turns a webhook delivery (the event-type header plus the JSON body) into a
+//! [`WebhookEvent`]. A [`Bot`] routes those events to [`BotHandler`]s — a prompt template
+//! ([`PromptHandler`]), a [`Pipeline`], or any async closure ([`handler_fn`]) — and wraps the
+//! result in a [`BotReply`] ready to be posted back as a comment. Serving HTTP, verifying
+//! webhook signatures and posting the reply are left to the host application.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::bots::{Bot, PromptHandler, Provider};
+//!
+//! # async fn run(event_header: &str, body: &str) -> Result<(), gemini_oxide::GeminiError> {
+//! let bot = Bot::new()
+//!     .ignore_author("review-bot")
+//!     .on_pull_request_opened(PromptHandler::new(
+//!         "Review pull request {{repo}}#{{number}}: {{title}}\n\n{{body}}",
+//!     ))
+//!     .on_command(
+//!         "/gemini triage",
+//!         PromptHandler::new("Triage issue {{repo}}#{{number}}. Focus: {{args}}"),
+//!     );
+//!
+//! if let Some(reply) = bot.handle(Provider::GitHub, event_header, body).await? {
+//!     // POST reply.body as a comment on reply.repo#reply.number.
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::pipeline::{self, Pipeline};
+use crate::{Configure, Gemini, GeminiError};
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+
+/// The service that sent a webhook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Provider {
+    /// `event` is the `X-GitHub-Event` header.
+    GitHub,
+    /// `event` is the `X-Gitlab-Event` header.
+    GitLab,
+}
+
+/// A webhook delivery a bot can act on.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// A pull (merge) request was opened or reopened.
+    PullRequestOpened {
+        repo: String,
+        number: u64,
+        title: String,
+        body: String,
+        author: String,
+        url: String,
+    },
+    /// An issue was opened or reopened.
+    IssueOpened {
+        repo: String,
+        number: u64,
+        title: String,
+        body: String,
+        author: String,
+        url: String,
+    },
+    /// A comment was posted on an issue or pull request.
+    Comment {
+        repo: String,
+        /// The issue or pull request commented on.
+        number: u64,
+        body: String,
+        author: String,
+        url: String,
+        on_pull_request: bool,
+    },
+}
+
+impl WebhookEvent {
+    /// The repository, as `owner/name` (GitHub) or `group/project` (GitLab).
+    pub fn repo(&self) -> &str {
+        match self {
+            Self::PullRequestOpened { repo, .. }
+            | Self::IssueOpened { repo, .. }
+            | Self::Comment { repo, .. } => repo,
+        }
+    }
+
+    /// The issue or pull request number.
+    pub fn number(&self) -> u64 {
+        match self {
+            Self::PullRequestOpened { number, .. }
+            | Self::IssueOpened { number, .. }
+            | Self::Comment { number, .. } => *number,
+        }
+    }
+
+    /// Who triggered the event.
+    pub fn author(&self) -> &str {
+        match self {
+            Self::PullRequestOpened { author, .. }
+            | Self::IssueOpened { author, .. }
+            | Self::Comment { author, .. } => author,
+        }
+    }
+
+    /// Template variables describing the event: `kind`, `repo`, `number`, `title`, `body`,
+    /// `author`, `url` and `comment`. Variables that do not apply are empty.
+    pub fn variables(&self) -> BTreeMap<String, String> {
+        let (kind, title, body, url, comment) = match self {
+            Self::PullRequestOpened {
+                title, body, url, ..
+            } => (
+                "pull_request_opened",
+                title.as_str(),
+                body.as_str(),
+                url,
+                "",
+            ),
+            Self::IssueOpened {
+                title, body, url, ..
+            } => ("issue_opened", title.as_str(), body.as_str(), url, ""),
+            Self::Comment { body, url, .. } => ("comment", "", "", url, body.as_str()),
+        };
+        [
+            ("kind", kind.to_string()),
+            ("repo", self.repo().to_string()),
+            ("number", self.number().to_string()),
+            ("title", title.to_string()),
+            ("body", body.to_string()),
+            ("author", self.author().to_string()),
+            ("url", url.clone()),
+            ("comment", comment.to_string()),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect()
+    }
+}
+
+/// Parse a webhook delivery. Deliveries a bot does not act on (pushes, closed issues, edited
+/// comments, ...) yield `None`.
+///
+/// # Errors
+///
+/// Returns `GeminiError::JsonParseFailed` if `payload` is not JSON, or
+/// `GeminiError::ValidationFailed` if a supported event lacks a required field.
+pub fn parse_webhook(
+    provider: Provider,
+    event: &str,
+    payload: &str,
+) -> Result<Option<WebhookEvent>, GeminiError> {
+    let payload: Value = serde_json::from_str(payload).map_err(GeminiError::JsonParseFailed)?;
+    let p = Payload(&payload);
+    match provider {
+        Provider::GitHub => parse_github(event, p),
+        Provider::GitLab => parse_gitlab(event, p),
+    }
+}
+
+fn parse_github(event: &str, p: Payload) -> Result<Option<WebhookEvent>, GeminiError> {
+    let action = p.opt_str("/action");
+    let repo = || p.str("/repository/full_name");
+    Ok(match (event, action) {
+        ("pull_request", Some("opened" | "reopened")) => Some(WebhookEvent::PullRequestOpened {
+            repo: repo()?,
+            number: p.num("/pull_request/number")?,
+            title: p.str("/pull_request/title")?,
+            body: p.opt_str("/pull_request/body").unwrap_or_default().into(),
+            author: p.str("/pull_request/user/login")?,
+            url: p.str("/pull_request/html_url")?,
+        }),
+        ("issues", Some("opened" | "reopened")) => Some(WebhookEvent::IssueOpened {
+            repo: repo()?,
+            number: p.num("/issue/number")?,
+            title: p.str("/issue/title")?,
+            body: p.opt_str("/issue/body").unwrap_or_default().into(),
+            author: p.str("/issue/user/login")?,
+            url: p.str("/issue/html_url")?,
+        }),
+        ("issue_comment", Some("created")) => Some(WebhookEvent::Comment {
+            repo: repo()?,
+            number: p.num("/issue/number")?,
+            body: p.str("/comment/body")?,
+            author: p.str("/comment/user/login")?,
+            url: p.str("/comment/html_url")?,
+            on_pull_request: p.0.pointer("/issue/pull_request").is_some(),
+        }),
+        _ => None,
+    })
+}
+
+fn parse_gitlab(event: &str, p: Payload) -> Result<Option<WebhookEvent>, GeminiError> {
+    let action = p.opt_str("/object_attributes/action");
+    let repo = || p.str("/project/path_with_namespace");
+    let opened = matches!(action, Some("open" | "reopen"));
+    Ok(match event {
+        "Merge Request Hook" if opened => Some(WebhookEvent::PullRequestOpened {
+            repo: repo()?,
+            number: p.num("/object_attributes/iid")?,
+            title: p.str("/object_attributes/title")?,
+            body: p
+                .opt_str("/object_attributes/description")
+                .unwrap_or_default()
+                .into(),
+            author: p.str("/user/username")?,
+            url: p.str("/object_attributes/url")?,
+        }),
+        "Issue Hook" if opened => Some(WebhookEvent::IssueOpened {
+            repo: repo()?,
+            number: p.num("/object_attributes/iid")?,
+            title: p.str("/object_attributes/title")?,
+            body: p
+                .opt_str("/object_attributes/description")
+                .unwrap_or_default()
+                .into(),
+            author: p.str("/user/username")?,
+            url: p.str("/object_attributes/url")?,
+        }),
+        "Note Hook" => {
+            let on_pull_request = match p.opt_str("/object_attributes/noteable_type") {
+                Some("MergeRequest") => true,
+                Some("Issue") => false,
+                _ => return Ok(None),
+            };
+            let number = if on_pull_request {
+                p.num("/merge_request/iid")?
+            } else {
+                p.num("/issue/iid")?
+            };
+            Some(WebhookEvent::Comment {
+                repo: repo()?,
+                number,
+                body: p.str("/object_attributes/note")?,
+                author: p.str("/user/username")?,
+                url: p.str("/object_attributes/url")?,
+                on_pull_request,
+            })
+        }
+        _ => None,
+    })
+}
+
+/// JSON-pointer accessors that report missing fields.
+#[derive(Clone, Copy)]
+struct Payload<'a>(&'a Value);
+
+impl Payload<'_> {
+    fn opt_str(&self, pointer: &str) -> Option<&str> {
+        self.0.pointer(pointer).and_then(Value::as_str)
+    }
+
+    fn str(&self, pointer: &str) -> Result<String, GeminiError> {
+        self.opt_str(pointer)
+            .map(str::to_string)
+            .ok_or_else(|| missing(pointer))
+    }
+
+    fn num(&self, pointer: &str) -> Result<u64, GeminiError> {
+        self.0
+            .pointer(pointer)
+            .and_then(Value::as_u64)
+            .ok_or_else(|| missing(pointer))
+    }
+}
+
+fn missing(pointer: &str) -> GeminiError {
+    GeminiError::ValidationFailed(format!("webhook payload is missing `{pointer}`"))
+}
+
+/// Produces the reply text for an event.
+pub trait BotHandler: Send + Sync {
+    /// Handle `event`. `variables` are the event's [`variables`](WebhookEvent::variables) plus
+    /// `args`: for commands, the text after the trigger phrase.
+    fn handle<'a>(
+        &'a self,
+        event: &'a WebhookEvent,
+        variables: &'a BTreeMap<String, String>,
+    ) -> BoxFuture<'a, Result<String, GeminiError>>;
+}
+
+/// Renders a prompt template with the event's variables (`{{title}}`, `{{body}}`, `{{args}}`,
+/// ...) and returns the model's text response.
+pub struct PromptHandler {
+    template: String,
+    configure: Option<Configure>,
+}
+
+impl PromptHandler {
+    /// Answer with the response to `template`.
+    pub fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+            configure: None,
+        }
+    }
+
+    /// Customize each request (model, tools, ...).
+    #[must_use]
+    pub fn configure(mut self, f: impl Fn(Gemini) -> Gemini + Send + Sync + 'static) -> Self {
+        self.configure = Some(Box::new(f));
+        self
+    }
+}
+
+impl BotHandler for PromptHandler {
+    fn handle<'a>(
+        &'a self,
+        _event: &'a WebhookEvent,
+        variables: &'a BTreeMap<String, String>,
+    ) -> BoxFuture<'a, Result<String, GeminiError>> {
+        let request = Gemini::new(pipeline::render(&self.template, variables));
+        let request = match &self.configure {
+            Some(configure) => configure(request),
+            None => request,
+        };
+        Box::pin(request.text())
+    }
+}
+
+/// Runs a [`Pipeline`] with the event's variables as inputs and returns the last step's output.
+pub struct PipelineHandler<F> {
+    build: F,
+}
+
+impl<F> PipelineHandler<F>
+where
+    F: Fn() -> Pipeline + Send + Sync,
+{
+    /// Build a fresh pipeline for every event with `build`.
+    pub fn new(build: F) -> Self {
+        Self { build }
+    }
+}
+
+impl<F> BotHandler for PipelineHandler<F>
+where
+    F: Fn() -> Pipeline + Send + Sync,
+{
+    fn handle<'a>(
+        &'a self,
+        _event: &'a WebhookEvent,
+        variables: &'a BTreeMap<String, String>,
+    ) -> BoxFuture<'a, Result<String, GeminiError>> {
+        let pipeline = variables
+            .iter()
+            .fold((self.build)(), |p, (name, value)| p.input(name, value));
+        Box::pin(async move {
+            let run = pipeline.run().await?;
+            Ok(run.output().unwrap_or_default().to_string())
+        })
+    }
+}
+
+struct FnHandler<F>(F);
+
+impl<F, Fut> BotHandler for FnHandler<F>
+where
+    F: Fn(WebhookEvent, BTreeMap<String, String>) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<String, GeminiError>> + Send + 'static,
+{
+    fn handle<'a>(
+        &'a self,
+        event: &'a WebhookEvent,
+        variables: &'a BTreeMap<String, String>,
+    ) -> BoxFuture<'a, Result<String, GeminiError>> {
+        Box::pin((self.0)(event.clone(), variables.clone()))
+    }
+}
+
+/// Wrap an async closure as a [`BotHandler`].
+pub fn handler_fn<F, Fut>(f: F) -> impl BotHandler
+where
+    F: Fn(WebhookEvent, BTreeMap<String, String>) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<String, GeminiError>> + Send + 'static,
+{
+    FnHandler(f)
+}
+
+/// A comment to post in response to an event.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BotReply {
+    pub provider: Provider,
+    pub repo: String,
+    /// The issue or pull request to comment on.
+    pub number: u64,
+    /// Markdown comment body.
+    pub body: String,
+}
+
+/// Routes webhook events to handlers.
+#[derive(Default)]
+pub struct Bot {
+    pull_request: Option<Arc<dyn BotHandler>>,
+    issue: Option<Arc<dyn BotHandler>>,
+    commands: Vec<(String, Arc<dyn BotHandler>)>,
+    ignored_authors: Vec<String>,
+    footer: Option<String>,
+}
+
+impl Bot {
+    /// A bot with no handlers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle newly opened pull (merge) requests.
+    #[must_use]
+    pub fn on_pull_request_opened(mut self, handler: impl BotHandler + 'static) -> Self {
+        self.pull_request = Some(Arc::new(handler));
+        self
+    }
+
+    /// Handle newly opened issues.
+    #[must_use]
+    pub fn on_issue_opened(mut self, handler: impl BotHandler + 'static) -> Self {
+        self.issue = Some(Arc::new(handler));
+        self
+    }
+
+    /// Handle comments with a line starting with `trigger` (case-insensitive). The rest of that
+    /// line is available as `{{args}}`. The first matching command wins.
+    #[must_use]
+    pub fn on_command(
+        mut self,
+        trigger: impl Into<String>,
+        handler: impl BotHandler + 'static,
+    ) -> Self {
+        self.commands.push((trigger.into(), Arc::new(handler)));
+        self
+    }
+
+    /// Ignore events triggered by `login`, typically the bot's own account, so it never
+    /// answers itself.
+    #[must_use]
+    pub fn ignore_author(mut self, login: impl Into<String>) -> Self {
+        self.ignored_authors.push(login.into());
+        self
+    }
+
+    /// Append `text` to every reply, below a rule.
+    #[must_use]
+    pub fn footer(mut self, text: impl Into<String>) -> Self {
+        self.footer = Some(text.into());
+        self
+    }
+
+    /// Parse a webhook delivery and handle it. Returns `None` if no handler applies.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by [`parse_webhook`] or by the handler.
+    pub async fn handle(
+        &self,
+        provider: Provider,
+        event: &str,
+        payload: &str,
+    ) -> Result<Option<BotReply>, GeminiError> {
+        match parse_webhook(provider, event, payload)? {
+            Some(event) => self.dispatch(provider, &event).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Handle an already parsed event. Returns `None` if no handler applies.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by the handler.
+    pub async fn dispatch(
+        &self,
+        provider: Provider,
+        event: &WebhookEvent,
+    ) -> Result<Option<BotReply>, GeminiError> {
+        if self.ignored_authors.iter().any(|a| a == event.author()) {
+            return Ok(None);
+        }
+        let mut variables = event.variables();
+        let handler = match event {
+            WebhookEvent::PullRequestOpened { .. } => self.pull_request.as_ref(),
+            WebhookEvent::IssueOpened { .. } => self.issue.as_ref(),
+            WebhookEvent::Comment { body, .. } => {
+                self.commands.iter().find_map(|(trigger, handler)| {
+                    let args = command_args(body, trigger)?;
+                    variables.insert("args".to_string(), args);
+                    Some(handler)
+                })
+            }
+        };
+        let Some(handler) = handler else {
+            return Ok(None);
+        };
+        variables.entry("args".to_string()).or_default();
+
+        let mut body = handler.handle(event, &variables).await?.trim().to_string();
+        if let Some(footer) = &self.footer {
+            body.push_str(&format!("\n\n---\n{footer}"));
+        }
+        Ok(Some(BotReply {
+            provider,
+            repo: event.repo().to_string(),
+            number: event.number(),
+            body,
+        }))
+    }
+}
+
+/// The rest of the first line of `comment` that starts with `trigger`, if any.
+fn command_args(comment: &str, trigger: &str) -> Option<String> {
+    comment.lines().find_map(|line| {
+        let line = line.trim();
+        let head = line.get(..trigger.len())?;
+        let rest = &line[trigger.len()..];
+        let boundary = rest.is_empty() || rest.starts_with(char::is_whitespace);
+        (head.eq_ignore_ascii_case(trigger) && boundary).then(|| rest.trim().to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GITHUB_COMMENT: &str = r#"{
+        "action": "created",
+        "repository": { "full_name": "acme/app" },
+        "issue": { "number": 7, "pull_request": { "url": "..." } },
+        "comment": {
+            "body": "Thanks!\n/gemini review focus on error handling",
+            "user": { "login": "alice" },
+            "html_url": "https://github.com/acme/app/pull/7#c1"
+        }
+    }"#;
+
+    #[test]
+    fn test_parse_github_and_gitlab_payloads() {
+        let event = parse_webhook(Provider::GitHub, "issue_comment", GITHUB_COMMENT)
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            &event,
+            WebhookEvent::Comment { number: 7, on_pull_request: true, author, .. } if author == "alice"
+        ));
+        assert_eq!(parse_webhook(Provider::GitHub, "push", "{}").unwrap(), None);
+
+        let gitlab = r#"{
+            "user": { "username": "bob" },
+            "project": { "path_with_namespace": "group/app" },
+            "object_attributes": {
+                "action": "open", "iid": 12, "title": "Add cache",
+                "description": null, "url": "https://gitlab.com/group/app/-/merge_requests/12"
+            }
+        }"#;
+        let event = parse_webhook(Provider::GitLab, "Merge Request Hook", gitlab)
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.repo(), "group/app");
+        assert_eq!(event.variables()["title"], "Add cache");
+        assert_eq!(event.variables()["body"], "");
+
+        let err = parse_webhook(
+            Provider::GitLab,
+            "Issue Hook",
+            r#"{"object_attributes":{"action":"open"}}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(err, GeminiError::ValidationFailed(_)));
+    }
+
+    #[test]
+    fn test_command_args_need_a_word_boundary() {
+        assert_eq!(
+            command_args("hi\n/Gemini review  now ", "/gemini review"),
+            Some("now".to_string())
+        );
+        assert_eq!(command_args("/gemini reviewer", "/gemini review"), None);
+        assert_eq!(
+            command_args("/gemini review", "/gemini review"),
+            Some(String::new())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bot_routes_commands_and_ignores_itself() {
+        let bot = Bot::new()
+            .ignore_author("bot")
+            .footer("_automated review_")
+            .on_command(
+                "/gemini review",
+                handler_fn(|event, vars| async move {
+                    Ok(format!("Reviewing #{} ({})", event.number(), vars["args"]))
+                }),
+            );
+
+        let reply = bot
+            .handle(Provider::GitHub, "issue_comment", GITHUB_COMMENT)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reply.repo, "acme/app");
+        assert_eq!(
+            reply.body,
+            "Reviewing #7 (focus on error handling)\n\n---\n_automated review_"
+        );
+
+        let own = GITHUB_COMMENT.replace("alice", "bot");
+        assert_eq!(
+            bot.handle(Provider::GitHub, "issue_comment", &own)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
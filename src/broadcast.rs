@@ -0,0 +1,172 @@
+//! Splitting one event stream between several consumers.
+//!
+//! [`BroadcastExt::broadcast`] drives a `stream()` in a background task and hands every event
+//! to `n` independent streams, so one generation can feed a UI, a logger and an accumulator
+//! at once without running the request again. Events are buffered per consumer up to a
+//! capacity; a consumer that falls further behind skips the oldest events and receives
+//! [`BroadcastError::Lagged`] with the number it missed, then carries on. The request keeps
+//! running while at least one consumer is alive and is stopped when all are dropped.
+//!
+//! ```rust,no_run
+//! use futures_util::StreamExt;
+//! use gemini_oxide::broadcast::BroadcastExt;
+//! use gemini_oxide::{Gemini, StreamEvent};
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let mut streams = Gemini::new("Explain lifetimes").stream()?.broadcast(2);
+//! let (mut ui, log) = (streams.remove(0), streams.remove(0));
+//!
+//! tokio::spawn(log.for_each(|event| async move { println!("{event:?}") }));
+//! while let Some(event) = ui.next().await {
+//!     if let StreamEvent::Message { content, .. } = event? {
+//!         print!("{content}");
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{GeminiError, StreamEvent};
+use futures_util::stream::{BoxStream, Stream, StreamExt};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Events buffered per consumer by [`BroadcastExt::broadcast`].
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// A failure seen by one consumer of a broadcast stream.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum BroadcastError {
+    /// The consumer fell behind and missed this many events.
+    #[error("consumer fell behind and missed {0} events")]
+    Lagged(u64),
+    /// The source stream failed; every consumer sees the same error.
+    #[error(transparent)]
+    Source(Arc<GeminiError>),
+}
+
+impl From<BroadcastError> for GeminiError {
+    fn from(error: BroadcastError) -> Self {
+        match error {
+            BroadcastError::Lagged(_) => GeminiError::RuntimeError(error.to_string()),
+            BroadcastError::Source(source) => Arc::try_unwrap(source)
+                .unwrap_or_else(|shared| GeminiError::RuntimeError(shared.to_string())),
+        }
+    }
+}
+
+/// One consumer's view of a broadcast stream.
+pub type BroadcastStream = BoxStream<'static, Result<StreamEvent, BroadcastError>>;
+
+/// Splits an event stream between several consumers.
+pub trait BroadcastExt: Stream<Item = Result<StreamEvent, GeminiError>> + Sized {
+    /// `n` streams that each receive every event, buffering [`DEFAULT_CAPACITY`] events.
+    ///
+    /// Must be called within a Tokio runtime.
+    fn broadcast(self, n: usize) -> Vec<BroadcastStream>
+    where
+        Self: Send + 'static,
+    {
+        self.broadcast_with_capacity(n, DEFAULT_CAPACITY)
+    }
+
+    /// `n` streams that each receive every event, buffering up to `capacity` events (at
+    /// least one) for a consumer before it starts lagging.
+    ///
+    /// Must be called within a Tokio runtime.
+    fn broadcast_with_capacity(self, n: usize, capacity: usize) -> Vec<BroadcastStream>
+    where
+        Self: Send + 'static,
+    {
+        let (sender, receiver) = broadcast::channel(capacity.max(1));
+        // Subscribe everyone before the first event is sent.
+        let mut receivers: Vec<_> = (1..n).map(|_| sender.subscribe()).collect();
+        if n > 0 {
+            receivers.insert(0, receiver);
+        }
+        let streams = receivers.into_iter().map(consume).collect();
+
+        tokio::spawn(async move {
+            let source = self;
+            tokio::pin!(source);
+            while let Some(item) = source.next().await {
+                let item = item.map_err(|e| BroadcastError::Source(Arc::new(e)));
+                // Stop the request once nobody is listening.
+                if sender.send(item).is_err() {
+                    break;
+                }
+            }
+        });
+        streams
+    }
+}
+
+impl<S> BroadcastExt for S where S: Stream<Item = Result<StreamEvent, GeminiError>> {}
+
+fn consume(
+    mut receiver: broadcast::Receiver<Result<StreamEvent, BroadcastError>>,
+) -> BroadcastStream {
+    Box::pin(async_stream::stream! {
+        loop {
+            match receiver.recv().await {
+                Ok(item) => yield item,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    yield Err(BroadcastError::Lagged(missed));
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+
+    fn message(content: &str) -> Result<StreamEvent, GeminiError> {
+        Ok(StreamEvent::Message {
+            role: "model".into(),
+            content: content.into(),
+            delta: Some(true),
+            timestamp: String::new(),
+        })
+    }
+
+    fn text(item: &Result<StreamEvent, BroadcastError>) -> String {
+        match item {
+            Ok(StreamEvent::Message { content, .. }) => content.clone(),
+            Ok(other) => format!("{other:?}"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_every_consumer_sees_every_event() {
+        let source = stream::iter(vec![
+            message("a"),
+            message("b"),
+            Err(GeminiError::ApiError("quota".into())),
+        ]);
+        let streams = source.broadcast(3);
+        assert_eq!(streams.len(), 3);
+        for consumer in streams {
+            let items: Vec<_> = consumer.collect().await;
+            let texts: Vec<_> = items.iter().map(text).collect();
+            assert_eq!(texts, ["a", "b", "Gemini API Error: quota"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_consumer_lags_and_continues() {
+        let source = stream::iter((0..5).map(|i| message(&i.to_string())));
+        let mut streams = source.broadcast_with_capacity(1, 2);
+        let slow = streams.pop().unwrap();
+        // Let the source run to completion before reading.
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        let items: Vec<_> = slow.collect().await;
+        assert!(matches!(items[0], Err(BroadcastError::Lagged(3))));
+        assert_eq!(text(&items[1]), "3");
+        assert_eq!(text(&items[2]), "4");
+    }
+}
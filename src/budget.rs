@@ -0,0 +1,226 @@
+//! Deadline- and token-budget-aware request planning.
+//!
+//! Set a latency target with [`Gemini::deadline`](crate::Gemini::deadline) and/or a token
+//! budget with [`Gemini::token_budget`](crate::Gemini::token_budget), and the request is planned
+//! before launch: a [`BudgetPolicy`] estimates how many output tokens each model can produce in
+//! time, switches from the strong model to the fast one when the strong model cannot fit, and
+//! caps the answer length. The decision is recorded on
+//! [`GeminiJsonOutput::budget`](crate::GeminiJsonOutput::budget).
+//!
+//! The CLI has no output-token flag, so the cap is passed to the model as a length instruction.
+//! The deadline shapes the plan; it does not cancel a request that overruns it. Combine it with
+//! [`Gemini::timeout`](crate::Gemini::timeout) for a hard limit.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Latency characteristics of one model, used to estimate what fits a deadline.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ModelProfile {
+    pub model: String,
+    /// Typical time until the first output token.
+    pub first_token: Duration,
+    /// Typical output throughput.
+    pub tokens_per_second: f64,
+}
+
+impl ModelProfile {
+    pub fn new(model: impl Into<String>, first_token: Duration, tokens_per_second: f64) -> Self {
+        Self {
+            model: model.into(),
+            first_token,
+            tokens_per_second,
+        }
+    }
+
+    /// How many output tokens the model is expected to produce within `time`.
+    pub fn tokens_within(&self, time: Duration) -> u64 {
+        let generating = time.saturating_sub(self.first_token).as_secs_f64();
+        (generating * self.tokens_per_second.max(0.0)) as u64
+    }
+
+    /// Expected time to produce `tokens` output tokens.
+    pub fn latency_for(&self, tokens: u64) -> Duration {
+        let generating = tokens as f64 / self.tokens_per_second.max(f64::EPSILON);
+        self.first_token + Duration::from_secs_f64(generating)
+    }
+}
+
+/// Model profiles and thresholds used to plan budgeted requests.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BudgetPolicy {
+    /// The fast model to fall back to under tight deadlines.
+    pub fast: ModelProfile,
+    /// The model used when no model is pinned or routed.
+    pub strong: ModelProfile,
+    /// The smallest useful answer; the strong model is abandoned if it cannot produce this
+    /// many tokens in time.
+    pub min_output_tokens: u64,
+}
+
+impl Default for BudgetPolicy {
+    fn default() -> Self {
+        Self {
+            fast: ModelProfile::new("gemini-2.5-flash", Duration::from_millis(1_500), 150.0),
+            strong: ModelProfile::new("gemini-2.5-pro", Duration::from_secs(4), 60.0),
+            min_output_tokens: 256,
+        }
+    }
+}
+
+/// A request's latency and token limits.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Budget {
+    /// Time by which the answer should be complete.
+    pub deadline: Option<Duration>,
+    /// Total tokens (prompt and context plus answer) the request may use.
+    pub token_budget: Option<u64>,
+    pub policy: BudgetPolicy,
+}
+
+/// The plan made for a budgeted request.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BudgetDecision {
+    /// The model the request runs on.
+    pub model: String,
+    /// The answer length the model was asked to stay within, if any.
+    pub max_output_tokens: Option<u64>,
+    /// Expected time to complete an answer of `max_output_tokens`, if a deadline was set.
+    pub estimated_latency: Option<Duration>,
+    /// Human-readable notes on every adjustment made.
+    pub reasons: Vec<String>,
+}
+
+impl BudgetDecision {
+    /// The instruction appended to the prompt to cap the answer length.
+    pub(crate) fn instruction(&self) -> Option<String> {
+        self.max_output_tokens.map(|tokens| {
+            format!(
+                "Keep your answer under about {tokens} tokens (roughly {} words).",
+                tokens * 3 / 4
+            )
+        })
+    }
+}
+
+impl Budget {
+    /// Plan a request whose prompt and context total `input_chars` characters.
+    ///
+    /// `preferred` is the model that would otherwise be used (pinned, routed, or `None` for the
+    /// policy's strong model); a `pinned` model is never switched, only capped.
+    pub fn plan(
+        &self,
+        preferred: Option<&str>,
+        pinned: bool,
+        input_chars: usize,
+    ) -> BudgetDecision {
+        let policy = &self.policy;
+        let mut reasons = Vec::new();
+        let mut profile = match preferred {
+            Some(model) if model == policy.fast.model => policy.fast.clone(),
+            Some(model) => ModelProfile {
+                model: model.to_string(),
+                ..policy.strong.clone()
+            },
+            None => policy.strong.clone(),
+        };
+
+        let mut cap = self.token_budget.map(|budget| {
+            let input_tokens = input_chars.div_ceil(4) as u64;
+            let left = budget.saturating_sub(input_tokens);
+            if left < policy.min_output_tokens {
+                reasons.push(format!(
+                    "input uses ~{input_tokens} of the {budget}-token budget; answer kept at {} tokens",
+                    policy.min_output_tokens
+                ));
+                policy.min_output_tokens
+            } else {
+                left
+            }
+        });
+
+        if let Some(deadline) = self.deadline {
+            let fits = |p: &ModelProfile| p.tokens_within(deadline) >= policy.min_output_tokens;
+            if !fits(&profile) && !pinned && profile.model != policy.fast.model {
+                reasons.push(format!(
+                    "{} fits only ~{} tokens in {deadline:?}; switched to {}",
+                    profile.model,
+                    profile.tokens_within(deadline),
+                    policy.fast.model
+                ));
+                profile = policy.fast.clone();
+            }
+            let in_time = profile.tokens_within(deadline);
+            if in_time < policy.min_output_tokens {
+                reasons.push(format!(
+                    "{deadline:?} allows only ~{in_time} tokens on {}; the deadline may be missed",
+                    profile.model
+                ));
+            }
+            let in_time = in_time.max(policy.min_output_tokens);
+            if cap.is_none_or(|c| in_time < c) {
+                reasons.push(format!(
+                    "answer capped at {in_time} tokens to finish within {deadline:?}"
+                ));
+                cap = Some(in_time);
+            }
+        }
+
+        BudgetDecision {
+            estimated_latency: self
+                .deadline
+                .and(cap)
+                .map(|tokens| profile.latency_for(tokens)),
+            model: profile.model,
+            max_output_tokens: cap,
+            reasons,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(deadline_ms: Option<u64>, tokens: Option<u64>) -> Budget {
+        Budget {
+            deadline: deadline_ms.map(Duration::from_millis),
+            token_budget: tokens,
+            policy: BudgetPolicy::default(),
+        }
+    }
+
+    #[test]
+    fn test_tight_deadline_switches_to_fast_model() {
+        // Pro: 2s after first token * 60 tok/s = 120 < 256; flash: 4.5s * 150 = 675.
+        let decision = budget(Some(6_000), Some(10_000)).plan(None, false, 400);
+        assert_eq!(decision.model, "gemini-2.5-flash");
+        assert_eq!(decision.max_output_tokens, Some(675));
+        assert_eq!(
+            decision.estimated_latency,
+            Some(Duration::from_millis(6_000))
+        );
+        assert!(decision.reasons[0].starts_with("gemini-2.5-pro fits only ~120 tokens"));
+    }
+
+    #[test]
+    fn test_pinned_model_is_capped_not_switched() {
+        let decision = budget(Some(6_000), None).plan(Some("gemini-2.5-pro"), true, 0);
+        assert_eq!(decision.model, "gemini-2.5-pro");
+        assert_eq!(decision.max_output_tokens, Some(256));
+        assert!(decision.reasons[0].contains("the deadline may be missed"));
+    }
+
+    #[test]
+    fn test_token_budget_leaves_room_for_input() {
+        let decision = budget(None, Some(1_000)).plan(Some("gemini-2.5-flash"), false, 2_000);
+        assert_eq!(decision.model, "gemini-2.5-flash");
+        assert_eq!(decision.max_output_tokens, Some(500));
+        assert_eq!(decision.estimated_latency, None);
+        assert!(decision.reasons.is_empty());
+        assert_eq!(
+            decision.instruction().unwrap(),
+            "Keep your answer under about 500 tokens (roughly 375 words)."
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Cooperative cancellation of in-flight requests.
+//!
+//! A [`CancellationToken`] is a cheap, cloneable flag. Attach it to requests with
+//! [`Gemini::cancel_on`](crate::Gemini::cancel_on) and call [`cancel`](CancellationToken::cancel)
+//! from anywhere (a "stop" button, a shutdown signal, a parent task): every running `text()`,
+//! `json()` or `stream()` sharing the token kills its CLI process, closes its pipes and fails
+//! with `GeminiError::Cancelled`. Requests started after cancellation fail without launching.
+//!
+//! The API mirrors `tokio_util::sync::CancellationToken` without the extra dependency.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::cancel::CancellationToken;
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() {
+//! let token = CancellationToken::new();
+//! let request = tokio::spawn(
+//!     Gemini::new("Refactor the crate").yolo().cancel_on(token.clone()).text(),
+//! );
+//!
+//! // The user pressed "stop".
+//! token.cancel();
+//! assert!(request.await.unwrap().is_err());
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// A shared flag that cancels every request it is attached to.
+#[derive(Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<watch::Sender<bool>>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CancellationToken {
+    /// A token that has not been cancelled.
+    pub fn new() -> Self {
+        Self {
+            cancelled: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Cancel the token and every clone of it. Calling it again has no effect.
+    pub fn cancel(&self) {
+        self.cancelled.send_replace(true);
+    }
+
+    /// Whether [`cancel`](Self::cancel) has been called.
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// Wait until the token is cancelled. Returns immediately if it already is.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.cancelled.subscribe();
+        // The sender lives as long as `self`, so this only returns once cancelled.
+        let _ = receiver.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_cancel_wakes_every_clone() {
+        let token = CancellationToken::new();
+        let waiter = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+        assert!(!token.is_cancelled());
+
+        token.clone().cancel();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("waiter was not woken")
+            .unwrap();
+        assert!(token.is_cancelled());
+        // Already cancelled: returns at once.
+        token.cancelled().await;
+    }
+}
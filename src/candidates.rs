@@ -0,0 +1,104 @@
+//! N-best answers from a single request.
+//!
+//! The CLI has no `candidate_count` option, so [`Gemini::candidates`](crate::Gemini::candidates)
+//! asks the model for several distinct answers in one run, each with a self-assessed score, and
+//! parses them into [`Candidate`]s, best first. This saves the round trips of running the same
+//! prompt `n` times when a UI lets users pick or rank alternatives.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let titles = Gemini::new("Suggest a title for a blog post about Rust error handling")
+//!     .candidates(3)
+//!     .await?;
+//! for candidate in titles {
+//!     println!("{:?} {}", candidate.score, candidate.text);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::tasks::parse_json_payload;
+use crate::GeminiError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One of several alternative answers.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Candidate {
+    /// The answer.
+    pub text: String,
+    /// Why generation of this answer stopped, if reported. The CLI does not report it per
+    /// candidate, so this is `None` for now.
+    pub finish_reason: Option<String>,
+    /// The model's own rating of the answer, from 0.0 to 1.0, if it gave one.
+    pub score: Option<f64>,
+}
+
+/// `prompt` extended with the instruction to return `n` alternatives.
+pub(crate) fn prompt(prompt: &str, n: usize) -> String {
+    format!(
+        "{prompt}\n\nWrite {n} distinct alternative answers to the request above. Respond with \
+         only a JSON array of {n} objects, best answer first, each with the fields \"text\" (the \
+         complete answer) and \"score\" (a number from 0.0 to 1.0 rating how good it is). Do not \
+         wrap it in Markdown and do not add any commentary."
+    )
+}
+
+/// Parse up to `n` candidates from the model's reply, sorted by descending score.
+pub(crate) fn parse(reply: &str, n: usize) -> Result<Vec<Candidate>, GeminiError> {
+    let invalid = |reason: &str| GeminiError::ValidationFailed(format!("{reason}: {reply}"));
+    let Ok(Value::Array(items)) = parse_json_payload(reply) else {
+        return Err(invalid("candidates are not a JSON array"));
+    };
+    let mut candidates = Vec::with_capacity(items.len());
+    for item in items {
+        let (text, score) = match &item {
+            Value::String(text) => (text.clone(), None),
+            Value::Object(fields) => match fields.get("text").and_then(Value::as_str) {
+                Some(text) => (
+                    text.to_string(),
+                    fields
+                        .get("score")
+                        .and_then(Value::as_f64)
+                        .map(|score| score.clamp(0.0, 1.0)),
+                ),
+                None => return Err(invalid("candidate without text")),
+            },
+            _ => return Err(invalid("malformed candidate")),
+        };
+        candidates.push(Candidate {
+            text,
+            finish_reason: None,
+            score,
+        });
+    }
+    if candidates.is_empty() {
+        return Err(invalid("no candidates"));
+    }
+    // Stable, so unscored candidates keep the model's order after the scored ones.
+    candidates.sort_by(|a, b| b.score.unwrap_or(-1.0).total_cmp(&a.score.unwrap_or(-1.0)));
+    candidates.truncate(n);
+    Ok(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates_are_parsed_and_ranked() {
+        let reply = "```json\n[{\"text\": \"B\", \"score\": 0.4}, {\"text\": \"A\", \"score\": 0.9}, \"C\", {\"text\": \"D\", \"score\": 0.1}]\n```";
+        let candidates = parse(reply, 3).unwrap();
+        let texts: Vec<_> = candidates.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, ["A", "B", "D"]);
+        assert_eq!(candidates[0].score, Some(0.9));
+
+        assert!(matches!(
+            parse("Here you go: A, B", 2),
+            Err(GeminiError::ValidationFailed(_))
+        ));
+        assert!(parse("[{\"score\": 1}]", 1).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! What this build of the SDK and the installed CLI can do.
+//!
+//! Orchestration layers that schedule work across hosts need to know what each one supports
+//! before routing a request to it. [`detect`] (or [`crate::capabilities`] for the `gemini` on
+//! the `PATH`) reports the crate version and Cargo features compiled in, together with the
+//! version of the CLI found at runtime and the features that version supports.
+//!
+//! ```rust,no_run
+//! # async fn run() {
+//! let capabilities = gemini_oxide::capabilities().await;
+//! match &capabilities.cli {
+//!     Ok(cli) if cli.stream_json => println!("streaming with CLI {}", cli.version),
+//!     Ok(cli) => println!("CLI {} cannot stream", cli.version),
+//!     Err(reason) => println!("no usable CLI: {reason}"),
+//! }
+//! # }
+//! ```
+
+use crate::compat::CliVersion;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// How this crate was built.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BuildInfo {
+    /// The crate version.
+    pub version: &'static str,
+    /// The optional Cargo features compiled in, e.g. `sqlx`.
+    pub features: Vec<&'static str>,
+    /// The operating system the crate was compiled for.
+    pub target_os: &'static str,
+    /// Whether CLI runs can be put in their own process group and killed as a whole
+    /// (see [`spawn`](crate::spawn)).
+    pub process_groups: bool,
+}
+
+/// What the installed CLI supports.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CliCapabilities {
+    /// The version the CLI reported.
+    pub version: CliVersion,
+    /// Whether it supports `stream` and the other streaming methods.
+    pub stream_json: bool,
+    /// Whether it takes the prompt as a positional argument (older versions use `--prompt`).
+    pub positional_prompt: bool,
+}
+
+/// The capabilities of this build and of a CLI.
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    /// How this crate was built.
+    pub build: BuildInfo,
+    /// What the CLI supports, or why it could not be asked.
+    #[serde(serialize_with = "serialize_cli")]
+    pub cli: Result<CliCapabilities, String>,
+}
+
+/// How this crate was built.
+pub fn build_info() -> BuildInfo {
+    let mut features = Vec::new();
+    if cfg!(feature = "sqlx") {
+        features.push("sqlx");
+    }
+    if cfg!(feature = "mcp-server") {
+        features.push("mcp-server");
+    }
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        features,
+        target_os: std::env::consts::OS,
+        process_groups: cfg!(unix),
+    }
+}
+
+/// The capabilities of this build and of the CLI at `bin_path`.
+pub async fn detect(bin_path: impl AsRef<Path>) -> Capabilities {
+    let cli = CliVersion::detect(bin_path)
+        .await
+        .map(|version| CliCapabilities {
+            version,
+            stream_json: version.supports_stream_json(),
+            positional_prompt: version.takes_positional_prompt(),
+        })
+        .map_err(|e| e.to_string());
+    Capabilities {
+        build: build_info(),
+        cli,
+    }
+}
+
+fn serialize_cli<S: serde::Serializer>(
+    cli: &Result<CliCapabilities, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match cli {
+        Ok(cli) => cli.serialize(serializer),
+        Err(error) => serde_json::json!({ "error": error }).serialize(serializer),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_missing_cli_is_reported() {
+        let capabilities = detect("/nonexistent/gemini").await;
+        assert_eq!(capabilities.build.version, env!("CARGO_PKG_VERSION"));
+        assert!(capabilities.cli.is_err());
+        let json = serde_json::to_value(&capabilities).unwrap();
+        assert!(json["cli"]["error"].is_string());
+    }
+}
@@ -0,0 +1,258 @@
+//! Cargo and compiler integration for fix-it tooling.
+//!
+//! [`CargoCheck`] runs `cargo check --message-format=json`, collects the compiler's
+//! [`Diagnostic`]s with their source spans, and turns each one into a fix-suggestion request
+//! built on [`ErrorExplainer`](crate::tasks::ErrorExplainer).
+//!
+//! ```rust,no_run
+//! use gemini_oxide::cargo::CargoCheck;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let check = CargoCheck::new("path/to/crate");
+//! for diagnostic in check.diagnostics().await?.iter().filter(|d| d.is_error()) {
+//!     let explanation = check
+//!         .fix_request(diagnostic)
+//!         .configure(|g| g.model("gemini-2.5-pro"))
+//!         .run(diagnostic.text())
+//!         .await?;
+//!     println!("{}: {}", diagnostic.message, explanation.summary);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::tasks::{ErrorExplainer, ErrorExplanation};
+use crate::GeminiError;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// A rustc error code such as `E0382`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DiagnosticCode {
+    pub code: String,
+}
+
+/// A source region a diagnostic points at.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Span {
+    /// Path of the file, relative to the workspace root.
+    #[serde(rename = "file_name")]
+    pub file: String,
+    pub line_start: usize,
+    pub line_end: usize,
+    pub column_start: usize,
+    pub column_end: usize,
+    /// Whether this is the main location of the diagnostic.
+    pub is_primary: bool,
+    /// The compiler's label for the span, e.g. "value moved here".
+    #[serde(default)]
+    pub label: Option<String>,
+    /// A replacement the compiler itself suggests for the span.
+    #[serde(default)]
+    pub suggested_replacement: Option<String>,
+}
+
+/// A compiler diagnostic, as emitted by `cargo check --message-format=json`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Diagnostic {
+    /// `"error"`, `"warning"`, `"note"`, ...
+    pub level: String,
+    pub message: String,
+    #[serde(default)]
+    pub code: Option<DiagnosticCode>,
+    #[serde(default)]
+    pub spans: Vec<Span>,
+    /// Nested notes and help messages.
+    #[serde(default)]
+    pub children: Vec<Diagnostic>,
+    /// The human-readable rendering rustc would print.
+    #[serde(default)]
+    pub rendered: Option<String>,
+}
+
+impl Diagnostic {
+    /// Whether this is an error (including internal compiler errors).
+    pub fn is_error(&self) -> bool {
+        self.level.starts_with("error")
+    }
+
+    /// The primary span, if any.
+    pub fn primary_span(&self) -> Option<&Span> {
+        self.spans.iter().find(|s| s.is_primary)
+    }
+
+    /// The rendered diagnostic, or the bare message if rustc did not render one.
+    pub fn text(&self) -> &str {
+        self.rendered.as_deref().unwrap_or(&self.message)
+    }
+
+    /// Every distinct file referenced by this diagnostic and its children.
+    pub fn files(&self) -> Vec<&str> {
+        let mut files: Vec<&str> = Vec::new();
+        let children = self.children.iter().flat_map(|c| &c.spans);
+        for span in self.spans.iter().chain(children) {
+            if !files.contains(&span.file.as_str()) {
+                files.push(&span.file);
+            }
+        }
+        files
+    }
+}
+
+/// Runs `cargo check` on a crate or workspace and explains its diagnostics.
+#[derive(Debug, Clone)]
+pub struct CargoCheck {
+    dir: PathBuf,
+    cargo: PathBuf,
+    args: Vec<String>,
+}
+
+impl CargoCheck {
+    /// Check the crate or workspace in `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            cargo: PathBuf::from("cargo"),
+            args: Vec::new(),
+        }
+    }
+
+    /// Set the path to the `cargo` binary. Defaults to `"cargo"` on the PATH.
+    #[must_use]
+    pub fn cargo_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cargo = path.into();
+        self
+    }
+
+    /// Pass an extra argument to `cargo check`, e.g. `"--all-targets"`.
+    #[must_use]
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// The directory being checked.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Run `cargo check` and collect its diagnostics, deduplicated, in emission order.
+    ///
+    /// A failing build is not an error: its diagnostics are returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::RuntimeError` if cargo cannot be started, or if it fails without
+    /// reporting any diagnostic (e.g. no `Cargo.toml`).
+    pub async fn diagnostics(&self) -> Result<Vec<Diagnostic>, GeminiError> {
+        let output = Command::new(&self.cargo)
+            .arg("check")
+            .arg("--message-format=json")
+            .args(&self.args)
+            .current_dir(&self.dir)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|e| GeminiError::RuntimeError(format!("failed to run cargo: {e}")))?;
+
+        let diagnostics = parse_messages(&String::from_utf8_lossy(&output.stdout));
+        if !output.status.success() && diagnostics.is_empty() {
+            return Err(GeminiError::RuntimeError(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+        Ok(diagnostics)
+    }
+
+    /// A fix-suggestion request for `diagnostic`, with every file it references attached.
+    ///
+    /// Customize it further (model, radius, ...) and run it with
+    /// [`diagnostic.text()`](Diagnostic::text).
+    pub fn fix_request(&self, diagnostic: &Diagnostic) -> ErrorExplainer {
+        ErrorExplainer::new().files(diagnostic.files().into_iter().map(|f| self.dir.join(f)))
+    }
+
+    /// Ask the model to explain `diagnostic` and suggest fixes, with default settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by [`ErrorExplainer::run`].
+    pub async fn suggest_fixes(
+        &self,
+        diagnostic: &Diagnostic,
+    ) -> Result<ErrorExplanation, GeminiError> {
+        self.fix_request(diagnostic).run(diagnostic.text()).await
+    }
+}
+
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    #[serde(default)]
+    message: Option<Diagnostic>,
+}
+
+/// Collect compiler diagnostics from cargo's JSON message stream, skipping other messages.
+fn parse_messages(stdout: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for line in stdout.lines() {
+        let Ok(message) = serde_json::from_str::<CargoMessage>(line) else {
+            continue;
+        };
+        if message.reason != "compiler-message" {
+            continue;
+        }
+        if let Some(diagnostic) = message.message {
+            if !diagnostics.contains(&diagnostic) {
+                diagnostics.push(diagnostic);
+            }
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = r#"{"reason":"compiler-artifact","package_id":"dep 0.1.0"}
+{"reason":"compiler-message","package_id":"app 0.1.0","message":{"message":"borrow of moved value: `v`","code":{"code":"E0382","explanation":"..."},"level":"error","spans":[{"file_name":"src/main.rs","byte_start":60,"byte_end":61,"line_start":4,"line_end":4,"column_start":20,"column_end":21,"is_primary":true,"text":[],"label":"value borrowed here after move","suggested_replacement":null},{"file_name":"src/main.rs","byte_start":40,"byte_end":41,"line_start":3,"line_end":3,"column_start":10,"column_end":11,"is_primary":false,"text":[],"label":"value moved here"}],"children":[{"message":"consider cloning","code":null,"level":"help","spans":[{"file_name":"src/util.rs","byte_start":0,"byte_end":1,"line_start":1,"line_end":1,"column_start":1,"column_end":2,"is_primary":true,"text":[],"label":null,"suggested_replacement":".clone()"}],"children":[],"rendered":null}],"rendered":"error[E0382]: borrow of moved value: `v`\n"}}
+not json
+{"reason":"compiler-message","package_id":"app 0.1.0","message":{"message":"unused variable: `x`","code":null,"level":"warning","spans":[],"children":[],"rendered":"warning: unused variable"}}
+{"reason":"build-finished","success":false}"#;
+
+    #[test]
+    fn test_parse_messages_keeps_compiler_diagnostics() {
+        let diagnostics = parse_messages(OUTPUT);
+        assert_eq!(diagnostics.len(), 2);
+
+        let error = &diagnostics[0];
+        assert!(error.is_error());
+        assert_eq!(error.code.as_ref().unwrap().code, "E0382");
+        assert_eq!(error.primary_span().unwrap().line_start, 4);
+        assert_eq!(
+            error.children[0].spans[0].suggested_replacement.as_deref(),
+            Some(".clone()")
+        );
+        assert_eq!(error.files(), ["src/main.rs", "src/util.rs"]);
+        assert!(error.text().starts_with("error[E0382]"));
+
+        assert!(!diagnostics[1].is_error());
+        assert!(diagnostics[1].primary_span().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_missing_cargo_is_reported() {
+        let err = CargoCheck::new(".")
+            .cargo_path("/nonexistent/cargo")
+            .diagnostics()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, GeminiError::RuntimeError(msg) if msg.contains("failed to run cargo"))
+        );
+    }
+}
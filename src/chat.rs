@@ -0,0 +1,450 @@
+//! Relaying streamed responses to chat platforms.
+//!
+//! Chat APIs let a bot post a message and then edit it, but rate-limit edits (Slack's
+//! `chat.update`, Discord's message PATCH) and cap message length. A [`StreamBridge`] batches the
+//! model's deltas into periodic edits of one message, starts a new message when the current one
+//! is full, and backs off when the platform answers with [`Delivery::RetryAfter`].
+//!
+//! The platform calls themselves are supplied by implementing [`ChatSink`] with the host
+//! application's HTTP client.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::chat::{ChatSink, Delivery, StreamBridge};
+//! use gemini_oxide::{Gemini, GeminiError};
+//! use futures_util::future::BoxFuture;
+//!
+//! struct SlackThread;
+//!
+//! impl ChatSink for SlackThread {
+//!     fn post<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Delivery<String>, GeminiError>> {
+//!         Box::pin(async move { /* chat.postMessage */ Ok(Delivery::Delivered("ts".into())) })
+//!     }
+//!     fn edit<'a>(&'a self, id: &'a str, text: &'a str) -> BoxFuture<'a, Result<Delivery<()>, GeminiError>> {
+//!         Box::pin(async move { /* chat.update */ Ok(Delivery::Delivered(())) })
+//!     }
+//! }
+//!
+//! # async fn run() -> Result<(), GeminiError> {
+//! let stream = Gemini::new("Summarize today's incidents").stream()?;
+//! let summary = StreamBridge::slack().forward(&SlackThread, stream).await?;
+//! println!("posted {} message(s)", summary.messages.len());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::clock::{self, Clock};
+use crate::{GeminiError, StreamEvent};
+use futures_util::future::BoxFuture;
+use futures_util::stream::{Stream, StreamExt};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The result of a chat API call that may be rate limited.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delivery<T> {
+    /// The call succeeded.
+    Delivered(T),
+    /// The platform rejected the call; retry after the given delay (e.g. from `Retry-After`).
+    RetryAfter(Duration),
+}
+
+/// A chat conversation (channel, thread, DM) the bridge writes to.
+pub trait ChatSink: Send + Sync {
+    /// Post a new message and return its id.
+    fn post<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Delivery<String>, GeminiError>>;
+
+    /// Replace the text of message `id`.
+    fn edit<'a>(
+        &'a self,
+        id: &'a str,
+        text: &'a str,
+    ) -> BoxFuture<'a, Result<Delivery<()>, GeminiError>>;
+}
+
+/// What a [`StreamBridge::forward`] call posted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BridgeSummary {
+    /// Ids of the posted messages, in order.
+    pub messages: Vec<String>,
+    /// Number of successful edits.
+    pub edits: usize,
+    /// The full relayed text.
+    pub text: String,
+}
+
+/// Forwards a response stream into throttled, chunked chat message updates.
+#[derive(Clone)]
+pub struct StreamBridge {
+    min_interval: Duration,
+    max_chars: usize,
+    max_retries: u32,
+    cursor: String,
+    clock: Arc<dyn Clock>,
+}
+
+impl fmt::Debug for StreamBridge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamBridge")
+            .field("min_interval", &self.min_interval)
+            .field("max_chars", &self.max_chars)
+            .field("max_retries", &self.max_retries)
+            .field("cursor", &self.cursor)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for StreamBridge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamBridge {
+    /// One edit per second, 4 000 characters per message, a `▌` cursor while streaming.
+    pub fn new() -> Self {
+        Self {
+            min_interval: Duration::from_secs(1),
+            max_chars: 4_000,
+            max_retries: 5,
+            cursor: " ▌".to_string(),
+            clock: clock::default_clock(),
+        }
+    }
+
+    /// Settings for Slack: one `chat.update` per second, 3 900-character messages.
+    pub fn slack() -> Self {
+        Self::new().max_chars(3_900)
+    }
+
+    /// Settings for Discord: 5 edits per 5 seconds with headroom, 2 000-character messages.
+    pub fn discord() -> Self {
+        Self::new()
+            .min_interval(Duration::from_millis(1_200))
+            .max_chars(2_000)
+    }
+
+    /// Minimum time between two calls to the platform.
+    #[must_use]
+    pub fn min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    /// Maximum characters per message, including the cursor. Longer responses continue in a
+    /// new message.
+    #[must_use]
+    pub fn max_chars(mut self, chars: usize) -> Self {
+        self.max_chars = chars;
+        self
+    }
+
+    /// How many consecutive rate-limit answers to tolerate before giving up.
+    #[must_use]
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Text appended to the message while the response is still streaming. Empty to disable.
+    #[must_use]
+    pub fn cursor(mut self, cursor: impl Into<String>) -> Self {
+        self.cursor = cursor.into();
+        self
+    }
+
+    /// Measure the throttle and rate-limit waits with `clock`. Defaults to
+    /// [`TokioClock`](clock::TokioClock).
+    #[must_use]
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Relay the model's messages from `stream` to `sink` until the stream ends.
+    ///
+    /// Text is flushed at most once per [`min_interval`](Self::min_interval), and always once
+    /// at the end without the cursor.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error raised by the sink or the stream (after flushing what was
+    /// received), or `GeminiError::RuntimeError` if the platform keeps rate limiting beyond
+    /// [`max_retries`](Self::max_retries).
+    pub async fn forward<S>(
+        &self,
+        sink: &dyn ChatSink,
+        stream: S,
+    ) -> Result<BridgeSummary, GeminiError>
+    where
+        S: Stream<Item = Result<StreamEvent, GeminiError>>,
+    {
+        futures_util::pin_mut!(stream);
+        let mut relay = Relay::new(self, sink);
+        let mut stream_error = None;
+        let mut ended = false;
+        while !ended {
+            let event = if relay.dirty {
+                tokio::select! {
+                    event = stream.next() => Some(event),
+                    () = self.clock.sleep_until(relay.next_call) => None,
+                }
+            } else {
+                Some(stream.next().await)
+            };
+            match event {
+                None => relay.flush(false).await?,
+                Some(None) => ended = true,
+                Some(Some(Err(e))) => {
+                    stream_error = Some(e);
+                    ended = true;
+                }
+                Some(Some(Ok(StreamEvent::Message {
+                    role,
+                    content,
+                    delta,
+                    ..
+                }))) if role != "user" => {
+                    relay.append(&content, !delta.unwrap_or(false));
+                    if self.clock.now() >= relay.next_call {
+                        relay.flush(false).await?;
+                    }
+                }
+                Some(Some(Ok(_))) => {}
+            }
+        }
+        relay.flush(true).await?;
+        match stream_error {
+            Some(e) => Err(e),
+            None => Ok(relay.summary),
+        }
+    }
+}
+
+/// The state of one [`StreamBridge::forward`] call.
+struct Relay<'a> {
+    bridge: &'a StreamBridge,
+    sink: &'a dyn ChatSink,
+    summary: BridgeSummary,
+    /// Text of the message being edited.
+    current: String,
+    /// Id of the message being edited, once posted.
+    current_id: Option<String>,
+    /// Whether `current` has changed since it was last sent.
+    dirty: bool,
+    next_call: Instant,
+}
+
+impl<'a> Relay<'a> {
+    fn new(bridge: &'a StreamBridge, sink: &'a dyn ChatSink) -> Self {
+        Self {
+            bridge,
+            sink,
+            summary: BridgeSummary::default(),
+            current: String::new(),
+            current_id: None,
+            dirty: false,
+            next_call: bridge.clock.now(),
+        }
+    }
+
+    fn append(&mut self, content: &str, complete: bool) {
+        if complete && !self.summary.text.is_empty() && !self.summary.text.ends_with('\n') {
+            self.summary.text.push('\n');
+            self.current.push('\n');
+        }
+        self.summary.text.push_str(content);
+        self.current.push_str(content);
+        self.dirty = true;
+    }
+
+    /// Send pending text, splitting off full messages first. `last` drops the cursor.
+    async fn flush(&mut self, last: bool) -> Result<(), GeminiError> {
+        let limit = self
+            .bridge
+            .max_chars
+            .saturating_sub(self.bridge.cursor.chars().count())
+            .max(1);
+        while self.current.chars().count() > limit {
+            let split = split_point(&self.current, limit);
+            let rest = self.current.split_off(split);
+            let full = std::mem::replace(&mut self.current, rest.trim_start().to_string());
+            self.send(&full).await?;
+            self.current_id = None;
+        }
+        if !self.dirty || self.current.trim().is_empty() {
+            self.dirty = false;
+            return Ok(());
+        }
+        let text = if last {
+            self.current.clone()
+        } else {
+            format!("{}{}", self.current, self.bridge.cursor)
+        };
+        self.send(&text).await?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Post or edit the current message, waiting out the throttle and any rate limits.
+    async fn send(&mut self, text: &str) -> Result<(), GeminiError> {
+        let mut retries = 0;
+        loop {
+            self.bridge.clock.sleep_until(self.next_call).await;
+            self.next_call = self.bridge.clock.now() + self.bridge.min_interval;
+            let delivery = match &self.current_id {
+                Some(id) => self.sink.edit(id, text).await?.map(|()| None),
+                None => self.sink.post(text).await?.map(Some),
+            };
+            match delivery {
+                Delivery::Delivered(Some(id)) => {
+                    self.summary.messages.push(id.clone());
+                    self.current_id = Some(id);
+                    return Ok(());
+                }
+                Delivery::Delivered(None) => {
+                    self.summary.edits += 1;
+                    return Ok(());
+                }
+                Delivery::RetryAfter(delay) => {
+                    retries += 1;
+                    if retries > self.bridge.max_retries {
+                        return Err(GeminiError::RuntimeError(format!(
+                            "chat platform still rate limiting after {} retries",
+                            self.bridge.max_retries
+                        )));
+                    }
+                    self.next_call = self.bridge.clock.now() + delay.max(self.bridge.min_interval);
+                }
+            }
+        }
+    }
+}
+
+impl<T> Delivery<T> {
+    fn map<U>(self, f: impl FnOnce(T) -> U) -> Delivery<U> {
+        match self {
+            Delivery::Delivered(value) => Delivery::Delivered(f(value)),
+            Delivery::RetryAfter(delay) => Delivery::RetryAfter(delay),
+        }
+    }
+}
+
+/// Byte index to split `text` at so the first part has at most `limit` characters, preferring
+/// the last newline, then the last space.
+fn split_point(text: &str, limit: usize) -> usize {
+    let hard = text
+        .char_indices()
+        .nth(limit)
+        .map_or(text.len(), |(i, _)| i);
+    let head = &text[..hard];
+    match head.rfind('\n').or_else(|| head.rfind(' ')) {
+        Some(i) if i > 0 => i,
+        _ => hard,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Records calls; rate limits the first `limited` calls.
+    #[derive(Default)]
+    struct RecordingSink {
+        calls: Mutex<Vec<String>>,
+        limited: Mutex<u32>,
+    }
+
+    impl RecordingSink {
+        fn limited(&self) -> bool {
+            let mut limited = self.limited.lock().unwrap();
+            let hit = *limited > 0;
+            *limited = limited.saturating_sub(1);
+            hit
+        }
+    }
+
+    impl ChatSink for RecordingSink {
+        fn post<'a>(
+            &'a self,
+            text: &'a str,
+        ) -> BoxFuture<'a, Result<Delivery<String>, GeminiError>> {
+            Box::pin(async move {
+                if self.limited() {
+                    return Ok(Delivery::RetryAfter(Duration::from_millis(5)));
+                }
+                let mut calls = self.calls.lock().unwrap();
+                calls.push(format!("post {text}"));
+                Ok(Delivery::Delivered(format!("m{}", calls.len())))
+            })
+        }
+
+        fn edit<'a>(
+            &'a self,
+            id: &'a str,
+            text: &'a str,
+        ) -> BoxFuture<'a, Result<Delivery<()>, GeminiError>> {
+            Box::pin(async move {
+                self.calls.lock().unwrap().push(format!("edit {id} {text}"));
+                Ok(Delivery::Delivered(()))
+            })
+        }
+    }
+
+    fn delta(text: &str) -> Result<StreamEvent, GeminiError> {
+        Ok(StreamEvent::Message {
+            role: "model".into(),
+            content: text.into(),
+            delta: Some(true),
+            timestamp: String::new(),
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_deltas_are_batched_into_edits() {
+        let sink = RecordingSink::default();
+        let events = futures_util::stream::iter([delta("Hel"), delta("lo"), delta(" world")]);
+        let summary = StreamBridge::new()
+            .cursor("…")
+            .forward(&sink, events)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.text, "Hello world");
+        assert_eq!(
+            *sink.calls.lock().unwrap(),
+            ["post Hel…", "edit m1 Hello world"]
+        );
+        assert_eq!((summary.messages.len(), summary.edits), (1, 1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_long_text_is_split_and_rate_limits_are_retried() {
+        let sink = RecordingSink {
+            limited: Mutex::new(2),
+            ..Default::default()
+        };
+        let events = futures_util::stream::iter([delta("one two three four")]);
+        let summary = StreamBridge::new()
+            .cursor("")
+            .max_chars(9)
+            .forward(&sink, events)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *sink.calls.lock().unwrap(),
+            ["post one two", "post three", "post four"]
+        );
+        assert_eq!(summary.messages, ["m1", "m2", "m3"]);
+    }
+
+    #[test]
+    fn test_split_point_prefers_whitespace() {
+        assert_eq!(split_point("ab\ncd ef", 7), 2);
+        assert_eq!(split_point("abcdef", 3), 3);
+        assert_eq!(split_point("héllo wörld", 8), 6);
+    }
+}
@@ -0,0 +1,389 @@
+//! Rolling back file edits made by the agent.
+//!
+//! With [`Gemini::checkpointing`](crate::Gemini::checkpointing), the CLI snapshots the project
+//! before every tool call that edits files: the files go into a shadow git repository under
+//! `~/.gemini/history/<project hash>` (your own repository is never touched) and a checkpoint
+//! record naming the snapshot goes into `~/.gemini/tmp/<project hash>/checkpoints`. The
+//! project hash is the SHA-256 of the project's absolute path.
+//!
+//! [`Checkpoints`] reads those records for one project and restores the files of any of them,
+//! as the CLI's interactive `/restore` command does. Only files are restored; the CLI's
+//! conversation history is left alone.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::checkpoints::Checkpoints;
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let result = Gemini::new("Rename the config module")
+//!     .yolo()
+//!     .checkpointing()
+//!     .current_dir("/srv/app")
+//!     .text()
+//!     .await;
+//! if result.is_err() {
+//!     let checkpoints = Checkpoints::new("/srv/app");
+//!     if let Some(first) = checkpoints.list().await?.first() {
+//!         checkpoints.restore(&first.id).await?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::GeminiError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// A snapshot taken before a file-editing tool call.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Checkpoint {
+    /// The checkpoint's name, e.g. `2025-06-01T10-00-00_000Z-main.rs-replace`. Sorting by id
+    /// sorts by time.
+    pub id: String,
+    /// The tool about to run when the snapshot was taken.
+    pub tool_name: Option<String>,
+    /// The tool's arguments.
+    pub tool_args: Value,
+    /// The file the tool was about to edit.
+    pub file_path: Option<String>,
+    /// The snapshot's commit in the shadow repository.
+    pub commit_hash: String,
+}
+
+/// The checkpoint record the CLI writes.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+    commit_hash: String,
+    #[serde(default)]
+    tool_call: Option<ToolCall>,
+    #[serde(default)]
+    file_path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ToolCall {
+    name: String,
+    #[serde(default)]
+    args: Value,
+}
+
+/// The checkpoints of one project.
+#[derive(Debug, Clone)]
+pub struct Checkpoints {
+    project_root: PathBuf,
+    gemini_home: Option<PathBuf>,
+    git: PathBuf,
+}
+
+impl Checkpoints {
+    /// The checkpoints of the project the CLI ran in, i.e. the request's
+    /// [`current_dir`](crate::Gemini::current_dir).
+    pub fn new(project_root: impl Into<PathBuf>) -> Self {
+        Self {
+            project_root: project_root.into(),
+            gemini_home: None,
+            git: PathBuf::from("git"),
+        }
+    }
+
+    /// The CLI's configuration directory. Defaults to `~/.gemini`.
+    #[must_use]
+    pub fn gemini_home(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.gemini_home = Some(dir.into());
+        self
+    }
+
+    /// Set the path to the `git` binary. Defaults to `"git"` on the PATH.
+    #[must_use]
+    pub fn git_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.git = path.into();
+        self
+    }
+
+    /// Every checkpoint of the project, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::StorageError` if the project or checkpoint directory cannot be
+    /// read. A project without checkpoints has none.
+    pub async fn list(&self) -> Result<Vec<Checkpoint>, GeminiError> {
+        let dir = self.dir("tmp")?.join("checkpoints");
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(storage_error(&dir, e)),
+        };
+        let mut checkpoints = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| storage_error(&dir, e))?
+        {
+            let path = entry.path();
+            let Some(id) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".json"))
+            else {
+                continue;
+            };
+            let text = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|e| storage_error(&path, e))?;
+            // Skip records written by other tools or CLI versions.
+            let Ok(record) = serde_json::from_str::<Record>(&text) else {
+                continue;
+            };
+            let (tool_name, tool_args) = match record.tool_call {
+                Some(call) => (Some(call.name), call.args),
+                None => (None, Value::Null),
+            };
+            checkpoints.push(Checkpoint {
+                id: id.to_string(),
+                tool_name,
+                tool_args,
+                file_path: record.file_path,
+                commit_hash: record.commit_hash,
+            });
+        }
+        checkpoints.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(checkpoints)
+    }
+
+    /// Restore the project's files to the checkpoint `id` and return it.
+    ///
+    /// Files are put back as they were in the snapshot, and files created since, other than
+    /// those ignored by the project's `.gitignore`, are deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::ValidationFailed` if there is no such checkpoint, or
+    /// `GeminiError::RuntimeError` if git fails.
+    pub async fn restore(&self, id: &str) -> Result<Checkpoint, GeminiError> {
+        let checkpoint = self
+            .list()
+            .await?
+            .into_iter()
+            .find(|c| c.id == id)
+            .ok_or_else(|| GeminiError::ValidationFailed(format!("no checkpoint named {id}")))?;
+        let git_dir = self.dir("history")?.join(".git");
+        let root = self.root()?;
+        self.git(
+            &git_dir,
+            &root,
+            &["restore", "--source", &checkpoint.commit_hash, "."],
+        )
+        .await?;
+        self.git(&git_dir, &root, &["clean", "-f", "-d"]).await?;
+        Ok(checkpoint)
+    }
+
+    /// The absolute project path, as the CLI saw it.
+    fn root(&self) -> Result<PathBuf, GeminiError> {
+        std::fs::canonicalize(&self.project_root).map_err(|e| storage_error(&self.project_root, e))
+    }
+
+    /// The project's directory under `~/.gemini/<kind>`.
+    fn dir(&self, kind: &str) -> Result<PathBuf, GeminiError> {
+        let home = match &self.gemini_home {
+            Some(home) => home.clone(),
+            None => std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(|home| PathBuf::from(home).join(".gemini"))
+                .ok_or_else(|| {
+                    GeminiError::StorageError("cannot locate the home directory".to_string())
+                })?,
+        };
+        let root = self.root()?;
+        Ok(home
+            .join(kind)
+            .join(sha256_hex(root.to_string_lossy().as_bytes())))
+    }
+
+    async fn git(
+        &self,
+        git_dir: &Path,
+        work_tree: &Path,
+        args: &[&str],
+    ) -> Result<(), GeminiError> {
+        let output = Command::new(&self.git)
+            .args(args)
+            .current_dir(work_tree)
+            .env("GIT_DIR", git_dir)
+            .env("GIT_WORK_TREE", work_tree)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|e| GeminiError::RuntimeError(format!("failed to run git: {e}")))?;
+        if !output.status.success() {
+            return Err(GeminiError::RuntimeError(format!(
+                "git {} failed: {}",
+                args.first().unwrap_or(&""),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn storage_error(path: &Path, error: std::io::Error) -> GeminiError {
+    GeminiError::StorageError(format!("failed to read {}: {error}", path.display()))
+}
+
+/// SHA-256 of `data` as lowercase hex, matching the CLI's project hash.
+fn sha256_hex(data: &[u8]) -> String {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    h.iter().map(|word| format!("{word:08x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_matches_known_digests() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            sha256_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_restore_rolls_back_edits() {
+        let root = std::env::temp_dir().join(format!("gemini-ckpt-test-{}", std::process::id()));
+        let (project, home) = (root.join("project"), root.join("home"));
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("main.rs"), "fn main() {}\n").unwrap();
+        let checkpoints = Checkpoints::new(&project).gemini_home(&home);
+
+        // Snapshot the project the way the CLI does.
+        let git_dir = checkpoints.dir("history").unwrap().join(".git");
+        std::fs::create_dir_all(&git_dir).unwrap();
+        let project = checkpoints.root().unwrap();
+        for args in [
+            &["init", "--quiet"][..],
+            &["add", "--all"],
+            &[
+                "-c",
+                "user.name=t",
+                "-c",
+                "user.email=t@t",
+                "commit",
+                "--quiet",
+                "-m",
+                "snapshot",
+            ],
+        ] {
+            checkpoints.git(&git_dir, &project, args).await.unwrap();
+        }
+        let head = std::fs::read_to_string(git_dir.join("HEAD")).unwrap();
+        let branch = head.trim().strip_prefix("ref: ").unwrap();
+        let commit = std::fs::read_to_string(git_dir.join(branch)).unwrap();
+        let records = checkpoints.dir("tmp").unwrap().join("checkpoints");
+        std::fs::create_dir_all(&records).unwrap();
+        std::fs::write(
+            records.join("2025-06-01T10-00-00_000Z-main.rs-replace.json"),
+            serde_json::json!({
+                "history": [],
+                "clientHistory": [],
+                "toolCall": { "name": "replace", "args": { "file_path": "main.rs" } },
+                "commitHash": commit.trim(),
+                "filePath": "main.rs",
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let listed = checkpoints.list().await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].tool_name.as_deref(), Some("replace"));
+
+        std::fs::write(project.join("main.rs"), "broken").unwrap();
+        std::fs::write(project.join("new.rs"), "// added by the agent").unwrap();
+        checkpoints.restore(&listed[0].id).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(project.join("main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+        assert!(!project.join("new.rs").exists());
+        assert!(matches!(
+            checkpoints.restore("missing").await,
+            Err(GeminiError::ValidationFailed(_))
+        ));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+//! Fail fast while the CLI, or the API behind it, is down.
+//!
+//! A [`CircuitBreaker`] counts consecutive failed runs. Once `failure_threshold` runs in a row
+//! have failed, the circuit opens: requests fail at once with `GeminiError::CircuitOpen`
+//! instead of launching the CLI, until `cooldown` has passed. Then a single trial request is
+//! let through (half-open). If it succeeds the circuit closes again; if it fails the circuit
+//! stays open for another cooldown.
+//!
+//! Only failures that point at the backend count: launch failures, timeouts, and the
+//! transient errors a [`RetryPolicy`] would retry. Validation errors, policy denials and
+//! cancellations leave the circuit as it is.
+//!
+//! Share one breaker between requests with [`Gemini::circuit_breaker`](crate::Gemini::circuit_breaker)
+//! or [`GeminiClient::circuit_breaker`](crate::client::GeminiClient::circuit_breaker); the
+//! client reports its status in [`GeminiClient::state`](crate::client::GeminiClient::state).
+//!
+//! ```rust
+//! use gemini_oxide::circuit::{CircuitBreaker, CircuitState};
+//! use gemini_oxide::client::GeminiClient;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! let breaker = Arc::new(CircuitBreaker::new(5, Duration::from_secs(30)));
+//! let client = GeminiClient::new().circuit_breaker(breaker.clone());
+//! assert_eq!(breaker.status().state, CircuitState::Closed);
+//! ```
+
+use crate::clock::{self, Clock};
+use crate::retry::RetryPolicy;
+use crate::GeminiError;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Whether a [`CircuitBreaker`] lets requests through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests run normally.
+    Closed,
+    /// Requests fail without launching the CLI for another `retry_in`.
+    Open { retry_in: Duration },
+    /// The cooldown is over; the next request is a trial that decides whether to close.
+    HalfOpen,
+}
+
+/// A point-in-time view of a [`CircuitBreaker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CircuitStatus {
+    pub state: CircuitState,
+    /// Failed runs since the last success.
+    pub consecutive_failures: u32,
+}
+
+/// Stops launching the CLI after repeated failures. See the [module docs](self).
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    clock: Arc<dyn Clock>,
+    state: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    failures: u32,
+    opened_at: Option<Instant>,
+    /// Whether the half-open trial request is running.
+    trial: bool,
+}
+
+impl CircuitBreaker {
+    /// Open after `failure_threshold` consecutive failures (at least one) and try again after
+    /// `cooldown`.
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            clock: clock::default_clock(),
+            state: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Measure the cooldown with `clock` instead of Tokio's.
+    #[must_use]
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The current state and failure count.
+    pub fn status(&self) -> CircuitStatus {
+        let inner = self.lock();
+        let state = match inner.opened_at {
+            None => CircuitState::Closed,
+            Some(opened) => match self.remaining(opened) {
+                Some(retry_in) => CircuitState::Open { retry_in },
+                None => CircuitState::HalfOpen,
+            },
+        };
+        CircuitStatus {
+            state,
+            consecutive_failures: inner.failures,
+        }
+    }
+
+    /// Let a run start, or fail with `CircuitOpen` while the circuit is open or a trial is
+    /// already running.
+    pub(crate) fn admit(self: &Arc<Self>) -> Result<CircuitPermit, GeminiError> {
+        let mut inner = self.lock();
+        let trial = match inner.opened_at {
+            None => false,
+            Some(opened) => {
+                if let Some(retry_in) = self.remaining(opened) {
+                    return Err(GeminiError::CircuitOpen(retry_in));
+                }
+                if inner.trial {
+                    return Err(GeminiError::CircuitOpen(Duration::ZERO));
+                }
+                inner.trial = true;
+                true
+            }
+        };
+        Ok(CircuitPermit {
+            breaker: self.clone(),
+            trial,
+        })
+    }
+
+    /// The rest of the cooldown of a circuit opened at `opened`, or `None` once it is over.
+    fn remaining(&self, opened: Instant) -> Option<Duration> {
+        let elapsed = self.clock.now().saturating_duration_since(opened);
+        self.cooldown
+            .checked_sub(elapsed)
+            .filter(|left| !left.is_zero())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Whether `error` says the backend is unhealthy, rather than something about the request.
+fn counts_as_failure(error: &GeminiError) -> bool {
+    matches!(error, GeminiError::Timeout(_)) || RetryPolicy::is_transient(error)
+}
+
+/// A run let through by a [`CircuitBreaker`]; report how it went with
+/// [`record`](Self::record). Dropping it unreported frees the trial slot without a verdict.
+pub(crate) struct CircuitPermit {
+    breaker: Arc<CircuitBreaker>,
+    trial: bool,
+}
+
+impl CircuitPermit {
+    pub(crate) fn record<T>(mut self, result: &Result<T, GeminiError>) {
+        let mut inner = self.breaker.lock();
+        match result {
+            Ok(_) => *inner = Inner::default(),
+            Err(error) if counts_as_failure(error) => {
+                inner.failures = inner.failures.saturating_add(1);
+                if self.trial || inner.failures >= self.breaker.failure_threshold {
+                    inner.opened_at = Some(self.breaker.clock.now());
+                }
+            }
+            Err(_) => {}
+        }
+        if self.trial {
+            inner.trial = false;
+            self.trial = false;
+        }
+    }
+}
+
+impl Drop for CircuitPermit {
+    fn drop(&mut self) {
+        if self.trial {
+            self.breaker.lock().trial = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fail(breaker: &Arc<CircuitBreaker>) {
+        let error = GeminiError::Timeout(Duration::from_secs(1));
+        breaker.admit().unwrap().record::<()>(&Err(error));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_opens_after_consecutive_failures_and_recovers_after_a_trial() {
+        let breaker = Arc::new(CircuitBreaker::new(2, Duration::from_secs(30)));
+        fail(&breaker);
+        // A request-level error is not the backend's fault.
+        let invalid = GeminiError::ValidationFailed("bad schema".into());
+        breaker.admit().unwrap().record::<()>(&Err(invalid));
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+        fail(&breaker);
+
+        assert_eq!(breaker.status().consecutive_failures, 2);
+        assert!(matches!(
+            breaker.admit(),
+            Err(GeminiError::CircuitOpen(wait)) if wait == Duration::from_secs(30)
+        ));
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert_eq!(breaker.status().state, CircuitState::HalfOpen);
+        let trial = breaker.admit().unwrap();
+        assert!(breaker.admit().is_err());
+        drop(trial);
+        let trial = breaker.admit().unwrap();
+        trial.record(&Ok(()));
+        assert_eq!(
+            breaker.status(),
+            CircuitStatus {
+                state: CircuitState::Closed,
+                consecutive_failures: 0
+            }
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_trial_reopens() {
+        let breaker = Arc::new(CircuitBreaker::new(1, Duration::from_secs(10)));
+        fail(&breaker);
+        tokio::time::advance(Duration::from_secs(10)).await;
+        fail(&breaker);
+        assert!(matches!(
+            breaker.status().state,
+            CircuitState::Open { retry_in } if retry_in == Duration::from_secs(10)
+        ));
+    }
+}
@@ -0,0 +1,547 @@
+//! Verification of cited sources.
+//!
+//! Grounded answers cite web pages, often quoting them: `"rates rose 0.25%" (https://...)`.
+//! Models sometimes misquote or invent such snippets. [`verify`] fetches every cited URL with a
+//! [`PageFetcher`] and checks that each quoted snippet really appears on the page, giving every
+//! citation a [`CitationStatus`]. Attach a fetcher with
+//! [`Gemini::verify_citations`](crate::Gemini::verify_citations) to have `json()` do this and
+//! report the results in [`GeminiJsonOutput::citations`](crate::GeminiJsonOutput::citations).
+//!
+//! A snippet counts as found if it appears in the page's visible text, ignoring case, markup,
+//! whitespace and the style of quotes. [`CurlFetcher`] downloads pages with `curl`; plug in an
+//! HTTP client by implementing the trait or wrapping an async closure with [`fetcher_fn`].
+//!
+//! With search grounding, the stream also reports which pages the answer rests on:
+//! [`StreamEvent::Grounding`](crate::StreamEvent::Grounding) carries the search queries, the
+//! [`GroundingSource`]s and the [`GroundingCitation`]s linking spans of the answer to them, with
+//! the model's confidence in each.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::citations::CurlFetcher;
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let output = Gemini::new("Summarize today's rate decision, quoting your sources")
+//!     .verify_citations(CurlFetcher::new())
+//!     .json()
+//!     .await?;
+//! for check in output.citations.iter().filter(|c| !c.is_verified()) {
+//!     eprintln!("{} could not be verified: {:?}", check.url, check.status);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::GeminiError;
+use futures_util::future::{join_all, BoxFuture};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Characters that end a URL in running text.
+const URL_TERMINATORS: &[char] = &['<', '>', '"', '\'', '`', ')', ']', '}', '|'];
+
+/// Downloads the content of cited pages.
+pub trait PageFetcher: Send + Sync {
+    /// Fetch the page at `url` (HTML or plain text).
+    fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<String, GeminiError>>;
+}
+
+/// Fetches pages with the `curl` command, following redirects.
+#[derive(Debug, Clone)]
+pub struct CurlFetcher {
+    timeout: Duration,
+}
+
+impl Default for CurlFetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CurlFetcher {
+    /// A fetcher giving each page 10 seconds.
+    pub fn new() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Give up on a page after `timeout`.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl PageFetcher for CurlFetcher {
+    fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<String, GeminiError>> {
+        Box::pin(async move {
+            let output = Command::new("curl")
+                .args(["--silent", "--show-error", "--fail", "--location"])
+                .arg("--max-time")
+                .arg(self.timeout.as_secs_f64().to_string())
+                .arg("--")
+                .arg(url)
+                .stdin(Stdio::null())
+                .kill_on_drop(true)
+                .output()
+                .await
+                .map_err(|e| GeminiError::RuntimeError(format!("failed to run `curl`: {e}")))?;
+            if !output.status.success() {
+                return Err(GeminiError::RuntimeError(
+                    String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                ));
+            }
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        })
+    }
+}
+
+struct FnFetcher<F>(F);
+
+impl<F, Fut> PageFetcher for FnFetcher<F>
+where
+    F: Fn(String) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<String, GeminiError>> + Send + 'static,
+{
+    fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<String, GeminiError>> {
+        Box::pin((self.0)(url.to_string()))
+    }
+}
+
+/// Wrap an async closure, e.g. a call through your HTTP client, as a [`PageFetcher`].
+///
+/// ```rust
+/// use gemini_oxide::citations::fetcher_fn;
+///
+/// let fetcher = fetcher_fn(|url| async move {
+///     // Fetch `url` with your HTTP client here.
+///     Ok(format!("<p>Contents of {url}</p>"))
+/// });
+/// ```
+pub fn fetcher_fn<F, Fut>(f: F) -> impl PageFetcher
+where
+    F: Fn(String) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<String, GeminiError>> + Send + 'static,
+{
+    FnFetcher(f)
+}
+
+/// A source cited in an answer.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Citation {
+    /// The cited URL.
+    pub url: String,
+    /// The snippet quoted from the source, if the citation quotes one.
+    pub quote: Option<String>,
+}
+
+/// Whether a citation holds up.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CitationStatus {
+    /// The quoted snippet appears on the page.
+    Verified,
+    /// The page was fetched but does not contain the quoted snippet.
+    QuoteNotFound,
+    /// The page was fetched; the citation quotes nothing to check.
+    Unquoted,
+    /// The page could not be fetched.
+    Unreachable { error: String },
+}
+
+/// The verification result of one citation.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CitationCheck {
+    /// The cited URL.
+    pub url: String,
+    /// The quoted snippet, if any.
+    pub quote: Option<String>,
+    /// The outcome.
+    #[serde(flatten)]
+    pub status: CitationStatus,
+}
+
+impl CitationCheck {
+    /// Whether the quoted snippet was found on the page.
+    pub fn is_verified(&self) -> bool {
+        self.status == CitationStatus::Verified
+    }
+}
+
+/// A web page an answer was grounded in.
+///
+/// Parses both this form and the Gemini API's grounding chunks (`{"web": {"uri", "title"}}`).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "SourceRepr")]
+pub struct GroundingSource {
+    /// The page's URL.
+    pub url: String,
+    /// The page's title, if reported.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SourceRepr {
+    Api { web: WebSource },
+    Flat(WebSource),
+}
+
+#[derive(Deserialize)]
+struct WebSource {
+    #[serde(alias = "uri")]
+    url: String,
+    #[serde(default)]
+    title: Option<String>,
+}
+
+impl From<SourceRepr> for GroundingSource {
+    fn from(repr: SourceRepr) -> Self {
+        let (SourceRepr::Api { web } | SourceRepr::Flat(web)) = repr;
+        Self {
+            url: web.url,
+            title: web.title,
+        }
+    }
+}
+
+/// A span of the answer and the [`GroundingSource`]s supporting it.
+///
+/// Parses both this form and the Gemini API's grounding supports (`segment`,
+/// `groundingChunkIndices`, `confidenceScores`).
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(from = "CitationRepr")]
+pub struct GroundingCitation {
+    /// The supported text.
+    pub text: String,
+    /// Where the text starts in the answer, in bytes, if reported.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<usize>,
+    /// Where the text ends in the answer, in bytes, if reported.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<usize>,
+    /// Indexes into the event's `sources`.
+    pub sources: Vec<usize>,
+    /// The model's confidence (0 to 1) in each of `sources`, in the same order. May be empty.
+    pub confidence: Vec<f64>,
+}
+
+impl GroundingCitation {
+    /// The cited sources among `sources`, with the confidence in each if reported.
+    pub fn cited<'a>(
+        &'a self,
+        sources: &'a [GroundingSource],
+    ) -> impl Iterator<Item = (&'a GroundingSource, Option<f64>)> + 'a {
+        self.sources
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &index)| Some((sources.get(index)?, self.confidence.get(i).copied())))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CitationRepr {
+    #[serde(rename_all = "camelCase")]
+    Api {
+        segment: Segment,
+        #[serde(default)]
+        grounding_chunk_indices: Vec<usize>,
+        #[serde(default)]
+        confidence_scores: Vec<f64>,
+    },
+    Flat {
+        text: String,
+        #[serde(default)]
+        start: Option<usize>,
+        #[serde(default)]
+        end: Option<usize>,
+        #[serde(default)]
+        sources: Vec<usize>,
+        #[serde(default)]
+        confidence: Vec<f64>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Segment {
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    start_index: Option<usize>,
+    #[serde(default)]
+    end_index: Option<usize>,
+}
+
+impl From<CitationRepr> for GroundingCitation {
+    fn from(repr: CitationRepr) -> Self {
+        match repr {
+            CitationRepr::Api {
+                segment,
+                grounding_chunk_indices,
+                confidence_scores,
+            } => Self {
+                text: segment.text,
+                start: segment.start_index,
+                end: segment.end_index,
+                sources: grounding_chunk_indices,
+                confidence: confidence_scores,
+            },
+            CitationRepr::Flat {
+                text,
+                start,
+                end,
+                sources,
+                confidence,
+            } => Self {
+                text,
+                start,
+                end,
+                sources,
+                confidence,
+            },
+        }
+    }
+}
+
+/// Every URL cited in `text`, with the quote preceding it on the same line, if any.
+pub fn extract(text: &str) -> Vec<Citation> {
+    let mut citations = Vec::new();
+    for line in text.lines() {
+        let mut rest = line;
+        while let Some(start) = find_url(rest) {
+            let (before, from_url) = rest.split_at(start);
+            let end = from_url.find(char::is_whitespace).unwrap_or(from_url.len());
+            let raw = &from_url[..end];
+            let url = raw[..raw.find(URL_TERMINATORS).unwrap_or(raw.len())]
+                .trim_end_matches(['.', ',', ';', ':', '!', '?']);
+            citations.push(Citation {
+                url: url.to_string(),
+                quote: last_quote(before),
+            });
+            rest = &from_url[end..];
+        }
+    }
+    citations
+}
+
+/// Check every citation in `text` against its source. Each URL is fetched once.
+pub async fn verify(text: &str, fetcher: &dyn PageFetcher) -> Vec<CitationCheck> {
+    let citations = extract(text);
+    let mut urls: Vec<&str> = citations.iter().map(|c| c.url.as_str()).collect();
+    urls.sort_unstable();
+    urls.dedup();
+    let pages: HashMap<&str, Result<String, GeminiError>> = urls
+        .iter()
+        .copied()
+        .zip(join_all(urls.iter().map(|url| fetcher.fetch(url))).await)
+        .map(|(url, page)| (url, page.map(|html| comparable(&visible_text(&html)))))
+        .collect();
+
+    citations
+        .iter()
+        .map(|citation| {
+            let status = match (&pages[citation.url.as_str()], &citation.quote) {
+                (Err(error), _) => CitationStatus::Unreachable {
+                    error: error.to_string(),
+                },
+                (Ok(_), None) => CitationStatus::Unquoted,
+                (Ok(page), Some(quote)) if page.contains(&comparable(quote)) => {
+                    CitationStatus::Verified
+                }
+                (Ok(_), Some(_)) => CitationStatus::QuoteNotFound,
+            };
+            CitationCheck {
+                url: citation.url.clone(),
+                quote: citation.quote.clone(),
+                status,
+            }
+        })
+        .collect()
+}
+
+fn find_url(text: &str) -> Option<usize> {
+    match (text.find("https://"), text.find("http://")) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// The last complete quotation in `text`, in straight or curly double quotes.
+fn last_quote(text: &str) -> Option<String> {
+    let text = text.replace(['\u{201c}', '\u{201d}'], "\"");
+    let mut parts = text.rsplit('"');
+    parts.next()?;
+    let quote = parts.next()?.trim();
+    // An odd number of quotes means the last one is unmatched.
+    let balanced = text.matches('"').count().is_multiple_of(2);
+    (balanced && !quote.is_empty()).then(|| quote.to_string())
+}
+
+/// The text of an HTML page, without tags, scripts and styles, with common entities decoded.
+fn visible_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(open) = rest.find('<') {
+        text.push_str(&rest[..open]);
+        let tag = &rest[open..];
+        let lower = tag.get(..7).unwrap_or(tag).to_ascii_lowercase();
+        let close = if lower.starts_with("<script") {
+            find_ignore_case(tag, "</script>")
+        } else if lower.starts_with("<style") {
+            find_ignore_case(tag, "</style>")
+        } else {
+            tag.find('>').map(|end| end + 1)
+        };
+        match close {
+            Some(close) => {
+                text.push(' ');
+                rest = &tag[close..];
+            }
+            None => {
+                rest = "";
+            }
+        }
+    }
+    text.push_str(rest);
+    [
+        ("&nbsp;", " "),
+        ("&quot;", "\""),
+        ("&#39;", "'"),
+        ("&apos;", "'"),
+        ("&lt;", "<"),
+        ("&gt;", ">"),
+        ("&amp;", "&"),
+    ]
+    .iter()
+    .fold(text, |text, (entity, plain)| text.replace(entity, plain))
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .to_ascii_lowercase()
+        .find(needle)
+        .map(|start| start + needle.len())
+}
+
+/// `text` lowercased with whitespace and quote styles normalized, for containment checks.
+fn comparable(text: &str) -> String {
+    crate::fingerprint::normalize(text)
+        .replace(['\u{2018}', '\u{2019}'], "'")
+        .replace(['\u{201c}', '\u{201d}'], "\"")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StreamEvent;
+
+    #[test]
+    fn test_urls_and_quotes_are_extracted() {
+        let answer = "The bank said \u{201c}rates will rise by 0.25%\u{201d} (https://news.example/rates).\n\
+                      See also https://stats.example/table, and \"a claim\" [src](https://blog.example/post)";
+        assert_eq!(
+            extract(answer),
+            vec![
+                Citation {
+                    url: "https://news.example/rates".into(),
+                    quote: Some("rates will rise by 0.25%".into()),
+                },
+                Citation {
+                    url: "https://stats.example/table".into(),
+                    quote: None,
+                },
+                Citation {
+                    url: "https://blog.example/post".into(),
+                    quote: Some("a claim".into()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grounding_events_parse_the_api_shape() {
+        let line = r#"{"type":"grounding_metadata","webSearchQueries":["fed rate decision"],
+            "groundingChunks":[{"web":{"uri":"https://news.example/rates","title":"Rates"}},{"web":{"uri":"https://stats.example/t"}}],
+            "groundingSupports":[{"segment":{"startIndex":0,"endIndex":18,"text":"Rates rose by 0.25"},
+                "groundingChunkIndices":[1,0],"confidenceScores":[0.9,0.6]}]}"#
+            .replace('\n', "");
+        let Ok(Some(StreamEvent::Grounding {
+            queries,
+            sources,
+            citations,
+            ..
+        })) = StreamEvent::parse_line(&line)
+        else {
+            panic!("expected a grounding event");
+        };
+        assert_eq!(queries, ["fed rate decision"]);
+        assert_eq!(sources[0].title.as_deref(), Some("Rates"));
+        assert_eq!(citations[0].end, Some(18));
+        let cited: Vec<_> = citations[0]
+            .cited(&sources)
+            .map(|(source, confidence)| (source.url.as_str(), confidence))
+            .collect();
+        assert_eq!(
+            cited,
+            [
+                ("https://stats.example/t", Some(0.9)),
+                ("https://news.example/rates", Some(0.6))
+            ]
+        );
+
+        let event = StreamEvent::Grounding {
+            queries: Vec::new(),
+            sources,
+            citations,
+            timestamp: String::new(),
+        };
+        let round_trip = StreamEvent::parse_line(&serde_json::to_string(&event).unwrap());
+        assert!(matches!(
+            round_trip,
+            Ok(Some(StreamEvent::Grounding { citations, .. })) if citations[0].confidence == [0.9, 0.6]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_quotes_are_checked_against_the_page() {
+        let fetcher = fetcher_fn(|url| async move {
+            match url.as_str() {
+                "https://a.example/" => Ok(
+                    "<html><style>p{}</style><p>Rates will <b>rise</b>\n by 0.25%</p></html>"
+                        .to_string(),
+                ),
+                _ => Err(GeminiError::RuntimeError("404 Not Found".into())),
+            }
+        });
+        let answer = "\"rates will rise by 0.25%\" (https://a.example/)\n\
+                      \"rates will fall\" (https://a.example/)\n\
+                      https://a.example/\n\
+                      \"anything\" https://b.example/";
+        let statuses: Vec<_> = verify(answer, &fetcher)
+            .await
+            .into_iter()
+            .map(|check| check.status)
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                CitationStatus::Verified,
+                CitationStatus::QuoteNotFound,
+                CitationStatus::Unquoted,
+                CitationStatus::Unreachable {
+                    error: "Runtime Error: 404 Not Found".into()
+                },
+            ]
+        );
+    }
+}
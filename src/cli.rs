@@ -0,0 +1,213 @@
+//! Managing the installed CLI.
+//!
+//! [`GeminiCli`] runs the CLI's management subcommands rather than a prompt: listing,
+//! installing and uninstalling extensions, and reporting its version. Choose which installed
+//! extensions a request loads with [`Gemini::extensions`](crate::Gemini::extensions).
+//!
+//! Extension listings are parsed from the CLI's human-readable output, which is not a stable
+//! interface; fields the parser does not recognize are left empty.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::cli::GeminiCli;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let cli = GeminiCli::new();
+//! let installed = cli.list_extensions().await?;
+//! if !installed.iter().any(|e| e.name == "security") {
+//!     cli.install_extension("https://github.com/gemini-cli-extensions/security")
+//!         .await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::compat::CliVersion;
+use crate::GeminiError;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// An extension installed in the CLI.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Extension {
+    pub name: String,
+    pub version: Option<String>,
+    /// Whether the CLI loads the extension by default.
+    pub enabled: bool,
+    /// Where the extension is installed.
+    pub path: Option<String>,
+    /// Where the extension was installed from.
+    pub source: Option<String>,
+}
+
+/// A handle to the `gemini` binary for management commands.
+#[derive(Debug, Clone)]
+pub struct GeminiCli {
+    bin_path: PathBuf,
+    env: Vec<(String, String)>,
+}
+
+impl Default for GeminiCli {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GeminiCli {
+    /// The `gemini` binary on the `PATH`.
+    pub fn new() -> Self {
+        Self {
+            bin_path: PathBuf::from("gemini"),
+            env: Vec::new(),
+        }
+    }
+
+    /// Path to the `gemini` binary. See [`Gemini::bin_path`](crate::Gemini::bin_path).
+    #[must_use]
+    pub fn bin_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.bin_path = path.into();
+        self
+    }
+
+    /// Set an environment variable for the CLI process, e.g. `HOME` to manage another
+    /// user's extensions.
+    #[must_use]
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// The CLI's version. See [`CliVersion::detect`].
+    pub async fn version(&self) -> Result<CliVersion, GeminiError> {
+        CliVersion::detect(&self.bin_path).await
+    }
+
+    /// The installed extensions (`gemini extensions list`).
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::CliLaunchFailed` if the CLI cannot be started, or
+    /// `GeminiError::RuntimeError` with its stderr if it fails.
+    pub async fn list_extensions(&self) -> Result<Vec<Extension>, GeminiError> {
+        let output = self.run(&["extensions", "list"]).await?;
+        Ok(parse_extensions(&output))
+    }
+
+    /// Install an extension from a git URL or local path (`gemini extensions install`) and
+    /// return the CLI's report.
+    ///
+    /// Extensions that ask for confirmation during installation fail, since there is no one
+    /// to answer.
+    ///
+    /// # Errors
+    ///
+    /// As for [`list_extensions`](Self::list_extensions).
+    pub async fn install_extension(&self, source: &str) -> Result<String, GeminiError> {
+        self.run(&["extensions", "install", source]).await
+    }
+
+    /// Uninstall the extension `name` (`gemini extensions uninstall`) and return the CLI's
+    /// report.
+    ///
+    /// # Errors
+    ///
+    /// As for [`list_extensions`](Self::list_extensions).
+    pub async fn uninstall_extension(&self, name: &str) -> Result<String, GeminiError> {
+        self.run(&["extensions", "uninstall", name]).await
+    }
+
+    /// Run the CLI with `args` and return its stdout.
+    async fn run(&self, args: &[&str]) -> Result<String, GeminiError> {
+        let output = Command::new(&self.bin_path)
+            .args(args)
+            .envs(self.env.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(GeminiError::CliLaunchFailed)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(GeminiError::RuntimeError(stderr.into_owned()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Parse `gemini extensions list` output.
+///
+/// Each extension starts with a `✓ name (version)` line (`✗` when disabled), followed by
+/// indented `Key: value` details.
+fn parse_extensions(output: &str) -> Vec<Extension> {
+    let mut extensions: Vec<Extension> = Vec::new();
+    for line in output.lines() {
+        let trimmed = line.trim();
+        let header = ["✓ ", "✗ "]
+            .iter()
+            .find_map(|marker| trimmed.strip_prefix(marker).map(|rest| (marker, rest)));
+        if let Some((marker, rest)) = header {
+            let (name, version) = match rest.split_once(" (") {
+                Some((name, version)) => (name, version.strip_suffix(')')),
+                None => (rest, None),
+            };
+            extensions.push(Extension {
+                name: name.trim().to_string(),
+                version: version.map(str::to_string),
+                enabled: *marker != "✗ ",
+                path: None,
+                source: None,
+            });
+            continue;
+        }
+        let (Some(extension), Some((key, value))) =
+            (extensions.last_mut(), trimmed.split_once(':'))
+        else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match key {
+            "Path" => extension.path = Some(value),
+            "Source" => extension.source = Some(value),
+            "Enabled" | "Enabled (User)" => {
+                extension.enabled = value.eq_ignore_ascii_case("true");
+            }
+            _ => {}
+        }
+    }
+    extensions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_extension_list() {
+        let output = "\
+✓ security (1.2.0)
+ Path: /home/me/.gemini/extensions/security
+ Source: https://github.com/gemini-cli-extensions/security (Type: git)
+ Enabled (User): true
+
+✗ db (0.1.0)
+ Path: /home/me/.gemini/extensions/db
+";
+        let extensions = parse_extensions(output);
+        assert_eq!(extensions.len(), 2);
+        assert_eq!(extensions[0].name, "security");
+        assert_eq!(extensions[0].version.as_deref(), Some("1.2.0"));
+        assert!(extensions[0].enabled);
+        assert_eq!(
+            extensions[0].source.as_deref(),
+            Some("https://github.com/gemini-cli-extensions/security (Type: git)")
+        );
+        assert_eq!(extensions[1].name, "db");
+        assert!(!extensions[1].enabled);
+        assert_eq!(
+            extensions[1].path.as_deref(),
+            Some("/home/me/.gemini/extensions/db")
+        );
+        assert!(parse_extensions("No extensions installed.").is_empty());
+    }
+}
@@ -0,0 +1,383 @@
+//! Shared request defaults.
+//!
+//! A [`GeminiClient`] holds the settings every request in an application should share — the
+//! binary path, model, approval mode, include directories, environment and timeout — and
+//! produces pre-configured [`Gemini`] builders with [`GeminiClient::prompt`]. Settings on the
+//! returned builder override the client's defaults for that request only.
+//!
+//! The client is cheap to clone; build it once at startup and pass it around. Clones share
+//! its bookkeeping: [`GeminiClient::state`] reports the CLI processes its requests are running,
+//! their cache use and the status of its [circuit breaker](GeminiClient::circuit_breaker).
+//! [`GeminiClient::shutdown`] stops every clone from starting requests and waits for the ones
+//! in flight, cancelling those still running when the grace period ends.
+//!
+//! ```rust
+//! use gemini_oxide::client::GeminiClient;
+//! use std::time::Duration;
+//!
+//! let client = GeminiClient::new()
+//!     .bin_path("/opt/gemini/bin/gemini")
+//!     .model("gemini-2.5-pro")
+//!     .env("GOOGLE_CLOUD_PROJECT", "my-project")
+//!     .timeout(Duration::from_secs(120));
+//!
+//! let review = client.prompt("Review this diff").context("...");
+//! let quick = client.prompt("Summarize the ticket").model("gemini-2.5-flash");
+//! ```
+
+use crate::billing::UsageLedger;
+use crate::circuit::{CircuitBreaker, CircuitStatus};
+use crate::clock::Clock;
+use crate::jobs::{CacheStats, Monitor, ProcessInfo, ShutdownSummary};
+use crate::policy::ApprovalMode;
+use crate::proxy::ProxyConfig;
+use crate::safety::SafetyLock;
+use crate::simulate::Simulator;
+use crate::Gemini;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A factory for [`Gemini`] requests with shared defaults.
+#[derive(Clone)]
+pub struct GeminiClient {
+    defaults: Gemini,
+    monitor: Arc<Monitor>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+}
+
+/// A point-in-time view of a [`GeminiClient`], from [`GeminiClient::state`].
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ClientState {
+    /// Requests started and not yet finished, including ones waiting to retry.
+    pub in_flight: usize,
+    /// Live CLI processes started by the client's requests, oldest first.
+    pub processes: Vec<ProcessInfo>,
+    /// Cache lookups made by the client's requests.
+    pub cache: CacheStats,
+    /// The client's circuit breaker, if it has one.
+    pub circuit: Option<CircuitStatus>,
+    /// Whether [`GeminiClient::shutdown`] has been called.
+    pub shut_down: bool,
+}
+
+impl Default for GeminiClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GeminiClient {
+    /// A client whose requests use the same defaults as [`Gemini::new`].
+    pub fn new() -> Self {
+        let monitor = Arc::new(Monitor::default());
+        let mut defaults = Gemini::new("");
+        defaults.monitors.push(monitor.clone());
+        Self {
+            defaults,
+            monitor,
+            circuit_breaker: None,
+        }
+    }
+
+    /// A client whose requests are answered by a default [`Simulator`] instead of the CLI,
+    /// for development without a `gemini` binary or API key.
+    ///
+    /// Use [`simulator`](Self::simulator) to configure the responses.
+    pub fn simulated() -> Self {
+        Self::new().simulator(Simulator::new())
+    }
+
+    /// Start a request for `prompt` with the client's defaults.
+    pub fn prompt(&self, prompt: impl Into<String>) -> Gemini {
+        Gemini {
+            prompt: prompt.into(),
+            ..self.defaults.clone()
+        }
+    }
+
+    /// Path to the `gemini` binary. See [`Gemini::bin_path`].
+    #[must_use]
+    pub fn bin_path(self, path: impl Into<PathBuf>) -> Self {
+        self.defaults(|request| request.bin_path(path))
+    }
+
+    /// Model for every request. See [`Gemini::model`].
+    #[must_use]
+    pub fn model(self, name: &str) -> Self {
+        self.defaults(|request| request.model(name))
+    }
+
+    /// Approve all tool actions automatically. See [`Gemini::yolo`].
+    #[must_use]
+    pub fn yolo(self) -> Self {
+        self.defaults(Gemini::yolo)
+    }
+
+    /// Approval mode of every request. See [`Gemini::approval`].
+    #[must_use]
+    pub fn approval(self, mode: ApprovalMode) -> Self {
+        self.defaults(|request| request.approval(mode))
+    }
+
+    /// Give every request access to `dir`. See [`Gemini::include`].
+    #[must_use]
+    pub fn include(self, dir: &str) -> Self {
+        self.defaults(|request| request.include(dir))
+    }
+
+    /// Set an environment variable for every CLI process. See [`Gemini::env`].
+    #[must_use]
+    pub fn env(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.defaults(|request| request.env(key, value))
+    }
+
+    /// Set several environment variables for every CLI process. See [`Gemini::envs`].
+    #[must_use]
+    pub fn envs<K, V>(self, vars: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.defaults(|request| request.envs(vars))
+    }
+
+    /// Route every request's traffic through a proxy. See [`Gemini::proxy`].
+    #[must_use]
+    pub fn proxy(self, config: impl Into<ProxyConfig>) -> Self {
+        let config = config.into();
+        self.defaults(|request| request.proxy(config))
+    }
+
+    /// Refuse risky [`yolo`](Gemini::yolo) requests. See [`Gemini::safety_lock`].
+    #[must_use]
+    pub fn safety_lock(self, lock: SafetyLock) -> Self {
+        self.defaults(|request| request.safety_lock(lock))
+    }
+
+    /// Attach a label to every request. See [`Gemini::label`].
+    #[must_use]
+    pub fn label(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.defaults(|request| request.label(key, value))
+    }
+
+    /// Record every request's token usage in `ledger`. See [`Gemini::usage_ledger`].
+    #[must_use]
+    pub fn usage_ledger(self, ledger: Arc<UsageLedger>) -> Self {
+        self.defaults(|request| request.usage_ledger(ledger))
+    }
+
+    /// Time limit for each CLI process. See [`Gemini::timeout`].
+    #[must_use]
+    pub fn timeout(self, limit: Duration) -> Self {
+        self.defaults(|request| request.timeout(limit))
+    }
+
+    /// Measure and wait on time with `clock`. See [`Gemini::clock`].
+    #[must_use]
+    pub fn clock(self, clock: Arc<dyn Clock>) -> Self {
+        self.defaults(|request| request.clock(clock))
+    }
+
+    /// Stop launching the CLI for every request while `breaker` is open. See
+    /// [`Gemini::circuit_breaker`]; its status is reported in [`state`](Self::state).
+    #[must_use]
+    pub fn circuit_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.circuit_breaker = Some(breaker.clone());
+        self.defaults(|request| request.circuit_breaker(breaker))
+    }
+
+    /// Answer every request with `simulator`. See [`Gemini::simulator`].
+    #[must_use]
+    pub fn simulator(self, simulator: Simulator) -> Self {
+        self.defaults(|request| request.simulator(simulator))
+    }
+
+    /// Any other default, applied to the template every request starts from.
+    ///
+    /// ```rust
+    /// use gemini_oxide::client::GeminiClient;
+    /// use gemini_oxide::retry::RetryPolicy;
+    ///
+    /// let client = GeminiClient::new().defaults(|request| request.retry(RetryPolicy::new()));
+    /// ```
+    #[must_use]
+    pub fn defaults(mut self, f: impl FnOnce(Gemini) -> Gemini) -> Self {
+        self.defaults = f(self.defaults);
+        self
+    }
+
+    /// A snapshot of the client's live CLI processes, cache use and circuit breaker, for admin
+    /// endpoints.
+    pub fn state(&self) -> ClientState {
+        ClientState {
+            in_flight: self.monitor.in_flight(),
+            processes: self.monitor.processes(),
+            cache: self.monitor.cache(),
+            circuit: self
+                .circuit_breaker
+                .as_ref()
+                .map(|breaker| breaker.status()),
+            shut_down: self.monitor.is_closed(),
+        }
+    }
+
+    /// Stop accepting requests and wait up to `grace` for the ones in flight to finish.
+    ///
+    /// From now on every request from this client, or any clone of it, fails with a
+    /// `RuntimeError` before launching the CLI. Requests still running when `grace` runs out
+    /// are cancelled, which kills their CLI processes, and waited for.
+    pub async fn shutdown(&self, grace: Duration) -> ShutdownSummary {
+        self.monitor.close();
+        let running = self.monitor.in_flight();
+        let drained = tokio::select! {
+            () = self.monitor.idle() => true,
+            () = self.defaults.clock.sleep(grace) => false,
+        };
+        // Also stops requests that are only waiting out a retry backoff.
+        self.monitor.shutdown.cancel();
+        let overdue = if drained { 0 } else { self.monitor.in_flight() };
+        self.monitor.idle().await;
+        ShutdownSummary {
+            drained: running.saturating_sub(overdue),
+            cancelled: overdue,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_start_from_the_defaults() {
+        let client = GeminiClient::new()
+            .bin_path("/opt/gemini")
+            .model("gemini-2.5-pro")
+            .include("docs")
+            .env("GOOGLE_CLOUD_PROJECT", "demo");
+
+        let cmd = client.prompt("Summarize").build_command("text");
+        let std = cmd.as_std();
+        assert_eq!(std.get_program(), "/opt/gemini");
+        let args: Vec<_> = std.get_args().collect();
+        assert!(args.windows(2).any(|w| w == ["--model", "gemini-2.5-pro"]));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--include-directories", "docs"]));
+        assert_eq!(args.last().unwrap(), &"Summarize");
+        assert!(std
+            .get_envs()
+            .any(|(key, value)| key == "GOOGLE_CLOUD_PROJECT" && value == Some("demo".as_ref())));
+
+        // Per-request settings override the defaults without changing the client.
+        let flash = client.prompt("Quick").model("gemini-2.5-flash");
+        assert_eq!(flash.model.as_deref(), Some("gemini-2.5-flash"));
+        assert_eq!(
+            client.prompt("Again").model.as_deref(),
+            Some("gemini-2.5-pro")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_simulated_client_needs_no_binary() {
+        let client = GeminiClient::simulated()
+            .bin_path("/nonexistent/gemini")
+            .simulator(Simulator::new().respond_when("weather", "Sunny"));
+
+        let text = client.prompt("weather?").text().await.unwrap();
+        assert_eq!(text, "Sunny");
+
+        let output = client
+            .prompt("weather?")
+            .model("gemini-2.5-flash")
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(output.response, "Sunny");
+        assert_eq!(output.served_model(), Some("gemini-2.5-flash"));
+
+        let transcript = client.prompt("weather?").transcript().await.unwrap();
+        assert_eq!(transcript.text, "Sunny");
+    }
+
+    #[tokio::test]
+    async fn test_state_reports_cache_use() {
+        use crate::storage::InMemoryCacheStore;
+
+        let client = GeminiClient::simulated()
+            .defaults(|request| request.cache(Arc::new(InMemoryCacheStore::new())));
+        client.prompt("weather?").text().await.unwrap();
+        client.clone().prompt("weather?").text().await.unwrap();
+
+        let state = client.state();
+        assert_eq!(state.cache, CacheStats { hits: 1, misses: 1 });
+        assert!(state.processes.is_empty());
+        assert_eq!(state.circuit, None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_circuit_breaker_opens_after_failures() {
+        use crate::circuit::CircuitState;
+        use crate::testing::FaultyCli;
+        use crate::GeminiError;
+
+        let cli = FaultyCli::new()
+            .exit_with(1, "Error: 503 Service Unavailable")
+            .install()
+            .unwrap();
+        let client = GeminiClient::new()
+            .bin_path(cli.path())
+            .circuit_breaker(Arc::new(CircuitBreaker::new(2, Duration::from_secs(60))));
+        for _ in 0..2 {
+            let result = client.prompt("Hi").text().await;
+            assert!(matches!(result, Err(GeminiError::RuntimeError(_))));
+        }
+
+        let result = client.prompt("Hi").json().await;
+        assert!(matches!(result, Err(GeminiError::CircuitOpen(_))));
+        let circuit = client.state().circuit.unwrap();
+        assert!(matches!(circuit.state, CircuitState::Open { .. }));
+        assert_eq!(circuit.consecutive_failures, 2);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shutdown_refuses_new_requests_and_cancels_overdue_ones() {
+        use crate::testing::FaultyCli;
+        use crate::GeminiError;
+
+        let cli = FaultyCli::new()
+            .slow_first_byte(Duration::from_secs(30))
+            .install()
+            .unwrap();
+        let client = GeminiClient::new().bin_path(cli.path());
+        let running = tokio::spawn(client.prompt("Hi").text());
+        while client.state().processes.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(client.state().in_flight, 1);
+
+        let summary = client.shutdown(Duration::from_millis(100)).await;
+        assert_eq!(
+            summary,
+            ShutdownSummary {
+                drained: 0,
+                cancelled: 1
+            }
+        );
+        assert!(matches!(
+            running.await.unwrap(),
+            Err(GeminiError::Cancelled)
+        ));
+        let state = client.state();
+        assert!(state.shut_down);
+        assert_eq!(state.in_flight, 0);
+        assert!(state.processes.is_empty());
+
+        let refused = client.clone().prompt("Again").json().await;
+        assert!(matches!(refused, Err(GeminiError::RuntimeError(_))));
+    }
+}
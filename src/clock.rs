@@ -0,0 +1,161 @@
+//! The time source behind retries, timeouts, schedules and rate limits.
+//!
+//! Everything in this crate that waits or measures elapsed time asks a [`Clock`]: retry
+//! backoff, [`timeout`](crate::Gemini::timeout) and [`heartbeat`](crate::Gemini::heartbeat) on a
+//! request, [`Scheduler`](crate::schedule::Scheduler) runs, and the rate limits of
+//! [`Ingestor`](crate::ingest::Ingestor) and [`StreamBridge`](crate::chat::StreamBridge). The
+//! default, [`TokioClock`], uses `tokio::time`, so tests can call `tokio::time::pause()` (or use
+//! `#[tokio::test(start_paused = true)]`) and let minutes of backoff or a daily schedule pass
+//! instantly. Its wall-clock time follows the paused clock too, which is what schedules are
+//! computed from.
+//!
+//! A different clock is set per request with [`Gemini::clock`](crate::Gemini::clock), or for
+//! every request with [`GeminiClient::clock`](crate::client::GeminiClient::clock).
+//!
+//! ```rust
+//! use gemini_oxide::clock::{Clock, TokioClock};
+//! use std::time::Duration;
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() {
+//!     tokio::time::pause();
+//!     let clock = TokioClock;
+//!     let start = clock.now();
+//!     // Returns at once: nothing else is running, so Tokio skips ahead an hour.
+//!     clock.sleep(Duration::from_secs(3600)).await;
+//!     assert!(clock.now() - start >= Duration::from_secs(3600));
+//! }
+//! ```
+
+use futures_util::future::BoxFuture;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+
+/// A source of time that can be waited on.
+pub trait Clock: Send + Sync {
+    /// The current monotonic time.
+    fn now(&self) -> Instant;
+
+    /// The current wall-clock time.
+    fn system_time(&self) -> SystemTime;
+
+    /// Complete at `deadline`.
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()>;
+
+    /// Complete after `duration`.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self.sleep_until(self.now() + duration)
+    }
+}
+
+/// The default [`Clock`], backed by `tokio::time`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    /// The system time, moved by however far Tokio's clock has been paused or advanced.
+    fn system_time(&self) -> SystemTime {
+        let now = SystemTime::now();
+        let real_now = std::time::Instant::now();
+        let virtual_now = Instant::now().into_std();
+        match virtual_now.checked_duration_since(real_now) {
+            Some(ahead) => now + ahead,
+            None => now - real_now.duration_since(virtual_now),
+        }
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep_until(deadline))
+    }
+}
+
+/// The clock used when none is set.
+pub(crate) fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(TokioClock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Returns from every sleep at once, recording how long it was asked to wait.
+    #[derive(Default)]
+    struct RecordingClock {
+        sleeps: Mutex<Vec<Duration>>,
+    }
+
+    impl Clock for RecordingClock {
+        fn now(&self) -> Instant {
+            Instant::now()
+        }
+
+        fn system_time(&self) -> SystemTime {
+            SystemTime::now()
+        }
+
+        fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            self.sleeps.lock().unwrap().push(wait);
+            Box::pin(std::future::ready(()))
+        }
+
+        fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+            self.sleeps.lock().unwrap().push(duration);
+            Box::pin(std::future::ready(()))
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_retry_backoff_waits_on_the_request_clock() {
+        use crate::retry::RetryPolicy;
+        use crate::testing::FaultyCli;
+        use crate::{Gemini, GeminiError};
+
+        let cli = FaultyCli::new()
+            .exit_with(1, "Error: 429 Too Many Requests")
+            .install()
+            .unwrap();
+        let clock = Arc::new(RecordingClock::default());
+        let policy = RetryPolicy::new()
+            .max_attempts(3)
+            .initial_backoff(Duration::from_secs(3600))
+            .jitter(0.0);
+        let result = Gemini::new("Hi")
+            .bin_path(cli.path())
+            .retry(policy)
+            .clock(clock.clone())
+            .text()
+            .await;
+        assert!(matches!(
+            result,
+            Err(GeminiError::RetriesExhausted { attempts: 3, .. })
+        ));
+        assert_eq!(
+            *clock.sleeps.lock().unwrap(),
+            [policy.backoff(1), policy.backoff(2)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_system_time_follows_paused_time() {
+        // The clocks are read one after another, so allow for the thread being preempted.
+        let slack = Duration::from_millis(100);
+        let clock = TokioClock;
+        let wall = clock.system_time();
+        std::thread::sleep(Duration::from_millis(300));
+        let frozen = clock.system_time();
+        assert!(frozen.duration_since(wall).unwrap_or_default() < slack);
+
+        clock.sleep(Duration::from_secs(86_400)).await;
+        let moved = clock.system_time().duration_since(wall).unwrap();
+        assert!(moved > Duration::from_secs(86_400) - slack);
+        assert!(moved < Duration::from_secs(86_400) + slack);
+    }
+}
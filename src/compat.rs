@@ -0,0 +1,278 @@
+//! Compatibility across CLI versions.
+//!
+//! Fleets rarely run a single CLI version. Flags and the `stream-json` event schema have changed
+//! between releases, and this module papers over the differences so one crate version can
+//! drive them all:
+//!
+//! - **Flags.** Tell a request which CLI it talks to with
+//!   [`Gemini::cli_version`](crate::Gemini::cli_version) (see [`CliVersion::detect`]) and the
+//!   command line is built the way that version expects. CLIs before 0.8.0 take the prompt
+//!   through `--prompt` rather than as a positional argument, and streaming needs 0.9.0.
+//! - **Events.** Events that do not parse as a current [`StreamEvent`](crate::StreamEvent) are
+//!   [upgraded](upgrade_event) before giving up: legacy event types and field names are mapped
+//!   to the current ones, and a missing `timestamp` is filled in as empty. Current events are
+//!   parsed directly, at no extra cost. Event types introduced by newer CLIs are passed on as
+//!   [`StreamEvent::Other`](crate::StreamEvent::Other) rather than failing the stream.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::compat::CliVersion;
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let version = CliVersion::detect("gemini").await?;
+//! let text = Gemini::new("Hello").cli_version(version).text().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::GeminiError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// The first version taking the prompt as a positional argument.
+const POSITIONAL_PROMPT: CliVersion = CliVersion::new(0, 8, 0);
+/// The first version with `--output-format stream-json`.
+const STREAM_JSON: CliVersion = CliVersion::new(0, 9, 0);
+
+/// Legacy event types and their current names.
+const EVENT_TYPES: &[(&str, &str)] = &[
+    ("content", "message"),
+    ("tool_call", "tool_use"),
+    ("tool_call_request", "tool_use"),
+    ("tool_response", "tool_result"),
+    ("tool_call_response", "tool_result"),
+    ("finished", "result"),
+    ("done", "result"),
+    ("thinking", "thought"),
+    ("reasoning", "thought"),
+    ("grounding_metadata", "grounding"),
+];
+
+/// The event types this version parses, after upgrading legacy names.
+const CURRENT_TYPES: &[&str] = &[
+    "init",
+    "message",
+    "thought",
+    "tool_use",
+    "tool_result",
+    "result",
+    "grounding",
+    "error",
+    "heartbeat",
+    "plan",
+    "plan_update",
+    "other",
+];
+
+/// Legacy field names of each current event type, and their current names.
+const EVENT_FIELDS: &[(&str, &str, &str)] = &[
+    ("message", "text", "content"),
+    ("thought", "text", "content"),
+    ("thought", "description", "content"),
+    ("tool_use", "name", "tool_name"),
+    ("tool_use", "args", "parameters"),
+    ("tool_use", "arguments", "parameters"),
+    ("tool_result", "id", "tool_id"),
+    ("tool_result", "call_id", "tool_id"),
+    ("tool_result", "result", "output"),
+    ("result", "usage", "stats"),
+    ("init", "sessionId", "session_id"),
+    ("error", "error", "message"),
+];
+
+/// A CLI version, as reported by `gemini --version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct CliVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl CliVersion {
+    /// The version `major.minor.patch`.
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// The first `major.minor.patch` version number in `text`, e.g. in `"0.9.1-nightly"`.
+    pub fn parse(text: &str) -> Option<Self> {
+        text.split(|c: char| !c.is_ascii_digit() && c != '.')
+            .find_map(|word| {
+                let mut numbers = word.split('.').map(|n| n.parse().ok());
+                match (numbers.next(), numbers.next(), numbers.next()) {
+                    (Some(Some(major)), Some(Some(minor)), Some(Some(patch))) => {
+                        Some(Self::new(major, minor, patch))
+                    }
+                    _ => None,
+                }
+            })
+    }
+
+    /// Ask the CLI at `bin_path` for its version.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::CliLaunchFailed` if the CLI cannot be run, or
+    /// `GeminiError::RuntimeError` if it reports no version number.
+    pub async fn detect(bin_path: impl AsRef<Path>) -> Result<Self, GeminiError> {
+        let output = Command::new(bin_path.as_ref())
+            .arg("--version")
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(GeminiError::CliLaunchFailed)?;
+        let reported = String::from_utf8_lossy(&output.stdout);
+        Self::parse(&reported).ok_or_else(|| {
+            GeminiError::RuntimeError(format!("unrecognized CLI version: {:?}", reported.trim()))
+        })
+    }
+
+    /// Whether this version takes the prompt as a positional argument rather than `--prompt`.
+    pub fn takes_positional_prompt(self) -> bool {
+        self >= POSITIONAL_PROMPT
+    }
+
+    /// Whether this version supports `--output-format stream-json`.
+    pub fn supports_stream_json(self) -> bool {
+        self >= STREAM_JSON
+    }
+}
+
+impl fmt::Display for CliVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// `event` with legacy type and field names mapped to the current schema.
+///
+/// Fields already present under their current name are never overwritten.
+pub fn upgrade_event(mut event: Value) -> Value {
+    let Some(fields) = event.as_object_mut() else {
+        return event;
+    };
+    let kind = match fields.get("type").and_then(Value::as_str) {
+        Some(kind) => EVENT_TYPES
+            .iter()
+            .find(|(legacy, _)| *legacy == kind)
+            .map_or(kind, |(_, current)| current)
+            .to_string(),
+        None => return event,
+    };
+    for (_, legacy, current) in EVENT_FIELDS.iter().filter(|(k, ..)| *k == kind) {
+        if !fields.contains_key(*current) {
+            if let Some(value) = fields.remove(*legacy) {
+                fields.insert(current.to_string(), value);
+            }
+        }
+    }
+    if kind != "error" && !fields.contains_key("timestamp") {
+        fields.insert("timestamp".into(), Value::String(String::new()));
+    }
+    fields.insert("type".into(), Value::String(kind));
+    event
+}
+
+/// Parse a `stream-json` line, upgrading legacy events that do not parse as they are.
+pub(crate) fn parse_event<T: serde::de::DeserializeOwned>(
+    line: &str,
+) -> Result<T, serde_json::Error> {
+    serde_json::from_str(line).or_else(|error| {
+        serde_json::from_str::<Value>(line)
+            .ok()
+            .and_then(|event| serde_json::from_value(upgrade_event(event)).ok())
+            .ok_or(error)
+    })
+}
+
+/// The type and content of `line` if it is an event of a type this version does not know.
+pub(crate) fn unknown_event(line: &str) -> Option<(String, Value)> {
+    let event: Value = serde_json::from_str(line).ok()?;
+    let kind = event.get("type")?.as_str()?;
+    let current = EVENT_TYPES
+        .iter()
+        .find(|(legacy, _)| *legacy == kind)
+        .map_or(kind, |(_, current)| current);
+    (!CURRENT_TYPES.contains(&current)).then(|| (kind.to_string(), event.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StreamEvent;
+
+    #[test]
+    fn test_versions_are_parsed_and_compared() {
+        assert_eq!(
+            CliVersion::parse("gemini 0.9.1-nightly.20250101"),
+            Some(CliVersion::new(0, 9, 1))
+        );
+        assert_eq!(CliVersion::parse("unknown"), None);
+        assert!(!CliVersion::new(0, 7, 3).takes_positional_prompt());
+        assert!(CliVersion::new(0, 10, 0).supports_stream_json());
+    }
+
+    #[test]
+    fn test_legacy_events_are_upgraded() {
+        let event: StreamEvent =
+            parse_event(r#"{"type":"tool_call","name":"read_file","args":{"path":"a"}}"#).unwrap();
+        assert!(matches!(
+            event,
+            StreamEvent::ToolUse { tool_name, .. } if tool_name == "read_file"
+        ));
+        let event: StreamEvent =
+            parse_event(r#"{"type":"content","role":"model","text":"Hi","delta":true}"#).unwrap();
+        assert!(matches!(event, StreamEvent::Message { content, .. } if content == "Hi"));
+        assert!(parse_event::<StreamEvent>(r#"{"type":"unheard_of"}"#).is_err());
+    }
+
+    #[test]
+    fn test_unknown_event_types_are_passed_on() {
+        let line = r#"{"type":"citation_batch","items":[1,2]}"#;
+        assert!(matches!(
+            StreamEvent::parse_line(line),
+            Ok(Some(StreamEvent::Other { event_type, raw })) if event_type == "citation_batch" && raw["items"][1] == 2
+        ));
+        // Known types with missing fields, and values without a type, still fail.
+        assert!(StreamEvent::parse_line(r#"{"type":"tool_use"}"#).is_err());
+        assert!(StreamEvent::parse_line(r#"{"kind":"message"}"#).is_err());
+
+        let other = StreamEvent::parse_line(line).unwrap().unwrap();
+        let round_trip = StreamEvent::parse_line(&serde_json::to_string(&other).unwrap());
+        assert!(matches!(
+            round_trip,
+            Ok(Some(StreamEvent::Other { event_type, .. })) if event_type == "citation_batch"
+        ));
+        for kind in CURRENT_TYPES {
+            assert!(unknown_event(&format!(r#"{{"type":"{kind}"}}"#)).is_none());
+        }
+    }
+
+    #[test]
+    fn test_thoughts_are_parsed_apart_from_messages() {
+        let event: StreamEvent = parse_event(
+            r#"{"type":"thought","content":"Check the lockfile.","subject":"Planning","timestamp":""}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            event,
+            StreamEvent::Thought { content, subject: Some(subject), .. }
+                if content == "Check the lockfile." && subject == "Planning"
+        ));
+        let event: StreamEvent =
+            parse_event(r#"{"type":"thinking","description":"Hmm."}"#).unwrap();
+        assert!(matches!(
+            event,
+            StreamEvent::Thought { content, subject: None, .. } if content == "Hmm."
+        ));
+    }
+}
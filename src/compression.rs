@@ -0,0 +1,203 @@
+//! Prompt compression.
+//!
+//! Pasted logs, documents and code carry much that costs tokens without helping the model:
+//! runs of spaces and blank lines, repeated lines, decorative separators. A [`Compression`]
+//! strips these from the prompt and piped context before a request is sent. It can also have
+//! the model condense long context, dropping what is irrelevant to the prompt, in a separate
+//! request first. The estimated savings are reported as a [`CompressionReport`] on
+//! [`GeminiJsonOutput::compression`](crate::GeminiJsonOutput::compression).
+//!
+//! Tokens are estimated at four characters each; the numbers are meant for comparison, not
+//! billing.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::compression::Compression;
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let output = Gemini::new("Why did the deploy fail?")
+//!     .context(std::fs::read_to_string("deploy.log").unwrap())
+//!     .compress(Compression::new().condense_over(20_000))
+//!     .json()
+//!     .await?;
+//! if let Some(report) = output.compression {
+//!     println!("saved about {} tokens", report.saved_tokens());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+/// Which compression passes to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    collapse_whitespace: bool,
+    strip_boilerplate: bool,
+    condense_over: Option<usize>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compression {
+    /// Collapse whitespace and strip boilerplate; no model condensation.
+    pub fn new() -> Self {
+        Self {
+            collapse_whitespace: true,
+            strip_boilerplate: true,
+            condense_over: None,
+        }
+    }
+
+    /// Whether to collapse runs of spaces and tabs within lines, trailing whitespace and runs
+    /// of blank lines. Indentation is kept. On by default.
+    #[must_use]
+    pub fn collapse_whitespace(mut self, enabled: bool) -> Self {
+        self.collapse_whitespace = enabled;
+        self
+    }
+
+    /// Whether to drop repeated consecutive lines and separator lines made only of
+    /// punctuation such as `=====` or `-----`. On by default.
+    #[must_use]
+    pub fn strip_boilerplate(mut self, enabled: bool) -> Self {
+        self.strip_boilerplate = enabled;
+        self
+    }
+
+    /// Have the model condense piped context longer than `chars` characters to what is
+    /// relevant to the prompt, in a separate request, before sending the real one.
+    ///
+    /// Condensation only runs for [`text`](crate::Gemini::text) and
+    /// [`json`](crate::Gemini::json) requests; streams get the other passes only.
+    #[must_use]
+    pub fn condense_over(mut self, chars: usize) -> Self {
+        self.condense_over = Some(chars);
+        self
+    }
+
+    /// Whether `context` is long enough to be condensed by the model.
+    pub(crate) fn should_condense(&self, context: &str) -> bool {
+        self.condense_over
+            .is_some_and(|limit| context.chars().count() > limit)
+    }
+
+    /// Run the text passes over `text`.
+    pub fn apply(&self, text: &str) -> String {
+        let mut lines: Vec<String> = Vec::new();
+        for line in text.lines() {
+            let line = if self.collapse_whitespace {
+                collapse(line)
+            } else {
+                line.to_string()
+            };
+            if self.strip_boilerplate {
+                if is_separator(&line) {
+                    continue;
+                }
+                if !line.trim().is_empty() && lines.last() == Some(&line) {
+                    continue;
+                }
+            }
+            if self.collapse_whitespace
+                && line.is_empty()
+                && lines.last().is_none_or(String::is_empty)
+            {
+                continue;
+            }
+            lines.push(line);
+        }
+        if self.collapse_whitespace {
+            while lines.last().is_some_and(String::is_empty) {
+                lines.pop();
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// The instruction asking the model to condense `context` for `prompt`.
+pub(crate) fn condense_prompt(prompt: &str) -> String {
+    format!(
+        "The text on standard input is context for the task below. Condense it: keep every \
+         fact, name, number, error message and code snippet relevant to the task verbatim, \
+         summarize the rest briefly and drop what is irrelevant. Respond with only the \
+         condensed context.\n\nTask: {prompt}"
+    )
+}
+
+/// Estimated token savings of a compressed request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CompressionReport {
+    /// Estimated tokens of the prompt and context as given.
+    pub original_tokens: u64,
+    /// Estimated tokens of the prompt and context as sent.
+    pub compressed_tokens: u64,
+    /// Whether the model condensed the context.
+    pub condensed: bool,
+}
+
+impl CompressionReport {
+    /// Estimated tokens saved. Condensation requests themselves are not subtracted.
+    pub fn saved_tokens(&self) -> u64 {
+        self.original_tokens.saturating_sub(self.compressed_tokens)
+    }
+}
+
+/// A rough token count for `text`: one token per four characters.
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.chars().count() as u64).div_ceil(4)
+}
+
+/// `line` with inner runs of spaces and tabs collapsed and trailing whitespace removed.
+fn collapse(line: &str) -> String {
+    let body = line.trim_start();
+    let mut collapsed = line[..line.len() - body.len()].to_string();
+    for (i, word) in body.split_whitespace().enumerate() {
+        if i > 0 {
+            collapsed.push(' ');
+        }
+        collapsed.push_str(word);
+    }
+    if body.is_empty() {
+        collapsed.clear();
+    }
+    collapsed
+}
+
+/// Whether `line` is a decorative separator such as `=====`, `-----` or `# ******`.
+fn is_separator(line: &str) -> bool {
+    let symbols: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut chars = symbols.trim_start_matches(['#', '/', '*']).chars();
+    match chars.next() {
+        Some(first) => {
+            first.is_ascii_punctuation()
+                && symbols.len() >= 4
+                && chars.all(|c| c == first || "#/*".contains(c))
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whitespace_and_boilerplate_are_removed() {
+        let log = "==========\nERROR   disk  full   \nERROR disk full\n\n\n\n    at main.rs:3\n// --------\n\n";
+        let compressed = Compression::new().apply(log);
+        assert_eq!(compressed, "ERROR disk full\n\n    at main.rs:3");
+        assert!(estimate_tokens(&compressed) < estimate_tokens(log));
+
+        let untouched = Compression::new()
+            .collapse_whitespace(false)
+            .strip_boilerplate(false)
+            .apply("a  b\n=====");
+        assert_eq!(untouched, "a  b\n=====");
+    }
+}
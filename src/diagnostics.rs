@@ -0,0 +1,184 @@
+//! Diagnostics for failed CLI launches.
+//!
+//! "Failed to start Gemini CLI" alone rarely says what to fix. When the CLI cannot be launched,
+//! the SDK gathers the environment the launch depended on — which binary it tried and where it
+//! was found, the `PATH`, the Node.js version and whether the CLI's `~/.gemini` configuration
+//! directory exists — into [`LaunchDiagnostics`]. They are attached to the
+//! `GeminiError::CliLaunchFailed` error, included in its source's message, and available
+//! through [`GeminiError::launch_diagnostics`](crate::GeminiError::launch_diagnostics).
+//!
+//! ```rust,no_run
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() {
+//! if let Err(e) = Gemini::new("Hello").bin_path("/opt/missing/gemini").text().await {
+//!     if let Some(diagnostics) = e.launch_diagnostics() {
+//!         eprintln!("{diagnostics}");
+//!     }
+//! }
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The environment a failed CLI launch depended on.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LaunchDiagnostics {
+    /// The binary the SDK tried to run.
+    pub bin_path: PathBuf,
+    /// Where the binary was found, if it exists as given or on the `PATH`.
+    pub resolved: Option<PathBuf>,
+    /// The entries of the `PATH` environment variable.
+    pub path: Vec<PathBuf>,
+    /// The output of `node --version`, if Node.js is installed.
+    pub node_version: Option<String>,
+    /// The CLI's configuration directory (`~/.gemini`), if the home directory is known.
+    pub config_dir: Option<PathBuf>,
+    /// Whether the configuration directory exists.
+    pub config_dir_exists: bool,
+    /// The working directory the CLI was started in.
+    pub current_dir: Option<PathBuf>,
+}
+
+impl LaunchDiagnostics {
+    /// Gather diagnostics for launching `bin_path` in `current_dir`.
+    pub fn gather(bin_path: &Path, current_dir: Option<&Path>) -> Self {
+        let path: Vec<PathBuf> = std::env::var_os("PATH")
+            .map(|paths| std::env::split_paths(&paths).collect())
+            .unwrap_or_default();
+        let config_dir = std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(".gemini"));
+        Self {
+            bin_path: bin_path.to_path_buf(),
+            resolved: resolve(bin_path, &path),
+            node_version: node_version(),
+            config_dir_exists: config_dir.as_deref().is_some_and(Path::is_dir),
+            config_dir,
+            current_dir: current_dir
+                .map(Path::to_path_buf)
+                .or_else(|| std::env::current_dir().ok()),
+            path,
+        }
+    }
+}
+
+impl fmt::Display for LaunchDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let missing = |found: bool| if found { "" } else { " (missing)" };
+        match &self.resolved {
+            Some(resolved) => writeln!(
+                f,
+                "binary: {} -> {}",
+                self.bin_path.display(),
+                resolved.display()
+            )?,
+            None => writeln!(f, "binary: {} (not found)", self.bin_path.display())?,
+        }
+        let path: Vec<_> = self.path.iter().map(|p| p.display().to_string()).collect();
+        writeln!(
+            f,
+            "PATH: {}",
+            path.join(if cfg!(windows) { ";" } else { ":" })
+        )?;
+        writeln!(
+            f,
+            "node: {}",
+            self.node_version.as_deref().unwrap_or("not found")
+        )?;
+        match &self.config_dir {
+            Some(dir) => writeln!(
+                f,
+                "config: {}{}",
+                dir.display(),
+                missing(self.config_dir_exists)
+            )?,
+            None => writeln!(f, "config: home directory unknown")?,
+        }
+        match &self.current_dir {
+            Some(dir) => write!(
+                f,
+                "working directory: {}{}",
+                dir.display(),
+                missing(dir.is_dir())
+            ),
+            None => write!(f, "working directory: unknown"),
+        }
+    }
+}
+
+/// A launch error carrying its diagnostics. The original error is its source.
+#[derive(Debug)]
+pub(crate) struct LaunchFailure {
+    pub(crate) error: io::Error,
+    pub(crate) diagnostics: LaunchDiagnostics,
+}
+
+impl LaunchFailure {
+    /// Wrap `error`, keeping its kind, so it still reads as the original I/O error.
+    pub(crate) fn wrap(error: io::Error, diagnostics: LaunchDiagnostics) -> io::Error {
+        io::Error::new(error.kind(), Self { error, diagnostics })
+    }
+}
+
+impl fmt::Display for LaunchFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n{}", self.error, self.diagnostics)
+    }
+}
+
+impl std::error::Error for LaunchFailure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// `bin_path` itself if it names a file, or else its location on `path`.
+fn resolve(bin_path: &Path, path: &[PathBuf]) -> Option<PathBuf> {
+    if bin_path.components().count() > 1 {
+        return bin_path.is_file().then(|| bin_path.to_path_buf());
+    }
+    path.iter().find_map(|dir| {
+        let candidate = dir.join(bin_path);
+        let windows_scripts = ["cmd", "exe", "bat"]
+            .iter()
+            .filter(|_| cfg!(windows))
+            .map(|extension| candidate.with_extension(extension));
+        std::iter::once(candidate.clone())
+            .chain(windows_scripts)
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+fn node_version() -> Option<String> {
+    let output = Command::new("node")
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !version.is_empty()).then_some(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_binary_is_reported() {
+        let diagnostics = LaunchDiagnostics::gather(Path::new("/nonexistent/gemini"), None);
+        assert_eq!(diagnostics.resolved, None);
+        let report = diagnostics.to_string();
+        assert!(report.contains("binary: /nonexistent/gemini (not found)"));
+        assert!(report.contains("PATH: "));
+
+        let error = LaunchFailure::wrap(io::Error::from(io::ErrorKind::NotFound), diagnostics);
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().contains("(not found)"));
+    }
+}
@@ -0,0 +1,153 @@
+//! Stable, normalization-aware hashes of requests.
+//!
+//! Two requests that differ only in whitespace, or in the order of their attached files and
+//! include directories, ask the same thing. A [`Fingerprint`] hashes labelled components so
+//! that such requests produce the same value: text fields are [normalized](normalize), and
+//! set-like components are sorted before hashing. The hash is 64-bit FNV-1a, so fingerprints are
+//! identical across processes, platforms and compiler versions and can be used to shard work or
+//! detect duplicate runs.
+//!
+//! [`Gemini::fingerprint`](crate::Gemini::fingerprint) fingerprints a whole request. Because
+//! the normalization also applies to piped files, where indentation can matter, and a 64-bit
+//! hash can be collided on purpose, the response cache and pipeline checkpoints are not keyed
+//! by it: they hash the request's exact bytes with SHA-256.
+//!
+//! ```rust
+//! use gemini_oxide::fingerprint::Fingerprint;
+//!
+//! let a = Fingerprint::new()
+//!     .field("prompt", "Summarize   the\nlogs ")
+//!     .item("file", "b.log contents")
+//!     .item("file", "a.log contents");
+//! let b = Fingerprint::new()
+//!     .field("prompt", "Summarize the logs")
+//!     .item("file", "a.log contents")
+//!     .item("file", "b.log contents");
+//! assert_eq!(a.finish(), b.finish());
+//! ```
+
+use std::collections::BTreeMap;
+
+/// Collapse every run of whitespace (including line breaks) into one space and trim the ends.
+pub fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The fingerprint of a single piece of text, after [normalization](normalize).
+pub fn of_text(text: &str) -> String {
+    Fingerprint::new().field("text", text).finish()
+}
+
+/// Builds a fingerprint from labelled components.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fingerprint {
+    fields: BTreeMap<String, Vec<u8>>,
+    items: BTreeMap<String, Vec<Vec<u8>>>,
+}
+
+impl Fingerprint {
+    /// An empty fingerprint.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the text component `label`, normalized. Setting a label again replaces it.
+    #[must_use]
+    pub fn field(self, label: impl Into<String>, text: &str) -> Self {
+        self.raw(label, normalize(text).into_bytes())
+    }
+
+    /// Set the component `label` to exact bytes. Setting a label again replaces it.
+    #[must_use]
+    pub fn raw(mut self, label: impl Into<String>, bytes: impl Into<Vec<u8>>) -> Self {
+        self.fields.insert(label.into(), bytes.into());
+        self
+    }
+
+    /// Add a member to the unordered collection `label`. Text is normalized; the order in which
+    /// members are added does not matter.
+    #[must_use]
+    pub fn item(self, label: impl Into<String>, text: &str) -> Self {
+        self.raw_item(label, normalize(text).into_bytes())
+    }
+
+    /// Add exact bytes to the unordered collection `label`.
+    #[must_use]
+    pub fn raw_item(mut self, label: impl Into<String>, bytes: impl Into<Vec<u8>>) -> Self {
+        self.items
+            .entry(label.into())
+            .or_default()
+            .push(bytes.into());
+        self
+    }
+
+    /// The fingerprint as 16 hex digits.
+    pub fn finish(&self) -> String {
+        format!("{:016x}", hash(self.parts()))
+    }
+
+    /// The labelled components in a canonical order, for hashing.
+    pub(crate) fn parts(&self) -> Vec<&[u8]> {
+        let mut parts: Vec<&[u8]> = Vec::new();
+        for (label, value) in &self.fields {
+            parts.extend([b"field".as_slice(), label.as_bytes(), value]);
+        }
+        for (label, values) in &self.items {
+            let mut values: Vec<&Vec<u8>> = values.iter().collect();
+            values.sort();
+            parts.extend([b"items".as_slice(), label.as_bytes()]);
+            parts.extend(values.into_iter().map(Vec::as_slice));
+        }
+        parts
+    }
+}
+
+/// 64-bit FNV-1a over length-prefixed parts.
+pub(crate) fn hash<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut hash = OFFSET;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(PRIME);
+        }
+    };
+    for part in parts {
+        feed(&(part.len() as u64).to_le_bytes());
+        feed(part);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_collapses_whitespace() {
+        assert_eq!(normalize("  a\tb \r\n\n c  "), "a b c");
+        assert_eq!(of_text("a  b"), of_text("a\nb"));
+        assert_ne!(of_text("a b"), of_text("A b"));
+    }
+
+    #[test]
+    fn test_labels_and_kinds_are_kept_apart() {
+        let field = Fingerprint::new().field("a", "x");
+        assert_ne!(field.finish(), Fingerprint::new().field("b", "x").finish());
+        assert_ne!(field.finish(), Fingerprint::new().item("a", "x").finish());
+        // Length prefixes prevent ambiguity between adjacent parts.
+        assert_ne!(
+            Fingerprint::new().item("f", "ab").item("f", "c").finish(),
+            Fingerprint::new().item("f", "a").item("f", "bc").finish()
+        );
+    }
+
+    #[test]
+    fn test_hash_is_stable() {
+        // Pinned so that shared caches stay valid across releases.
+        assert_eq!(hash([]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(of_text("hello"), "31b3958f8f0314e2");
+    }
+}
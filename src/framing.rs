@@ -0,0 +1,238 @@
+//! Framing piped files as labelled code blocks.
+//!
+//! By default [`Gemini::file`](crate::Gemini::file) pipes a file's bytes as they are, so the
+//! model sees one run of text with no file boundaries. With
+//! [`Gemini::frame_files`](crate::Gemini::frame_files), each file is sent under a `File:` line
+//! in a fenced code block tagged with its language, which helps the model tell files apart and
+//! read them as the right language.
+//!
+//! Languages are found by a [`LanguageDetector`]. [`DefaultDetector`] looks at the file name
+//! and extension, then at the content (a shebang, `<?php`, `<?xml`, JSON); any closure
+//! `Fn(&Path, &str) -> Option<String>` can be used instead.
+//!
+//! ```rust
+//! use gemini_oxide::framing::{frame, DefaultDetector};
+//! use std::path::Path;
+//!
+//! let framed = frame(Path::new("src/main.rs"), "fn main() {}", &DefaultDetector);
+//! assert_eq!(framed, "File: src/main.rs\n```rust\nfn main() {}\n```");
+//! ```
+
+use std::path::Path;
+
+/// Names the language of a file, as a code block tag such as `rust` or `python`.
+pub trait LanguageDetector: Send + Sync {
+    /// The language of the file at `path` with `content`, if known.
+    fn detect(&self, path: &Path, content: &str) -> Option<String>;
+}
+
+impl<F> LanguageDetector for F
+where
+    F: Fn(&Path, &str) -> Option<String> + Send + Sync,
+{
+    fn detect(&self, path: &Path, content: &str) -> Option<String> {
+        self(path, content)
+    }
+}
+
+/// Detects common languages from file names, extensions and content.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultDetector;
+
+/// File extensions (lowercase) and their code block tags.
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("pyi", "python"),
+    ("js", "javascript"),
+    ("mjs", "javascript"),
+    ("cjs", "javascript"),
+    ("jsx", "jsx"),
+    ("ts", "typescript"),
+    ("mts", "typescript"),
+    ("tsx", "tsx"),
+    ("go", "go"),
+    ("java", "java"),
+    ("kt", "kotlin"),
+    ("kts", "kotlin"),
+    ("scala", "scala"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cc", "cpp"),
+    ("cpp", "cpp"),
+    ("cxx", "cpp"),
+    ("hpp", "cpp"),
+    ("hh", "cpp"),
+    ("cs", "csharp"),
+    ("swift", "swift"),
+    ("m", "objectivec"),
+    ("rb", "ruby"),
+    ("php", "php"),
+    ("pl", "perl"),
+    ("lua", "lua"),
+    ("r", "r"),
+    ("dart", "dart"),
+    ("ex", "elixir"),
+    ("exs", "elixir"),
+    ("erl", "erlang"),
+    ("hs", "haskell"),
+    ("ml", "ocaml"),
+    ("clj", "clojure"),
+    ("zig", "zig"),
+    ("sh", "bash"),
+    ("bash", "bash"),
+    ("zsh", "zsh"),
+    ("fish", "fish"),
+    ("ps1", "powershell"),
+    ("sql", "sql"),
+    ("html", "html"),
+    ("htm", "html"),
+    ("vue", "vue"),
+    ("svelte", "svelte"),
+    ("css", "css"),
+    ("scss", "scss"),
+    ("json", "json"),
+    ("jsonl", "json"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("toml", "toml"),
+    ("xml", "xml"),
+    ("md", "markdown"),
+    ("proto", "protobuf"),
+    ("graphql", "graphql"),
+    ("tf", "hcl"),
+    ("diff", "diff"),
+    ("patch", "diff"),
+];
+
+/// Whole file names (lowercase) and their code block tags.
+const FILE_NAMES: &[(&str, &str)] = &[
+    ("dockerfile", "dockerfile"),
+    ("containerfile", "dockerfile"),
+    ("makefile", "makefile"),
+    ("gnumakefile", "makefile"),
+    ("cmakelists.txt", "cmake"),
+    ("cargo.lock", "toml"),
+    ("gemfile", "ruby"),
+    ("rakefile", "ruby"),
+    ("justfile", "just"),
+];
+
+/// Interpreters named in shebang lines and their code block tags.
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("python", "python"),
+    ("node", "javascript"),
+    ("deno", "typescript"),
+    ("bash", "bash"),
+    ("zsh", "zsh"),
+    ("sh", "bash"),
+    ("ruby", "ruby"),
+    ("perl", "perl"),
+    ("php", "php"),
+    ("lua", "lua"),
+];
+
+impl LanguageDetector for DefaultDetector {
+    fn detect(&self, path: &Path, content: &str) -> Option<String> {
+        let lookup = |table: &[(&str, &'static str)], key: &str| {
+            table
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, tag)| tag.to_string())
+        };
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        lookup(FILE_NAMES, &name)
+            .or_else(|| {
+                let extension = path.extension()?.to_string_lossy().to_lowercase();
+                lookup(EXTENSIONS, &extension)
+            })
+            .or_else(|| detect_content(content))
+    }
+}
+
+/// The language of `content` from its first line, or `json` if it parses as JSON.
+fn detect_content(content: &str) -> Option<String> {
+    let first = content.trim_start().lines().next().unwrap_or_default();
+    if let Some(shebang) = first.strip_prefix("#!") {
+        // `#!/usr/bin/env python3`, `#!/bin/sh -e`.
+        let mut words = shebang.split_whitespace();
+        let program = words.next()?;
+        let program = match program.rsplit('/').next() {
+            Some("env") => words.find(|w| !w.starts_with('-'))?,
+            name => name?,
+        };
+        return INTERPRETERS
+            .iter()
+            .find(|(interpreter, _)| program.starts_with(interpreter))
+            .map(|(_, tag)| tag.to_string());
+    }
+    if first.starts_with("<?php") {
+        return Some("php".into());
+    }
+    if first.starts_with("<?xml") {
+        return Some("xml".into());
+    }
+    let trimmed = content.trim();
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(trimmed).is_ok()
+    {
+        return Some("json".into());
+    }
+    None
+}
+
+/// `content` as a fenced code block under a `File:` line, tagged with the language `detector`
+/// finds. The fence is made longer than any run of backticks in the content.
+pub fn frame(path: &Path, content: &str, detector: &dyn LanguageDetector) -> String {
+    let language = detector.detect(path, content).unwrap_or_default();
+    let longest_run = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    let content = content.strip_suffix('\n').unwrap_or(content);
+    format!(
+        "File: {}\n{fence}{language}\n{content}\n{fence}",
+        path.display()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(path: &str, content: &str) -> Option<String> {
+        DefaultDetector.detect(Path::new(path), content)
+    }
+
+    #[test]
+    fn test_languages_are_detected_from_names_then_content() {
+        assert_eq!(detect("src/lib.RS", "").as_deref(), Some("rust"));
+        assert_eq!(
+            detect("deploy/Dockerfile", "").as_deref(),
+            Some("dockerfile")
+        );
+        assert_eq!(
+            detect("bin/run", "#!/usr/bin/env python3\nprint(1)").as_deref(),
+            Some("python")
+        );
+        assert_eq!(
+            detect("bin/setup", "#!/bin/sh -e\n").as_deref(),
+            Some("bash")
+        );
+        assert_eq!(detect("data", "{\"a\": [1, 2]}").as_deref(), Some("json"));
+        assert_eq!(detect("notes", "Just some text."), None);
+    }
+
+    #[test]
+    fn test_fence_outgrows_backticks_in_content() {
+        let framed = frame(
+            Path::new("README.md"),
+            "Run:\n```sh\nmake\n```\n",
+            &DefaultDetector,
+        );
+        assert_eq!(
+            framed,
+            "File: README.md\n````markdown\nRun:\n```sh\nmake\n```\n````"
+        );
+        let custom = |_: &Path, _: &str| Some("text".to_string());
+        assert!(frame(Path::new("a.rs"), "x", &custom).contains("```text\n"));
+    }
+}
@@ -0,0 +1,106 @@
+//! Sampling parameters and output limits for the model.
+//!
+//! The CLI has no command-line flags for sampling, but it reads generation settings from its
+//! settings files. When [`Gemini::temperature`](crate::Gemini::temperature),
+//! [`top_p`](crate::Gemini::top_p), [`top_k`](crate::Gemini::top_k) or
+//! [`max_output_tokens`](crate::Gemini::max_output_tokens) is set, the SDK writes a
+//! temporary settings file holding a model config override with these values and points the
+//! CLI at it through `GEMINI_CLI_SYSTEM_SETTINGS_PATH`. The file is deleted when the run ends.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! // Near-deterministic output for extraction tasks.
+//! let date = Gemini::new("Extract the invoice date as YYYY-MM-DD: ...")
+//!     .temperature(0.0)
+//!     .text()
+//!     .await?;
+//! // More varied output for brainstorming.
+//! let ideas = Gemini::new("Name ideas for a coffee shop")
+//!     .temperature(1.3)
+//!     .top_p(0.95)
+//!     .top_k(64)
+//!     .text()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// Generation parameters. Unset values keep the CLI's defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct GenerationConfig {
+    /// Randomness of token selection, from 0.0 (near-deterministic) to 2.0.
+    pub temperature: Option<f32>,
+    /// Nucleus sampling: only tokens within this cumulative probability are considered,
+    /// from 0.0 to 1.0.
+    pub top_p: Option<f32>,
+    /// Only the `top_k` most likely tokens are considered.
+    pub top_k: Option<u32>,
+    /// The most tokens the model may generate per response.
+    pub max_output_tokens: Option<u32>,
+}
+
+impl GenerationConfig {
+    /// Whether every parameter is left at the CLI's default.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The parameters in the API's `generateContentConfig` form.
+    pub(crate) fn content_config(&self) -> Map<String, Value> {
+        let mut config = Map::new();
+        if let Some(temperature) = self.temperature {
+            config.insert("temperature".into(), json!(temperature));
+        }
+        if let Some(top_p) = self.top_p {
+            config.insert("topP".into(), json!(top_p));
+        }
+        if let Some(top_k) = self.top_k {
+            config.insert("topK".into(), json!(top_k));
+        }
+        if let Some(max_output_tokens) = self.max_output_tokens {
+            config.insert("maxOutputTokens".into(), json!(max_output_tokens));
+        }
+        config
+    }
+
+    /// The CLI settings applying the parameters to every model, or `None` if none is set.
+    pub(crate) fn settings(&self) -> Option<Value> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(json!({
+            "modelConfigs": {
+                "overrides": [{
+                    "match": {},
+                    "modelConfig": { "generateContentConfig": self.content_config() },
+                }],
+            },
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_set_parameters_are_written() {
+        assert_eq!(GenerationConfig::default().settings(), None);
+
+        let config = GenerationConfig {
+            temperature: Some(0.5),
+            top_k: Some(40),
+            ..GenerationConfig::default()
+        };
+        let settings = config.settings().unwrap();
+        assert_eq!(
+            settings["modelConfigs"]["overrides"][0]["modelConfig"]["generateContentConfig"],
+            json!({"temperature": 0.5, "topK": 40})
+        );
+    }
+}
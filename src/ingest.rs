@@ -0,0 +1,506 @@
+//! Answering prompts read from a message queue.
+//!
+//! A common deployment consumes prompts from Kafka, NATS or SQS and publishes the answers to
+//! an output topic. An [`Ingestor`] does the consuming: it pulls messages from a
+//! [`MessageSource`], runs each through a [`GeminiClient`], publishes a [`QueueResult`] to a
+//! [`MessageSink`] and acknowledges the message. The broker clients themselves are supplied by
+//! implementing the two traits; [`InMemoryQueue`] is an in-process implementation of both,
+//! for tests and as a template.
+//!
+//! The ingestor only asks for as many messages as it has free slots (see
+//! [`max_in_flight`](Ingestor::max_in_flight)), so a backlog stays in the broker, where it can
+//! be observed and redelivered, rather than piling up in memory. Requests can share a
+//! [`GeminiPool`] with the rest of the application and be spaced by
+//! [`min_interval`](Ingestor::min_interval) to stay under a rate limit.
+//!
+//! Delivery is at least once. A message is acknowledged after its result is published;
+//! transient failures (see [`RetryPolicy::is_transient`]) and failed publishes are handed back
+//! with [`MessageSource::nack`] for redelivery, and other failures are published as error
+//! results.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::client::GeminiClient;
+//! use gemini_oxide::ingest::{InMemoryQueue, Ingestor, QueueMessage};
+//! use std::sync::Arc;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let input = Arc::new(InMemoryQueue::new());
+//! let output = Arc::new(InMemoryQueue::new());
+//! input.push(QueueMessage::new("ticket-1", "Classify: the app crashes on login"));
+//! input.close();
+//!
+//! let report = Ingestor::new(GeminiClient::new(), input, output.clone())
+//!     .max_in_flight(8)
+//!     .run()
+//!     .await?;
+//! println!("{} answered, {} failed", report.succeeded, report.failed);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::cancel::CancellationToken;
+use crate::client::GeminiClient;
+use crate::pool::GeminiPool;
+use crate::retry::RetryPolicy;
+use crate::GeminiError;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Notify, Semaphore};
+use tokio::time::Instant;
+
+/// Default number of messages processed at once.
+const DEFAULT_MAX_IN_FLIGHT: usize = 4;
+
+/// A prompt read from a queue.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct QueueMessage {
+    /// The broker's id for the message, used to acknowledge it.
+    pub id: String,
+    pub prompt: String,
+    /// Data piped to the model alongside the prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// Headers or attributes, copied to the result for correlation.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl QueueMessage {
+    /// A message asking `prompt`.
+    pub fn new(id: impl Into<String>, prompt: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            prompt: prompt.into(),
+            context: None,
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    /// Pipe `context` to the model.
+    #[must_use]
+    pub fn context(mut self, context: impl Into<String>) -> Self {
+        self.context = Some(context.into());
+        self
+    }
+
+    /// Add an attribute.
+    #[must_use]
+    pub fn attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// The answer to a [`QueueMessage`], published to the output topic.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct QueueResult {
+    /// The id of the message answered.
+    pub message_id: String,
+    /// The message's attributes.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+    /// The model's answer, if the request succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    /// Why the request failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A queue or topic prompts are consumed from.
+pub trait MessageSource: Send + Sync {
+    /// Wait for up to `max` messages (at least one is asked for). Return an empty batch if
+    /// none arrived within the broker's poll window, and `None` once the source is closed.
+    ///
+    /// The call may be dropped when the ingestor is stopped; messages it had received but not
+    /// returned are expected to be redelivered by the broker.
+    fn receive(&self, max: usize) -> BoxFuture<'_, Result<Option<Vec<QueueMessage>>, GeminiError>>;
+
+    /// Mark `message` as processed.
+    fn ack<'a>(&'a self, message: &'a QueueMessage) -> BoxFuture<'a, Result<(), GeminiError>>;
+
+    /// Hand `message` back for redelivery.
+    fn nack<'a>(&'a self, message: &'a QueueMessage) -> BoxFuture<'a, Result<(), GeminiError>>;
+}
+
+/// A topic results are published to.
+pub trait MessageSink: Send + Sync {
+    /// Publish `result`.
+    fn publish<'a>(&'a self, result: &'a QueueResult) -> BoxFuture<'a, Result<(), GeminiError>>;
+}
+
+/// Counts of what an [`Ingestor`] did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestReport {
+    /// Messages received from the source.
+    pub received: u64,
+    /// Messages answered and acknowledged.
+    pub succeeded: u64,
+    /// Messages whose request failed, published as error results and acknowledged.
+    pub failed: u64,
+    /// Messages handed back for redelivery.
+    pub requeued: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    received: AtomicU64,
+    succeeded: AtomicU64,
+    failed: AtomicU64,
+    requeued: AtomicU64,
+}
+
+impl Counters {
+    fn report(&self) -> IngestReport {
+        IngestReport {
+            received: self.received.load(Ordering::SeqCst),
+            succeeded: self.succeeded.load(Ordering::SeqCst),
+            failed: self.failed.load(Ordering::SeqCst),
+            requeued: self.requeued.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Consumes prompts from a [`MessageSource`] and publishes answers to a [`MessageSink`].
+#[derive(Clone)]
+pub struct Ingestor {
+    client: GeminiClient,
+    source: Arc<dyn MessageSource>,
+    sink: Arc<dyn MessageSink>,
+    max_in_flight: usize,
+    pool: Option<GeminiPool>,
+    min_interval: Duration,
+    stop: Option<CancellationToken>,
+}
+
+impl Ingestor {
+    /// Answer messages from `source` with requests from `client`, publishing to `sink`.
+    pub fn new(
+        client: GeminiClient,
+        source: Arc<dyn MessageSource>,
+        sink: Arc<dyn MessageSink>,
+    ) -> Self {
+        Self {
+            client,
+            source,
+            sink,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            pool: None,
+            min_interval: Duration::ZERO,
+            stop: None,
+        }
+    }
+
+    /// Process at most `n` messages at once (at least one). No more are received until a slot
+    /// frees up.
+    #[must_use]
+    pub fn max_in_flight(mut self, n: usize) -> Self {
+        self.max_in_flight = n.max(1);
+        self
+    }
+
+    /// Run requests through `pool`, sharing its concurrency limit with the rest of the
+    /// application.
+    #[must_use]
+    pub fn pool(mut self, pool: GeminiPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Start requests at least `interval` apart, as measured by the client's
+    /// [`clock`](GeminiClient::clock).
+    #[must_use]
+    pub fn min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    /// Stop receiving once `token` is cancelled. Messages in flight are still finished.
+    #[must_use]
+    pub fn stop_on(mut self, token: CancellationToken) -> Self {
+        self.stop = Some(token);
+        self
+    }
+
+    /// Consume messages until the source closes or the [`stop_on`](Self::stop_on) token is
+    /// cancelled, then wait for the messages in flight.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from [`MessageSource::receive`], after the messages in flight
+    /// have finished. Failures of individual messages are counted in the report instead.
+    pub async fn run(&self) -> Result<IngestReport, GeminiError> {
+        let slots = Arc::new(Semaphore::new(self.max_in_flight));
+        let counters = Arc::new(Counters::default());
+        let next_start = Arc::new(tokio::sync::Mutex::new(None));
+        let stop = self.stop.clone().unwrap_or_default();
+        let mut outcome = Ok(());
+
+        loop {
+            let first = tokio::select! {
+                permit = slots.clone().acquire_owned() => {
+                    permit.expect("the ingestor's semaphore is never closed")
+                }
+                () = stop.cancelled() => break,
+            };
+            // Ask for as many messages as there are free slots, and no more.
+            let mut permits = vec![first];
+            while let Ok(permit) = slots.clone().try_acquire_owned() {
+                permits.push(permit);
+            }
+            let batch = tokio::select! {
+                batch = self.source.receive(permits.len()) => batch,
+                () = stop.cancelled() => break,
+            };
+            let messages = match batch {
+                Ok(Some(messages)) => messages,
+                Ok(None) => break,
+                Err(error) => {
+                    outcome = Err(error);
+                    break;
+                }
+            };
+            counters
+                .received
+                .fetch_add(messages.len() as u64, Ordering::SeqCst);
+            for message in messages {
+                // A source returning more than asked for still only gets `max_in_flight` slots.
+                let permit = match permits.pop() {
+                    Some(permit) => permit,
+                    None => slots
+                        .clone()
+                        .acquire_owned()
+                        .await
+                        .expect("the ingestor's semaphore is never closed"),
+                };
+                let ingestor = self.clone();
+                let counters = counters.clone();
+                let next_start = next_start.clone();
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    ingestor.process(message, &counters, &next_start).await;
+                });
+            }
+        }
+
+        // Every slot is free once every message in flight has finished.
+        let _all = slots
+            .acquire_many(self.max_in_flight as u32)
+            .await
+            .expect("the ingestor's semaphore is never closed");
+        outcome.map(|()| counters.report())
+    }
+
+    async fn process(
+        &self,
+        message: QueueMessage,
+        counters: &Counters,
+        next_start: &tokio::sync::Mutex<Option<Instant>>,
+    ) {
+        let mut request = self.client.prompt(message.prompt.as_str());
+        if !self.min_interval.is_zero() {
+            let clock = request.clock.clone();
+            let mut next = next_start.lock().await;
+            if let Some(at) = *next {
+                clock.sleep_until(at).await;
+            }
+            *next = Some(clock.now() + self.min_interval);
+        }
+        if let Some(context) = &message.context {
+            request = request.context(context.as_str());
+        }
+        let result = match &self.pool {
+            Some(pool) => pool.text(request).await,
+            None => request.text().await,
+        };
+        if let Err(error) = &result {
+            if RetryPolicy::is_transient(error) {
+                self.requeue(&message, counters).await;
+                return;
+            }
+        }
+        let succeeded = result.is_ok();
+        let (response, error) = match result {
+            Ok(response) => (Some(response), None),
+            Err(error) => (None, Some(error.to_string())),
+        };
+        let published = QueueResult {
+            message_id: message.id.clone(),
+            attributes: message.attributes.clone(),
+            response,
+            error,
+        };
+        if self.sink.publish(&published).await.is_err() {
+            self.requeue(&message, counters).await;
+            return;
+        }
+        // An unacknowledged message is redelivered by the broker and answered again.
+        let _ = self.source.ack(&message).await;
+        let counter = if succeeded {
+            &counters.succeeded
+        } else {
+            &counters.failed
+        };
+        counter.fetch_add(1, Ordering::SeqCst);
+    }
+
+    async fn requeue(&self, message: &QueueMessage, counters: &Counters) {
+        let _ = self.source.nack(message).await;
+        counters.requeued.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// An in-process queue, usable as both a [`MessageSource`] and a [`MessageSink`].
+///
+/// Received messages are removed immediately; [`nack`](MessageSource::nack) puts them back at
+/// the front. Published results are kept and can be read with
+/// [`published`](Self::published).
+#[derive(Default)]
+pub struct InMemoryQueue {
+    state: Mutex<QueueState>,
+    arrived: Notify,
+}
+
+#[derive(Default)]
+struct QueueState {
+    messages: VecDeque<QueueMessage>,
+    published: Vec<QueueResult>,
+    closed: bool,
+}
+
+impl InMemoryQueue {
+    /// An empty, open queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a message to the back of the queue.
+    pub fn push(&self, message: QueueMessage) {
+        self.lock().messages.push_back(message);
+        self.arrived.notify_waiters();
+    }
+
+    /// Close the queue: [`receive`](MessageSource::receive) drains the remaining messages,
+    /// then returns `None`.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.arrived.notify_waiters();
+    }
+
+    /// Messages waiting to be received.
+    pub fn pending(&self) -> usize {
+        self.lock().messages.len()
+    }
+
+    /// Results published so far, in publishing order.
+    pub fn published(&self) -> Vec<QueueResult> {
+        self.lock().published.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl MessageSource for InMemoryQueue {
+    fn receive(&self, max: usize) -> BoxFuture<'_, Result<Option<Vec<QueueMessage>>, GeminiError>> {
+        Box::pin(async move {
+            loop {
+                // Register for wakeups before checking, so a push in between is not missed.
+                let arrived = self.arrived.notified();
+                {
+                    let mut state = self.lock();
+                    if !state.messages.is_empty() {
+                        let n = max.max(1).min(state.messages.len());
+                        return Ok(Some(state.messages.drain(..n).collect()));
+                    }
+                    if state.closed {
+                        return Ok(None);
+                    }
+                }
+                arrived.await;
+            }
+        })
+    }
+
+    fn ack<'a>(&'a self, _message: &'a QueueMessage) -> BoxFuture<'a, Result<(), GeminiError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn nack<'a>(&'a self, message: &'a QueueMessage) -> BoxFuture<'a, Result<(), GeminiError>> {
+        self.lock().messages.push_front(message.clone());
+        self.arrived.notify_waiters();
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl MessageSink for InMemoryQueue {
+    fn publish<'a>(&'a self, result: &'a QueueResult) -> BoxFuture<'a, Result<(), GeminiError>> {
+        self.lock().published.push(result.clone());
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::Simulator;
+
+    fn client() -> GeminiClient {
+        GeminiClient::new().simulator(
+            Simulator::new()
+                .rule(|prompt| Some(prompt.to_uppercase()))
+                .latency(Duration::from_secs(1)),
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_only_free_slots_are_received() {
+        let input = Arc::new(InMemoryQueue::new());
+        let output = Arc::new(InMemoryQueue::new());
+        for i in 0..5 {
+            input.push(
+                QueueMessage::new(format!("m{i}"), format!("q{i}")).attribute("n", i.to_string()),
+            );
+        }
+        input.close();
+        let ingestor = Ingestor::new(client(), input.clone(), output.clone()).max_in_flight(2);
+        let running = tokio::spawn(async move { ingestor.run().await });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(input.pending(), 3);
+
+        let report = running.await.unwrap().unwrap();
+        assert_eq!(
+            (report.received, report.succeeded, report.failed),
+            (5, 5, 0)
+        );
+        let mut published = output.published();
+        published.sort_by(|a, b| a.message_id.cmp(&b.message_id));
+        assert_eq!(published.len(), 5);
+        assert_eq!(published[3].response.as_deref(), Some("Q3"));
+        assert_eq!(published[3].attributes["n"], "3");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stop_drains_in_flight_messages() {
+        let input = Arc::new(InMemoryQueue::new());
+        let output = Arc::new(InMemoryQueue::new());
+        input.push(QueueMessage::new("m0", "q0"));
+        let stop = CancellationToken::new();
+        let ingestor = Ingestor::new(client(), input.clone(), output.clone())
+            .min_interval(Duration::from_secs(5))
+            .stop_on(stop.clone());
+        let running = tokio::spawn(async move { ingestor.run().await });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        stop.cancel();
+        let report = running.await.unwrap().unwrap();
+        assert_eq!(report.succeeded, 1);
+        assert_eq!(output.published()[0].response.as_deref(), Some("Q0"));
+    }
+}
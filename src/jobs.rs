@@ -0,0 +1,1046 @@
+//! Background execution.
+//!
+//! [`Gemini::spawn_detached`](crate::Gemini::spawn_detached) starts a request on the Tokio
+//! runtime and returns a [`JobHandle`] immediately. The handle is cheap to clone, so a web
+//! handler can store it (keyed by [`JobHandle::id`]) and let another endpoint poll
+//! [`status`](JobHandle::status), wait with [`await_result`](JobHandle::await_result), or
+//! [`cancel`](JobHandle::cancel) the run.
+//!
+//! For HTTP pollers, [`wait_with_timeout`](JobHandle::wait_with_timeout) long-polls for the
+//! job to finish, and [`events_since`](JobHandle::events_since) returns the [`JobEvent`]s
+//! recorded after a cursor, so each poll only transfers what is new. Every job records its
+//! status changes; jobs started with
+//! [`Gemini::spawn_detached_streaming`](crate::Gemini::spawn_detached_streaming) also record
+//! the model's stream events as they arrive.
+//!
+//! A [`JobQueue`] limits how many jobs run at once. Jobs are submitted with a [`Priority`]:
+//! interactive jobs outrank every queued batch job, and a batch limit keeps slots free for
+//! interactive work, so a user-facing request never waits behind a bulk backfill. Queue wait
+//! times are reported per priority class under [`metrics::QUEUE_WAIT_MS`].
+//! [`JobQueue::state`] reports running CLI processes, queue depth and cache hit rates for
+//! admin endpoints, and [`JobQueue::shutdown`] drains the queue for a clean rolling deploy.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::jobs::{JobQueue, Priority};
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() {
+//! let queue = JobQueue::new(4).batch_limit(3);
+//! for file in ["a.rs", "b.rs", "c.rs", "d.rs", "e.rs"] {
+//!     queue.submit(Gemini::new("Document this file").file(file), Priority::Batch);
+//! }
+//! // Runs as soon as a slot frees up, ahead of the queued batch jobs.
+//! let answer = queue.submit(Gemini::new("What does a.rs do?"), Priority::Interactive);
+//! let output = answer.await_result().await;
+//! # }
+//! ```
+
+use crate::cancel::CancellationToken;
+use crate::metrics::{self, MetricsSink};
+use crate::progress::ProgressTracker;
+use crate::{Gemini, GeminiError, GeminiJsonOutput, StreamEvent};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, watch, Notify};
+
+/// The lifecycle state of a background job.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    /// The job is waiting in a [`JobQueue`] for a free slot.
+    Queued,
+    /// The CLI is still running.
+    Running,
+    /// The run finished and produced a response.
+    Succeeded,
+    /// The run failed; `error` is the error message.
+    Failed { error: String },
+    /// The run was cancelled and the CLI killed.
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Whether the job has stopped, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+/// A unique identifier for a background job.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct JobId(String);
+
+impl JobId {
+    fn next() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        Self(format!("job-{}-{n}", crate::storage::now_millis()))
+    }
+
+    /// The identifier as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for JobId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Something that happened during a job, as returned by [`JobHandle::events_since`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JobEvent {
+    /// The event's position in the job's log, from 0. Pass `seq + 1` as the next cursor.
+    pub seq: u64,
+    /// Milliseconds since the job was started.
+    pub elapsed_ms: u64,
+    #[serde(flatten)]
+    pub kind: JobEventKind,
+}
+
+/// What a [`JobEvent`] reports.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", content = "event", rename_all = "snake_case")]
+pub enum JobEventKind {
+    /// The job's status changed.
+    Status(JobStatus),
+    /// The model streamed an event (streaming jobs only).
+    Stream(StreamEvent),
+}
+
+/// The events recorded after a cursor.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JobEvents {
+    pub events: Vec<JobEvent>,
+    /// The cursor to pass to the next [`events_since`](JobHandle::events_since) call.
+    pub next_cursor: u64,
+    /// Whether the job has finished, so no more events will follow.
+    pub finished: bool,
+}
+
+struct JobState {
+    status: watch::Receiver<JobStatus>,
+    result: Mutex<Option<Result<GeminiJsonOutput, GeminiError>>>,
+    cancel: Notify,
+    started: Instant,
+    events: Mutex<Vec<JobEvent>>,
+    /// The number of recorded events.
+    recorded: watch::Sender<u64>,
+}
+
+impl JobState {
+    fn record(&self, kind: JobEventKind) {
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        let seq = events.len() as u64;
+        events.push(JobEvent {
+            seq,
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            kind,
+        });
+        self.recorded.send_replace(seq + 1);
+    }
+
+    /// Record the status change and publish it to waiters.
+    fn set_status(&self, sender: &watch::Sender<JobStatus>, status: JobStatus) {
+        self.record(JobEventKind::Status(status.clone()));
+        let _ = sender.send(status);
+    }
+}
+
+/// A handle to a request running in the background.
+#[derive(Clone)]
+pub struct JobHandle {
+    id: JobId,
+    state: Arc<JobState>,
+    progress: Option<ProgressTracker>,
+}
+
+impl JobHandle {
+    pub(crate) fn spawn(request: Gemini, streaming: bool) -> Self {
+        Self::start(
+            JobId::next(),
+            request,
+            streaming,
+            JobStatus::Running,
+            async { Some(()) },
+        )
+    }
+
+    /// Spawn a job that runs once `admit` yields a guard, which is held until the run ends.
+    fn start<G, F>(
+        id: JobId,
+        mut request: Gemini,
+        streaming: bool,
+        initial: JobStatus,
+        admit: F,
+    ) -> Self
+    where
+        G: Send + 'static,
+        F: Future<Output = Option<G>> + Send + 'static,
+    {
+        if streaming && request.progress.is_none() {
+            request.progress = Some(ProgressTracker::default());
+        }
+        let progress = request.progress.clone();
+        let (status_tx, status_rx) = watch::channel(initial.clone());
+        let state = Arc::new(JobState {
+            status: status_rx,
+            result: Mutex::new(None),
+            cancel: Notify::new(),
+            started: Instant::now(),
+            events: Mutex::new(Vec::new()),
+            recorded: watch::Sender::new(0),
+        });
+        state.record(JobEventKind::Status(initial));
+
+        let task_state = state.clone();
+        tokio::spawn(async move {
+            let admitted = tokio::select! {
+                biased;
+                _ = task_state.cancel.notified() => {
+                    task_state.set_status(&status_tx, JobStatus::Cancelled);
+                    return;
+                }
+                guard = admit => guard,
+            };
+            let Some(guard) = admitted else {
+                let error = "the job queue is shut down".to_string();
+                *task_state.result.lock().unwrap_or_else(|e| e.into_inner()) =
+                    Some(Err(GeminiError::RuntimeError(error.clone())));
+                task_state.set_status(&status_tx, JobStatus::Failed { error });
+                return;
+            };
+            if *status_tx.borrow() != JobStatus::Running {
+                task_state.set_status(&status_tx, JobStatus::Running);
+            }
+
+            // Dropping the request future on cancel kills the CLI (unless it was detached).
+            let run = async {
+                if streaming {
+                    run_streaming(request, &task_state).await
+                } else {
+                    request.json().await
+                }
+            };
+            let status = tokio::select! {
+                result = run => {
+                    let status = match &result {
+                        Ok(_) => JobStatus::Succeeded,
+                        Err(e) => JobStatus::Failed { error: e.to_string() },
+                    };
+                    *task_state.result.lock().unwrap_or_else(|e| e.into_inner()) = Some(result);
+                    status
+                }
+                _ = task_state.cancel.notified() => JobStatus::Cancelled,
+            };
+            // Free the queue slot before waiters observe the final status.
+            drop(guard);
+            task_state.set_status(&status_tx, status);
+        });
+
+        Self {
+            id,
+            state,
+            progress,
+        }
+    }
+
+    /// The job's identifier.
+    pub fn id(&self) -> &JobId {
+        &self.id
+    }
+
+    /// The job's current status, without waiting.
+    pub fn status(&self) -> JobStatus {
+        self.state.status.borrow().clone()
+    }
+
+    /// The estimated fraction of the run completed, from `0.0` to `1.0`. See [`progress`].
+    ///
+    /// Streaming jobs are tracked even without [`Gemini::progress`]; with
+    /// [`Gemini::plan_events`] the estimate follows the agent's plan. Returns `None` for
+    /// non-streaming jobs without a tracker, and whenever the tracker has nothing to go by.
+    ///
+    /// [`progress`]: crate::progress
+    pub fn progress(&self) -> Option<f32> {
+        self.progress.as_ref()?.progress()
+    }
+
+    /// Request cancellation. Has no effect once the job has finished.
+    pub fn cancel(&self) {
+        self.state.cancel.notify_one();
+    }
+
+    /// Wait up to `timeout` for the job to finish, then return its status, finished or not.
+    ///
+    /// Suited to long-polling: an HTTP handler can hold the request open until the job is
+    /// done or the timeout passes, and the client polls again if it is still running.
+    pub async fn wait_with_timeout(&self, timeout: Duration) -> JobStatus {
+        let _ = tokio::time::timeout(timeout, self.finished()).await;
+        self.status()
+    }
+
+    /// The events recorded at or after `cursor`, without waiting. Start with 0 and pass each
+    /// response's `next_cursor` to the next call.
+    pub fn events_since(&self, cursor: u64) -> JobEvents {
+        // Read the status first: if the job had finished, its last event is already recorded.
+        let finished = self.status().is_finished();
+        let events = self.state.events.lock().unwrap_or_else(|e| e.into_inner());
+        let start = (cursor as usize).min(events.len());
+        JobEvents {
+            events: events[start..].to_vec(),
+            next_cursor: events.len().max(start) as u64,
+            finished,
+        }
+    }
+
+    /// Like [`events_since`](Self::events_since), but if there are no new events, wait up to
+    /// `timeout` for one.
+    pub async fn wait_for_events(&self, cursor: u64, timeout: Duration) -> JobEvents {
+        let mut recorded = self.state.recorded.subscribe();
+        let _ = tokio::time::timeout(timeout, recorded.wait_for(|&n| n > cursor)).await;
+        self.events_since(cursor)
+    }
+
+    /// Wait until the job has stopped, without taking its result.
+    async fn finished(&self) {
+        let mut status = self.state.status.clone();
+        let _ = status.wait_for(JobStatus::is_finished).await;
+    }
+
+    /// Wait for the job to finish and take its result.
+    ///
+    /// # Errors
+    ///
+    /// Returns the run's own error if it failed, and `GeminiError::RuntimeError` if it was
+    /// cancelled or its result was already taken through another clone of the handle.
+    pub async fn await_result(&self) -> Result<GeminiJsonOutput, GeminiError> {
+        let mut status = self.state.status.clone();
+        let finished = status
+            .wait_for(JobStatus::is_finished)
+            .await
+            .map(|s| s.clone())
+            .unwrap_or(JobStatus::Cancelled);
+        if finished == JobStatus::Cancelled {
+            return Err(GeminiError::RuntimeError(format!(
+                "job {} was cancelled",
+                self.id
+            )));
+        }
+        self.state
+            .result
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .unwrap_or_else(|| {
+                Err(GeminiError::RuntimeError(format!(
+                    "result of job {} was already taken",
+                    self.id
+                )))
+            })
+    }
+}
+
+/// Run `request` with `stream()`, recording every event, and assemble the output from them.
+async fn run_streaming(request: Gemini, state: &JobState) -> Result<GeminiJsonOutput, GeminiError> {
+    let labels = request.labels.clone();
+    let events = request.stream()?;
+    futures_util::pin_mut!(events);
+    let mut response = String::new();
+    let mut stats = None;
+    while let Some(event) = events.next().await {
+        let event = event?;
+        let mut failed = None;
+        match &event {
+            StreamEvent::Message { role, content, .. } if role != "user" => {
+                response.push_str(content);
+            }
+            StreamEvent::Result {
+                stats: reported, ..
+            } => {
+                stats = reported.typed().cloned();
+            }
+            StreamEvent::Error { message } => {
+                failed = Some(GeminiError::ApiError(message.clone()));
+            }
+            _ => {}
+        }
+        // Log the error event before failing, so the job's history shows what went wrong.
+        state.record(JobEventKind::Stream(event));
+        if let Some(error) = failed {
+            return Err(error);
+        }
+    }
+    Ok(GeminiJsonOutput {
+        response,
+        stats,
+        error: None,
+        routing: None,
+        budget: None,
+        injection_findings: Vec::new(),
+        citations: Vec::new(),
+        compression: None,
+        truncated: false,
+        labels,
+    })
+}
+
+impl fmt::Debug for JobHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobHandle")
+            .field("id", &self.id)
+            .field("status", &self.status())
+            .finish()
+    }
+}
+
+/// How urgently a queued job should run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Bulk work that can wait; runs only when no interactive job is queued.
+    Batch,
+    /// A user is waiting; runs ahead of every queued batch job.
+    Interactive,
+}
+
+impl Priority {
+    /// The label value used in metrics.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Batch => "batch",
+            Priority::Interactive => "interactive",
+        }
+    }
+}
+
+/// A concurrency-limited queue of background jobs with priority classes.
+///
+/// Within a class, jobs start in submission order. Cloning the queue shares its slots.
+#[derive(Clone)]
+pub struct JobQueue {
+    shared: Arc<Mutex<Inner>>,
+    monitor: Arc<Monitor>,
+    metrics: Option<Arc<dyn MetricsSink>>,
+}
+
+struct Inner {
+    concurrency: usize,
+    batch_limit: usize,
+    running: usize,
+    running_batch: usize,
+    interactive: VecDeque<Waiter>,
+    batch: VecDeque<Waiter>,
+    /// Unfinished jobs, queued or running.
+    jobs: Vec<JobHandle>,
+    closed: bool,
+}
+
+/// A point-in-time view of a [`JobQueue`], from [`JobQueue::state`].
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct QueueState {
+    /// Maximum jobs running at once.
+    pub concurrency: usize,
+    /// Jobs holding a slot.
+    pub running: usize,
+    /// Interactive jobs waiting for a slot.
+    pub queued_interactive: usize,
+    /// Batch jobs waiting for a slot.
+    pub queued_batch: usize,
+    /// Whether [`JobQueue::shutdown`] has been called.
+    pub shut_down: bool,
+    /// Live CLI processes started by the queue's jobs, oldest first.
+    pub processes: Vec<ProcessInfo>,
+    /// Cache lookups made by the queue's jobs.
+    pub cache: CacheStats,
+}
+
+/// A live CLI process.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProcessInfo {
+    /// The OS process id, if the process had not already exited when it was registered.
+    pub pid: Option<u32>,
+    /// The model passed to the CLI, if any.
+    pub model: Option<String>,
+    /// Time since the process was started.
+    pub age: Duration,
+}
+
+/// Cache hit and miss counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// The share of lookups served from the cache, or `None` before the first lookup.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self.hits + self.misses;
+        (total > 0).then(|| self.hits as f64 / total as f64)
+    }
+}
+
+#[derive(Debug)]
+struct LiveProcess {
+    pid: Option<u32>,
+    model: Option<String>,
+    started: Instant,
+}
+
+/// Tracks the processes and cache lookups of the requests run by a [`JobQueue`].
+#[derive(Debug, Default)]
+pub(crate) struct Monitor {
+    next: AtomicU64,
+    processes: Mutex<BTreeMap<u64, LiveProcess>>,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    /// Requests started and not yet finished.
+    in_flight: AtomicUsize,
+    /// Set by [`close`](Self::close); new requests are refused from then on.
+    closed: AtomicBool,
+    /// Cancels the requests still in flight when a shutdown's grace period runs out.
+    pub(crate) shutdown: CancellationToken,
+    /// Woken whenever a request finishes.
+    finished: Notify,
+}
+
+impl Monitor {
+    pub(crate) fn register(
+        self: &Arc<Self>,
+        pid: Option<u32>,
+        model: Option<String>,
+    ) -> Registration {
+        let key = self.next.fetch_add(1, Ordering::Relaxed);
+        self.processes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                key,
+                LiveProcess {
+                    pid,
+                    model,
+                    started: Instant::now(),
+                },
+            );
+        Registration {
+            monitor: self.clone(),
+            key,
+        }
+    }
+
+    /// Count a request as in flight until the ticket is dropped, or refuse it once the monitor
+    /// is closed.
+    pub(crate) fn begin(self: &Arc<Self>) -> Result<Ticket, GeminiError> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(GeminiError::RuntimeError("the client is shut down".into()));
+        }
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+        Ok(Ticket {
+            monitor: self.clone(),
+        })
+    }
+
+    /// Refuse every request that has not started yet.
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Wait until no request is in flight.
+    pub(crate) async fn idle(&self) {
+        loop {
+            let finished = self.finished.notified();
+            futures_util::pin_mut!(finished);
+            // Register before checking, so a request finishing in between still wakes us.
+            finished.as_mut().enable();
+            if self.in_flight() == 0 {
+                return;
+            }
+            finished.await;
+        }
+    }
+
+    pub(crate) fn record_cache(&self, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn processes(&self) -> Vec<ProcessInfo> {
+        self.processes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .map(|process| ProcessInfo {
+                pid: process.pid,
+                model: process.model.clone(),
+                age: process.started.elapsed(),
+            })
+            .collect()
+    }
+
+    pub(crate) fn cache(&self) -> CacheStats {
+        CacheStats {
+            hits: self.cache_hits.load(Ordering::Relaxed),
+            misses: self.cache_misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A live process's entry in a [`Monitor`]; dropping it removes the entry.
+pub(crate) struct Registration {
+    monitor: Arc<Monitor>,
+    key: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.monitor
+            .processes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.key);
+    }
+}
+
+/// A request's entry in a [`Monitor`]'s in-flight count; dropping it ends the request.
+pub(crate) struct Ticket {
+    monitor: Arc<Monitor>,
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        self.monitor.in_flight.fetch_sub(1, Ordering::AcqRel);
+        self.monitor.finished.notify_waiters();
+    }
+}
+
+/// The outcome of [`JobQueue::shutdown`] and
+/// [`GeminiClient::shutdown`](crate::client::GeminiClient::shutdown).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ShutdownSummary {
+    /// Running jobs (or client requests) that finished within the grace period.
+    pub drained: usize,
+    /// Jobs cancelled: every queued job, and running jobs still in flight after the grace period.
+    /// For a client, the requests still in flight after the grace period.
+    pub cancelled: usize,
+}
+
+/// A queued job waiting for a slot.
+struct Waiter {
+    job: JobId,
+    slot: oneshot::Sender<Slot>,
+}
+
+/// A running job's claim on a queue slot; dropping it frees the slot.
+struct Slot {
+    queue: Arc<Mutex<Inner>>,
+    priority: Priority,
+    /// Cleared when the slot was never handed to a job.
+    armed: bool,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let mut state = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        state.running -= 1;
+        if self.priority == Priority::Batch {
+            state.running_batch -= 1;
+        }
+        dispatch(&self.queue, &mut state);
+    }
+}
+
+/// Hand free slots to waiting jobs, interactive first.
+fn dispatch(queue: &Arc<Mutex<Inner>>, state: &mut Inner) {
+    while state.running < state.concurrency {
+        let (waiter, priority) = if let Some(waiter) = state.interactive.pop_front() {
+            (waiter, Priority::Interactive)
+        } else if state.running_batch < state.batch_limit {
+            match state.batch.pop_front() {
+                Some(waiter) => (waiter, Priority::Batch),
+                None => break,
+            }
+        } else {
+            break;
+        };
+        let slot = Slot {
+            queue: queue.clone(),
+            priority,
+            armed: true,
+        };
+        // The waiter is gone if its job was cancelled while queued.
+        if let Err(mut slot) = waiter.slot.send(slot) {
+            slot.armed = false;
+            continue;
+        }
+        state.running += 1;
+        if priority == Priority::Batch {
+            state.running_batch += 1;
+        }
+    }
+}
+
+impl JobQueue {
+    /// A queue running at most `concurrency` jobs at once (at least one).
+    pub fn new(concurrency: usize) -> Self {
+        let concurrency = concurrency.max(1);
+        Self {
+            shared: Arc::new(Mutex::new(Inner {
+                concurrency,
+                batch_limit: concurrency,
+                running: 0,
+                running_batch: 0,
+                interactive: VecDeque::new(),
+                batch: VecDeque::new(),
+                jobs: Vec::new(),
+                closed: false,
+            })),
+            monitor: Arc::new(Monitor::default()),
+            metrics: None,
+        }
+    }
+
+    /// Run at most `limit` batch jobs at once (at least one), keeping the remaining slots for
+    /// interactive jobs. Defaults to the full concurrency.
+    #[must_use]
+    pub fn batch_limit(self, limit: usize) -> Self {
+        self.lock().batch_limit = limit.max(1);
+        self
+    }
+
+    /// Report each job's queue wait time, labelled by priority, to `sink`.
+    #[must_use]
+    pub fn metrics(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(sink);
+        self
+    }
+
+    /// Queue `request` and return a handle to it. The job runs `json()` once a slot is free.
+    ///
+    /// After [`shutdown`](Self::shutdown) the job fails immediately with
+    /// `GeminiError::RuntimeError`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    pub fn submit(&self, mut request: Gemini, priority: Priority) -> JobHandle {
+        request.monitors.push(self.monitor.clone());
+        let id = JobId::next();
+        let admit = self.enqueue(id.clone(), priority);
+        let metrics = self.metrics.clone();
+        let labels = request.labels.clone();
+        let queued_at = Instant::now();
+        let handle = JobHandle::start(id, request, false, JobStatus::Queued, async move {
+            let slot = admit.await?;
+            if let Some(metrics) = metrics {
+                metrics.observe(
+                    metrics::QUEUE_WAIT_MS,
+                    queued_at.elapsed().as_secs_f64() * 1000.0,
+                    &metrics::with_labels(&[("priority", priority.as_str())], &labels),
+                );
+            }
+            Some(slot)
+        });
+        let mut state = self.lock();
+        state.jobs.retain(|job| !job.status().is_finished());
+        if !state.closed {
+            state.jobs.push(handle.clone());
+        }
+        handle
+    }
+
+    /// Stop accepting jobs, cancel the queued ones, and give running jobs up to `grace` to
+    /// finish before cancelling (and killing) them too.
+    ///
+    /// Clones of the queue share its state, so a shutdown through any clone closes them all.
+    pub async fn shutdown(&self, grace: Duration) -> ShutdownSummary {
+        let (queued, running) = {
+            let mut state = self.lock();
+            state.closed = true;
+            let state = &mut *state;
+            let waiting: Vec<Waiter> = state
+                .interactive
+                .drain(..)
+                .chain(state.batch.drain(..))
+                .collect();
+            let (queued, running): (Vec<JobHandle>, Vec<JobHandle>) =
+                std::mem::take(&mut state.jobs)
+                    .into_iter()
+                    .filter(|job| !job.status().is_finished())
+                    .partition(|job| waiting.iter().any(|w| w.job == job.id));
+            // Cancel before dropping the waiters, so the jobs end as cancelled, not failed.
+            for job in &queued {
+                job.cancel();
+            }
+            (queued, running)
+        };
+
+        let all_finished = futures_util::future::join_all(running.iter().map(JobHandle::finished));
+        let _ = tokio::time::timeout(grace, all_finished).await;
+        let overdue: Vec<&JobHandle> = running
+            .iter()
+            .filter(|job| !job.status().is_finished())
+            .collect();
+        for job in &overdue {
+            job.cancel();
+        }
+        futures_util::future::join_all(
+            queued
+                .iter()
+                .chain(overdue.iter().copied())
+                .map(JobHandle::finished),
+        )
+        .await;
+
+        ShutdownSummary {
+            drained: running.len() - overdue.len(),
+            cancelled: queued.len() + overdue.len(),
+        }
+    }
+
+    /// A snapshot of the queue's slots, waiting jobs, live CLI processes and cache use.
+    pub fn state(&self) -> QueueState {
+        let (concurrency, running, queued_interactive, queued_batch, shut_down) = {
+            let state = self.lock();
+            (
+                state.concurrency,
+                state.running,
+                state.interactive.len(),
+                state.batch.len(),
+                state.closed,
+            )
+        };
+        QueueState {
+            concurrency,
+            running,
+            queued_interactive,
+            queued_batch,
+            shut_down,
+            processes: self.monitor.processes(),
+            cache: self.monitor.cache(),
+        }
+    }
+
+    /// Whether [`shutdown`](Self::shutdown) has been called.
+    pub fn is_shut_down(&self) -> bool {
+        self.lock().closed
+    }
+
+    /// Jobs waiting for a slot.
+    pub fn queued(&self) -> usize {
+        let state = self.lock();
+        state.interactive.len() + state.batch.len()
+    }
+
+    /// Jobs holding a slot.
+    pub fn running(&self) -> usize {
+        self.lock().running
+    }
+
+    /// Join the queue now, so that submission order is kept, and wait for a slot.
+    fn enqueue(
+        &self,
+        job: JobId,
+        priority: Priority,
+    ) -> impl Future<Output = Option<Slot>> + Send + 'static {
+        let (tx, rx) = oneshot::channel();
+        let waiter = Waiter { job, slot: tx };
+        let queue = self.shared.clone();
+        {
+            let mut state = self.lock();
+            // A closed queue drops the waiter, which fails the wait.
+            if !state.closed {
+                match priority {
+                    Priority::Interactive => state.interactive.push_back(waiter),
+                    Priority::Batch => state.batch.push_back(waiter),
+                }
+                dispatch(&queue, &mut state);
+            }
+        }
+        async move {
+            // Keeps the queue alive while waiting.
+            let _queue = queue;
+            rx.await.ok()
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.shared.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for JobQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct("JobQueue")
+            .field("concurrency", &state.concurrency)
+            .field("batch_limit", &state.batch_limit)
+            .field("running", &state.running)
+            .field("queued", &(state.interactive.len() + state.batch.len()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_ids_are_unique() {
+        let a = JobId::next();
+        let b = JobId::next();
+        assert_ne!(a, b);
+        assert!(a.as_str().starts_with("job-"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_launch_is_reported() {
+        let job = Gemini::new("hi")
+            .bin_path("/nonexistent/gemini")
+            .spawn_detached();
+        assert!(matches!(
+            job.await_result().await,
+            Err(GeminiError::CliLaunchFailed(_))
+        ));
+        assert!(matches!(job.status(), JobStatus::Failed { .. }));
+        assert!(job.await_result().await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_events_can_be_polled_incrementally() {
+        let simulator = crate::simulate::Simulator::new()
+            .fallback("abcdef")
+            .latency(Duration::from_secs(1))
+            .chunks(3, Duration::from_secs(1));
+        let job = Gemini::new("hi")
+            .simulator(simulator)
+            .spawn_detached_streaming();
+
+        let first = job.events_since(0);
+        assert!(matches!(
+            first.events[..],
+            [JobEvent {
+                seq: 0,
+                kind: JobEventKind::Status(JobStatus::Running),
+                ..
+            }]
+        ));
+        assert!(!first.finished);
+        assert_eq!(
+            job.wait_with_timeout(Duration::from_millis(500)).await,
+            JobStatus::Running
+        );
+
+        let next = job
+            .wait_for_events(first.next_cursor, Duration::from_secs(5))
+            .await;
+        assert!(matches!(
+            next.events[0].kind,
+            JobEventKind::Stream(StreamEvent::Init { .. })
+        ));
+        assert_eq!(
+            job.wait_with_timeout(Duration::from_secs(10)).await,
+            JobStatus::Succeeded
+        );
+        let rest = job.events_since(next.next_cursor);
+        assert!(rest.finished);
+        assert!(matches!(
+            rest.events.last().map(|e| &e.kind),
+            Some(JobEventKind::Status(JobStatus::Succeeded))
+        ));
+        assert_eq!(job.events_since(rest.next_cursor).events.len(), 0);
+        assert_eq!(job.await_result().await.unwrap().response, "abcdef");
+        assert_eq!(job.progress(), Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn test_interactive_jobs_outrank_queued_batch_jobs() {
+        let queue = JobQueue::new(1);
+        let first = queue.enqueue(JobId::next(), Priority::Batch).await.unwrap();
+        let batch = queue.enqueue(JobId::next(), Priority::Batch);
+        let interactive = queue.enqueue(JobId::next(), Priority::Interactive);
+        assert_eq!((queue.running(), queue.queued()), (1, 2));
+
+        drop(first);
+        let slot = interactive.await.unwrap();
+        assert_eq!(slot.priority, Priority::Interactive);
+        assert_eq!(queue.queued(), 1);
+        drop(slot);
+        assert_eq!(batch.await.unwrap().priority, Priority::Batch);
+    }
+
+    #[tokio::test]
+    async fn test_zero_batch_limit_still_runs_batch_jobs() {
+        let queue = JobQueue::new(2).batch_limit(0);
+        let slot = queue.enqueue(JobId::next(), Priority::Batch).await;
+        assert!(slot.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_batch_limit_reserves_slots_and_cancelled_waiters_are_skipped() {
+        let queue = JobQueue::new(2).batch_limit(1);
+        let running = queue.enqueue(JobId::next(), Priority::Batch).await.unwrap();
+        let cancelled = queue.enqueue(JobId::next(), Priority::Batch);
+        let waiting = queue.enqueue(JobId::next(), Priority::Batch);
+        assert_eq!(queue.running(), 1);
+        let _interactive = queue
+            .enqueue(JobId::next(), Priority::Interactive)
+            .await
+            .unwrap();
+        assert_eq!(queue.running(), 2);
+
+        drop(cancelled);
+        drop(running);
+        let started = waiting.await;
+        assert!(started.is_some());
+        assert_eq!((queue.running(), queue.queued()), (2, 0));
+    }
+
+    #[test]
+    fn test_state_lists_live_processes_and_cache_use() {
+        let queue = JobQueue::new(1);
+        let registration = queue
+            .monitor
+            .register(Some(42), Some("gemini-2.5-flash".to_string()));
+        queue.monitor.record_cache(true);
+        queue.monitor.record_cache(false);
+        queue.monitor.record_cache(false);
+
+        let state = queue.state();
+        assert_eq!(state.processes.len(), 1);
+        assert_eq!(state.processes[0].pid, Some(42));
+        assert_eq!(
+            state.processes[0].model.as_deref(),
+            Some("gemini-2.5-flash")
+        );
+        assert_eq!(state.cache, CacheStats { hits: 1, misses: 2 });
+        assert_eq!(CacheStats::default().hit_rate(), None);
+        assert!((state.cache.hit_rate().unwrap() - 1.0 / 3.0).abs() < 1e-9);
+
+        drop(registration);
+        assert!(queue.state().processes.is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+//! Output language enforcement.
+//!
+//! [`Gemini::respond_in`](crate::Gemini::respond_in) adds an explicit language instruction to
+//! the prompt and checks the reply with [`detect`], a lightweight stopword- and script-based
+//! detector. If the reply is confidently in another language the request is retried once with
+//! a stricter instruction.
+
+use serde::{Deserialize, Serialize};
+
+/// A supported output language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    En,
+    De,
+    Fr,
+    Es,
+    It,
+    Pt,
+    Nl,
+    Ru,
+    Ja,
+    Zh,
+    Ko,
+    Ar,
+}
+
+/// Minimum number of stopword hits before a Latin-script text is attributed to a language.
+const MIN_STOPWORD_HITS: usize = 3;
+
+impl Language {
+    /// All supported languages.
+    pub const ALL: [Language; 12] = [
+        Language::En,
+        Language::De,
+        Language::Fr,
+        Language::Es,
+        Language::It,
+        Language::Pt,
+        Language::Nl,
+        Language::Ru,
+        Language::Ja,
+        Language::Zh,
+        Language::Ko,
+        Language::Ar,
+    ];
+
+    /// The ISO 639-1 code, e.g. `"de"`.
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::De => "de",
+            Language::Fr => "fr",
+            Language::Es => "es",
+            Language::It => "it",
+            Language::Pt => "pt",
+            Language::Nl => "nl",
+            Language::Ru => "ru",
+            Language::Ja => "ja",
+            Language::Zh => "zh",
+            Language::Ko => "ko",
+            Language::Ar => "ar",
+        }
+    }
+
+    /// The English name, e.g. `"German"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::En => "English",
+            Language::De => "German",
+            Language::Fr => "French",
+            Language::Es => "Spanish",
+            Language::It => "Italian",
+            Language::Pt => "Portuguese",
+            Language::Nl => "Dutch",
+            Language::Ru => "Russian",
+            Language::Ja => "Japanese",
+            Language::Zh => "Chinese",
+            Language::Ko => "Korean",
+            Language::Ar => "Arabic",
+        }
+    }
+
+    /// Look up a language by ISO 639-1 code.
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|l| l.code().eq_ignore_ascii_case(code))
+    }
+
+    /// The instruction appended to the prompt. `strict` is used for the retry.
+    pub fn instruction(self, strict: bool) -> String {
+        let base = format!(
+            "Respond only in {} ({}), regardless of the language of the question or any provided context. \
+             Keep code, identifiers and quoted material unchanged.",
+            self.name(),
+            self.code()
+        );
+        if strict {
+            format!(
+                "{base} Your previous answer was not in {}. Every sentence of your answer must be in {}.",
+                self.name(),
+                self.name()
+            )
+        } else {
+            base
+        }
+    }
+
+    /// Whether `text` is plausibly in this language.
+    ///
+    /// Returns `true` when the detector is not confident either way (e.g. very short replies).
+    pub fn matches(self, text: &str) -> bool {
+        detect(text).is_none_or(|detected| detected == self)
+    }
+
+    fn stopwords(self) -> &'static [&'static str] {
+        match self {
+            Language::En => &[
+                "the", "and", "is", "are", "of", "to", "in", "that", "it", "for", "with", "this",
+                "you", "not", "be", "was", "have", "on",
+            ],
+            Language::De => &[
+                "der", "die", "das", "und", "ist", "nicht", "ein", "eine", "zu", "den", "mit",
+                "sich", "auf", "für", "ich", "sie", "es", "dem", "sind", "auch",
+            ],
+            Language::Fr => &[
+                "le", "la", "les", "et", "est", "un", "une", "des", "du", "que", "pour", "dans",
+                "pas", "qui", "sur", "avec", "ce", "sont", "vous", "nous",
+            ],
+            Language::Es => &[
+                "el", "la", "los", "las", "y", "es", "un", "una", "que", "de", "del", "por",
+                "para", "con", "no", "se", "su", "como", "está", "son",
+            ],
+            Language::It => &[
+                "il", "lo", "la", "gli", "le", "e", "è", "un", "una", "che", "di", "per", "con",
+                "non", "sono", "della", "del", "come", "questo", "anche",
+            ],
+            Language::Pt => &[
+                "o", "a", "os", "as", "e", "é", "um", "uma", "que", "do", "da", "para", "com",
+                "não", "se", "são", "como", "mais", "por", "isso",
+            ],
+            Language::Nl => &[
+                "de", "het", "een", "en", "is", "van", "niet", "dat", "op", "te", "zijn", "met",
+                "voor", "ook", "maar", "er", "wordt", "naar", "ik", "je",
+            ],
+            Language::Ru | Language::Ja | Language::Zh | Language::Ko | Language::Ar => &[],
+        }
+    }
+}
+
+/// Detect the dominant language of `text`.
+///
+/// Non-Latin scripts are identified by their Unicode ranges; Latin-script languages by
+/// stopword frequency. Fenced code blocks are ignored. Returns `None` when there is not
+/// enough signal to decide.
+pub fn detect(text: &str) -> Option<Language> {
+    let prose = strip_code_blocks(text);
+
+    if let Some(language) = detect_script(&prose) {
+        return Some(language);
+    }
+
+    let words: Vec<String> = prose
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+
+    let mut scores: Vec<(Language, usize)> = Language::ALL
+        .into_iter()
+        .filter(|l| !l.stopwords().is_empty())
+        .map(|l| {
+            let stopwords = l.stopwords();
+            let hits = words
+                .iter()
+                .filter(|w| stopwords.contains(&w.as_str()))
+                .count();
+            (l, hits)
+        })
+        .collect();
+    scores.sort_by_key(|s| std::cmp::Reverse(s.1));
+
+    match scores.as_slice() {
+        [(best, best_hits), (_, runner_up), ..]
+            if *best_hits >= MIN_STOPWORD_HITS && best_hits > runner_up =>
+        {
+            Some(*best)
+        }
+        _ => None,
+    }
+}
+
+fn detect_script(text: &str) -> Option<Language> {
+    let (mut latin, mut cyrillic, mut kana, mut han, mut hangul, mut arabic) = (0, 0, 0, 0, 0, 0);
+    for c in text.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | 'À'..='ɏ' => latin += 1,
+            '\u{0400}'..='\u{04FF}' => cyrillic += 1,
+            '\u{3040}'..='\u{30FF}' => kana += 1,
+            '\u{4E00}'..='\u{9FFF}' => han += 1,
+            '\u{AC00}'..='\u{D7AF}' | '\u{1100}'..='\u{11FF}' => hangul += 1,
+            '\u{0600}'..='\u{06FF}' => arabic += 1,
+            _ => {}
+        }
+    }
+
+    let non_latin = cyrillic + kana + han + hangul + arabic;
+    if non_latin == 0 || non_latin < latin {
+        return None;
+    }
+    let language = if kana > 0 {
+        Language::Ja
+    } else if hangul >= han && hangul >= cyrillic && hangul >= arabic {
+        Language::Ko
+    } else if han >= cyrillic && han >= arabic {
+        Language::Zh
+    } else if cyrillic >= arabic {
+        Language::Ru
+    } else {
+        Language::Ar
+    };
+    Some(language)
+}
+
+fn strip_code_blocks(text: &str) -> String {
+    let mut in_fence = false;
+    text.lines()
+        .filter(|line| {
+            if line.trim_start().starts_with("```") {
+                in_fence = !in_fence;
+                return false;
+            }
+            !in_fence
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_latin_languages() {
+        assert_eq!(
+            detect("The cat is on the mat and it is happy with the sun."),
+            Some(Language::En)
+        );
+        assert_eq!(
+            detect("Die Katze ist auf der Matte und sie ist nicht müde."),
+            Some(Language::De)
+        );
+        assert_eq!(
+            detect("Le chat est sur le tapis et il est avec les enfants."),
+            Some(Language::Fr)
+        );
+    }
+
+    #[test]
+    fn test_detects_scripts_and_ignores_code() {
+        assert_eq!(detect("Привет, как дела?"), Some(Language::Ru));
+        assert_eq!(detect("これはテストです"), Some(Language::Ja));
+        assert_eq!(detect("안녕하세요 반갑습니다"), Some(Language::Ko));
+        assert_eq!(
+            detect("Die Antwort ist:\n```\nthe and is of to in that\n```\nDas ist nicht alles."),
+            Some(Language::De)
+        );
+    }
+
+    #[test]
+    fn test_short_text_is_inconclusive() {
+        assert_eq!(detect("42"), None);
+        assert!(Language::De.matches("OK"));
+        assert!(!Language::De.matches("This is the answer and it is in English."));
+        assert_eq!(Language::from_code("DE"), Some(Language::De));
+    }
+}
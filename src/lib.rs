@@ -118,13 +118,128 @@
 //! }
 //! ```
 
-use futures_util::stream::Stream;
+// Lets `#[derive(JsonSchema)]` refer to `::gemini_oxide` from inside this crate too.
+extern crate self as gemini_oxide;
+
+pub mod billing;
+pub mod bots;
+pub mod broadcast;
+pub mod budget;
+pub mod cancel;
+pub mod candidates;
+pub mod capabilities;
+pub mod cargo;
+pub mod chat;
+pub mod checkpoints;
+pub mod circuit;
+pub mod citations;
+pub mod cli;
+pub mod client;
+pub mod clock;
+pub mod compat;
+pub mod compression;
+pub mod diagnostics;
+pub mod fingerprint;
+pub mod framing;
+pub mod generation;
+pub mod ingest;
+pub mod injection;
+pub mod jobs;
+pub mod judge;
+pub mod language;
+pub mod markdown;
+pub mod mcp;
+pub mod metrics;
+pub mod moderation;
+pub mod pipeline;
+pub mod plan;
+pub mod policy;
+pub mod pool;
+pub mod postprocess;
+pub mod progress;
+pub mod proxy;
+pub mod qa;
+pub mod refusal;
+pub mod replay;
+pub mod report;
+pub mod retry;
+pub mod routing;
+pub mod safety;
+pub mod schedule;
+pub mod schema;
+pub mod session;
+pub mod simulate;
+pub mod spawn;
+pub mod storage;
+pub mod tasks;
+#[cfg(unix)]
+pub mod testing;
+pub mod transcript;
+pub mod truncation;
+pub mod verify;
+pub mod wire;
+pub mod workspace;
+
+mod settings;
+mod temp;
+
+use billing::{UsageEvent, UsageLedger};
+use budget::{Budget, BudgetDecision, BudgetPolicy};
+use cancel::CancellationToken;
+use candidates::Candidate;
+use circuit::CircuitBreaker;
+use citations::{CitationCheck, GroundingCitation, GroundingSource, PageFetcher};
+use clock::Clock;
+use compat::CliVersion;
+use compression::{Compression, CompressionReport};
+use diagnostics::{LaunchDiagnostics, LaunchFailure};
+use framing::{DefaultDetector, LanguageDetector};
+use futures_util::future::Either;
+use futures_util::stream::{Stream, StreamExt};
+use generation::GenerationConfig;
+use injection::{Finding, InjectionGuard};
+use jobs::JobHandle;
+use language::Language;
+use mcp::McpServerConfig;
+use metrics::MetricsSink;
+use moderation::Moderator;
+use plan::{PlanDetector, PlanStep, PlanStepStatus};
+use policy::{ApprovalMode, ToolApprover, ToolContext, ToolPolicy};
+use postprocess::ProcessorChain;
+use progress::ProgressTracker;
+use proxy::ProxyConfig;
+use refusal::{Outcome, RefusalDetector};
+use retry::RetryPolicy;
+use routing::{ModelRouter, PromptFeatures, RoutingDecision};
+use safety::SafetyLock;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use session::SessionId;
+use simulate::Simulator;
+#[cfg(unix)]
+use spawn::PreExecHook;
+use spawn::SpawnOptions;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use storage::CacheStore;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
+use tokio::sync::oneshot;
+use transcript::{Checkpointer, RunTranscript, StreamTimer};
+use truncation::Truncation;
+
+/// The capabilities of this build and of the `gemini` CLI on the `PATH`.
+///
+/// See [`capabilities::detect`] for a CLI elsewhere.
+pub async fn capabilities() -> capabilities::Capabilities {
+    capabilities::detect("gemini").await
+}
+
+/// A caller-supplied customization applied to every request a helper builds.
+pub(crate) type Configure = Box<dyn Fn(Gemini) -> Gemini + Send + Sync>;
 
 // =========================================================================
 //  1. The Builder (Ergonomic Interface)
@@ -133,15 +248,72 @@ use tokio::process::Command;
 /// The primary builder struct for constructing Gemini requests.
 ///
 /// Use `Gemini::new(prompt)` to start a request chain.
+#[derive(Clone)]
 pub struct Gemini {
     bin_path: PathBuf,
+    cli_version: Option<CliVersion>,
+    current_dir: Option<PathBuf>,
     prompt: String,
+    system: Option<String>,
+    memory: Vec<Memory>,
     input_data: Option<String>,
     input_files: Vec<PathBuf>,
+    context_file_over: Option<usize>,
+    sensitive_context: bool,
+    compression: Option<Compression>,
+    truncation: Option<Truncation>,
+    framing: Option<Arc<dyn LanguageDetector>>,
     model: Option<String>,
+    generation: GenerationConfig,
+    router: Option<ModelRouter>,
+    budget: Option<Budget>,
     include_dirs: Vec<String>,
-    yolo: bool,
+    all_files: bool,
+    allowed_tools: Vec<String>,
+    excluded_tools: Vec<String>,
+    safety_lock: Option<SafetyLock>,
+    mcp_servers: BTreeMap<String, McpServerConfig>,
+    extensions: Vec<String>,
+    approval: ApprovalMode,
+    checkpointing: bool,
+    resume: Option<SessionId>,
+    sandbox: bool,
+    sandbox_image: Option<String>,
     debug: bool,
+    debug_filter: Vec<String>,
+    detach_on_drop: bool,
+    timeout: Option<Duration>,
+    cancel: Option<CancellationToken>,
+    retry: Option<RetryPolicy>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    clock: Arc<dyn Clock>,
+    spawn_options: SpawnOptions,
+    proxy: Option<ProxyConfig>,
+    env: Vec<(String, String)>,
+    #[cfg(unix)]
+    pre_exec: Vec<PreExecHook>,
+    cache: Option<Arc<dyn CacheStore>>,
+    post_processors: ProcessorChain,
+    respond_in: Option<Language>,
+    language_retry: bool,
+    response_schema: Option<serde_json::Value>,
+    injection_guard: Option<InjectionGuard>,
+    moderator: Option<Arc<dyn Moderator>>,
+    citation_fetcher: Option<Arc<dyn PageFetcher>>,
+    refusal_detector: Option<RefusalDetector>,
+    tool_policy: Option<Arc<ToolPolicy>>,
+    tool_approver: Option<Arc<dyn ToolApprover>>,
+    metrics: Option<Arc<dyn MetricsSink>>,
+    usage_ledger: Option<Arc<UsageLedger>>,
+    labels: BTreeMap<String, String>,
+    heartbeat: Option<Duration>,
+    plan_events: bool,
+    progress: Option<ProgressTracker>,
+    transcript_checkpoint: Option<(PathBuf, Duration)>,
+    simulator: Option<Simulator>,
+    /// Set by a [`jobs::JobQueue`] or [`client::GeminiClient`] to track the request's processes
+    /// and cache use.
+    monitors: Vec<Arc<jobs::Monitor>>,
 }
 
 impl Gemini {
@@ -162,16 +334,120 @@ impl Gemini {
     pub fn new(prompt: impl Into<String>) -> Self {
         Self {
             bin_path: PathBuf::from("gemini"),
+            cli_version: None,
+            current_dir: None,
             prompt: prompt.into(),
+            system: None,
+            memory: Vec::new(),
             input_data: None,
             input_files: Vec::new(),
+            context_file_over: None,
+            sensitive_context: false,
+            compression: None,
+            truncation: None,
+            framing: None,
             model: None,
+            generation: GenerationConfig::default(),
+            router: None,
+            budget: None,
             include_dirs: Vec::new(),
-            yolo: false,
+            all_files: false,
+            allowed_tools: Vec::new(),
+            excluded_tools: Vec::new(),
+            safety_lock: None,
+            mcp_servers: BTreeMap::new(),
+            extensions: Vec::new(),
+            approval: ApprovalMode::Default,
+            checkpointing: false,
+            resume: None,
+            sandbox: false,
+            sandbox_image: None,
             debug: false,
+            debug_filter: Vec::new(),
+            detach_on_drop: false,
+            timeout: None,
+            retry: None,
+            circuit_breaker: None,
+            clock: clock::default_clock(),
+            cancel: None,
+            spawn_options: SpawnOptions::new(),
+            proxy: None,
+            env: Vec::new(),
+            #[cfg(unix)]
+            pre_exec: Vec::new(),
+            cache: None,
+            post_processors: ProcessorChain::new(),
+            respond_in: None,
+            language_retry: false,
+            response_schema: None,
+            injection_guard: None,
+            moderator: None,
+            citation_fetcher: None,
+            refusal_detector: None,
+            tool_policy: None,
+            tool_approver: None,
+            metrics: None,
+            usage_ledger: None,
+            labels: BTreeMap::new(),
+            heartbeat: None,
+            plan_events: false,
+            progress: None,
+            transcript_checkpoint: None,
+            simulator: None,
+            monitors: Vec::new(),
         }
     }
 
+    /// Set a system instruction: how the model should behave, separate from the prompt.
+    ///
+    /// The instruction is written to a temporary file passed to the CLI as its system prompt
+    /// (`GEMINI_SYSTEM_MD`), which replaces the CLI's built-in system prompt for this request.
+    /// The file is deleted when the run ends.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Why does my borrow checker error happen?")
+    ///     .system("You are a patient Rust mentor. Answer in at most three short paragraphs.");
+    /// ```
+    #[must_use]
+    pub fn system(mut self, instruction: impl Into<String>) -> Self {
+        self.system = Some(instruction.into());
+        self
+    }
+
+    /// Add project memory: persistent instructions the CLI loads like a `GEMINI.md` file,
+    /// alongside the project's own.
+    ///
+    /// Unlike [`system`](Self::system), memory keeps the CLI's built-in system prompt. The
+    /// memory of a request is written to a `GEMINI.md` in a temporary directory included in
+    /// the workspace (`--include-directories`, with the `loadMemoryFromIncludeDirectories`
+    /// setting), so the project itself is never modified. The file is deleted when the run
+    /// ends. Can be called multiple times; the pieces are joined in order.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Add a health check endpoint")
+    ///     .memory("This service uses axum 0.7. Never add new dependencies.")
+    ///     .memory_file("docs/CONVENTIONS.md");
+    /// ```
+    #[must_use]
+    pub fn memory(mut self, text: impl Into<String>) -> Self {
+        self.memory.push(Memory::Text(text.into()));
+        self
+    }
+
+    /// Add the contents of a file to the request's [`memory`](Self::memory). The file is read
+    /// when the CLI is launched.
+    #[must_use]
+    pub fn memory_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.memory.push(Memory::File(path.into()));
+        self
+    }
+
     /// Set the path to the `gemini` binary.
     ///
     /// Defaults to `"gemini"` (assuming it is in your system PATH).
@@ -182,6 +458,42 @@ impl Gemini {
         self
     }
 
+    /// Build the command line for this CLI version instead of the latest.
+    ///
+    /// See [`compat`] for the differences handled, and [`CliVersion::detect`] to ask the CLI.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::compat::CliVersion;
+    /// let req = Gemini::new("Hi").cli_version(CliVersion::new(0, 7, 2));
+    /// ```
+    #[must_use]
+    pub fn cli_version(mut self, version: CliVersion) -> Self {
+        self.cli_version = Some(version);
+        self
+    }
+
+    /// Run the CLI in `dir` instead of the current process's working directory.
+    ///
+    /// The agent's file tools and relative [`include`](Self::include) directories resolve
+    /// against this directory, so a server can point each request at its own workspace. Paths
+    /// given to [`file`](Self::file) are read by this process and stay relative to its own
+    /// working directory. See [`workspace::scratch`] for isolated per-run directories.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Fix the build").yolo().current_dir("/tmp/checkout");
+    /// ```
+    #[must_use]
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
     /// Select a specific Gemini model.
     ///
     /// If not specified, the CLI defaults to the currently active model (usually `gemini-2.0-flash` or similar).
@@ -198,6 +510,145 @@ impl Gemini {
         self
     }
 
+    /// Set the sampling temperature, from 0.0 (near-deterministic) to 2.0 (most creative).
+    ///
+    /// Values outside that range are clamped. See [`generation`] for how sampling parameters
+    /// reach the CLI.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Classify this ticket: ...").temperature(0.0);
+    /// ```
+    #[must_use]
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.generation.temperature = Some(temperature.clamp(0.0, 2.0));
+        self
+    }
+
+    /// Restrict sampling to the most likely tokens whose probabilities add up to `top_p`,
+    /// from 0.0 to 1.0. Values outside that range are clamped.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Write a haiku about Rust").top_p(0.9);
+    /// ```
+    #[must_use]
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.generation.top_p = Some(top_p.clamp(0.0, 1.0));
+        self
+    }
+
+    /// Restrict sampling to the `top_k` most likely tokens.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Write a haiku about Rust").top_k(40);
+    /// ```
+    #[must_use]
+    pub fn top_k(mut self, top_k: u32) -> Self {
+        self.generation.top_k = Some(top_k);
+        self
+    }
+
+    /// Cap the length of each response at `tokens` output tokens.
+    ///
+    /// The model stops mid-answer when it reaches the cap. [`GeminiJsonOutput::truncated`]
+    /// tells whether that happened, so you can ask it to continue.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Summarize the Rust book").max_output_tokens(256);
+    /// ```
+    #[must_use]
+    pub fn max_output_tokens(mut self, tokens: u32) -> Self {
+        self.generation.max_output_tokens = Some(tokens);
+        self
+    }
+
+    /// Let a [`ModelRouter`] pick the model based on the request's complexity.
+    ///
+    /// The router is only consulted when no explicit `.model(...)` was set. The decision
+    /// is recorded on [`GeminiJsonOutput::routing`] when using `.json()`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::routing::ModelRouter;
+    /// let req = Gemini::new("Summarize this log").router(ModelRouter::default());
+    /// ```
+    #[must_use]
+    pub fn router(mut self, router: ModelRouter) -> Self {
+        self.router = Some(router);
+        self
+    }
+
+    /// Plan the request to finish within `deadline`.
+    ///
+    /// Unless a model is pinned, a strong model that cannot produce a useful answer in time is
+    /// swapped for the fast one, and the answer length is capped to what fits. See [`budget`];
+    /// the decision is recorded on [`GeminiJsonOutput::budget`]. The request is not cancelled
+    /// if it overruns.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use std::time::Duration;
+    ///
+    /// let req = Gemini::new("Summarize this ticket").deadline(Duration::from_secs(8));
+    /// ```
+    #[must_use]
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.budget.get_or_insert_with(Budget::default).deadline = Some(deadline);
+        self
+    }
+
+    /// Limit the total tokens (prompt and context plus answer) the request should use.
+    ///
+    /// The answer length is capped to what is left after the estimated input size. Combine with
+    /// [`deadline`](Self::deadline) to plan for both.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Summarize this ticket").token_budget(4_000);
+    /// ```
+    #[must_use]
+    pub fn token_budget(mut self, tokens: u64) -> Self {
+        self.budget.get_or_insert_with(Budget::default).token_budget = Some(tokens);
+        self
+    }
+
+    /// Set the model profiles and thresholds used by [`deadline`](Self::deadline) and
+    /// [`token_budget`](Self::token_budget).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::budget::BudgetPolicy;
+    /// use std::time::Duration;
+    ///
+    /// let req = Gemini::new("Hi")
+    ///     .deadline(Duration::from_secs(3))
+    ///     .budget_policy(BudgetPolicy { min_output_tokens: 64, ..BudgetPolicy::default() });
+    /// ```
+    #[must_use]
+    pub fn budget_policy(mut self, policy: BudgetPolicy) -> Self {
+        self.budget.get_or_insert_with(Budget::default).policy = policy;
+        self
+    }
+
     /// Pipe raw text context (code, logs, data) directly into the model's standard input.
     ///
     /// This simulates running `echo "data" | gemini ...` in the shell.
@@ -207,6 +658,56 @@ impl Gemini {
         self
     }
 
+    /// Pass [`context`](Self::context) larger than `bytes` as an `@file` reference to a
+    /// temporary file instead of piping it through standard input.
+    ///
+    /// The file is readable only by the current user, sits in its own directory (added to the
+    /// [included directories](Self::include) so the CLI may read it) and is deleted when the
+    /// run ends. Context screened by an [`InjectionGuard`] or capped by
+    /// [`truncate_input`](Self::truncate_input) is always piped, as is context marked
+    /// [sensitive](Self::sensitive_context).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let log = "GET /health 200\n".repeat(100_000);
+    /// let req = Gemini::new("Summarize the traffic").context(log).context_file_over(1 << 20);
+    /// ```
+    #[must_use]
+    pub fn context_file_over(mut self, bytes: usize) -> Self {
+        self.context_file_over = Some(bytes);
+        self
+    }
+
+    /// Never write the [`context`](Self::context) to disk, whatever
+    /// [`context_file_over`](Self::context_file_over) says.
+    #[must_use]
+    pub fn sensitive_context(mut self) -> Self {
+        self.sensitive_context = true;
+        self
+    }
+
+    /// Compress the prompt and piped [`context`](Self::context) before sending them.
+    ///
+    /// See [`compression`] for the passes. The estimated tokens saved are reported on
+    /// [`GeminiJsonOutput::compression`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::compression::Compression;
+    /// let req = Gemini::new("Summarize the errors")
+    ///     .context("ERROR   disk full\nERROR   disk full\n==========")
+    ///     .compress(Compression::new());
+    /// ```
+    #[must_use]
+    pub fn compress(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
     /// Read a file from disk and pipe its content into the model's standard input.
     ///
     /// Can be called multiple times to include multiple files.
@@ -222,6 +723,71 @@ impl Gemini {
         self
     }
 
+    /// Cap the piped [`context`](Self::context) and [`file`](Self::file)s at a token budget,
+    /// keeping what the [`Truncation`]'s strategy prefers.
+    ///
+    /// See [`truncation`] for the strategies.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::truncation::Truncation;
+    /// let req = Gemini::new("What went wrong at the end of this run?")
+    ///     .file("server.log")
+    ///     .truncate_input(Truncation::keep_last(16_000));
+    /// ```
+    #[must_use]
+    pub fn truncate_input(mut self, truncation: Truncation) -> Self {
+        self.truncation = Some(truncation);
+        self
+    }
+
+    /// Pipe each [`file`](Self::file) as a fenced code block under a `File:` line, tagged with
+    /// its language, instead of as raw bytes.
+    ///
+    /// Languages come from [`DefaultDetector`]; see [`frame_files_with`](Self::frame_files_with)
+    /// to detect them differently.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Review this change")
+    ///     .file("src/parser.rs")
+    ///     .file("scripts/release")
+    ///     .frame_files();
+    /// ```
+    #[must_use]
+    pub fn frame_files(self) -> Self {
+        self.frame_files_with(Arc::new(DefaultDetector))
+    }
+
+    /// Like [`frame_files`](Self::frame_files), tagging each file with the language `detector`
+    /// finds.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::framing::{DefaultDetector, LanguageDetector};
+    /// use std::path::Path;
+    /// use std::sync::Arc;
+    ///
+    /// let detector = |path: &Path, content: &str| match path.extension() {
+    ///     Some(ext) if ext == "tpl" => Some("handlebars".to_string()),
+    ///     _ => DefaultDetector.detect(path, content),
+    /// };
+    /// let req = Gemini::new("Why does this template render blank?")
+    ///     .file("views/index.tpl")
+    ///     .frame_files_with(Arc::new(detector));
+    /// ```
+    #[must_use]
+    pub fn frame_files_with(mut self, detector: Arc<dyn LanguageDetector>) -> Self {
+        self.framing = Some(detector);
+        self
+    }
+
     /// Include a directory in the analysis workspace.
     ///
     /// This maps to the `--include-directories` flag of the CLI.
@@ -231,170 +797,2482 @@ impl Gemini {
         self
     }
 
+    /// Load every file in the working directory into the context (`--all-files`), for
+    /// whole-repository analysis without listing directories.
+    ///
+    /// The CLI still skips files ignored by `.gitignore` and `.geminiignore`. Large
+    /// repositories can exceed the model's context window; narrow them with
+    /// [`current_dir`](Self::current_dir) or a `.geminiignore`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Map the module dependencies").current_dir("service").all_files();
+    /// ```
+    #[must_use]
+    pub fn all_files(mut self) -> Self {
+        self.all_files = true;
+        self
+    }
+
     /// Enable "YOLO" mode (You Only Live Once).
     ///
     /// When enabled, the agent will automatically approve all tool use actions (like file edits or shell commands)
     /// without asking for user confirmation. Use with caution.
+    ///
+    /// Shorthand for `.approval(ApprovalMode::Yolo)`.
     #[must_use]
-    pub fn yolo(mut self) -> Self {
-        self.yolo = true;
-        self
+    pub fn yolo(self) -> Self {
+        self.approval(ApprovalMode::Yolo)
     }
 
-    /// Enable debug mode.
+    /// Choose which tool calls the agent may make without confirmation.
     ///
-    /// Passes the `--debug` flag to the CLI, causing it to emit verbose logs to stderr.
+    /// [`ApprovalMode::AutoEdit`] lets the agent edit files while shell commands still need
+    /// approval.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::policy::ApprovalMode;
+    /// let req = Gemini::new("Add doc comments to src/lib.rs").approval(ApprovalMode::AutoEdit);
+    /// ```
     #[must_use]
-    pub fn debug(mut self) -> Self {
-        self.debug = true;
+    pub fn approval(mut self, mode: ApprovalMode) -> Self {
+        self.approval = mode;
         self
     }
 
-    // =====================================================================
-    //  2. Execution Methods
-    // =====================================================================
-
-    /// Execute the request and return the raw text response.
+    /// Restrict the agent to these tools and run them without confirmation.
     ///
-    /// This method waits for the process to complete and returns the standard output as a String.
+    /// The tools are passed as `--allowed-tools`, which skips their confirmation, and as the
+    /// CLI's `tools.core` setting, which makes every other built-in tool unavailable. A
+    /// read-only list thus allows an unattended run without [`yolo`](Self::yolo). Shell
+    /// commands can be narrowed down as `run_shell_command(git status)`.
     ///
-    /// # Errors
+    /// # Example
     ///
-    /// Returns `GeminiError` if the CLI fails to start, exits with a non-zero code, or prints to stderr.
-    pub async fn text(self) -> Result<String, GeminiError> {
-        let output = self.execute_process("text").await?;
-        Ok(String::from_utf8_lossy(&output).trim().to_string())
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Find where the config is parsed")
+    ///     .allow_tools(["read_file", "read_many_files", "glob", "search_file_content"]);
+    /// ```
+    #[must_use]
+    pub fn allow_tools<I>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.allowed_tools.extend(tools.into_iter().map(Into::into));
+        self
     }
 
-    /// Execute the request and return a structured JSON response.
+    /// Make these tools unavailable to the agent, whatever the [approval mode](Self::approval).
     ///
-    /// This parses the output into `GeminiJsonOutput`, which contains the response text,
-    /// token usage statistics, and detailed tool usage metrics.
+    /// The tools are passed as the CLI's `tools.exclude` setting, so the model is never offered
+    /// them, even with [`yolo`](Self::yolo) or when they are also
+    /// [allowed](Self::allow_tools).
     ///
-    /// # Errors
+    /// # Example
     ///
-    /// Returns `GeminiError::JsonParseFailed` if the CLI output is not valid JSON.
-    pub async fn json(self) -> Result<GeminiJsonOutput, GeminiError> {
-        let output = self.execute_process("json").await?;
-        let parsed: GeminiJsonOutput =
-            serde_json::from_slice(&output).map_err(GeminiError::JsonParseFailed)?;
-
-        if let Some(err) = parsed.error {
-            return Err(GeminiError::ApiError(err.message));
-        }
-
-        Ok(parsed)
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Tidy up the README")
+    ///     .yolo()
+    ///     .exclude_tools(["run_shell_command", "web_fetch"]);
+    /// ```
+    #[must_use]
+    pub fn exclude_tools<I>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.excluded_tools
+            .extend(tools.into_iter().map(Into::into));
+        self
     }
 
-    /// Execute the request and return a real-time stream of events.
+    /// Refuse to run this request in [`yolo`](Self::yolo) mode if it could edit files outside
+    /// the lock's directories or run shell commands; it then fails with
+    /// `GeminiError::PolicyDenied` before the CLI is launched.
+    ///
+    /// Usually set for every request with
+    /// [`GeminiClient::safety_lock`](client::GeminiClient::safety_lock). See [`safety`] for
+    /// the rules.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::safety::SafetyLock;
+    /// let req = Gemini::new("Fix the lint warnings")
+    ///     .safety_lock(SafetyLock::new().allow_writes_in("/srv/sandboxes"))
+    ///     .current_dir("/srv/sandboxes/17")
+    ///     .yolo()
+    ///     .exclude_tools(["run_shell_command"]);
+    /// ```
+    #[must_use]
+    pub fn safety_lock(mut self, lock: SafetyLock) -> Self {
+        self.safety_lock = Some(lock);
+        self
+    }
+
+    /// Run this request without its [`safety_lock`](Self::safety_lock), for the rare
+    /// unattended run that needs the shell or edits elsewhere.
+    #[must_use]
+    pub fn override_safety_lock(mut self) -> Self {
+        self.safety_lock = None;
+        self
+    }
+
+    /// Make the MCP server `name` available to the agent for this request.
+    ///
+    /// The server is written to the CLI's temporary settings (see [`mcp`]); registering the
+    /// same name again replaces it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::mcp::McpServerConfig;
+    ///
+    /// let req = Gemini::new("List my open tickets")
+    ///     .mcp_server("tickets", McpServerConfig::stdio("ticket-mcp").arg("--read-only"));
+    /// ```
+    #[must_use]
+    pub fn mcp_server(mut self, name: impl Into<String>, config: McpServerConfig) -> Self {
+        self.mcp_servers.insert(name.into(), config);
+        self
+    }
+
+    /// Load only these installed CLI extensions for this request (`--extensions`).
+    ///
+    /// By default the CLI loads every enabled extension; pass `["none"]` to load none. Manage
+    /// the installed extensions with [`cli::GeminiCli`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Audit the migrations").extensions(["security", "db"]);
+    /// ```
+    #[must_use]
+    pub fn extensions<I>(mut self, names: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.extensions.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// Give the agent the tools of an in-process [`mcp::server::McpServer`].
+    ///
+    /// Registers the running server under its name, with its tools trusted. Only available
+    /// with the `mcp-server` feature.
+    #[cfg(feature = "mcp-server")]
+    #[must_use]
+    pub fn mcp_tools(self, server: &mcp::server::RunningMcpServer) -> Self {
+        self.mcp_server(server.name(), server.config())
+    }
+
+    /// Snapshot the project before every file edit the agent makes (`--checkpointing`), so the
+    /// edits can be rolled back with [`checkpoints::Checkpoints`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Upgrade the dependencies").yolo().checkpointing();
+    /// ```
+    #[must_use]
+    pub fn checkpointing(mut self) -> Self {
+        self.checkpointing = true;
+        self
+    }
+
+    /// Continue a conversation the CLI saved (`--resume`), with its full prior context.
+    ///
+    /// Take the ID from a stream's `Init` event with [`StreamEvent::session_id`], or resume
+    /// the most recent session with [`SessionId::latest`]. Sessions are saved per project, so
+    /// run the follow-up in the same [`current_dir`](Self::current_dir).
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use gemini_oxide::{Gemini, StreamEvent};
+    /// # use futures_util::StreamExt;
+    /// # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+    /// let mut session = None;
+    /// let mut stream = Box::pin(Gemini::new("Read src/lib.rs and list its modules").stream()?);
+    /// while let Some(event) = stream.next().await {
+    ///     session = session.or(event?.session_id());
+    /// }
+    /// if let Some(session) = session {
+    ///     let answer = Gemini::new("Which of them has no tests?").resume(session).text().await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn resume(mut self, session: impl Into<SessionId>) -> Self {
+        self.resume = Some(session.into());
+        self
+    }
+
+    /// Run the agent's tools inside the CLI's sandbox (`--sandbox`), a container or macOS
+    /// Seatbelt profile, so file edits and shell commands cannot touch the host.
+    ///
+    /// Combine with [`yolo`](Self::yolo) for unattended runs.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Run the test suite and fix failures").yolo().sandbox();
+    /// ```
+    #[must_use]
+    pub fn sandbox(mut self) -> Self {
+        self.sandbox = true;
+        self
+    }
+
+    /// Run the agent's tools in a sandbox built from this container image (`--sandbox-image`).
+    ///
+    /// Implies [`sandbox`](Self::sandbox).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Build the project")
+    ///     .yolo()
+    ///     .sandbox_image("ghcr.io/example/rust-sandbox:1.80");
+    /// ```
+    #[must_use]
+    pub fn sandbox_image(mut self, image: impl Into<String>) -> Self {
+        self.sandbox = true;
+        self.sandbox_image = Some(image.into());
+        self
+    }
+
+    /// Enable debug mode.
+    ///
+    /// Passes the `--debug` flag to the CLI, causing it to emit verbose logs to stderr.
+    #[must_use]
+    pub fn debug(mut self) -> Self {
+        self.debug = true;
+        self
+    }
+
+    /// Enable debug mode for the given categories only (e.g. `"tools"`, `"api"`).
+    ///
+    /// Passes `--debug` along with the categories as the CLI's `DEBUG` filter, so forwarding
+    /// the CLI's stderr to logs stays manageable. Calling it again adds categories.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Fix the failing test").debug_filter(["tools", "api"]);
+    /// ```
+    #[must_use]
+    pub fn debug_filter<I, S>(mut self, categories: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.debug = true;
+        self.debug_filter
+            .extend(categories.into_iter().map(Into::into));
+        self
+    }
+
+    /// Let the CLI run to completion even if the execution future or stream is dropped.
+    ///
+    /// By default, dropping a `text()`/`json()` future or a `stream()` (for example when an
+    /// HTTP request is aborted or a `tokio::time::timeout` fires) kills the CLI process, which is
+    /// then reaped in the background. Use this for fire-and-forget runs whose side effects
+    /// (e.g. file edits under `yolo()`) should finish regardless.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Update the changelog").yolo().detach_on_drop();
+    /// ```
+    #[must_use]
+    pub fn detach_on_drop(self) -> Self {
+        self.kill_on_drop(false)
+    }
+
+    /// Whether dropping the execution future or stream kills the CLI. Defaults to `true`;
+    /// `kill_on_drop(false)` is [`detach_on_drop`](Self::detach_on_drop).
+    ///
+    /// If the CLI runs in its own process group
+    /// ([`SpawnOptions::new_process_group`](spawn::SpawnOptions::new_process_group)), the whole
+    /// group is killed, so tool subprocesses started by the agent do not outlive it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::spawn::SpawnOptions;
+    ///
+    /// let req = Gemini::new("Run the test suite and fix failures")
+    ///     .yolo()
+    ///     .spawn_options(SpawnOptions::new().new_process_group())
+    ///     .kill_on_drop(true);
+    /// ```
+    #[must_use]
+    pub fn kill_on_drop(mut self, kill: bool) -> Self {
+        self.detach_on_drop = !kill;
+        self
+    }
+
+    /// Kill the CLI and fail with `GeminiError::Timeout` if it runs longer than `limit`.
+    ///
+    /// The limit applies to each CLI process: in `text()`/`json()` from launch until the
+    /// process exits, in `stream()` from launch until the stream ends. The process is killed on
+    /// expiry even with [`detach_on_drop`](Self::detach_on_drop).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use std::time::Duration;
+    ///
+    /// let req = Gemini::new("Summarize the changelog").timeout(Duration::from_secs(60));
+    /// ```
+    #[must_use]
+    pub fn timeout(mut self, limit: Duration) -> Self {
+        self.timeout = Some(limit);
+        self
+    }
+
+    /// Retry transient failures — quota and rate-limit errors, reset connections, failed
+    /// launches — with exponential backoff and jitter.
+    ///
+    /// Applies to `text()` and `json()`; each attempt gets its own [`timeout`](Self::timeout),
+    /// and cancelling the request also ends the wait between attempts. If every attempt fails,
+    /// the error is `GeminiError::RetriesExhausted`. See [`retry`] for which errors are retried.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::retry::RetryPolicy;
+    ///
+    /// let req = Gemini::new("Summarize the changelog").retry(RetryPolicy::new().max_attempts(4));
+    /// ```
+    #[must_use]
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Stop launching the CLI while `breaker` is open, and report each run's outcome to it.
+    ///
+    /// While the circuit is open, `text()`, `json()` and `stream()` fail at once with
+    /// `GeminiError::CircuitOpen`, which is not retried. Share one breaker between the requests
+    /// that use the same backend. See [`circuit`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::circuit::CircuitBreaker;
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let breaker = Arc::new(CircuitBreaker::new(5, Duration::from_secs(30)));
+    /// let req = Gemini::new("Summarize the changelog").circuit_breaker(breaker);
+    /// ```
+    #[must_use]
+    pub fn circuit_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.circuit_breaker = Some(breaker);
+        self
+    }
+
+    /// Measure and wait on time with `clock`: the [`timeout`](Self::timeout), the backoff
+    /// between [`retry`](Self::retry) attempts, [`heartbeat`](Self::heartbeat)s and
+    /// [`schedule`](Self::schedule)d runs.
+    ///
+    /// Defaults to [`TokioClock`](clock::TokioClock), which already follows
+    /// `tokio::time::pause()` in tests; see [`clock`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::clock::TokioClock;
+    /// use std::sync::Arc;
+    ///
+    /// let req = Gemini::new("Summarize the changelog").clock(Arc::new(TokioClock));
+    /// ```
+    #[must_use]
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Stop the request when `token` is cancelled.
+    ///
+    /// On cancellation the CLI is killed (even with [`detach_on_drop`](Self::detach_on_drop))
+    /// and `text()`, `json()` or the stream fail with `GeminiError::Cancelled`. A request whose
+    /// token is already cancelled fails without launching the CLI. One token can be shared by
+    /// many requests.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::cancel::CancellationToken;
+    ///
+    /// let token = CancellationToken::new();
+    /// let req = Gemini::new("Refactor the crate").cancel_on(token.clone());
+    /// // Later: token.cancel();
+    /// ```
+    #[must_use]
+    pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Route the CLI's traffic through `config` instead of the [system proxy](proxy::system).
+    ///
+    /// A URL proxies both HTTP and HTTPS traffic; [`ProxyConfig::new()`](ProxyConfig::new)
+    /// forces direct connections. The settings only apply to this request's CLI process, not
+    /// to the application's environment.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::proxy::ProxyConfig;
+    ///
+    /// let req = Gemini::new("Check the release notes").proxy("http://proxy.corp.example:3128");
+    /// let req = Gemini::new("Check the release notes")
+    ///     .proxy(ProxyConfig::new().https("http://proxy.corp.example:3128").bypass("localhost"));
+    /// ```
+    #[must_use]
+    pub fn proxy(mut self, config: impl Into<ProxyConfig>) -> Self {
+        self.proxy = Some(config.into());
+        self
+    }
+
+    /// Set an environment variable for the CLI process only, e.g. `GEMINI_API_KEY`.
+    ///
+    /// The variable is added to the environment the CLI inherits from this program, which is
+    /// left unchanged. It overrides inherited variables and proxy settings of the same name.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Hi")
+    ///     .env("GOOGLE_CLOUD_PROJECT", "my-project")
+    ///     .env("GOOGLE_CLOUD_LOCATION", "europe-west4");
+    /// ```
+    #[must_use]
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Set several environment variables for the CLI process. See [`env`](Self::env).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let key = std::env::var("TEAM_GEMINI_KEY").unwrap_or_default();
+    /// let req = Gemini::new("Hi").envs([("GEMINI_API_KEY", key)]);
+    /// ```
+    #[must_use]
+    pub fn envs<K, V>(mut self, vars: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env.extend(
+            vars.into_iter()
+                .map(|(key, value)| (key.into(), value.into())),
+        );
+        self
+    }
+
+    /// Launch the CLI with hardened process settings (user, group, process group).
+    ///
+    /// See [`spawn::SpawnOptions`]; they have no effect on platforms other than Unix.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::spawn::SpawnOptions;
+    ///
+    /// let req = Gemini::new("Review the diff")
+    ///     .spawn_options(SpawnOptions::new().user(65534).new_process_group());
+    /// ```
+    #[must_use]
+    pub fn spawn_options(mut self, options: SpawnOptions) -> Self {
+        self.spawn_options = options;
+        self
+    }
+
+    /// Run `hook` in the CLI process after `fork` and before `exec`, e.g. to enter namespaces,
+    /// install a seccomp filter or drop privileges. Hooks run in registration order, after the
+    /// [`spawn_options`](Self::spawn_options) are applied; an error aborts the launch with
+    /// `GeminiError::CliLaunchFailed`.
+    ///
+    /// # Safety
+    ///
+    /// The same contract as [`std::os::unix::process::CommandExt::pre_exec`]: the hook runs in
+    /// a forked copy of a possibly multi-threaded process, so it must only make
+    /// async-signal-safe calls — no allocation, no locks, no panics.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// // SAFETY: the hook does nothing.
+    /// let req = unsafe { Gemini::new("Review the diff").pre_exec(|| Ok(())) };
+    /// ```
+    #[cfg(unix)]
+    #[must_use]
+    pub unsafe fn pre_exec(
+        mut self,
+        hook: impl Fn() -> std::io::Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.pre_exec.push(Arc::new(hook));
+        self
+    }
+
+    /// Serve repeated identical requests from a [`CacheStore`].
+    ///
+    /// `text()` and `json()` derive a key from the prompt, context, file contents and options,
+    /// return a cached response if one exists, and cache successful responses otherwise.
+    /// Streams are never cached, and neither are requests with an
+    /// [`injection_guard`](Self::injection_guard), whose input must be inspected on every run.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::storage::InMemoryCacheStore;
+    /// use std::sync::Arc;
+    ///
+    /// let cache = Arc::new(InMemoryCacheStore::new());
+    /// let req = Gemini::new("Define entropy").cache(cache.clone());
+    /// ```
+    #[must_use]
+    pub fn cache(mut self, store: Arc<dyn CacheStore>) -> Self {
+        self.cache = Some(store);
+        self
+    }
+
+    /// Transform the response with a [`ProcessorChain`] before it is returned.
+    ///
+    /// Applies to the text of `text()`, the `response` field of `json()`, and complete
+    /// (non-delta) `Message` events of `stream()`.
+    ///
+    /// Delta `Message` events are fragments of the answer and are streamed on unprocessed:
+    /// processors such as [`StripMarkdown`](postprocess::StripMarkdown) need the whole text.
+    /// To post-process a streamed answer, collect its deltas and pass the text to
+    /// [`ProcessorChain::run`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::postprocess::ProcessorChain;
+    ///
+    /// let req = Gemini::new("List three fruits").post_process(
+    ///     ProcessorChain::new()
+    ///         .strip_markdown()
+    ///         .normalize_whitespace()
+    ///         .map(|text| text.to_lowercase()),
+    /// );
+    /// ```
+    #[must_use]
+    pub fn post_process(mut self, chain: ProcessorChain) -> Self {
+        self.post_processors = chain;
+        self
+    }
+
+    /// Require the response to be written in `language`.
+    ///
+    /// Appends an explicit language instruction to the prompt and verifies the reply with
+    /// [`language::detect`]. If the reply is confidently in another language, the request is
+    /// retried once with a stricter instruction; if that also fails, `text()` and `json()`
+    /// return `GeminiError::ValidationFailed`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::language::Language;
+    /// let req = Gemini::new("Explain photosynthesis").respond_in(Language::De);
+    /// ```
+    #[must_use]
+    pub fn respond_in(mut self, language: Language) -> Self {
+        self.respond_in = Some(language);
+        self
+    }
+
+    /// Screen piped context (`.context()` and `.file()`) for prompt-injection attempts.
+    ///
+    /// All piped input is read and screened before anything is written to the CLI. Findings
+    /// are reported on [`GeminiJsonOutput::injection_findings`] and to the guard's callback;
+    /// with [`InjectionAction::Reject`](injection::InjectionAction::Reject) the request fails
+    /// with `GeminiError::InjectionDetected` and the CLI process is killed.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::injection::{InjectionAction, InjectionGuard};
+    ///
+    /// let req = Gemini::new("Summarize this web page")
+    ///     .file("page.txt")
+    ///     .injection_guard(InjectionGuard::new(InjectionAction::Quarantine));
+    /// ```
+    #[must_use]
+    pub fn injection_guard(mut self, guard: InjectionGuard) -> Self {
+        self.injection_guard = Some(guard);
+        self
+    }
+
+    /// Constrain the response to JSON matching `schema`.
+    ///
+    /// The schema is sent to the model with the prompt, and `text()`, `json()` and
+    /// [`json_as()`](Self::json_as) fail with `GeminiError::ValidationFailed` if the response
+    /// (with any Markdown code fence stripped) is not JSON or does not conform. See [`schema`]
+    /// for the supported subset of JSON Schema; use [`response_type`](Self::response_type) to
+    /// derive the schema from a type.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use serde_json::json;
+    ///
+    /// let req = Gemini::new("Classify the sentiment of the review").response_schema(json!({
+    ///     "type": "object",
+    ///     "properties": { "sentiment": { "enum": ["positive", "neutral", "negative"] } },
+    ///     "required": ["sentiment"]
+    /// }));
+    /// ```
+    #[must_use]
+    pub fn response_schema(mut self, schema: serde_json::Value) -> Self {
+        self.response_schema = Some(schema);
+        self
+    }
+
+    /// Constrain the response to the [JSON Schema](schema::JsonSchema) of `T`; pair it with
+    /// [`json_as::<T>()`](Self::json_as).
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::schema::JsonSchema;
+    ///
+    /// #[derive(serde::Deserialize, JsonSchema)]
+    /// struct Verdict {
+    ///     approve: bool,
+    /// }
+    ///
+    /// # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+    /// let verdict: Verdict = Gemini::new("Should this PR be merged?")
+    ///     .response_type::<Verdict>()
+    ///     .json_as()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn response_type<T: schema::JsonSchema>(self) -> Self {
+        self.response_schema(T::json_schema())
+    }
+
+    /// Check the response with a [`Moderator`] before it is returned.
+    ///
+    /// Runs after post-processing. Blocked `text()`/`json()` responses fail with
+    /// `GeminiError::ContentBlocked`. In `stream()`, every `Message` event is moderated
+    /// (deltas together with the tail of the preceding text, so terms split across chunks are
+    /// still caught); a block ends the stream with an error and kills the CLI.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::moderation::KeywordModerator;
+    ///
+    /// let req = Gemini::new("Tell me a story")
+    ///     .moderator(KeywordModerator::new(["password", "ssn"]));
+    /// ```
+    #[must_use]
+    pub fn moderator(mut self, moderator: impl Moderator + 'static) -> Self {
+        self.moderator = Some(Arc::new(moderator));
+        self
+    }
+
+    /// Check the sources cited in `json()` responses, fetching each cited page with `fetcher`.
+    ///
+    /// Every URL in the response and the snippet quoted before it are reported in
+    /// [`GeminiJsonOutput::citations`] as verified, not found, unquoted or unreachable. The
+    /// response itself is returned either way. See [`citations`] for the matching rules.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::citations::CurlFetcher;
+    ///
+    /// let req = Gemini::new("Summarize the ruling, quoting your sources")
+    ///     .verify_citations(CurlFetcher::new());
+    /// ```
+    #[must_use]
+    pub fn verify_citations(mut self, fetcher: impl PageFetcher + 'static) -> Self {
+        self.citation_fetcher = Some(Arc::new(fetcher));
+        self
+    }
+
+    /// Fail `text()` and `json()` with `GeminiError::Refused` when the model refuses the task or
+    /// gives a non-answer ("I don't know", an empty response), using the default
+    /// [`RefusalDetector`].
+    ///
+    /// Catch the error to fall back to a different prompt or model instead of storing the
+    /// refusal as data.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use gemini_oxide::{Gemini, GeminiError};
+    /// # async fn run() -> Result<(), GeminiError> {
+    /// let summary = match Gemini::new("Summarize this contract").detect_refusals().text().await {
+    ///     Err(GeminiError::Refused(_)) => {
+    ///         Gemini::new("Summarize this contract").model("gemini-2.5-pro").text().await?
+    ///     }
+    ///     other => other?,
+    /// };
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn detect_refusals(self) -> Self {
+        self.refusal_detector(RefusalDetector::new())
+    }
+
+    /// Like [`detect_refusals`](Self::detect_refusals), with a customized detector.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::refusal::RefusalDetector;
+    ///
+    /// let req = Gemini::new("Résume ce contrat")
+    ///     .refusal_detector(RefusalDetector::new().refusal_phrase("je ne peux pas"));
+    /// ```
+    #[must_use]
+    pub fn refusal_detector(mut self, detector: RefusalDetector) -> Self {
+        self.refusal_detector = Some(detector);
+        self
+    }
+
+    /// Govern the agent's tool calls with a declarative [`ToolPolicy`].
+    ///
+    /// Tools the policy decides by name alone are passed to the CLI before it starts: tools it
+    /// always denies are made unavailable, as with [`exclude_tools`](Self::exclude_tools), and
+    /// tools it always allows are pre-approved with `--allowed-tools`.
+    ///
+    /// Every other decision is made on the `ToolUse` events reported by `stream()` (and
+    /// therefore `transcript()` and [`spawn_detached_streaming`](Self::spawn_detached_streaming)).
+    /// A denied call, or an `ask` decision that the [`tool_approver`](Self::tool_approver)
+    /// rejects or that no approver is configured for, ends the stream with
+    /// `GeminiError::PolicyDenied` and kills the CLI. The CLI reports a call as it runs it, so
+    /// in [`yolo`](Self::yolo) mode rules on paths and commands are a best-effort kill switch:
+    /// the denied call may already have run. `text()` and `json()` see no tool calls and fail
+    /// with `GeminiError::ValidationFailed` when a policy is set.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::policy::{Effect, PolicyRule, ToolPolicy};
+    ///
+    /// let policy = ToolPolicy::new(Effect::Deny)
+    ///     .rule(PolicyRule::new(Effect::Allow).tool("read_*").outside_cwd(false))
+    ///     .rule(PolicyRule::new(Effect::Allow).tool("write_file").path("src/*"));
+    /// // `run_shell_command` and the other unmatched tools are never offered.
+    /// let req = Gemini::new("Fix the failing test").yolo().tool_policy(policy);
+    /// ```
+    #[must_use]
+    pub fn tool_policy(mut self, policy: ToolPolicy) -> Self {
+        self.tool_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Resolve the [`tool_policy`](Self::tool_policy)'s `ask` decisions with a [`ToolApprover`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::policy::{approver_fn, ToolPolicy};
+    ///
+    /// let req = Gemini::new("Tidy up the repo")
+    ///     .tool_policy(ToolPolicy::default())
+    ///     .tool_approver(approver_fn(|call, reason| async move {
+    ///         println!("{reason}: {:?}", call.args);
+    ///         Ok(false)
+    ///     }));
+    /// ```
+    #[must_use]
+    pub fn tool_approver(mut self, approver: impl ToolApprover + 'static) -> Self {
+        self.tool_approver = Some(Arc::new(approver));
+        self
+    }
+
+    /// Report latency and throughput metrics to a [`MetricsSink`].
+    ///
+    /// Every `stream()` records time-to-first-event, time-to-first-text, total duration and
+    /// output tokens per second when it ends, labelled with the model.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::metrics::InMemoryMetrics;
+    /// use std::sync::Arc;
+    ///
+    /// let metrics = Arc::new(InMemoryMetrics::new());
+    /// let req = Gemini::new("Hi").metrics(metrics.clone());
+    /// ```
+    #[must_use]
+    pub fn metrics(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(sink);
+        self
+    }
+
+    /// Record the request's token usage, per model, in a [`UsageLedger`] for
+    /// [cost reports](billing).
+    ///
+    /// Every run that reaches the CLI, including retries, records one [`UsageEvent`] per model
+    /// with the request's [labels](Self::label). Cache hits are not recorded. `stream()` records
+    /// the stats of its `Result` event; `text()` needs the CLI's token counts too, so with a
+    /// ledger attached it runs like `json()` and returns the response text.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::billing::UsageLedger;
+    /// use std::sync::Arc;
+    ///
+    /// let ledger = Arc::new(UsageLedger::new());
+    /// let req = Gemini::new("Hi").label("team", "search").usage_ledger(ledger.clone());
+    /// ```
+    #[must_use]
+    pub fn usage_ledger(mut self, ledger: Arc<UsageLedger>) -> Self {
+        self.usage_ledger = Some(ledger);
+        self
+    }
+
+    /// Attach a `key`/`value` label to the request, e.g. the team to charge it to.
+    ///
+    /// Labels are carried, unchanged, to every place the request is accounted for: the
+    /// [metrics](Self::metrics) it emits (including queue wait in a [`jobs::JobQueue`]), the
+    /// [`ToolContext`] seen by [tool policies](Self::tool_policy) and approvers, the
+    /// [usage ledger](Self::usage_ledger), and [`GeminiJsonOutput::labels`]. They are not sent
+    /// to the CLI and do not affect [caching](Self::cache). Setting a key again replaces its value.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Reconcile yesterday's ledger")
+    ///     .label("team", "payments")
+    ///     .label("env", "prod");
+    /// ```
+    #[must_use]
+    pub fn label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+
+    /// Emit a [`StreamEvent::Heartbeat`] whenever the CLI has been silent for `interval`.
+    ///
+    /// Long tool executions can produce no output for minutes; heartbeats let clients tell a
+    /// busy run from a dead connection. They are generated by the SDK, not the CLI, and only
+    /// appear in `stream()`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use std::time::Duration;
+    ///
+    /// let req = Gemini::new("Refactor the crate").yolo().heartbeat(Duration::from_secs(15));
+    /// ```
+    #[must_use]
+    pub fn heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = Some(interval);
+        self
+    }
+
+    /// Append the stream's events and timing to the JSON Lines file at `path`, flushing at most
+    /// every `interval`, when the stream ends, and when it is dropped.
+    ///
+    /// The file is truncated when the stream starts. If the host process crashes, read what was
+    /// flushed with [`RunTranscript::recover`](transcript::RunTranscript::recover). Synthetic
+    /// events are not recorded. Applies to `stream()` and `transcript()`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use std::time::Duration;
+    ///
+    /// let req = Gemini::new("Migrate the test suite")
+    ///     .yolo()
+    ///     .transcript_checkpoint("runs/migrate.jsonl", Duration::from_secs(5));
+    /// ```
+    #[must_use]
+    pub fn transcript_checkpoint(mut self, path: impl Into<PathBuf>, interval: Duration) -> Self {
+        self.transcript_checkpoint = Some((path.into(), interval));
+        self
+    }
+
+    /// Emit SDK-generated [`StreamEvent::Plan`] and [`StreamEvent::PlanUpdate`] events when the
+    /// model's messages lay out a plan or announce its next step.
+    ///
+    /// Detection is heuristic (see [`plan`]); the events follow the `Message` that completed
+    /// them.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// let req = Gemini::new("Refactor the parser").yolo().plan_events();
+    /// ```
+    #[must_use]
+    pub fn plan_events(mut self) -> Self {
+        self.plan_events = true;
+        self
+    }
+
+    /// Feed `stream()` events into a [`ProgressTracker`].
+    ///
+    /// Keep a clone of the tracker and poll [`ProgressTracker::progress`] to drive a progress
+    /// bar. Estimates compare elapsed time and tool calls against the tracker's history of
+    /// previous runs, which is updated when each run finishes.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::progress::{ProgressTracker, RunHistory};
+    ///
+    /// let history = RunHistory::new(); // share across runs of the same kind
+    /// let tracker = ProgressTracker::new(history.clone());
+    /// let req = Gemini::new("Migrate the tests").yolo().progress(tracker.clone());
+    /// // ... while streaming: tracker.progress() -> Option<f32>
+    /// ```
+    #[must_use]
+    pub fn progress(mut self, tracker: ProgressTracker) -> Self {
+        self.progress = Some(tracker);
+        self
+    }
+
+    /// Answer the request with `simulator` instead of launching the CLI.
+    ///
+    /// See [`simulate`] for what is simulated. Use it to develop and demo without a `gemini`
+    /// binary or API key.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// use gemini_oxide::simulate::Simulator;
+    ///
+    /// let req = Gemini::new("Summarize the ticket")
+    ///     .simulator(Simulator::new().fallback("The login page times out on Safari."));
+    /// ```
+    #[must_use]
+    pub fn simulator(mut self, simulator: Simulator) -> Self {
+        self.simulator = Some(simulator);
+        self
+    }
+
+    // =====================================================================
+    //  2. Execution Methods
+    // =====================================================================
+
+    /// Execute the request and return the raw text response.
+    ///
+    /// This method waits for the process to complete and returns the standard output as a String.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError` if the CLI fails to start, exits with a non-zero code, or prints to stderr.
+    /// Returns `GeminiError::ValidationFailed` if a [`tool_policy`](Self::tool_policy) is set.
+    ///
+    /// With a [`usage_ledger`](Self::usage_ledger) attached, the CLI is asked for JSON output,
+    /// so the run's token usage can be recorded, and the response text is returned.
+    pub async fn text(mut self) -> Result<String, GeminiError> {
+        if self.usage_ledger.is_some() {
+            return self.json().await.map(|output| output.response);
+        }
+        self.check_tool_policy_is_enforced()?;
+        self.compress_input().await?;
+        let mut text = self.text_raw().await?;
+        if let Some(language) = self.respond_in {
+            if !language.matches(&text) {
+                text = self.language_retry().text_raw().await?;
+                Self::ensure_language(language, &text)?;
+            }
+        }
+        let text = self.post_processors.run(text).await?;
+        self.check_schema(&text)?;
+        self.moderate(&text).await?;
+        self.reject_refusal(&text)?;
+        Ok(text)
+    }
+
+    /// Execute the request and return a structured JSON response.
+    ///
+    /// This parses the output into `GeminiJsonOutput`, which contains the response text,
+    /// token usage statistics, and detailed tool usage metrics.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::JsonParseFailed` if the CLI output is not valid JSON, or
+    /// `GeminiError::ValidationFailed` if a [`tool_policy`](Self::tool_policy) is set.
+    pub async fn json(mut self) -> Result<GeminiJsonOutput, GeminiError> {
+        self.check_tool_policy_is_enforced()?;
+        let compression = self.compress_input().await?;
+        let mut output = self.json_raw().await?;
+        if let Some(language) = self.respond_in {
+            if !language.matches(&output.response) {
+                output = self.language_retry().json_raw().await?;
+                Self::ensure_language(language, &output.response)?;
+            }
+        }
+        output.compression = compression;
+        output.truncated = self.reached_output_limit(&output);
+        output.labels = self.labels.clone();
+        output.response = self.post_processors.run(output.response).await?;
+        self.check_schema(&output.response)?;
+        self.moderate(&output.response).await?;
+        self.reject_refusal(&output.response)?;
+        if let Some(fetcher) = &self.citation_fetcher {
+            output.citations = citations::verify(&output.response, fetcher.as_ref()).await;
+        }
+        Ok(output)
+    }
+
+    /// Execute the request and deserialize the model's `response` into `T`.
+    ///
+    /// Markdown code fences and surrounding prose are stripped before parsing. Use
+    /// [`tasks::Extractor`] instead to put a schema in the prompt and retry invalid replies.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::JsonParseFailed` if the CLI output is not valid JSON, or
+    /// `GeminiError::ResponseParseFailed` if the model's response does not deserialize into `T`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use gemini_oxide::Gemini;
+    /// #[derive(serde::Deserialize)]
+    /// struct Contact {
+    ///     name: String,
+    ///     email: String,
+    /// }
+    ///
+    /// # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+    /// let contact: Contact = Gemini::new("Extract the contact as JSON with name and email")
+    ///     .context("Reach out to Ada Lovelace at ada@example.com")
+    ///     .json_as()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn json_as<T: DeserializeOwned>(self) -> Result<T, GeminiError> {
+        let response = self.json().await?.response;
+        tasks::parse_json_payload(&response)
+            .and_then(serde_json::from_value)
+            .map_err(|source| GeminiError::ResponseParseFailed { source, response })
+    }
+
+    /// Execute the request and return a real-time stream of events.
     ///
     /// This is useful for building interactive UIs, chatbots, or monitoring tool execution in real-time.
     /// The stream yields `Result<StreamEvent, GeminiError>`.
     ///
     /// # Errors
     ///
-    /// Returns `GeminiError` if the CLI fails to start.
+    /// Returns `GeminiError` if the CLI fails to start, or `GeminiError::StorageError` if the
+    /// [transcript checkpoint](Self::transcript_checkpoint) file cannot be created.
+    ///
+    /// The CLI's stderr is collected while streaming. If it exits with a failure, the stream
+    /// ends with `GeminiError::RuntimeError` carrying what it wrote to stderr.
     ///
     /// # Panics
     ///
     /// Panics if it fails to open stdin/stdout pipes (which should be unreachable under normal OS conditions).
     pub fn stream(
-        self,
+        mut self,
     ) -> Result<impl Stream<Item = Result<StreamEvent, GeminiError>>, GeminiError> {
+        self.check_safety_lock()?;
+        let tickets = self.begin()?;
+        let permit = match &self.circuit_breaker {
+            Some(breaker) => Some(breaker.admit()?),
+            None => None,
+        };
+        let usage_ledger = self.usage_ledger.clone();
+        let labels = self.labels.clone();
+        if let Some(simulator) = &self.simulator {
+            let model = self.resolved_model();
+            let progress = self.progress.clone();
+            if let Some(tracker) = &progress {
+                tracker.begin();
+            }
+            let events = simulator
+                .stream(&self.prompt, model.as_deref())
+                .inspect(move |event| {
+                    let _tickets = &tickets;
+                    let Ok(event) = event else { return };
+                    if let Some(ledger) = &usage_ledger {
+                        ledger.record_stream_event(event, &labels);
+                    }
+                    if let Some(tracker) = &progress {
+                        tracker.observe(event);
+                    }
+                });
+            return Ok(Either::Left(events));
+        }
+        if let Some(version) = self.cli_version.filter(|v| !v.supports_stream_json()) {
+            return Err(GeminiError::RuntimeError(format!(
+                "CLI {version} cannot stream; stream-json output needs 0.9.0 or later"
+            )));
+        }
+        if let Some(compression) = self.compression.take() {
+            self.compress_text(&compression);
+        }
+        let context_file = match self.spill_context()? {
+            Some((spilled, file)) => {
+                self = spilled;
+                Some(file)
+            }
+            None => None,
+        };
+        let mut timer = StreamTimer::start();
+        let mut checkpoint = match &self.transcript_checkpoint {
+            Some((path, interval)) => Some(Checkpointer::create(path, *interval)?),
+            None => None,
+        };
         let mut cmd = self.build_command("stream-json");
         cmd.stdout(Stdio::piped())
             .stdin(Stdio::piped())
             .stderr(Stdio::piped());
 
-        let mut child = cmd.spawn().map_err(GeminiError::CliLaunchFailed)?;
-        let stdin = child.stdin.take().expect("Failed to open stdin");
-        let stdout = child.stdout.take().expect("Failed to open stdout");
+        let (mut child, temp_files) = self.spawn(cmd)?;
+        let mut group = self.process_group(&child);
+        let interrupts = self.interrupts();
+        let registration = self.register(&child);
+        let stdin = child.stdin.take().expect("Failed to open stdin");
+        let stdout = child.stdout.take().expect("Failed to open stdout");
+        // Drain stderr as it is written: with `--debug` it can fill the pipe and block the CLI.
+        let stderr = child.stderr.take().map(|mut pipe| {
+            tokio::spawn(async move {
+                use tokio::io::AsyncReadExt;
+                let mut collected = Vec::new();
+                let _ = pipe.read_to_end(&mut collected).await;
+                collected
+            })
+        });
+
+        // CRITICAL: Spawn a separate background task to write to stdin.
+        // This prevents deadlocks if the CLI produces output while we are still writing input.
+        let input_data = self.input_data.clone();
+        let input_files = self.input_files.clone();
+        let guard = self.injection_guard.clone();
+        let truncation = self.truncation.clone();
+        let framing = self.framing.clone();
+        let (verdict_tx, verdict_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let _ = Self::write_stdin(
+                stdin,
+                input_data,
+                input_files,
+                guard,
+                truncation,
+                framing,
+                verdict_tx,
+            )
+            .await;
+        });
+
+        let reader = BufReader::new(stdout);
+        let post_processors = self.post_processors.clone();
+        let moderator = self.moderator.clone();
+        let tool_policy = self.tool_policy.clone();
+        let tool_approver = self.tool_approver.clone();
+        let process_dir = std::env::current_dir().unwrap_or_default();
+        let cwd = match &self.current_dir {
+            Some(dir) => process_dir.join(dir),
+            None => process_dir,
+        };
+        let metrics = self.metrics.clone();
+        let heartbeat = self.heartbeat;
+        let clock = self.clock.clone();
+        let mut planner = self.plan_events.then(PlanDetector::new);
+        let progress = self.progress.clone();
+        if let Some(tracker) = &progress {
+            tracker.begin();
+        }
+
+        // Convert the newline-delimited JSON output into a Rust Stream
+        let stream = async_stream::try_stream! {
+            let _tickets = tickets;
+            let _registration = registration;
+            let _temp_files = temp_files;
+            let _context_file = context_file;
+            if let Ok(Err(rejected)) = verdict_rx.await {
+                group.kill();
+                let _ = child.start_kill();
+                Err(rejected)?;
+            }
+            let mut lines = reader.lines();
+            let mut moderated_tail = String::new();
+            let mut last_output = clock.now();
+            loop {
+                // `next_line` is cancel-safe, so timing out never loses partial output.
+                let read = async {
+                    match heartbeat {
+                        Some(interval) => tokio::select! {
+                            next = lines.next_line() => Some(next),
+                            () = clock.sleep(interval) => None,
+                        },
+                        None => Some(lines.next_line().await),
+                    }
+                };
+                let read = match interrupts.guard(read).await {
+                    Ok(read) => read,
+                    Err(stopped) => {
+                        group.kill();
+                        let _ = child.start_kill();
+                        Err(stopped)?;
+                        break;
+                    }
+                };
+                let Some(next) = read else {
+                    let idle_ms = clock.now().saturating_duration_since(last_output).as_millis() as u64;
+                    yield StreamEvent::Heartbeat { idle_ms };
+                    continue;
+                };
+                let Ok(Some(line)) = next else { break };
+                last_output = clock.now();
+                let Some(mut event) = StreamEvent::parse_line(&line)? else { continue };
+                timer.observe(&event);
+                if let Some(tracker) = &progress {
+                    tracker.observe(&event);
+                }
+                if let (Some(policy), StreamEvent::ToolUse { tool_name, parameters, .. }) =
+                    (&tool_policy, &event)
+                {
+                    let mut call =
+                        ToolContext::new(tool_name.clone(), parameters.clone(), cwd.clone());
+                    call.labels = labels.clone();
+                    if let Err(denied) =
+                        policy::enforce(policy, tool_approver.as_deref(), &call).await
+                    {
+                        group.kill();
+                        let _ = child.start_kill();
+                        Err(denied)?;
+                    }
+                }
+                // Deltas are fragments, so only complete messages are post-processed.
+                if let StreamEvent::Message { content, delta, .. } = &mut event {
+                    if !delta.unwrap_or(false) && !post_processors.is_empty() {
+                        *content = post_processors.run(std::mem::take(content)).await?;
+                    }
+                    if let Some(moderator) = &moderator {
+                        let window = format!("{moderated_tail}{content}");
+                        if let Err(blocked) = moderation::enforce(moderator.as_ref(), &window).await {
+                            group.kill();
+                            let _ = child.start_kill();
+                            Err(blocked)?;
+                        }
+                        moderated_tail = tail_chars(&window, MODERATION_OVERLAP);
+                    }
+                }
+                let planned = match (&mut planner, &event) {
+                    (Some(planner), StreamEvent::Message { role, content, delta, .. }) if role != "user" => {
+                        let mut planned = planner.push(content);
+                        if !delta.unwrap_or(false) {
+                            planned.extend(planner.finish());
+                        }
+                        planned
+                    }
+                    (Some(planner), _) => planner.finish(),
+                    (None, _) => Vec::new(),
+                };
+                if let Some(ledger) = &usage_ledger {
+                    ledger.record_stream_event(&event, &labels);
+                }
+                if let Some(checkpoint) = &mut checkpoint {
+                    checkpoint.record(&event, &timer)?;
+                }
+                yield event;
+                for plan_event in planned {
+                    if let Some(tracker) = &progress {
+                        tracker.observe(&plan_event);
+                    }
+                    yield plan_event;
+                }
+            }
+            // The CLI closed its output; wait for it to exit.
+            let status = match interrupts.guard(child.wait()).await {
+                Ok(status) => status.ok(),
+                Err(stopped) => {
+                    group.kill();
+                    let _ = child.start_kill();
+                    Err(stopped)?;
+                    None
+                }
+            };
+            // It has finished and its group is no longer ours to kill.
+            group.release();
+            if let Some(planner) = &mut planner {
+                for plan_event in planner.finish() {
+                    if let Some(tracker) = &progress {
+                        tracker.observe(&plan_event);
+                    }
+                    yield plan_event;
+                }
+            }
+            if let Some(checkpoint) = &mut checkpoint {
+                checkpoint.finish(&timer)?;
+            }
+            if let Some(metrics) = &metrics {
+                timer.emit(metrics.as_ref(), &labels);
+            }
+            let outcome = if status.is_some_and(|status| !status.success()) {
+                let stderr = match stderr {
+                    Some(task) => task.await.unwrap_or_default(),
+                    None => Vec::new(),
+                };
+                Err(GeminiError::RuntimeError(String::from_utf8_lossy(&stderr).into_owned()))
+            } else {
+                Ok(())
+            };
+            if let Some(permit) = permit {
+                permit.record(&outcome);
+            }
+            outcome?;
+        };
+
+        Ok(Either::Right(stream))
+    }
+
+    /// Stream the request, accumulating the model's text, and stop as soon as `stop` returns
+    /// `true` for the text so far.
+    ///
+    /// Stopping kills the CLI (even with [`detach_on_drop`](Self::detach_on_drop)), so bounded
+    /// answers — a JSON object, a line ending in a sentinel token — don't spend tokens on
+    /// whatever the model would have written next. Returns the text up to and including the
+    /// message that matched, or the whole answer if `stop` never matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by [`stream()`](Self::stream) before `stop` matches.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use gemini_oxide::Gemini;
+    /// # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+    /// let answer = Gemini::new("Reply with one line, then write END.")
+    ///     .stream_until(|text| text.contains("END"))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn stream_until(
+        mut self,
+        mut stop: impl FnMut(&str) -> bool,
+    ) -> Result<String, GeminiError> {
+        self.detach_on_drop = false;
+        let stream = self.stream()?;
+        futures_util::pin_mut!(stream);
+        let mut text = String::new();
+        while let Some(event) = stream.next().await {
+            let StreamEvent::Message {
+                role,
+                content,
+                delta,
+                ..
+            } = event?
+            else {
+                continue;
+            };
+            if role == "user" {
+                continue;
+            }
+            if !delta.unwrap_or(false) && !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text.push_str(&content);
+            if stop(&text) {
+                break;
+            }
+        }
+        Ok(text)
+    }
+
+    /// Start the request in the background and return a [`JobHandle`] immediately.
+    ///
+    /// The job runs `json()` on the Tokio runtime. Use the handle to poll its status, await
+    /// its result or cancel it; cancelling kills the CLI unless
+    /// [`detach_on_drop`](Self::detach_on_drop) is set. Like `json()`, the job fails with
+    /// `GeminiError::ValidationFailed` if a [`tool_policy`](Self::tool_policy) is set; use
+    /// [`spawn_detached_streaming`](Self::spawn_detached_streaming) instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use gemini_oxide::Gemini;
+    /// # async fn run() {
+    /// let job = Gemini::new("Audit the dependencies").yolo().spawn_detached();
+    /// println!("started {}", job.id());
+    ///
+    /// // Later, possibly from another task:
+    /// let output = job.await_result().await;
+    /// # }
+    /// ```
+    pub fn spawn_detached(self) -> JobHandle {
+        JobHandle::spawn(self, false)
+    }
+
+    /// Like [`spawn_detached`](Self::spawn_detached), but runs `stream()` and records every
+    /// event, so [`JobHandle::events_since`] reports the model's output as it arrives.
+    ///
+    /// The result is assembled from the stream: the model's text, and the stats of the final
+    /// `Result` event. Features that only apply to `json()`, such as caching and
+    /// post-processors, are not used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use gemini_oxide::Gemini;
+    /// # use std::time::Duration;
+    /// # async fn run() {
+    /// let job = Gemini::new("Migrate the tests to tokio").yolo().spawn_detached_streaming();
+    ///
+    /// // In a polling endpoint, with the cursor the client sent:
+    /// let page = job.wait_for_events(0, Duration::from_secs(25)).await;
+    /// // Respond with page.events and page.next_cursor.
+    /// # }
+    /// ```
+    pub fn spawn_detached_streaming(self) -> JobHandle {
+        JobHandle::spawn(self, true)
+    }
+
+    /// Run the request on a recurring schedule, delivering each text result to `sink`.
+    ///
+    /// `expr` is a five-field cron expression in UTC, a macro such as `@daily`, or an interval
+    /// such as `@every 15m`. A run that is due while the previous one is still in flight is
+    /// skipped; use [`schedule::Scheduler`] for jitter and other overlap policies.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::ValidationFailed` if the expression is malformed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use gemini_oxide::Gemini;
+    /// # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+    /// let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+    /// let handle = Gemini::new("Check the status page for new incidents").schedule("*/30 * * * *", tx)?;
+    ///
+    /// while let Some(run) = rx.recv().await {
+    ///     println!("{:?}", run.result);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn schedule(
+        self,
+        expr: &str,
+        sink: impl schedule::RunSink + 'static,
+    ) -> Result<schedule::ScheduleHandle, GeminiError> {
+        schedule::schedule(expr, self, sink)
+    }
+
+    /// Ask for `n` alternative answers in a single run and return them best first.
+    ///
+    /// The model rates each answer itself; see [`candidates`] for details. Fewer than `n`
+    /// candidates are returned if the model wrote fewer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::ValidationFailed` if the reply is not a list of candidates, or any
+    /// error raised by [`json`](Self::json).
+    pub async fn candidates(mut self, n: usize) -> Result<Vec<Candidate>, GeminiError> {
+        let n = n.max(1);
+        self.prompt = candidates::prompt(&self.prompt, n);
+        let output = self.json().await?;
+        candidates::parse(&output.response, n)
+    }
+
+    /// Execute the request as a stream, collect every event, and return a [`RunTranscript`].
+    ///
+    /// The transcript carries the model's text and its [`StreamTiming`](transcript::StreamTiming):
+    /// time-to-first-event, time-to-first-text, total duration and tokens per second.
+    /// Synthetic events such as heartbeats are not recorded.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error raised by [`stream`](Self::stream).
+    pub async fn transcript(self) -> Result<RunTranscript, GeminiError> {
+        let mut timer = StreamTimer::start();
+        let stream = self.stream()?;
+        futures_util::pin_mut!(stream);
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await {
+            let event = event?;
+            if event.is_synthetic() {
+                continue;
+            }
+            timer.observe(&event);
+            events.push(event);
+        }
+        Ok(RunTranscript::new(events, timer.finish()))
+    }
+
+    /// A stable hash of everything that determines the response: prompt, context, file
+    /// contents, model and flags. `None` if an input file cannot be read.
+    ///
+    /// Text is whitespace-normalized and attached files and include directories are
+    /// order-insensitive (see [`fingerprint`]), so requests that differ only in formatting share
+    /// a fingerprint. Use it to shard work or detect duplicate runs. The response cache and
+    /// pipeline checkpoints use an exact key instead, since whitespace in piped code matters.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use gemini_oxide::Gemini;
+    /// # async fn run() {
+    /// let a = Gemini::new("Summarize  the logs").fingerprint().await;
+    /// let b = Gemini::new("Summarize the logs\n").fingerprint().await;
+    /// assert_eq!(a, b);
+    /// # }
+    /// ```
+    pub async fn fingerprint(&self) -> Option<String> {
+        Some(self.fingerprint_parts(false).await?.finish())
+    }
+
+    /// The key this request's result is stored under: a SHA-256 of `scope` and the request's
+    /// exact prompt, context and file bytes. `None` if an input file cannot be read.
+    pub(crate) async fn exact_key(&self, scope: &[&[u8]]) -> Option<String> {
+        let fingerprint = self.fingerprint_parts(true).await?;
+        Some(storage::cache_key(
+            scope.iter().copied().chain(fingerprint.parts()),
+        ))
+    }
+
+    /// The components of the request's fingerprint. With `exact`, the prompt, context, system
+    /// instruction, memory and files are taken byte for byte, and files in the order they are
+    /// piped.
+    async fn fingerprint_parts(&self, exact: bool) -> Option<fingerprint::Fingerprint> {
+        let text = |fingerprint: fingerprint::Fingerprint, label: &str, value: &str| {
+            if exact {
+                fingerprint.raw(label, value)
+            } else {
+                fingerprint.field(label, value)
+            }
+        };
+        let mut fingerprint = fingerprint::Fingerprint::new()
+            .field("model", &self.resolved_model().unwrap_or_default())
+            .field("yolo", &(self.approval == ApprovalMode::Yolo).to_string());
+        fingerprint = text(fingerprint, "prompt", &self.effective_prompt());
+        fingerprint = text(
+            fingerprint,
+            "context",
+            self.input_data.as_deref().unwrap_or_default(),
+        );
+        for (i, path) in self.input_files.iter().enumerate() {
+            let bytes = tokio::fs::read(path).await.ok()?;
+            fingerprint = match (exact, String::from_utf8(bytes)) {
+                (true, Ok(text)) => fingerprint.raw(format!("file.{i}"), text),
+                (true, Err(e)) => fingerprint.raw(format!("file.{i}"), e.into_bytes()),
+                (false, Ok(text)) => fingerprint.item("file", &text),
+                (false, Err(e)) => fingerprint.raw_item("file", e.into_bytes()),
+            };
+        }
+        for dir in &self.include_dirs {
+            fingerprint = fingerprint.item("include", dir);
+        }
+        if self.all_files {
+            fingerprint = fingerprint.field("all_files", "true");
+        }
+        if self.framing.is_some() {
+            fingerprint = fingerprint.field("frame_files", "true");
+        }
+        if let Some(dir) = &self.current_dir {
+            fingerprint = fingerprint.field("cwd", &dir.to_string_lossy());
+        }
+        if self.sandbox {
+            fingerprint = fingerprint.field("sandbox", "true");
+        }
+        if let Some(image) = &self.sandbox_image {
+            fingerprint = fingerprint.field("sandbox_image", image);
+        }
+        for (name, value) in &self.env {
+            fingerprint = fingerprint.item("env", &format!("{name}={value}"));
+        }
+        if self.approval == ApprovalMode::AutoEdit {
+            fingerprint = fingerprint.field("approval", self.approval.as_str());
+        }
+        if let Some(system) = &self.system {
+            fingerprint = text(fingerprint, "system", system);
+        }
+        if let Some(memory) = self.memory_text().ok()? {
+            fingerprint = text(fingerprint, "memory", &memory);
+        }
+        if let Some(settings) = self.generation.settings() {
+            fingerprint = fingerprint.field("generation", &settings.to_string());
+        }
+        for tool in &self.allowed_tools {
+            fingerprint = fingerprint.item("allowed_tool", tool);
+        }
+        for tool in &self.excluded_tools {
+            fingerprint = fingerprint.item("excluded_tool", tool);
+        }
+        for extension in &self.extensions {
+            fingerprint = fingerprint.item("extension", extension);
+        }
+        if let Some(session) = &self.resume {
+            fingerprint = fingerprint.field("resume", session.as_str());
+        }
+        for (name, server) in &self.mcp_servers {
+            let config = serde_json::to_string(server).unwrap_or_default();
+            fingerprint = fingerprint.item("mcp_server", &format!("{name}={config}"));
+        }
+        if self.simulator.is_some() {
+            // Keep simulated answers apart from real ones in a shared cache.
+            fingerprint = fingerprint.field("simulated", "true");
+        }
+        if let Some(truncation) = &self.truncation {
+            fingerprint = fingerprint.field(
+                "truncation",
+                &serde_json::to_string(truncation).unwrap_or_default(),
+            );
+        }
+        Some(fingerprint)
+    }
+
+    // =====================================================================
+    //  3. Internal Helpers
+    // =====================================================================
+
+    async fn moderate(&self, text: &str) -> Result<(), GeminiError> {
+        match &self.moderator {
+            Some(moderator) => moderation::enforce(moderator.as_ref(), text).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Fail with `ValidationFailed` unless `text` conforms to the response schema, if any.
+    fn check_schema(&self, text: &str) -> Result<(), GeminiError> {
+        let Some(schema) = &self.response_schema else {
+            return Ok(());
+        };
+        let value = tasks::parse_json_payload(text).map_err(|e| {
+            GeminiError::ValidationFailed(format!("response is not valid JSON ({e})"))
+        })?;
+        schema::validate(schema, &value)
+            .map_err(|errors| GeminiError::ValidationFailed(errors.join("; ")))
+    }
+
+    fn reject_refusal(&self, text: &str) -> Result<(), GeminiError> {
+        match self.refusal_detector.as_ref().map(|d| d.classify(text)) {
+            Some(Outcome::Refused { reason }) => Err(GeminiError::Refused(reason)),
+            Some(Outcome::NonAnswer { reason }) => {
+                Err(GeminiError::Refused(format!("non-answer: {reason}")))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The router's decision for this request, if a router is set and no model was pinned.
+    fn routing_decision(&self) -> Option<RoutingDecision> {
+        if self.model.is_some() {
+            return None;
+        }
+        let features = PromptFeatures::analyze(
+            &self.prompt,
+            self.input_data.as_deref(),
+            self.input_files.len(),
+        );
+        self.router.as_ref().map(|r| r.route(&features))
+    }
+
+    /// The budget plan for this request, if a deadline or token budget is set.
+    fn budget_decision(&self) -> Option<BudgetDecision> {
+        let budget = self.budget.as_ref()?;
+        let preferred = self
+            .model
+            .clone()
+            .or_else(|| self.routing_decision().map(|d| d.model));
+        let files: u64 = self
+            .input_files
+            .iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|meta| meta.len())
+            .sum();
+        let input_chars =
+            self.prompt.len() + self.input_data.as_deref().map_or(0, str::len) + files as usize;
+        Some(budget.plan(preferred.as_deref(), self.model.is_some(), input_chars))
+    }
+
+    /// The model passed to the CLI: pinned, budgeted, or routed.
+    fn resolved_model(&self) -> Option<String> {
+        self.model
+            .clone()
+            .or_else(|| self.budget_decision().map(|d| d.model))
+            .or_else(|| self.routing_decision().map(|d| d.model))
+    }
+
+    /// Whether `output` used up the [`max_output_tokens`](Self::max_output_tokens) limit.
+    ///
+    /// Judged from the output tokens the CLI reports, or estimated from the response's length
+    /// if it reports none.
+    fn reached_output_limit(&self, output: &GeminiJsonOutput) -> bool {
+        let Some(limit) = self.generation.max_output_tokens else {
+            return false;
+        };
+        let reported = output.stats.as_ref().and_then(|stats| {
+            stats
+                .models
+                .values()
+                .filter_map(|model| model.tokens.get("candidates").copied())
+                .max()
+        });
+        reported.unwrap_or_else(|| compression::estimate_tokens(&output.response))
+            >= u64::from(limit)
+    }
+
+    /// A copy of this request that references its context from a temporary file rather than
+    /// piping it, if [`context_file_over`](Self::context_file_over) applies. The file must be
+    /// kept until the CLI exits.
+    fn spill_context(&self) -> Result<Option<(Self, temp::TempFile)>, GeminiError> {
+        let (Some(limit), Some(context)) = (self.context_file_over, &self.input_data) else {
+            return Ok(None);
+        };
+        if context.len() <= limit
+            || self.sensitive_context
+            || self.injection_guard.is_some()
+            || self.truncation.is_some()
+        {
+            return Ok(None);
+        }
+        let file = temp::TempFile::create("-context.txt", context.as_bytes()).map_err(|e| {
+            GeminiError::StorageError(format!("failed to write the context file: {e}"))
+        })?;
+        let mut spilled = Self {
+            input_data: None,
+            prompt: format!("{}\n\nContext: @{}", self.prompt, file.path().display()),
+            ..self.clone()
+        };
+        spilled
+            .include_dirs
+            .push(file.dir().to_string_lossy().into_owned());
+        Ok(Some((spilled, file)))
+    }
+
+    /// The request's memory, with memory files read, if any was added.
+    fn memory_text(&self) -> std::io::Result<Option<String>> {
+        if self.memory.is_empty() {
+            return Ok(None);
+        }
+        let pieces = self
+            .memory
+            .iter()
+            .map(|memory| match memory {
+                Memory::Text(text) => Ok(text.clone()),
+                Memory::File(path) => std::fs::read_to_string(path),
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        Ok(Some(pieces.join("\n\n")))
+    }
+
+    /// The settings to pass to the CLI for this run, if any.
+    fn cli_settings(&self) -> Option<serde_json::Value> {
+        let mut merged = self.generation.settings();
+        if !self.memory.is_empty() {
+            let context =
+                serde_json::json!({ "context": { "loadMemoryFromIncludeDirectories": true } });
+            settings::merge(merged.get_or_insert_with(|| serde_json::json!({})), context);
+        }
+        if !self.allowed_tools.is_empty() {
+            let tools = serde_json::json!({ "tools": { "core": self.allowed_tools } });
+            settings::merge(merged.get_or_insert_with(|| serde_json::json!({})), tools);
+        }
+        let excluded = self.unavailable_tools();
+        if !excluded.is_empty() {
+            let tools = serde_json::json!({ "tools": { "exclude": excluded } });
+            settings::merge(merged.get_or_insert_with(|| serde_json::json!({})), tools);
+        }
+        if !self.mcp_servers.is_empty() {
+            let servers = serde_json::json!({ "mcpServers": self.mcp_servers });
+            settings::merge(merged.get_or_insert_with(|| serde_json::json!({})), servers);
+        }
+        merged
+    }
+
+    /// Estimated tokens of the prompt and piped context.
+    fn input_tokens(&self) -> u64 {
+        compression::estimate_tokens(&self.prompt)
+            + self
+                .input_data
+                .as_deref()
+                .map_or(0, compression::estimate_tokens)
+    }
+
+    /// Run the text passes of `compression` over the prompt and piped context.
+    fn compress_text(&mut self, compression: &Compression) {
+        self.prompt = compression.apply(&self.prompt);
+        if let Some(context) = &self.input_data {
+            self.input_data = Some(compression.apply(context));
+        }
+    }
+
+    /// Compress the prompt and piped context, having the model condense long context if
+    /// configured. Runs at most once per request.
+    async fn compress_input(&mut self) -> Result<Option<CompressionReport>, GeminiError> {
+        let Some(compression) = self.compression.take() else {
+            return Ok(None);
+        };
+        let original_tokens = self.input_tokens();
+        self.compress_text(&compression);
+        let mut condensed = false;
+        if let Some(context) = self
+            .input_data
+            .take_if(|context| compression.should_condense(context))
+        {
+            let condenser = Self {
+                prompt: compression::condense_prompt(&self.prompt),
+                input_data: Some(context),
+                bin_path: self.bin_path.clone(),
+                current_dir: self.current_dir.clone(),
+                model: self.resolved_model(),
+                timeout: self.timeout,
+                cancel: self.cancel.clone(),
+                monitors: self.monitors.clone(),
+                retry: self.retry,
+                spawn_options: self.spawn_options,
+                proxy: self.proxy.clone(),
+                env: self.env.clone(),
+                ..Self::new("")
+            };
+            self.input_data = Some(condenser.text_raw().await?);
+            condensed = true;
+        }
+        Ok(Some(CompressionReport {
+            original_tokens,
+            compressed_tokens: self.input_tokens(),
+            condensed,
+        }))
+    }
+
+    /// A copy of this request with the stricter language instruction used for the retry.
+    fn language_retry(&self) -> Self {
+        Self {
+            language_retry: true,
+            ..self.clone()
+        }
+    }
+
+    fn ensure_language(expected: Language, text: &str) -> Result<(), GeminiError> {
+        match language::detect(text) {
+            Some(found) if found != expected => Err(GeminiError::ValidationFailed(format!(
+                "response is in {}, expected {}",
+                found.name(),
+                expected.name()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// The prompt sent to the CLI, including any SDK-added instructions.
+    fn effective_prompt(&self) -> String {
+        let mut prompt = self.prompt.clone();
+        if let Some(language) = self.respond_in {
+            prompt = format!("{prompt}\n\n{}", language.instruction(self.language_retry));
+        }
+        if let Some(instruction) = self.budget_decision().and_then(|d| d.instruction()) {
+            prompt = format!("{prompt}\n\n{instruction}");
+        }
+        if let Some(schema) = &self.response_schema {
+            prompt = format!(
+                "{prompt}\n\nRespond with a single JSON value that conforms to the JSON Schema \
+                 below. Do not wrap it in Markdown and do not add any commentary.\n\n\
+                 JSON Schema:\n{schema}"
+            );
+        }
+        prompt
+    }
+
+    /// Run the text request (or serve it from the cache) without post-processing.
+    async fn text_raw(&self) -> Result<String, GeminiError> {
+        self.with_retry(|| self.text_once()).await
+    }
+
+    async fn text_once(&self) -> Result<String, GeminiError> {
+        let key = self.cache_key("text").await;
+        if let Some(hit) = self.cache_get(key.as_deref()).await? {
+            return Ok(hit);
+        }
+
+        let output = self.execute_process("text").await?;
+        let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        self.cache_put(key.as_deref(), &text).await?;
+        Ok(text)
+    }
+
+    /// Run the JSON request (or serve it from the cache) without post-processing.
+    async fn json_raw(&self) -> Result<GeminiJsonOutput, GeminiError> {
+        self.with_retry(|| self.json_once()).await
+    }
+
+    async fn json_once(&self) -> Result<GeminiJsonOutput, GeminiError> {
+        let key = self.cache_key("json").await;
+        if let Some(hit) = self.cache_get(key.as_deref()).await? {
+            if let Ok(cached) = wire::from_str(&hit) {
+                return Ok(cached);
+            }
+        }
+
+        let output = self.execute_process("json").await?;
+        let parsed: GeminiJsonOutput =
+            serde_json::from_slice(&output.stdout).map_err(GeminiError::JsonParseFailed)?;
+
+        if let Some(err) = parsed.error {
+            return Err(GeminiError::ApiError(err.message));
+        }
+
+        let result = GeminiJsonOutput {
+            routing: self.routing_decision(),
+            budget: self.budget_decision(),
+            injection_findings: output.injection_findings,
+            ..parsed
+        };
+        if let Some(ledger) = &self.usage_ledger {
+            let at = storage::now_millis();
+            for event in UsageEvent::from_output(&result, &self.labels, at) {
+                ledger.record(event);
+            }
+        }
+
+        if let Ok(serialized) = wire::to_string(&result) {
+            self.cache_put(key.as_deref(), &serialized).await?;
+        }
+        Ok(result)
+    }
+
+    /// Run `attempt` until it succeeds, fails permanently or the retry policy gives up.
+    async fn with_retry<T, F, Fut>(&self, mut attempt: F) -> Result<T, GeminiError>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, GeminiError>>,
+    {
+        let Some(policy) = self.retry else {
+            return attempt().await;
+        };
+        let backoff = Interrupts {
+            deadline: None,
+            cancel: self.cancel_tokens(),
+            clock: self.clock.clone(),
+        };
+        let mut attempts = 1;
+        loop {
+            let error = match attempt().await {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            if attempts >= policy.max_attempts || !RetryPolicy::is_transient(&error) {
+                return Err(match attempts {
+                    1 => error,
+                    _ => GeminiError::RetriesExhausted {
+                        attempts,
+                        last: Box::new(error),
+                    },
+                });
+            }
+            if let Some(metrics) = &self.metrics {
+                metrics.increment(
+                    metrics::RETRIES,
+                    1,
+                    &metrics::with_labels(&[], &self.labels),
+                );
+            }
+            backoff
+                .guard(self.clock.sleep(policy.delay(attempts)))
+                .await?;
+            attempts += 1;
+        }
+    }
+
+    /// The cache key for this request, or `None` if caching is disabled, an injection guard
+    /// must inspect the input, or an input file cannot be read (in which case the request
+    /// simply bypasses the cache).
+    async fn cache_key(&self, format: &str) -> Option<String> {
+        self.cache.as_ref()?;
+        if self.injection_guard.is_some() {
+            return None;
+        }
+        self.exact_key(&[format.as_bytes()]).await
+    }
 
-        // CRITICAL: Spawn a separate background task to write to stdin.
-        // This prevents deadlocks if the CLI produces output while we are still writing input.
-        let input_data = self.input_data.clone();
-        let input_files = self.input_files.clone();
-        tokio::spawn(async move {
-            let _ = Self::write_stdin(stdin, input_data, input_files).await;
-        });
+    async fn cache_get(&self, key: Option<&str>) -> Result<Option<String>, GeminiError> {
+        match (&self.cache, key) {
+            (Some(cache), Some(key)) => {
+                let hit = cache.get(key).await?;
+                for monitor in &self.monitors {
+                    monitor.record_cache(hit.is_some());
+                }
+                Ok(hit)
+            }
+            _ => Ok(None),
+        }
+    }
 
-        let reader = BufReader::new(stdout);
+    async fn cache_put(&self, key: Option<&str>, value: &str) -> Result<(), GeminiError> {
+        match (&self.cache, key) {
+            (Some(cache), Some(key)) => cache.put(key, value.to_string()).await,
+            _ => Ok(()),
+        }
+    }
 
-        // Convert the newline-delimited JSON output into a Rust Stream
-        let stream = async_stream::try_stream! {
-            let mut lines = reader.lines();
-            while let Ok(Some(line)) = lines.next_line().await {
-                if line.trim().is_empty() { continue; }
-                let event: StreamEvent = serde_json::from_str(&line)
-                    .map_err(GeminiError::JsonParseFailed)?;
-                yield event;
-            }
-        };
+    /// Launch the CLI, unless the request is already cancelled.
+    ///
+    /// Returns the temporary files handed to the CLI, which must be kept until it exits.
+    fn spawn(
+        &self,
+        mut cmd: Command,
+    ) -> Result<(tokio::process::Child, Vec<temp::TempFile>), GeminiError> {
+        if self
+            .cancel_tokens()
+            .iter()
+            .any(CancellationToken::is_cancelled)
+        {
+            return Err(GeminiError::Cancelled);
+        }
+        let mut files = Vec::new();
+        if let Some(instruction) = &self.system {
+            let file =
+                temp::TempFile::create("-system.md", instruction.as_bytes()).map_err(|e| {
+                    GeminiError::StorageError(format!(
+                        "failed to write the system instruction: {e}"
+                    ))
+                })?;
+            cmd.env("GEMINI_SYSTEM_MD", file.path());
+            files.push(file);
+        }
+        if let Some(memory) = self
+            .memory_text()
+            .map_err(|e| GeminiError::StorageError(format!("failed to read a memory file: {e}")))?
+        {
+            let file =
+                temp::TempFile::create_named("GEMINI.md", memory.as_bytes()).map_err(|e| {
+                    GeminiError::StorageError(format!("failed to write the memory file: {e}"))
+                })?;
+            // The `=` form keeps the flag from swallowing anything after it.
+            cmd.arg(format!("--include-directories={}", file.dir().display()));
+            files.push(file);
+        }
+        if let Some(settings) = self.cli_settings() {
+            let file = temp::TempFile::create("-settings.json", settings.to_string().as_bytes())
+                .map_err(|e| {
+                    GeminiError::StorageError(format!("failed to write the CLI settings: {e}"))
+                })?;
+            cmd.env(settings::ENV, file.path());
+            files.push(file);
+        }
+        let child = cmd.spawn().map_err(|e| {
+            let diagnostics =
+                LaunchDiagnostics::gather(&self.bin_path, self.current_dir.as_deref());
+            GeminiError::CliLaunchFailed(LaunchFailure::wrap(e, diagnostics))
+        })?;
+        Ok((child, files))
+    }
 
-        Ok(stream)
+    /// The process group of a freshly launched CLI.
+    fn process_group(&self, child: &tokio::process::Child) -> spawn::ProcessGroup {
+        spawn::ProcessGroup::of(child, &self.spawn_options, !self.detach_on_drop)
     }
 
-    // =====================================================================
-    //  3. Internal Helpers
-    // =====================================================================
+    /// The timeout and cancellation for a CLI process launched now.
+    fn interrupts(&self) -> Interrupts {
+        Interrupts {
+            deadline: self.timeout.map(|limit| (self.clock.now() + limit, limit)),
+            cancel: self.cancel_tokens(),
+            clock: self.clock.clone(),
+        }
+    }
+
+    /// The request's own cancellation token, plus the shutdown tokens of the clients it
+    /// belongs to.
+    fn cancel_tokens(&self) -> Vec<CancellationToken> {
+        self.cancel
+            .iter()
+            .cloned()
+            .chain(self.monitors.iter().map(|monitor| monitor.shutdown.clone()))
+            .collect()
+    }
+
+    /// Count the request as in flight for its queues and clients until the tickets are
+    /// dropped, or refuse it if a client has shut down.
+    fn begin(&self) -> Result<Vec<jobs::Ticket>, GeminiError> {
+        self.monitors
+            .iter()
+            .map(|monitor| monitor.begin())
+            .collect()
+    }
+
+    /// Report a spawned CLI process to the monitoring queues and clients until the guards are
+    /// dropped.
+    fn register(&self, child: &tokio::process::Child) -> Vec<jobs::Registration> {
+        self.monitors
+            .iter()
+            .map(|monitor| monitor.register(child.id(), self.resolved_model()))
+            .collect()
+    }
 
     fn build_command(&self, format: &str) -> Command {
         let mut cmd = Command::new(&self.bin_path);
+        cmd.kill_on_drop(!self.detach_on_drop);
+        self.spawn_options.apply(&mut cmd);
+        self.proxy
+            .as_ref()
+            .unwrap_or_else(|| proxy::system())
+            .apply(&mut cmd);
+        cmd.envs(self.env.iter().map(|(key, value)| (key, value)));
+        #[cfg(unix)]
+        // SAFETY: hooks can only be registered through the `unsafe` `pre_exec`.
+        unsafe {
+            spawn::apply_pre_exec(&mut cmd, &self.pre_exec);
+        }
+        if let Some(dir) = &self.current_dir {
+            cmd.current_dir(dir);
+        }
         cmd.arg("--output-format").arg(format);
 
-        if let Some(m) = &self.model {
+        if let Some(m) = &self.resolved_model() {
             cmd.arg("--model").arg(m);
         }
-        if self.yolo {
-            cmd.arg("--yolo");
+        match self.approval {
+            ApprovalMode::Default => {}
+            // `--yolo` predates `--approval-mode` and works with every CLI version.
+            ApprovalMode::Yolo => {
+                cmd.arg("--yolo");
+            }
+            mode => {
+                cmd.arg("--approval-mode").arg(mode.as_str());
+            }
+        }
+        let preapproved = self.preapproved_tools();
+        if !preapproved.is_empty() {
+            // The `=` form keeps the list from swallowing the positional prompt.
+            cmd.arg(format!("--allowed-tools={}", preapproved.join(",")));
+        }
+        if !self.extensions.is_empty() {
+            cmd.arg(format!("--extensions={}", self.extensions.join(",")));
+        }
+        if self.checkpointing {
+            cmd.arg("--checkpointing");
+        }
+        if let Some(session) = &self.resume {
+            cmd.arg("--resume").arg(session.as_str());
+        }
+        if self.sandbox {
+            cmd.arg("--sandbox");
+        }
+        if let Some(image) = &self.sandbox_image {
+            cmd.arg("--sandbox-image").arg(image);
         }
         if self.debug {
             cmd.arg("--debug");
         }
+        if !self.debug_filter.is_empty() {
+            cmd.env("DEBUG", self.debug_filter.join(","));
+        }
         if !self.include_dirs.is_empty() {
             cmd.arg("--include-directories")
                 .arg(self.include_dirs.join(","));
         }
+        if self.all_files {
+            cmd.arg("--all-files");
+        }
 
-        cmd.arg(&self.prompt);
+        match self.cli_version {
+            Some(version) if !version.takes_positional_prompt() => {
+                cmd.arg("--prompt").arg(self.effective_prompt());
+            }
+            _ => {
+                cmd.arg(self.effective_prompt());
+            }
+        }
         cmd
     }
 
-    async fn execute_process(&self, format: &str) -> Result<Vec<u8>, GeminiError> {
+    /// The tools passed as `--allowed-tools`: the [allowed](Self::allow_tools) ones and those
+    /// the [`tool_policy`](Self::tool_policy) allows by name.
+    fn preapproved_tools(&self) -> Vec<String> {
+        let mut tools = self.allowed_tools.clone();
+        if let Some(policy) = &self.tool_policy {
+            let (allowed, _) = policy.cli_tools();
+            tools.extend(
+                allowed
+                    .into_iter()
+                    .filter(|t| !self.allowed_tools.contains(t)),
+            );
+        }
+        tools
+    }
+
+    /// The tools the agent is never offered: the [excluded](Self::exclude_tools) ones and
+    /// those the [`tool_policy`](Self::tool_policy) denies by name.
+    fn unavailable_tools(&self) -> Vec<String> {
+        let mut tools = self.excluded_tools.clone();
+        if let Some(policy) = &self.tool_policy {
+            let (_, excluded) = policy.cli_tools();
+            tools.extend(
+                excluded
+                    .into_iter()
+                    .filter(|t| !self.excluded_tools.contains(t)),
+            );
+        }
+        tools
+    }
+
+    /// Fail with `ValidationFailed` if a [`tool_policy`](Self::tool_policy) is set: only
+    /// `stream()` sees the agent's tool calls, so no other way of running can enforce it.
+    fn check_tool_policy_is_enforced(&self) -> Result<(), GeminiError> {
+        match &self.tool_policy {
+            Some(_) => Err(GeminiError::ValidationFailed(
+                "a tool policy is only enforced on stream(); use stream(), transcript() or \
+                 spawn_detached_streaming() instead"
+                    .into(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail with `PolicyDenied` if the [`safety_lock`](Self::safety_lock) refuses the request.
+    fn check_safety_lock(&self) -> Result<(), GeminiError> {
+        match &self.safety_lock {
+            Some(lock) => lock.check(self),
+            None => Ok(()),
+        }
+    }
+
+    async fn execute_process(&self, format: &str) -> Result<ProcessOutput, GeminiError> {
+        self.check_safety_lock()?;
+        let _tickets = self.begin()?;
+        let permit = match &self.circuit_breaker {
+            Some(breaker) => Some(breaker.admit()?),
+            None => None,
+        };
+        let result = self.execute_admitted(format).await;
+        if let Some(permit) = permit {
+            permit.record(&result);
+        }
+        result
+    }
+
+    async fn execute_admitted(&self, format: &str) -> Result<ProcessOutput, GeminiError> {
+        if let Some(simulator) = &self.simulator {
+            let model = self.resolved_model();
+            return Ok(ProcessOutput {
+                stdout: simulator
+                    .output(&self.prompt, model.as_deref(), format)
+                    .await,
+                injection_findings: Vec::new(),
+            });
+        }
+        match self.spill_context()? {
+            Some((spilled, _context_file)) => spilled.run_process(format).await,
+            None => self.run_process(format).await,
+        }
+    }
+
+    async fn run_process(&self, format: &str) -> Result<ProcessOutput, GeminiError> {
         let mut cmd = self.build_command(format);
         cmd.stdout(Stdio::piped())
             .stdin(Stdio::piped())
             .stderr(Stdio::piped());
 
-        let mut child = cmd.spawn().map_err(GeminiError::CliLaunchFailed)?;
+        let (mut child, _temp_files) = self.spawn(cmd)?;
+        let mut group = self.process_group(&child);
+        let interrupts = self.interrupts();
+        let _registration = self.register(&child);
 
         // Handle input piping in background to support large files
+        let (verdict_tx, verdict_rx) = oneshot::channel();
         if let Some(stdin) = child.stdin.take() {
             let data = self.input_data.clone();
             let files = self.input_files.clone();
+            let guard = self.injection_guard.clone();
+            let truncation = self.truncation.clone();
+            let framing = self.framing.clone();
             tokio::spawn(async move {
-                let _ = Self::write_stdin(stdin, data, files).await;
+                let _ =
+                    Self::write_stdin(stdin, data, files, guard, truncation, framing, verdict_tx)
+                        .await;
             });
         }
 
-        let output = child
-            .wait_with_output()
-            .await
-            .map_err(GeminiError::CliLaunchFailed)?;
+        let verdict = match interrupts.guard(verdict_rx).await {
+            Ok(verdict) => verdict,
+            Err(stopped) => {
+                group.kill();
+                let _ = child.kill().await;
+                return Err(stopped);
+            }
+        };
+        let injection_findings = match verdict {
+            Ok(Ok(findings)) => findings,
+            Ok(Err(rejected)) => {
+                group.kill();
+                let _ = child.kill().await;
+                return Err(rejected);
+            }
+            Err(_) => Vec::new(),
+        };
+
+        let output = match interrupts.guard(collect_output(&mut child)).await {
+            Ok(output) => {
+                group.release();
+                output.map_err(GeminiError::CliLaunchFailed)?
+            }
+            Err(stopped) => {
+                group.kill();
+                let _ = child.kill().await;
+                return Err(stopped);
+            }
+        };
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
             return Err(GeminiError::RuntimeError(stderr.into_owned()));
         }
 
-        Ok(output.stdout)
+        Ok(ProcessOutput {
+            stdout: output.stdout,
+            injection_findings,
+        })
     }
 
+    /// Write the piped context to the CLI's stdin.
+    ///
+    /// The injection verdict is always sent before the first byte is written. With a guard,
+    /// all input is read and screened up front so rejected content never reaches the CLI.
+    /// Files are framed after truncation, so fences are never cut.
     async fn write_stdin(
         mut stdin: tokio::process::ChildStdin,
         text: Option<String>,
         files: Vec<PathBuf>,
+        guard: Option<InjectionGuard>,
+        truncation: Option<Truncation>,
+        framing: Option<Arc<dyn LanguageDetector>>,
+        verdict: oneshot::Sender<Result<Vec<Finding>, GeminiError>>,
     ) -> std::io::Result<()> {
+        if guard.is_some() || truncation.is_some() || framing.is_some() {
+            let mut pieces = Vec::new();
+            let has_text = text.is_some();
+            if let Some(t) = text {
+                pieces.push(("context".to_string(), t));
+            }
+            for path in files {
+                match tokio::fs::read(&path).await {
+                    Ok(bytes) => pieces.push((
+                        path.display().to_string(),
+                        String::from_utf8_lossy(&bytes).into_owned(),
+                    )),
+                    Err(e) => {
+                        let _ = verdict.send(Ok(Vec::new()));
+                        return Err(e);
+                    }
+                }
+            }
+            if let Some(truncation) = truncation {
+                pieces = truncation.apply(pieces);
+            }
+            if let Some(detector) = framing {
+                // Truncation keeps the order, so the context is first if it survived.
+                let context_kept = has_text && pieces.first().is_some_and(|(s, _)| s == "context");
+                for (source, text) in pieces.iter_mut().skip(usize::from(context_kept)) {
+                    *text = framing::frame(Path::new(source), text, detector.as_ref());
+                }
+            }
+            let Some(guard) = guard else {
+                let _ = verdict.send(Ok(Vec::new()));
+                for (_, text) in pieces {
+                    stdin.write_all(text.as_bytes()).await?;
+                    stdin.write_all(b"\n").await?;
+                }
+                return Ok(());
+            };
+
+            let mut findings = Vec::new();
+            let mut screened = Vec::with_capacity(pieces.len());
+            for (source, text) in pieces {
+                match guard.apply(&source, text) {
+                    Ok((text, found)) => {
+                        screened.push(text);
+                        findings.extend(found);
+                    }
+                    Err(rejected) => {
+                        let _ = verdict.send(Err(rejected));
+                        return Ok(());
+                    }
+                }
+            }
+            let _ = verdict.send(Ok(findings));
+
+            for text in screened {
+                stdin.write_all(text.as_bytes()).await?;
+                stdin.write_all(b"\n").await?;
+            }
+            return Ok(());
+        }
+
+        let _ = verdict.send(Ok(Vec::new()));
         if let Some(t) = text {
             stdin.write_all(t.as_bytes()).await?;
             stdin.write_all(b"\n").await?;
@@ -413,6 +3291,101 @@ impl Gemini {
 //  4. Type Definitions
 // =========================================================================
 
+/// Characters of already-streamed text re-checked with each new delta during moderation.
+const MODERATION_OVERLAP: usize = 64;
+
+/// The last `n` characters of `text`.
+fn tail_chars(text: &str, n: usize) -> String {
+    let skip = text.chars().count().saturating_sub(n);
+    text.chars().skip(skip).collect()
+}
+
+/// A piece of a request's [`memory`](Gemini::memory).
+#[derive(Debug, Clone)]
+enum Memory {
+    Text(String),
+    File(PathBuf),
+}
+
+/// What can stop a running CLI process early: its timeout and its cancellation tokens.
+struct Interrupts {
+    /// When the timeout expires, and the configured limit.
+    deadline: Option<(tokio::time::Instant, Duration)>,
+    cancel: Vec<CancellationToken>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Interrupts {
+    /// Await `future`, failing with `Cancelled` or `Timeout` if either comes first.
+    async fn guard<T>(
+        &self,
+        future: impl std::future::Future<Output = T>,
+    ) -> Result<T, GeminiError> {
+        let cancelled = async {
+            if self.cancel.is_empty() {
+                std::future::pending::<()>().await;
+            }
+            futures_util::future::select_all(
+                self.cancel.iter().map(|token| Box::pin(token.cancelled())),
+            )
+            .await;
+        };
+        let expired = async {
+            match self.deadline {
+                Some((at, limit)) => {
+                    self.clock.sleep_until(at).await;
+                    limit
+                }
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            biased;
+            _ = cancelled => Err(GeminiError::Cancelled),
+            limit = expired => Err(GeminiError::Timeout(limit)),
+            output = future => Ok(output),
+        }
+    }
+}
+
+/// Read a child's stdout and stderr to the end and wait for it to exit.
+///
+/// Unlike `wait_with_output`, the child stays owned by the caller, so it can still be killed
+/// if this is abandoned.
+async fn collect_output(
+    child: &mut tokio::process::Child,
+) -> std::io::Result<std::process::Output> {
+    use tokio::io::AsyncReadExt;
+
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let read_stdout = async {
+        match child.stdout.take() {
+            Some(mut pipe) => pipe.read_to_end(&mut stdout).await.map(drop),
+            None => Ok(()),
+        }
+    };
+    let read_stderr = async {
+        match child.stderr.take() {
+            Some(mut pipe) => pipe.read_to_end(&mut stderr).await.map(drop),
+            None => Ok(()),
+        }
+    };
+    tokio::try_join!(read_stdout, read_stderr)?;
+    let status = child.wait().await?;
+    Ok(std::process::Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Everything collected from a finished CLI process.
+struct ProcessOutput {
+    stdout: Vec<u8>,
+    injection_findings: Vec<Finding>,
+}
+
 /// Structured response from the Gemini CLI when using JSON mode.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GeminiJsonOutput {
@@ -424,10 +3397,49 @@ pub struct GeminiJsonOutput {
     /// Error details if the API returned a structured error.
     #[serde(default)]
     pub error: Option<GeminiErrorDetail>,
+    /// The routing decision made by the SDK, if a [`ModelRouter`] was used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub routing: Option<RoutingDecision>,
+    /// The plan made by the SDK, if a [`deadline`](Gemini::deadline) or
+    /// [`token_budget`](Gemini::token_budget) was set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<BudgetDecision>,
+    /// Suspicious lines found in piped context, if an [`InjectionGuard`] was used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub injection_findings: Vec<Finding>,
+    /// Sources cited in the response and whether they hold up, if
+    /// [`verify_citations`](Gemini::verify_citations) was used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<CitationCheck>,
+    /// Estimated tokens saved, if [`compress`](Gemini::compress) was used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionReport>,
+    /// Whether the response was cut off at the [`max_output_tokens`](Gemini::max_output_tokens)
+    /// limit.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// The request's [labels](Gemini::label).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl GeminiJsonOutput {
+    /// The model that actually served the request.
+    ///
+    /// Prefers the model reported in the CLI's stats and falls back to the budget and routing
+    /// decisions.
+    pub fn served_model(&self) -> Option<&str> {
+        self.stats
+            .as_ref()
+            .and_then(|s| s.models.keys().next())
+            .map(String::as_str)
+            .or_else(|| self.budget.as_ref().map(|b| b.model.as_str()))
+            .or_else(|| self.routing.as_ref().map(|r| r.model.as_str()))
+    }
 }
 
 /// Aggregated statistics for the session.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct GeminiStats {
     /// Statistics per model (token counts, latency).
     pub models: HashMap<String, ModelStats>,
@@ -438,7 +3450,7 @@ pub struct GeminiStats {
 }
 
 /// Statistics specific to a single model interaction.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ModelStats {
     /// API performance metrics (latency, request count).
     pub api: HashMap<String, serde_json::Value>,
@@ -447,7 +3459,7 @@ pub struct ModelStats {
 }
 
 /// Summary of tool usage during the session.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ToolStats {
     pub total_calls: u64,
@@ -456,13 +3468,78 @@ pub struct ToolStats {
 }
 
 /// Summary of file changes made by the agent.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FileStats {
     pub total_lines_added: u64,
     pub total_lines_removed: u64,
 }
 
+/// The stats reported by a stream's [`Result`](StreamEvent::Result) event.
+///
+/// Parsed into the [`GeminiStats`] that [`json()`](Gemini::json) returns when they match its
+/// shape, and kept as raw JSON otherwise.
+///
+/// # Example
+///
+/// ```rust
+/// use gemini_oxide::StreamStats;
+/// use serde_json::json;
+///
+/// let stats = StreamStats::from(json!({
+///     "models": { "gemini-2.5-pro": { "api": {}, "tokens": { "candidates": 12 } } },
+///     "tools": { "totalCalls": 0, "totalSuccess": 0, "totalFail": 0 },
+///     "files": { "totalLinesAdded": 0, "totalLinesRemoved": 0 },
+/// }));
+/// let tokens = &stats.typed().unwrap().models["gemini-2.5-pro"].tokens;
+/// assert_eq!(tokens["candidates"], 12);
+///
+/// let stats = StreamStats::from(json!({ "total_tokens": 30 }));
+/// assert!(stats.typed().is_none());
+/// assert_eq!(stats.to_value()["total_tokens"], 30);
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum StreamStats {
+    /// Stats in the shape [`json()`](Gemini::json) reports.
+    Typed(GeminiStats),
+    /// Stats in any other shape.
+    Raw(serde_json::Value),
+}
+
+impl StreamStats {
+    /// The stats as [`GeminiStats`], if they have that shape.
+    pub fn typed(&self) -> Option<&GeminiStats> {
+        match self {
+            Self::Typed(stats) => Some(stats),
+            Self::Raw(_) => None,
+        }
+    }
+
+    /// The stats as JSON, whatever their shape.
+    pub fn to_value(&self) -> serde_json::Value {
+        match self {
+            Self::Typed(stats) => serde_json::to_value(stats).unwrap_or_default(),
+            Self::Raw(value) => value.clone(),
+        }
+    }
+}
+
+impl From<serde_json::Value> for StreamStats {
+    fn from(value: serde_json::Value) -> Self {
+        match GeminiStats::deserialize(&value) {
+            Ok(stats) => Self::Typed(stats),
+            Err(_) => Self::Raw(value),
+        }
+    }
+}
+
+impl From<GeminiStats> for StreamStats {
+    fn from(stats: GeminiStats) -> Self {
+        Self::Typed(stats)
+    }
+}
+
 /// Details of an error returned by the API.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GeminiErrorDetail {
@@ -475,7 +3552,7 @@ pub struct GeminiErrorDetail {
 /// Event types emitted during streaming.
 ///
 /// Use `StreamEvent` with the `.stream()` method to handle real-time updates.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum StreamEvent {
     /// Initial session metadata (model name, session ID).
@@ -491,6 +3568,14 @@ pub enum StreamEvent {
         delta: Option<bool>,
         timestamp: String,
     },
+    /// A chunk of a thinking model's reasoning, separate from the answer in `Message` events.
+    /// `subject` is the CLI's short heading for the thought, if any.
+    Thought {
+        content: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        subject: Option<String>,
+        timestamp: String,
+    },
     /// Notification that the agent is invoking a tool.
     ToolUse {
         tool_name: String,
@@ -507,28 +3592,176 @@ pub enum StreamEvent {
     /// Final completion event containing stats.
     Result {
         status: String,
-        stats: serde_json::Value,
+        stats: StreamStats,
+        timestamp: String,
+    },
+    /// The web search results the answer is grounded in, when search grounding is used. Also
+    /// parsed from the Gemini API's `groundingMetadata` field names.
+    Grounding {
+        /// The queries the model searched for.
+        #[serde(default, alias = "webSearchQueries")]
+        queries: Vec<String>,
+        /// The pages found.
+        #[serde(default, alias = "groundingChunks")]
+        sources: Vec<GroundingSource>,
+        /// Which spans of the answer each source supports.
+        #[serde(default, alias = "groundingSupports")]
+        citations: Vec<GroundingCitation>,
         timestamp: String,
     },
     /// An error occurred during the stream.
     Error { message: String },
+    /// Synthesized by the SDK (never by the CLI) when no output arrived for the configured
+    /// [`heartbeat`](Gemini::heartbeat) interval. `idle_ms` is the time since the last output.
+    Heartbeat { idle_ms: u64 },
+    /// Synthesized by the SDK when [`plan_events`](Gemini::plan_events) is enabled and the model
+    /// lays out (or revises) a plan.
+    Plan { steps: Vec<PlanStep> },
+    /// Synthesized by the SDK when [`plan_events`](Gemini::plan_events) is enabled and the model
+    /// announces its next action. `index` is the closest step of the current plan, if any.
+    PlanUpdate {
+        action: String,
+        index: Option<usize>,
+        status: PlanStepStatus,
+    },
+    /// An event of a type this version of the SDK does not know, e.g. from a newer CLI.
+    /// `raw` is the event as received.
+    Other {
+        event_type: String,
+        raw: serde_json::Value,
+    },
+}
+
+impl StreamEvent {
+    /// The CLI session this stream belongs to, if this is its `Init` event. Continue the
+    /// session with [`Gemini::resume`].
+    pub fn session_id(&self) -> Option<SessionId> {
+        match self {
+            StreamEvent::Init { session_id, .. } => Some(SessionId::new(session_id.as_str())),
+            _ => None,
+        }
+    }
+
+    /// Parse one line of `stream-json` output, as `stream()` does.
+    ///
+    /// Blank lines are skipped (`Ok(None)`), and legacy events are
+    /// [upgraded](compat::upgrade_event) before parsing. Never panics, whatever the input.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::JsonParseFailed` if the line is not JSON, or is an event of a
+    /// known type that does not parse. Events of unknown types become [`StreamEvent::Other`].
+    ///
+    /// ```rust
+    /// use gemini_oxide::StreamEvent;
+    ///
+    /// let line = r#"{"type":"message","role":"model","content":"Hi","delta":true,"timestamp":""}"#;
+    /// assert!(matches!(StreamEvent::parse_line(line), Ok(Some(StreamEvent::Message { .. }))));
+    /// assert!(matches!(StreamEvent::parse_line("  "), Ok(None)));
+    /// assert!(StreamEvent::parse_line("not json").is_err());
+    /// ```
+    pub fn parse_line(line: &str) -> Result<Option<StreamEvent>, GeminiError> {
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        compat::parse_event(line)
+            .map(Some)
+            .or_else(|error| match compat::unknown_event(line) {
+                Some((event_type, raw)) => Ok(Some(StreamEvent::Other { event_type, raw })),
+                None => Err(GeminiError::JsonParseFailed(error)),
+            })
+    }
+
+    /// Whether this event was generated by the SDK rather than emitted by the CLI.
+    pub fn is_synthetic(&self) -> bool {
+        matches!(
+            self,
+            StreamEvent::Heartbeat { .. }
+                | StreamEvent::Plan { .. }
+                | StreamEvent::PlanUpdate { .. }
+        )
+    }
 }
 
 /// Errors that can occur when using the SDK.
 #[derive(thiserror::Error, Debug)]
 pub enum GeminiError {
-    /// The `gemini` CLI could not be launched. Check that it is installed and in your PATH.
+    /// The `gemini` CLI could not be launched. Check that it is installed and in your PATH;
+    /// [`launch_diagnostics`](Self::launch_diagnostics) describes the environment it was
+    /// launched in.
     #[error("Failed to start Gemini CLI. Is it installed?")]
     CliLaunchFailed(#[source] std::io::Error),
     /// The CLI output could not be parsed as JSON.
     #[error("Failed to parse JSON output")]
     JsonParseFailed(#[source] serde_json::Error),
+    /// The model's response could not be deserialized into the type requested with
+    /// [`json_as`](Gemini::json_as). The CLI output itself was valid.
+    #[error("Failed to parse the model's response: {source}")]
+    ResponseParseFailed {
+        /// Why deserialization failed.
+        #[source]
+        source: serde_json::Error,
+        /// The model's response text.
+        response: String,
+    },
     /// The Gemini API returned an error (e.g., quota exceeded).
     #[error("Gemini API Error: {0}")]
     ApiError(String),
     /// A general runtime error (non-zero exit code or stderr output).
     #[error("Runtime Error: {0}")]
     RuntimeError(String),
+    /// The model's output did not satisfy the requested schema or validator.
+    #[error("Validation Error: {0}")]
+    ValidationFailed(String),
+    /// A storage backend failed to read or write data.
+    #[error("Storage Error: {0}")]
+    StorageError(String),
+    /// Piped context contained a likely prompt injection and the guard rejected the request.
+    #[error("Prompt Injection Detected: {0}")]
+    InjectionDetected(String),
+    /// A moderator blocked the model's output.
+    #[error("Content Blocked: {0}")]
+    ContentBlocked(String),
+    /// A tool call was denied by the request's tool policy.
+    #[error("Policy Denied: {0}")]
+    PolicyDenied(String),
+    /// The model refused the task or gave a non-answer, and refusal detection was enabled.
+    #[error("Model Refused: {0}")]
+    Refused(String),
+    /// The CLI ran longer than the request's [`timeout`](Gemini::timeout) and was killed.
+    #[error("Timed Out after {0:?}")]
+    Timeout(Duration),
+    /// The request's [`CancellationToken`] was cancelled and the CLI was killed.
+    #[error("Request Cancelled")]
+    Cancelled,
+    /// The request's [circuit breaker](Gemini::circuit_breaker) is open after repeated
+    /// failures; the CLI was not launched. Carries the rest of the cooldown, or zero while
+    /// another request is trying whether the backend has recovered.
+    #[error("Circuit Open: retry in {0:?}")]
+    CircuitOpen(Duration),
+    /// The request was [retried](Gemini::retry) and still failed.
+    #[error("Failed after {attempts} attempts: {last}")]
+    RetriesExhausted {
+        /// How many times the CLI was run.
+        attempts: u32,
+        /// The error from the last attempt.
+        #[source]
+        last: Box<GeminiError>,
+    },
+}
+
+impl GeminiError {
+    /// What the SDK found when the CLI failed to launch: the binary it tried, `PATH`, Node.js
+    /// version and configuration directory. `None` for other errors.
+    pub fn launch_diagnostics(&self) -> Option<&LaunchDiagnostics> {
+        match self {
+            Self::CliLaunchFailed(error) => error
+                .get_ref()?
+                .downcast_ref::<LaunchFailure>()
+                .map(|failure| &failure.diagnostics),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -540,7 +3773,7 @@ mod tests {
         let g = Gemini::new("hello");
         assert_eq!(g.prompt, "hello");
         assert_eq!(g.bin_path, PathBuf::from("gemini"));
-        assert!(!g.yolo);
+        assert_eq!(g.approval, ApprovalMode::Default);
     }
 
     #[test]
@@ -551,7 +3784,7 @@ mod tests {
             .model("gpt-4");
 
         assert_eq!(g.bin_path, PathBuf::from("/tmp/test"));
-        assert!(g.yolo);
+        assert_eq!(g.approval, ApprovalMode::Yolo);
         assert_eq!(g.model, Some("gpt-4".to_string()));
     }
 
@@ -565,4 +3798,359 @@ mod tests {
         assert!(debug_str.contains("test"));
         assert!(debug_str.contains("my-model"));
     }
+
+    #[test]
+    fn test_include_dirs_resolve_against_current_dir() {
+        let cmd = Gemini::new("Hi")
+            .current_dir("/srv/workspaces/42")
+            .include("docs")
+            .build_command("text");
+        let std = cmd.as_std();
+        assert_eq!(
+            std.get_current_dir(),
+            Some(std::path::Path::new("/srv/workspaces/42"))
+        );
+        let args: Vec<_> = std.get_args().collect();
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--include-directories", "docs"]));
+    }
+
+    #[test]
+    fn test_approval_mode_flags() {
+        let args = |g: Gemini| -> Vec<String> {
+            let cmd = g.build_command("text");
+            let args = cmd.as_std().get_args();
+            args.map(|a| a.to_string_lossy().into_owned()).collect()
+        };
+        let auto_edit = args(Gemini::new("Hi").approval(ApprovalMode::AutoEdit));
+        assert!(auto_edit
+            .windows(2)
+            .any(|w| w == ["--approval-mode", "auto_edit"]));
+        assert!(args(Gemini::new("Hi").yolo()).contains(&"--yolo".to_string()));
+        assert!(!args(Gemini::new("Hi"))
+            .iter()
+            .any(|a| a.starts_with("--approval")));
+    }
+
+    #[test]
+    fn test_allowed_tools_are_allowed_and_exclusive() {
+        let g = Gemini::new("Hi").allow_tools(["read_file", "glob"]);
+        let cmd = g.build_command("text");
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        assert!(args.contains(&"--allowed-tools=read_file,glob".as_ref()));
+        assert_eq!(
+            g.cli_settings().unwrap()["tools"]["core"],
+            serde_json::json!(["read_file", "glob"])
+        );
+    }
+
+    #[test]
+    fn test_excluded_tools_are_excluded_in_yolo_mode() {
+        let g = Gemini::new("Hi")
+            .yolo()
+            .allow_tools(["read_file"])
+            .exclude_tools(["run_shell_command"]);
+        let settings = g.cli_settings().unwrap();
+        assert_eq!(settings["tools"]["core"], serde_json::json!(["read_file"]));
+        assert_eq!(
+            settings["tools"]["exclude"],
+            serde_json::json!(["run_shell_command"])
+        );
+    }
+
+    #[test]
+    fn test_tool_policy_name_rules_reach_the_cli() {
+        use policy::{Effect, PolicyRule};
+
+        let policy = ToolPolicy::new(Effect::Deny)
+            .rule(PolicyRule::new(Effect::Allow).tool("read_file"))
+            .rule(
+                PolicyRule::new(Effect::Allow)
+                    .tool("write_file")
+                    .path("src/*"),
+            );
+        let g = Gemini::new("Hi")
+            .yolo()
+            .exclude_tools(["web_fetch"])
+            .tool_policy(policy);
+        let cmd = g.build_command("stream-json");
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        assert!(args.contains(&"--allowed-tools=read_file".as_ref()));
+        let excluded = g.cli_settings().unwrap()["tools"]["exclude"].clone();
+        let excluded: Vec<String> = serde_json::from_value(excluded).unwrap();
+        assert_eq!(excluded[0], "web_fetch");
+        assert!(excluded.contains(&"run_shell_command".to_string()));
+        assert!(!excluded.contains(&"write_file".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_tool_policy_refuses_unstreamed_runs() {
+        let g = Gemini::new("Hi")
+            .simulator(Simulator::new())
+            .tool_policy(ToolPolicy::default());
+        assert!(matches!(
+            g.clone().text().await,
+            Err(GeminiError::ValidationFailed(_))
+        ));
+        assert!(matches!(
+            g.json().await,
+            Err(GeminiError::ValidationFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_mcp_servers_are_written_to_settings() {
+        let g = Gemini::new("Hi")
+            .mcp_server("old", McpServerConfig::stdio("old-mcp"))
+            .mcp_server("tickets", McpServerConfig::stdio("old-mcp"))
+            .mcp_server("tickets", McpServerConfig::sse("http://localhost:9000/sse"));
+        let settings = g.cli_settings().unwrap();
+        assert_eq!(settings["mcpServers"]["old"]["command"], "old-mcp");
+        assert_eq!(
+            settings["mcpServers"]["tickets"],
+            serde_json::json!({ "url": "http://localhost:9000/sse" })
+        );
+    }
+
+    #[test]
+    fn test_all_files_flag() {
+        let cmd = Gemini::new("Hi").all_files().build_command("text");
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        assert!(args.contains(&"--all-files".as_ref()));
+        assert_eq!(args.last().unwrap(), &"Hi");
+        let cmd = Gemini::new("Hi").build_command("text");
+        assert!(!cmd.as_std().get_args().any(|a| a == "--all-files"));
+    }
+
+    #[test]
+    fn test_memory_enables_loading_from_include_dirs() {
+        let g = Gemini::new("Hi").memory("Use tabs.").memory("Be brief.");
+        assert_eq!(
+            g.memory_text().unwrap().as_deref(),
+            Some("Use tabs.\n\nBe brief.")
+        );
+        assert_eq!(
+            g.cli_settings().unwrap()["context"]["loadMemoryFromIncludeDirectories"],
+            true
+        );
+        assert!(Gemini::new("Hi").cli_settings().is_none());
+        assert!(Gemini::new("Hi")
+            .memory_file("/nonexistent/GEMINI.md")
+            .memory_text()
+            .is_err());
+    }
+
+    #[test]
+    fn test_extensions_flag() {
+        let cmd = Gemini::new("Hi")
+            .extensions(["security"])
+            .extensions(["db"])
+            .build_command("text");
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        assert!(args.contains(&"--extensions=security,db".as_ref()));
+        assert_eq!(args.last().unwrap(), &"Hi");
+    }
+
+    #[tokio::test]
+    async fn test_resume_flag() {
+        let init: StreamEvent = serde_json::from_str(
+            r#"{"type":"init","session_id":"3f2a","model":"m","timestamp":""}"#,
+        )
+        .unwrap();
+        let session = init.session_id().unwrap();
+        let cmd = Gemini::new("Go on").resume(session).build_command("text");
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        assert!(args.windows(2).any(|w| w == ["--resume", "3f2a"]));
+        assert_eq!(args.last().unwrap(), &"Go on");
+        assert_ne!(
+            Gemini::new("Go on")
+                .resume(SessionId::latest())
+                .fingerprint()
+                .await,
+            Gemini::new("Go on").fingerprint().await
+        );
+    }
+
+    #[test]
+    fn test_result_stats_are_typed_when_they_match() {
+        let typed = StreamEvent::parse_line(
+            r#"{"type":"result","status":"success","timestamp":"","stats":{"models":{"m":{"api":{},"tokens":{"total":7}}},"tools":{"totalCalls":1,"totalSuccess":1,"totalFail":0},"files":{"totalLinesAdded":2,"totalLinesRemoved":0}}}"#,
+        );
+        let Ok(Some(StreamEvent::Result { stats, .. })) = typed else {
+            panic!("expected a result, got {typed:?}");
+        };
+        let typed = stats.typed().unwrap();
+        assert_eq!(typed.models["m"].tokens["total"], 7);
+        assert_eq!(typed.tools.total_calls, 1);
+
+        let raw = StreamEvent::parse_line(
+            r#"{"type":"result","status":"success","timestamp":"","stats":{"total_tokens":7}}"#,
+        );
+        let Ok(Some(StreamEvent::Result { stats, .. })) = raw else {
+            panic!("expected a result, got {raw:?}");
+        };
+        assert!(stats.typed().is_none());
+        assert_eq!(stats.to_value()["total_tokens"], 7);
+    }
+
+    #[test]
+    fn test_sandbox_flags() {
+        let cmd = Gemini::new("Hi")
+            .yolo()
+            .sandbox_image("sandbox:1")
+            .checkpointing()
+            .build_command("text");
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        assert!(args.contains(&"--sandbox".as_ref()));
+        assert!(args.contains(&"--checkpointing".as_ref()));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--sandbox-image", "sandbox:1"]));
+    }
+
+    #[test]
+    fn test_old_cli_versions_get_the_prompt_flag() {
+        let cmd = Gemini::new("Hi")
+            .cli_version(CliVersion::new(0, 7, 0))
+            .build_command("json");
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        assert!(args.ends_with(&["--prompt".as_ref(), "Hi".as_ref()]));
+        assert!(Gemini::new("Hi")
+            .cli_version(CliVersion::new(0, 7, 0))
+            .stream()
+            .is_err());
+    }
+
+    #[test]
+    fn test_env_vars_reach_the_cli_only() {
+        let cmd = Gemini::new("test")
+            .env("GOOGLE_CLOUD_PROJECT", "demo")
+            .envs([
+                ("GEMINI_API_KEY", "secret"),
+                ("GOOGLE_CLOUD_PROJECT", "other"),
+            ])
+            .build_command("text");
+        let envs: HashMap<_, _> = cmd
+            .as_std()
+            .get_envs()
+            .map(|(key, value)| (key.to_str().unwrap(), value.and_then(|v| v.to_str())))
+            .collect();
+        assert_eq!(envs["GEMINI_API_KEY"], Some("secret"));
+        assert_eq!(envs["GOOGLE_CLOUD_PROJECT"], Some("other"));
+        assert!(std::env::var_os("GEMINI_API_KEY").is_none_or(|key| key != "secret"));
+    }
+
+    #[test]
+    fn test_debug_filter_limits_categories() {
+        let cmd = Gemini::new("test")
+            .debug_filter(["tools"])
+            .debug_filter(["api"])
+            .build_command("text");
+        let std = cmd.as_std();
+        assert!(std.get_args().any(|arg| arg == "--debug"));
+        assert!(std
+            .get_envs()
+            .any(|(key, value)| key == "DEBUG" && value == Some("tools,api".as_ref())));
+    }
+
+    #[test]
+    fn test_respond_in_appends_language_instruction() {
+        let g = Gemini::new("Explain gravity").respond_in(Language::De);
+        assert!(g.effective_prompt().starts_with("Explain gravity\n\n"));
+        assert!(g.effective_prompt().contains("German (de)"));
+        assert!(g
+            .language_retry()
+            .effective_prompt()
+            .contains("previous answer"));
+        assert_eq!(
+            Gemini::new("Explain gravity").effective_prompt(),
+            "Explain gravity"
+        );
+    }
+
+    #[test]
+    fn test_router_selects_model_unless_pinned() {
+        let routed = Gemini::new("What is 2+2?").router(ModelRouter::default());
+        let debug_str = format!("{:?}", routed.build_command("json"));
+        assert!(debug_str.contains("gemini-2.5-flash"));
+
+        let pinned = Gemini::new("What is 2+2?")
+            .router(ModelRouter::default())
+            .model("my-model");
+        assert!(pinned.routing_decision().is_none());
+        let debug_str = format!("{:?}", pinned.build_command("json"));
+        assert!(debug_str.contains("my-model"));
+        assert!(!debug_str.contains("gemini-2.5-flash"));
+    }
+
+    #[test]
+    fn test_deadline_overrides_routed_model_and_caps_answer() {
+        let req = Gemini::new("Explain why this deadlocks step by step")
+            .router(ModelRouter::default())
+            .deadline(Duration::from_secs(5));
+        let debug_str = format!("{:?}", req.build_command("json"));
+        assert!(debug_str.contains("gemini-2.5-flash"));
+        assert!(debug_str.contains("Keep your answer under about 525 tokens"));
+        assert_eq!(req.routing_decision().unwrap().model, "gemini-2.5-pro");
+    }
+
+    #[tokio::test]
+    async fn test_fingerprint_ignores_formatting_and_order() {
+        let a = Gemini::new("Compare  these\n")
+            .context("rows: 1, 2")
+            .include("src")
+            .include("tests")
+            .file("Cargo.toml")
+            .file("README.md");
+        let b = Gemini::new("Compare these")
+            .context(" rows:\t1, 2")
+            .include("tests")
+            .include("src")
+            .file("README.md")
+            .file("Cargo.toml");
+        assert_eq!(a.fingerprint().await, b.fingerprint().await);
+        assert!(a.fingerprint().await.is_some());
+        assert_ne!(
+            a.fingerprint().await,
+            b.model("gemini-2.5-flash").fingerprint().await
+        );
+        let missing = Gemini::new("x").file("does-not-exist.txt");
+        assert_eq!(missing.fingerprint().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_exact_key_keeps_whitespace_and_file_order() {
+        let nested = Gemini::new("Why does this fail?").context("if ok:\n    run()\nstop()");
+        let flat = Gemini::new("Why does this fail?").context("if ok:\n    run()\n    stop()");
+        assert_eq!(nested.fingerprint().await, flat.fingerprint().await);
+        let key = nested.exact_key(&[b"json"]).await.unwrap();
+        assert_ne!(Some(&key), flat.exact_key(&[b"json"]).await.as_ref());
+        assert_ne!(Some(&key), nested.exact_key(&[b"text"]).await.as_ref());
+        assert_eq!(key.len(), "gemini:".len() + 64);
+
+        let ab = Gemini::new("x").file("Cargo.toml").file("README.md");
+        let ba = Gemini::new("x").file("README.md").file("Cargo.toml");
+        assert_eq!(ab.fingerprint().await, ba.fingerprint().await);
+        assert_ne!(ab.exact_key(&[]).await, ba.exact_key(&[]).await);
+    }
+
+    #[tokio::test]
+    async fn test_cache_key_covers_isolation_and_skips_guarded_requests() {
+        use crate::injection::{InjectionAction, InjectionGuard};
+        use crate::storage::InMemoryCacheStore;
+
+        let plain = Gemini::new("Summarize").cache(Arc::new(InMemoryCacheStore::new()));
+        let key = plain.cache_key("json").await.unwrap();
+        for other in [
+            plain.clone().sandbox(),
+            plain.clone().sandbox_image("sandbox:latest"),
+            plain.clone().env("LANG", "de_DE.UTF-8"),
+        ] {
+            assert_ne!(other.cache_key("json").await.unwrap(), key);
+        }
+
+        let guarded = plain.injection_guard(InjectionGuard::new(InjectionAction::Reject));
+        assert_eq!(guarded.cache_key("json").await, None);
+    }
 }
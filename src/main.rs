@@ -1,4 +1,5 @@
 use futures::stream::{FuturesUnordered, StreamExt};
+use gemini_oxide::pool::GeminiPool;
 use gemini_oxide::Gemini;
 use std::time::Instant;
 
@@ -18,7 +19,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // --- Pattern 2: FuturesUnordered (Dynamic Concurrency) ---
     // Perfect for processing a list of items where order doesn't matter
-    // and you want to maximize throughput.
+    // and you want to maximize throughput. The pool caps how many CLI
+    // processes run at once so a long list does not exhaust the quota.
     println!("\n--- Pattern 2: Dynamic Concurrency (FuturesUnordered) ---");
     let prompts = vec![
         "What is 2+2?",
@@ -27,11 +29,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         "What is the speed of sound?",
     ];
 
+    let pool = GeminiPool::new(2);
     let mut futures = FuturesUnordered::new();
 
     for prompt in prompts {
+        let pool = pool.clone();
         futures.push(async move {
-            let res = Gemini::new(prompt).text().await;
+            let res = pool.text(Gemini::new(prompt)).await;
             (prompt, res)
         });
     }
@@ -0,0 +1,336 @@
+//! Rendering streamed Markdown while it is being written.
+//!
+//! Mid-stream, the model's Markdown is usually unfinished: a code fence without its closing
+//! line turns the rest of the document into code, and half an emphasis or link renders as
+//! stray symbols until the closing delta arrives. Re-rendering the raw text on every delta
+//! makes live UIs flicker. An [`IncrementalMarkdown`] folds the deltas into one document and
+//! hands out snapshots that are complete Markdown:
+//!
+//! * an open code fence is closed, and a fence line still being typed is held back;
+//! * in the paragraph being written, open inline code, `*`/`**`/`***` emphasis and `~~`
+//!   strikethrough are closed, and markers with nothing after them yet are held back;
+//! * a link whose URL is still arriving is shown as its text.
+//!
+//! The balancing is heuristic and only touches the end of the document, so finished blocks
+//! render the same in every snapshot. [`MarkdownExt::markdown`] turns a `stream()` into a
+//! stream of snapshots.
+//!
+//! ```rust
+//! use gemini_oxide::markdown::IncrementalMarkdown;
+//!
+//! let mut doc = IncrementalMarkdown::new();
+//! assert_eq!(doc.push("Use **iter"), "Use **iter**");
+//! assert_eq!(doc.push("ators** like:\n\n```rust\nv.iter()"), "Use **iterators** like:\n\n```rust\nv.iter()\n```");
+//! assert_eq!(doc.push(".map(f)\n```\n"), doc.source());
+//! ```
+
+use crate::{GeminiError, StreamEvent};
+use futures_util::stream::{BoxStream, Stream, StreamExt};
+
+/// A Markdown document assembled from streamed deltas.
+#[derive(Debug, Clone, Default)]
+pub struct IncrementalMarkdown {
+    source: String,
+    /// Where the incomplete last line starts.
+    line_start: usize,
+    /// Where the block being written starts: after the last blank line or closed fence.
+    block_start: usize,
+    /// The open code fence, if any.
+    fence: Option<Fence>,
+}
+
+/// An open code fence: its character and length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fence {
+    marker: char,
+    len: usize,
+}
+
+impl IncrementalMarkdown {
+    /// An empty document.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append `delta` and return a render-safe snapshot of the document.
+    pub fn push(&mut self, delta: &str) -> String {
+        self.source.push_str(delta);
+        while let Some(end) = self.source[self.line_start..].find('\n') {
+            let end = self.line_start + end + 1;
+            self.complete_line(self.line_start, end);
+            self.line_start = end;
+        }
+        self.snapshot()
+    }
+
+    /// Everything received so far, unchanged.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The document so far, as complete Markdown.
+    pub fn snapshot(&self) -> String {
+        let last_line = &self.source[self.line_start..];
+        if let Some(fence) = self.fence {
+            // A closing fence being typed is dropped rather than shown as code.
+            let typed = last_line.trim();
+            let body = if !typed.is_empty() && typed.chars().all(|c| c == fence.marker) {
+                &self.source[..self.line_start]
+            } else {
+                &self.source
+            };
+            let mut snapshot = body.to_string();
+            if !snapshot.ends_with('\n') {
+                snapshot.push('\n');
+            }
+            snapshot.extend(std::iter::repeat_n(fence.marker, fence.len));
+            return snapshot;
+        }
+        let mut end = self.source.len();
+        let typed = last_line.trim();
+        if (!typed.is_empty() && typed.chars().all(|c| c == '`' || c == '~'))
+            || fence_marker(last_line).is_some()
+        {
+            // A fence (or its info string) being typed.
+            end = self.line_start;
+        }
+        let mut snapshot = self.source[..self.block_start].to_string();
+        snapshot.push_str(&balance_inline(&self.source[self.block_start..end]));
+        snapshot
+    }
+
+    /// Track fences and blocks through the complete line `start..end`.
+    fn complete_line(&mut self, start: usize, end: usize) {
+        let line = &self.source[start..end];
+        match (self.fence, fence_marker(line)) {
+            (Some(fence), Some((marker, len, info)))
+                if marker == fence.marker && len >= fence.len && info.trim().is_empty() =>
+            {
+                self.fence = None;
+                self.block_start = end;
+            }
+            (Some(_), _) => {}
+            (None, Some((marker, len, _))) => {
+                self.fence = Some(Fence { marker, len });
+                self.block_start = start;
+            }
+            (None, None) if line.trim().is_empty() => self.block_start = end,
+            (None, None) => {}
+        }
+    }
+}
+
+/// The fence character, length and info string if `line` is a fence line.
+fn fence_marker(line: &str) -> Option<(char, usize, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let marker = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = rest.len() - rest.trim_start_matches(marker).len();
+    let info = &rest[len..];
+    (len >= 3 && !(marker == '`' && info.contains('`'))).then_some((marker, len, info))
+}
+
+/// An inline construct left open.
+#[derive(Debug, Clone, Copy)]
+struct Open {
+    at: usize,
+    marker: &'static str,
+}
+
+/// `text` (one block) with open inline constructs closed or held back.
+fn balance_inline(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut open: Vec<Open> = Vec::new();
+    let mut code: Option<(usize, usize)> = None;
+    // The start of the last link, and whether its URL has begun.
+    let mut link: Option<(usize, Option<usize>)> = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let run = bytes[i..].iter().take_while(|b| **b == c).count();
+        match c {
+            b'`' => {
+                match code {
+                    Some((_, len)) if len == run => code = None,
+                    Some(_) => {}
+                    None => code = Some((i, run)),
+                }
+                i += run;
+                continue;
+            }
+            _ if code.is_some() => {}
+            b'\\' => i += 1,
+            b'*' | b'~' => {
+                let marker = match (c, run) {
+                    (b'*', 1) => "*",
+                    (b'*', 2) => "**",
+                    (b'*', _) => "***",
+                    (_, 2) => "~~",
+                    _ => {
+                        i += run;
+                        continue;
+                    }
+                };
+                let before = text[..i].chars().next_back();
+                let after = text[i + run..].chars().next();
+                let at_line_start = before.is_none_or(|b| b == '\n');
+                let bullet = c == b'*' && at_line_start && after == Some(' ');
+                let can_close = before.is_some_and(|b| !b.is_whitespace());
+                let can_open = after.is_none_or(|a| !a.is_whitespace());
+                if !bullet {
+                    if can_close && open.last().is_some_and(|o| o.marker == marker) {
+                        open.pop();
+                    } else if can_open {
+                        open.push(Open { at: i, marker });
+                    }
+                }
+                i += run;
+                continue;
+            }
+            b'[' => link = Some((i, None)),
+            b']' if bytes.get(i + 1) == Some(&b'(') => {
+                if let Some((start, None)) = link {
+                    link = Some((start, Some(i)));
+                }
+            }
+            b')' if matches!(link, Some((_, Some(_)))) => link = None,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    if let Some((start, Some(close))) = link {
+        // Show the text of a link whose URL is incomplete.
+        return balance_inline(&format!("{}{}", &text[..start], &text[start + 1..close]));
+    }
+    let mut end = text.len();
+    if let Some((at, len)) = code {
+        if at + len == end {
+            end = at;
+        }
+    }
+    // Markers with nothing after them yet are held back.
+    while let Some(last) = open
+        .last()
+        .filter(|o| text[o.at..end].trim_matches(['*', '~', ' ']).is_empty())
+    {
+        end = last.at;
+        open.pop();
+    }
+    let mut out = text[..end].to_string();
+    match code.filter(|(at, _)| *at < end) {
+        Some((_, len)) => out.extend(std::iter::repeat_n('`', len)),
+        // Closing markers must follow the text directly.
+        None if !open.is_empty() => out.truncate(out.trim_end().len()),
+        None => {}
+    }
+    for o in open.iter().rev() {
+        out.push_str(o.marker);
+    }
+    out
+}
+
+/// Turns an event stream into Markdown snapshots.
+pub trait MarkdownExt: Stream<Item = Result<StreamEvent, GeminiError>> + Sized {
+    /// A render-safe snapshot of the model's text after each `Message` delta, built with
+    /// [`IncrementalMarkdown`]. Other events are skipped; errors are passed on.
+    fn markdown(self) -> BoxStream<'static, Result<String, GeminiError>>
+    where
+        Self: Send + 'static,
+    {
+        Box::pin(async_stream::try_stream! {
+            let mut events = Box::pin(self);
+            let mut document = IncrementalMarkdown::new();
+            while let Some(event) = events.next().await {
+                if let StreamEvent::Message { role, content, .. } = event? {
+                    if role != "user" && !content.is_empty() {
+                        yield document.push(&content);
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl<S> MarkdownExt for S where S: Stream<Item = Result<StreamEvent, GeminiError>> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshots(deltas: &[&str]) -> Vec<String> {
+        let mut doc = IncrementalMarkdown::new();
+        deltas.iter().map(|delta| doc.push(delta)).collect()
+    }
+
+    #[test]
+    fn test_fences_are_closed_and_partial_fence_lines_held_back() {
+        assert_eq!(
+            snapshots(&["Run:\n``", "`sh\nmake", "\n``", "`\nDone"]),
+            [
+                "Run:\n",
+                "Run:\n```sh\nmake\n```",
+                "Run:\n```sh\nmake\n```",
+                "Run:\n```sh\nmake\n```\nDone"
+            ]
+        );
+        assert_eq!(snapshots(&["~~~~\n```\n"]), ["~~~~\n```\n~~~~"]);
+    }
+
+    #[test]
+    fn test_inline_constructs_are_balanced() {
+        assert_eq!(balance_inline("a **bold"), "a **bold**");
+        assert_eq!(balance_inline("a **bold *and it"), "a **bold *and it***");
+        assert_eq!(
+            balance_inline("a **bold** b ~~gone "),
+            "a **bold** b ~~gone~~"
+        );
+        assert_eq!(balance_inline("call `iter(*x"), "call `iter(*x`");
+        assert_eq!(balance_inline("call `"), "call ");
+        assert_eq!(balance_inline("trailing **"), "trailing ");
+        assert_eq!(balance_inline("* item *em"), "* item *em*");
+        assert_eq!(balance_inline("2 * 3 = 6"), "2 * 3 = 6");
+        assert_eq!(
+            balance_inline("see [the docs](https://docs.rs/to"),
+            "see the docs"
+        );
+        assert_eq!(
+            balance_inline("see [the docs](https://x.y) *ok"),
+            "see [the docs](https://x.y) *ok*"
+        );
+        assert_eq!(balance_inline(r"not \*emphasis"), r"not \*emphasis");
+    }
+
+    #[test]
+    fn test_finished_blocks_are_left_alone() {
+        let snapshot = snapshots(&["*one*\n\n**two\n\nthree *four"]).remove(0);
+        assert_eq!(snapshot, "*one*\n\n**two\n\nthree *four*");
+    }
+
+    #[tokio::test]
+    async fn test_stream_yields_a_snapshot_per_delta() {
+        let message = |content: &str| {
+            Ok(StreamEvent::Message {
+                role: "model".into(),
+                content: content.into(),
+                delta: Some(true),
+                timestamp: String::new(),
+            })
+        };
+        let events = futures_util::stream::iter(vec![
+            Ok(StreamEvent::Init {
+                session_id: "s".into(),
+                model: "m".into(),
+                timestamp: String::new(),
+            }),
+            message("Use `Vec"),
+            message("<T>`."),
+        ]);
+        let snapshots: Vec<_> = events.markdown().collect().await;
+        let snapshots: Vec<_> = snapshots.into_iter().map(Result::unwrap).collect();
+        assert_eq!(snapshots, ["Use `Vec`", "Use `Vec<T>`."]);
+    }
+}
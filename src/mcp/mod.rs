@@ -0,0 +1,211 @@
+//! MCP (Model Context Protocol) servers registered per request.
+//!
+//! The CLI discovers MCP servers from the `mcpServers` section of its settings. Servers added
+//! with [`Gemini::mcp_server`](crate::Gemini::mcp_server) are written to the temporary
+//! settings file the SDK passes to the CLI through `GEMINI_CLI_SYSTEM_SETTINGS_PATH`, so a
+//! service can expose its own tools to the agent without touching `~/.gemini/settings.json`.
+//! The file is deleted when the run ends. A server registered under the same name as one in
+//! the user's settings replaces it for that run.
+//!
+//! With the `mcp-server` feature, [`server`] runs an MCP server inside your process that
+//! exposes Rust closures as tools.
+//!
+//! ```rust
+//! use gemini_oxide::mcp::McpServerConfig;
+//! use gemini_oxide::Gemini;
+//! use std::time::Duration;
+//!
+//! let req = Gemini::new("Open a ticket for the failing build")
+//!     .mcp_server(
+//!         "tickets",
+//!         McpServerConfig::stdio("/usr/local/bin/ticket-mcp")
+//!             .arg("--project=infra")
+//!             .env("TICKET_TOKEN", "secret")
+//!             .timeout(Duration::from_secs(30)),
+//!     )
+//!     .mcp_server(
+//!         "search",
+//!         McpServerConfig::http("https://mcp.internal.example/search")
+//!             .header("Authorization", "Bearer secret")
+//!             .trust(),
+//!     );
+//! ```
+
+#[cfg(feature = "mcp-server")]
+pub mod server;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How the CLI reaches one MCP server, in the shape of a `mcpServers` settings entry.
+///
+/// Build one with [`stdio`](Self::stdio), [`sse`](Self::sse) or [`http`](Self::http).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpServerConfig {
+    /// Executable launched by the CLI, speaking MCP over stdin/stdout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Environment variables for the `command` process.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Working directory of the `command` process.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// Server-Sent Events endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Streamable HTTP endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_url: Option<String>,
+    /// HTTP headers sent to `url` or `http_url`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Request timeout in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Run the server's tools without asking for confirmation.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub trust: bool,
+    /// Only offer these of the server's tools to the model.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_tools: Vec<String>,
+    /// Never offer these of the server's tools to the model.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_tools: Vec<String>,
+}
+
+impl McpServerConfig {
+    /// A server the CLI launches as `command`, talking to it over stdin/stdout.
+    pub fn stdio(command: impl Into<String>) -> Self {
+        Self {
+            command: Some(command.into()),
+            ..Self::default()
+        }
+    }
+
+    /// A running server reached over Server-Sent Events at `url`.
+    pub fn sse(url: impl Into<String>) -> Self {
+        Self {
+            url: Some(url.into()),
+            ..Self::default()
+        }
+    }
+
+    /// A running server reached over streamable HTTP at `url`.
+    pub fn http(url: impl Into<String>) -> Self {
+        Self {
+            http_url: Some(url.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Append a command-line argument for the `stdio` command.
+    #[must_use]
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Append command-line arguments for the `stdio` command.
+    #[must_use]
+    pub fn args<I>(mut self, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set an environment variable for the `stdio` command.
+    #[must_use]
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Run the `stdio` command in `dir`.
+    #[must_use]
+    pub fn cwd(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(dir.into());
+        self
+    }
+
+    /// Send an HTTP header to an `sse` or `http` server.
+    #[must_use]
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Give up on a request to the server after `limit`.
+    #[must_use]
+    pub fn timeout(mut self, limit: Duration) -> Self {
+        self.timeout = Some(limit.as_millis() as u64);
+        self
+    }
+
+    /// Run the server's tools without confirmation, whatever the
+    /// [approval mode](crate::Gemini::approval).
+    #[must_use]
+    pub fn trust(mut self) -> Self {
+        self.trust = true;
+        self
+    }
+
+    /// Only offer these of the server's tools to the model.
+    #[must_use]
+    pub fn include_tools<I>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.include_tools.extend(tools.into_iter().map(Into::into));
+        self
+    }
+
+    /// Never offer these of the server's tools to the model.
+    #[must_use]
+    pub fn exclude_tools<I>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.exclude_tools.extend(tools.into_iter().map(Into::into));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_settings_entry_uses_cli_field_names() {
+        let config = McpServerConfig::stdio("ticket-mcp")
+            .args(["--project", "infra"])
+            .env("TOKEN", "t")
+            .timeout(Duration::from_secs(5))
+            .exclude_tools(["delete_ticket"]);
+        assert_eq!(
+            serde_json::to_value(&config).unwrap(),
+            json!({
+                "command": "ticket-mcp",
+                "args": ["--project", "infra"],
+                "env": {"TOKEN": "t"},
+                "timeout": 5000,
+                "excludeTools": ["delete_ticket"],
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(McpServerConfig::http("https://x").trust()).unwrap(),
+            json!({"httpUrl": "https://x", "trust": true})
+        );
+    }
+}
@@ -0,0 +1,560 @@
+//! An in-process MCP server exposing Rust closures as tools to the agent.
+//!
+//! Register tools on an [`McpServer`] and either
+//!
+//! * [`serve`](McpServer::serve) it on a localhost port for the lifetime of the returned
+//!   [`RunningMcpServer`], and hand it to requests with
+//!   [`Gemini::mcp_tools`](crate::Gemini::mcp_tools), which registers it in the CLI's settings; or
+//! * [`serve_stdio`](McpServer::serve_stdio) it when your binary is itself launched by the CLI as
+//!   a stdio server (see [`McpServerConfig::stdio`]).
+//!
+//! The server speaks JSON-RPC over MCP's streamable HTTP transport (one JSON response per
+//! POST, no server-initiated messages) or newline-delimited stdio. It implements
+//! `initialize`, `ping`, `tools/list` and `tools/call`. Tools are registered as
+//! [trusted](McpServerConfig::trust), since they are the application's own code.
+//!
+//! Over HTTP, other local processes and web pages (through DNS rebinding) can reach the port
+//! too. Each [`serve`](McpServer::serve) therefore generates a random bearer token, which
+//! [`RunningMcpServer::config`] passes to the CLI as an `Authorization` header; requests
+//! without it are answered `401`. Requests whose `Host` is not localhost, or that carry an
+//! `Origin` other than localhost, are answered `403`.
+//!
+//! Only available with the `mcp-server` feature.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::mcp::server::McpServer;
+//! use gemini_oxide::Gemini;
+//! use serde_json::json;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let tools = McpServer::new("inventory")
+//!     .tool(
+//!         "stock_level",
+//!         "Units in stock for a SKU",
+//!         json!({"type": "object", "properties": {"sku": {"type": "string"}}, "required": ["sku"]}),
+//!         |args| async move {
+//!             let sku = args["sku"].as_str().ok_or("missing sku")?;
+//!             Ok(format!("{sku}: 42 units"))
+//!         },
+//!     )
+//!     .serve()
+//!     .await?;
+//!
+//! let answer = Gemini::new("Do we need to reorder SKU A-100?")
+//!     .mcp_tools(&tools)
+//!     .text()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use super::McpServerConfig;
+use crate::schema::JsonSchema;
+use futures_util::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// The protocol version answered to clients that do not ask for one.
+const PROTOCOL_VERSION: &str = "2025-03-26";
+
+/// Largest request body accepted over HTTP.
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+type Handler = Arc<dyn Fn(Value) -> BoxFuture<'static, Result<String, String>> + Send + Sync>;
+
+#[derive(Clone)]
+struct RustTool {
+    name: String,
+    description: String,
+    input_schema: Value,
+    handler: Handler,
+}
+
+/// A set of Rust tools to expose over MCP.
+#[derive(Clone)]
+pub struct McpServer {
+    name: String,
+    tools: Vec<RustTool>,
+}
+
+impl McpServer {
+    /// An empty server; `name` is the key it is registered under in the CLI's settings.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            tools: Vec::new(),
+        }
+    }
+
+    /// Register a tool taking JSON arguments that match `input_schema`.
+    ///
+    /// The handler's `Ok` text is returned to the model; an `Err` is returned as a tool error
+    /// the model can react to. Registering the same name again replaces the tool.
+    #[must_use]
+    pub fn tool<F, Fut>(
+        mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        input_schema: Value,
+        handler: F,
+    ) -> Self
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, String>> + Send + 'static,
+    {
+        let tool = RustTool {
+            name: name.into(),
+            description: description.into(),
+            input_schema,
+            handler: Arc::new(move |args| Box::pin(handler(args))),
+        };
+        self.tools.retain(|t| t.name != tool.name);
+        self.tools.push(tool);
+        self
+    }
+
+    /// Register a tool whose arguments deserialize into `T`, with `T`'s [`JsonSchema`] as the
+    /// input schema. Arguments that do not deserialize are reported to the model as an error.
+    ///
+    /// MCP expects an object schema, so `T` is normally a struct or map.
+    #[must_use]
+    pub fn typed_tool<T, F, Fut>(
+        self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        handler: F,
+    ) -> Self
+    where
+        T: DeserializeOwned + JsonSchema + Send + 'static,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, String>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        self.tool(name, description, T::json_schema(), move |args| {
+            let handler = handler.clone();
+            async move {
+                let args =
+                    serde_json::from_value(args).map_err(|e| format!("invalid arguments: {e}"))?;
+                handler(args).await
+            }
+        })
+    }
+
+    /// Serve on a free port on `127.0.0.1` until the returned handle is dropped.
+    ///
+    /// Only requests bearing the token in [`RunningMcpServer::config`] are answered.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from generating the token or binding the port.
+    pub async fn serve(self) -> io::Result<RunningMcpServer> {
+        let token: Arc<str> = bearer_token()?.into();
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let name = self.name.clone();
+        let server = Arc::new(self);
+        let task_token = token.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (server, token) = (server.clone(), task_token.clone());
+                tokio::spawn(async move {
+                    // A broken connection only affects that request.
+                    let _ = server.serve_http(stream, &token).await;
+                });
+            }
+        });
+        Ok(RunningMcpServer {
+            name,
+            addr,
+            token: token.to_string(),
+            task,
+        })
+    }
+
+    /// Serve newline-delimited JSON-RPC on stdin/stdout until stdin closes.
+    ///
+    /// Use this when the CLI launches your binary as a stdio MCP server. Nothing else may
+    /// write to stdout meanwhile.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error reading stdin or writing stdout.
+    pub async fn serve_stdio(self) -> io::Result<()> {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let mut stdout = tokio::io::stdout();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(reply) = self.handle_message(line.as_bytes()).await {
+                stdout.write_all(reply.to_string().as_bytes()).await?;
+                stdout.write_all(b"\n").await?;
+                stdout.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer one HTTP request bearing `token`, then close the connection.
+    async fn serve_http(&self, stream: TcpStream, token: &str) -> io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await?;
+        let mut content_length = 0;
+        let (mut host, mut origin, mut authorization) = (None, None, None);
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                let value = value.trim().to_string();
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "content-length" => content_length = value.parse().unwrap_or(0),
+                    "host" => host = Some(value),
+                    "origin" => origin = Some(value),
+                    "authorization" => authorization = Some(value),
+                    _ => {}
+                }
+            }
+        }
+        let method = request_line.split_whitespace().next().unwrap_or_default();
+        let authorized = authorization
+            .as_deref()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|presented| constant_time_eq(presented.trim(), token));
+        let local_origin = origin.as_deref().is_none_or(|origin| {
+            ["http://", "https://"]
+                .iter()
+                .find_map(|scheme| origin.strip_prefix(scheme))
+                .is_some_and(is_localhost)
+        });
+        let (status, body) = if !host.as_deref().is_some_and(is_localhost) || !local_origin {
+            ("403 Forbidden", None)
+        } else if !authorized {
+            ("401 Unauthorized", None)
+        } else if method != "POST" {
+            ("405 Method Not Allowed", None)
+        } else if content_length > MAX_BODY {
+            ("413 Payload Too Large", None)
+        } else {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).await?;
+            match self.handle_message(&body).await {
+                Some(reply) => ("200 OK", Some(reply.to_string())),
+                None => ("202 Accepted", None),
+            }
+        };
+        let mut response = format!("HTTP/1.1 {status}\r\nConnection: close\r\n");
+        if status.starts_with("401") {
+            response.push_str("WWW-Authenticate: Bearer\r\n");
+        }
+        match body {
+            Some(body) => response.push_str(&format!(
+                "Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )),
+            None => response.push_str("Content-Length: 0\r\n\r\n"),
+        }
+        let mut stream = reader.into_inner();
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+
+    /// The reply to a JSON-RPC message or batch, or `None` if it held only notifications.
+    async fn handle_message(&self, body: &[u8]) -> Option<Value> {
+        let message: Value = match serde_json::from_slice(body) {
+            Ok(message) => message,
+            Err(e) => return Some(error(Value::Null, -32700, &format!("parse error: {e}"))),
+        };
+        match message {
+            Value::Array(batch) => {
+                let mut replies = Vec::new();
+                for request in batch {
+                    replies.extend(self.handle_request(request).await);
+                }
+                (!replies.is_empty()).then_some(Value::Array(replies))
+            }
+            request => self.handle_request(request).await,
+        }
+    }
+
+    async fn handle_request(&self, request: Value) -> Option<Value> {
+        // Notifications (and responses to requests we never send) carry no reply.
+        let id = request.get("id").cloned()?;
+        let method = request.get("method").and_then(Value::as_str)?;
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        let result = match method {
+            "initialize" => json!({
+                "protocolVersion": params
+                    .get("protocolVersion")
+                    .and_then(Value::as_str)
+                    .unwrap_or(PROTOCOL_VERSION),
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": self.name, "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "ping" => json!({}),
+            "tools/list" => json!({
+                "tools": self.tools.iter().map(|tool| json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "inputSchema": tool.input_schema,
+                })).collect::<Vec<_>>(),
+            }),
+            "tools/call" => {
+                let name = params.get("name").and_then(Value::as_str).unwrap_or("");
+                let Some(tool) = self.tools.iter().find(|t| t.name == name) else {
+                    return Some(error(id, -32602, &format!("unknown tool: {name}")));
+                };
+                let args = params.get("arguments").cloned().unwrap_or(json!({}));
+                let (text, is_error) = match (tool.handler)(args).await {
+                    Ok(text) => (text, false),
+                    Err(message) => (message, true),
+                };
+                json!({
+                    "content": [{ "type": "text", "text": text }],
+                    "isError": is_error,
+                })
+            }
+            other => return Some(error(id, -32601, &format!("method not found: {other}"))),
+        };
+        Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+    }
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// A random bearer token: 32 bytes from the operating system, hex-encoded.
+fn bearer_token() -> io::Result<String> {
+    let mut bytes = [0u8; 32];
+    #[cfg(unix)]
+    {
+        use std::io::Read;
+        std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    }
+    #[cfg(not(unix))]
+    {
+        use std::collections::hash_map::RandomState;
+        use std::hash::{BuildHasher, Hasher};
+        // Each `RandomState` is keyed from the operating system's random source.
+        for chunk in bytes.chunks_mut(8) {
+            let random = RandomState::new().build_hasher().finish();
+            chunk.copy_from_slice(&random.to_le_bytes());
+        }
+    }
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Whether `a` equals `b`, in time that does not depend on where they differ.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+/// Whether `authority` (`host[:port]`, or an origin without its scheme) names this machine.
+fn is_localhost(authority: &str) -> bool {
+    let host = match authority.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    matches!(
+        host.to_ascii_lowercase().as_str(),
+        "localhost" | "127.0.0.1" | "::1"
+    )
+}
+
+/// An [`McpServer`] listening on localhost. Dropping it stops the server.
+pub struct RunningMcpServer {
+    name: String,
+    addr: SocketAddr,
+    token: String,
+    task: JoinHandle<()>,
+}
+
+impl RunningMcpServer {
+    /// The name the server is registered under.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The address the server listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The MCP endpoint.
+    pub fn url(&self) -> String {
+        format!("http://{}/mcp", self.addr)
+    }
+
+    /// The settings entry pointing the CLI at this server, with its tools trusted and the
+    /// server's bearer token.
+    pub fn config(&self) -> McpServerConfig {
+        McpServerConfig::http(self.url())
+            .header("Authorization", format!("Bearer {}", self.token))
+            .trust()
+    }
+}
+
+impl Drop for RunningMcpServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn server() -> McpServer {
+        McpServer::new("calc").typed_tool(
+            "double",
+            "Double each number",
+            |numbers: BTreeMap<String, i64>| async move {
+                let doubled: Option<Vec<String>> = numbers
+                    .iter()
+                    .map(|(key, n)| Some(format!("{key}={}", n.checked_mul(2)?)))
+                    .collect();
+                doubled
+                    .map(|d| d.join(","))
+                    .ok_or_else(|| "overflow".into())
+            },
+        )
+    }
+
+    async fn call(server: &McpServer, request: Value) -> Value {
+        server
+            .handle_message(request.to_string().as_bytes())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_lists_and_calls_tools() {
+        let server = server();
+        let list = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"}),
+        )
+        .await;
+        assert_eq!(list["result"]["tools"][0]["name"], "double");
+        assert_eq!(
+            list["result"]["tools"][0]["inputSchema"],
+            json!({"type": "object", "additionalProperties": {"type": "integer"}})
+        );
+
+        let ok = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 2, "method": "tools/call",
+                   "params": {"name": "double", "arguments": {"n": 21}}}),
+        )
+        .await;
+        assert_eq!(ok["result"]["content"][0]["text"], "n=42");
+        assert_eq!(ok["result"]["isError"], false);
+
+        let bad = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 3, "method": "tools/call",
+                   "params": {"name": "double", "arguments": "x"}}),
+        )
+        .await;
+        assert_eq!(bad["result"]["isError"], true);
+
+        let unknown = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 4, "method": "tools/call", "params": {"name": "nope"}}),
+        )
+        .await;
+        assert_eq!(unknown["error"]["code"], -32602);
+
+        let notification = json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+        assert!(server
+            .handle_message(notification.to_string().as_bytes())
+            .await
+            .is_none());
+    }
+
+    /// POST an `initialize` request with `headers` and return the raw response.
+    async fn post(running: &RunningMcpServer, headers: &str) -> String {
+        let body = json!({"jsonrpc": "2.0", "id": 1, "method": "initialize",
+                          "params": {"protocolVersion": "2025-06-18"}})
+        .to_string();
+        let mut stream = TcpStream::connect(running.addr()).await.unwrap();
+        stream
+            .write_all(
+                format!(
+                    "POST /mcp HTTP/1.1\r\n{headers}Content-Type: text/plain\r\n\
+                     Content-Length: {}\r\n\r\n{body}",
+                    body.len()
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_serves_json_rpc_over_http() {
+        let running = server().serve().await.unwrap();
+        let authorization = &running.config().headers["Authorization"];
+        let response = post(
+            &running,
+            &format!("Host: localhost:1234\r\nAuthorization: {authorization}\r\n"),
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let reply: Value =
+            serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(reply["result"]["protocolVersion"], "2025-06-18");
+        assert_eq!(reply["result"]["serverInfo"]["name"], "calc");
+        assert_eq!(running.config().http_url, Some(running.url()));
+        assert!(running.config().trust);
+    }
+
+    #[tokio::test]
+    async fn test_unauthenticated_and_cross_origin_requests_are_refused() {
+        let running = server().serve().await.unwrap();
+        let authorization = &running.config().headers["Authorization"];
+        assert!(authorization.starts_with("Bearer ") && authorization.len() > 40);
+
+        let missing = post(&running, "Host: 127.0.0.1\r\n").await;
+        assert!(missing.starts_with("HTTP/1.1 401 Unauthorized"));
+        let wrong = post(
+            &running,
+            "Host: 127.0.0.1\r\nAuthorization: Bearer guess\r\n",
+        )
+        .await;
+        assert!(wrong.starts_with("HTTP/1.1 401 Unauthorized"));
+
+        let rebound = format!("Host: attacker.example:80\r\nAuthorization: {authorization}\r\n");
+        assert!(post(&running, &rebound).await.starts_with("HTTP/1.1 403"));
+        let cross_origin = format!(
+            "Host: localhost\r\nOrigin: https://attacker.example\r\nAuthorization: {authorization}\r\n"
+        );
+        assert!(post(&running, &cross_origin)
+            .await
+            .starts_with("HTTP/1.1 403"));
+        let local_origin = format!(
+            "Host: [::1]:8080\r\nOrigin: http://localhost:3000\r\nAuthorization: {authorization}\r\n"
+        );
+        assert!(post(&running, &local_origin)
+            .await
+            .starts_with("HTTP/1.1 200"));
+    }
+}
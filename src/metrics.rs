@@ -0,0 +1,162 @@
+//! Metrics emission.
+//!
+//! The SDK reports counters and observations (latencies, throughput) to a [`MetricsSink`].
+//! Implement the trait to forward them to Prometheus, StatsD, OpenTelemetry or similar, and
+//! attach it with [`Gemini::metrics`](crate::Gemini::metrics). [`InMemoryMetrics`] records
+//! everything in memory, which is handy in tests.
+//!
+//! Metric names are exported as constants so dashboards and alerts can refer to them safely.
+//! Every metric also carries the request's [labels](crate::Gemini::label), so usage can be
+//! broken down by team or tenant; labels the SDK sets itself, such as `model`, take precedence.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Time from launch to the first event of a stream, in milliseconds.
+pub const STREAM_TIME_TO_FIRST_EVENT_MS: &str = "gemini.stream.time_to_first_event_ms";
+/// Time from launch to the first text delta of a stream, in milliseconds.
+pub const STREAM_TIME_TO_FIRST_TEXT_MS: &str = "gemini.stream.time_to_first_text_ms";
+/// Total stream duration, in milliseconds.
+pub const STREAM_DURATION_MS: &str = "gemini.stream.duration_ms";
+/// Output tokens per second over the text-producing part of a stream.
+pub const STREAM_TOKENS_PER_SECOND: &str = "gemini.stream.tokens_per_second";
+
+/// Time a job spent in a [`JobQueue`](crate::jobs::JobQueue) before starting, in milliseconds.
+/// Labelled with `priority`.
+pub const QUEUE_WAIT_MS: &str = "gemini.queue.wait_ms";
+
+/// Requests run again after a transient failure, per [`Gemini::retry`](crate::Gemini::retry).
+pub const RETRIES: &str = "gemini.retries";
+
+/// A destination for SDK metrics.
+///
+/// Labels are passed as `(key, value)` pairs.
+pub trait MetricsSink: Send + Sync {
+    /// Add `value` to a monotonically increasing counter.
+    fn increment(&self, name: &str, value: u64, labels: &[(&str, &str)]);
+
+    /// Record a single observation for a histogram or summary.
+    fn observe(&self, name: &str, value: f64, labels: &[(&str, &str)]);
+}
+
+/// A recorded metric series, identified by name and sorted labels.
+pub type SeriesKey = (String, Vec<(String, String)>);
+
+/// A [`MetricsSink`] that keeps every counter and observation in memory.
+#[derive(Debug, Default)]
+pub struct InMemoryMetrics {
+    counters: Mutex<BTreeMap<SeriesKey, u64>>,
+    observations: Mutex<BTreeMap<SeriesKey, Vec<f64>>>,
+}
+
+impl InMemoryMetrics {
+    /// Create an empty recorder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sum of a counter across all label sets.
+    pub fn counter(&self, name: &str) -> u64 {
+        self.counters
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|((n, _), _)| n == name)
+            .map(|(_, v)| v)
+            .sum()
+    }
+
+    /// All observations of a metric across all label sets, in recording order per series.
+    pub fn observations(&self, name: &str) -> Vec<f64> {
+        self.observations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|((n, _), _)| n == name)
+            .flat_map(|(_, v)| v.iter().copied())
+            .collect()
+    }
+
+    /// Every series that has been recorded, counters and observations alike.
+    pub fn series(&self) -> Vec<SeriesKey> {
+        let mut keys: Vec<SeriesKey> = self
+            .counters
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys()
+            .cloned()
+            .collect();
+        keys.extend(
+            self.observations
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .keys()
+                .cloned(),
+        );
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+}
+
+/// `own` followed by those of `labels` whose keys `own` does not set.
+pub(crate) fn with_labels<'a>(
+    own: &[(&'a str, &'a str)],
+    labels: &'a BTreeMap<String, String>,
+) -> Vec<(&'a str, &'a str)> {
+    let mut all = own.to_vec();
+    all.extend(
+        labels
+            .iter()
+            .filter(|(key, _)| !own.iter().any(|(k, _)| k == key))
+            .map(|(k, v)| (k.as_str(), v.as_str())),
+    );
+    all
+}
+
+fn series_key(name: &str, labels: &[(&str, &str)]) -> SeriesKey {
+    let mut labels: Vec<(String, String)> = labels
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    labels.sort();
+    (name.to_string(), labels)
+}
+
+impl MetricsSink for InMemoryMetrics {
+    fn increment(&self, name: &str, value: u64, labels: &[(&str, &str)]) {
+        *self
+            .counters
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(series_key(name, labels))
+            .or_insert(0) += value;
+    }
+
+    fn observe(&self, name: &str, value: f64, labels: &[(&str, &str)]) {
+        self.observations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(series_key(name, labels))
+            .or_default()
+            .push(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_memory_metrics_aggregate_by_name() {
+        let metrics = InMemoryMetrics::new();
+        metrics.increment("calls", 1, &[("model", "a")]);
+        metrics.increment("calls", 2, &[("model", "b")]);
+        metrics.observe("latency", 1.5, &[("model", "a")]);
+        metrics.observe("latency", 2.5, &[("model", "a")]);
+
+        assert_eq!(metrics.counter("calls"), 3);
+        assert_eq!(metrics.observations("latency"), vec![1.5, 2.5]);
+        assert_eq!(metrics.series().len(), 3);
+    }
+}
@@ -0,0 +1,236 @@
+//! Content moderation of model output.
+//!
+//! A [`Moderator`] inspects response text before the SDK hands it back to the caller and can
+//! block it. Attach one with [`Gemini::moderator`](crate::Gemini::moderator); blocked responses
+//! surface as `GeminiError::ContentBlocked`.
+//!
+//! [`KeywordModerator`] is the built-in, blocking terms and regular expressions. External moderation services can be
+//! plugged in by implementing the trait or wrapping an async closure with [`moderator_fn`].
+//! Redaction (rather than blocking) is better expressed as a
+//! [post-processor](crate::postprocess).
+
+use crate::GeminiError;
+use futures_util::future::BoxFuture;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+/// The outcome of moderating a piece of text.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "verdict", rename_all = "snake_case")]
+pub enum ModerationVerdict {
+    /// The text may be returned.
+    Allow,
+    /// The text must not be returned.
+    Block { reason: String },
+}
+
+/// Decides whether model output may be returned.
+pub trait Moderator: Send + Sync {
+    /// Moderate `text`.
+    fn moderate<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> BoxFuture<'a, Result<ModerationVerdict, GeminiError>>;
+}
+
+/// Blocks text containing any configured term or matching any configured pattern.
+///
+/// Terms match case-insensitively on word boundaries. A trailing `*` turns a term into a
+/// prefix match (`"explo*"` matches "exploit" and "explosive"); multi-word terms match phrases.
+/// [`pattern`](Self::pattern)s are regular expressions, for text with a shape rather than a
+/// wording, such as card or API key numbers.
+///
+/// ```rust
+/// use gemini_oxide::moderation::KeywordModerator;
+/// use regex::Regex;
+///
+/// let moderator = KeywordModerator::new(["password"])
+///     .pattern(Regex::new(r"\bsk-[A-Za-z0-9]{20,}\b").unwrap());
+/// assert!(moderator.find("use sk-abcdefghijklmnopqrstuvwxyz").is_some());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KeywordModerator {
+    terms: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl KeywordModerator {
+    /// Create a moderator blocking the given terms.
+    pub fn new<I, S>(terms: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            terms: terms.into_iter().map(|t| t.into().to_lowercase()).collect(),
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Add a term.
+    #[must_use]
+    pub fn term(mut self, term: impl Into<String>) -> Self {
+        self.terms.push(term.into().to_lowercase());
+        self
+    }
+
+    /// Add a regular expression; text containing a match is blocked. Matching is
+    /// case-sensitive unless the pattern says otherwise, e.g. with `(?i)`.
+    #[must_use]
+    pub fn pattern(mut self, pattern: Regex) -> Self {
+        self.patterns.push(pattern);
+        self
+    }
+
+    /// The first configured term found in `text`, or else the first pattern matching it.
+    pub fn find(&self, text: &str) -> Option<&str> {
+        let words: Vec<String> = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+
+        self.terms
+            .iter()
+            .find(|term| {
+                let parts: Vec<&str> = term.split_whitespace().collect();
+                !parts.is_empty()
+                    && words.windows(parts.len()).any(|window| {
+                        window
+                            .iter()
+                            .zip(&parts)
+                            .all(|(word, part)| match part.strip_suffix('*') {
+                                Some(prefix) => word.starts_with(prefix),
+                                None => word == part,
+                            })
+                    })
+            })
+            .map(String::as_str)
+            .or_else(|| {
+                self.patterns
+                    .iter()
+                    .find(|pattern| pattern.is_match(text))
+                    .map(Regex::as_str)
+            })
+    }
+}
+
+impl Moderator for KeywordModerator {
+    fn moderate<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> BoxFuture<'a, Result<ModerationVerdict, GeminiError>> {
+        let verdict = match self.find(text) {
+            Some(term) => ModerationVerdict::Block {
+                reason: format!("matched blocked term `{term}`"),
+            },
+            None => ModerationVerdict::Allow,
+        };
+        Box::pin(async move { Ok(verdict) })
+    }
+}
+
+struct FnModerator<F>(F);
+
+impl<F, Fut> Moderator for FnModerator<F>
+where
+    F: Fn(String) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<ModerationVerdict, GeminiError>> + Send + 'static,
+{
+    fn moderate<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> BoxFuture<'a, Result<ModerationVerdict, GeminiError>> {
+        Box::pin((self.0)(text.to_string()))
+    }
+}
+
+/// Wrap an async closure, e.g. a call to an external moderation API, as a [`Moderator`].
+///
+/// ```rust
+/// use gemini_oxide::moderation::{moderator_fn, ModerationVerdict};
+///
+/// let moderator = moderator_fn(|text| async move {
+///     // Call out to a moderation service here.
+///     Ok(if text.len() > 10_000 {
+///         ModerationVerdict::Block { reason: "too long".into() }
+///     } else {
+///         ModerationVerdict::Allow
+///     })
+/// });
+/// ```
+pub fn moderator_fn<F, Fut>(f: F) -> impl Moderator
+where
+    F: Fn(String) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<ModerationVerdict, GeminiError>> + Send + 'static,
+{
+    FnModerator(f)
+}
+
+/// Run `moderator` on `text`, turning a block into `GeminiError::ContentBlocked`.
+pub(crate) async fn enforce(moderator: &dyn Moderator, text: &str) -> Result<(), GeminiError> {
+    match moderator.moderate(text).await? {
+        ModerationVerdict::Allow => Ok(()),
+        ModerationVerdict::Block { reason } => Err(GeminiError::ContentBlocked(reason)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyword_matching() {
+        let moderator = KeywordModerator::new(["password", "explo*", "credit card"]);
+        assert_eq!(moderator.find("My PASSWORD is hunter2"), Some("password"));
+        assert_eq!(moderator.find("a working exploit"), Some("explo*"));
+        assert_eq!(
+            moderator.find("store the credit  card number"),
+            Some("credit card")
+        );
+        assert_eq!(moderator.find("passwords are plural"), None);
+        assert_eq!(moderator.find("credit is not a card"), None);
+    }
+
+    #[tokio::test]
+    async fn test_pattern_blocks_matching_text() {
+        let card = Regex::new(r"\b(?:\d{4}[ -]?){3}\d{4}\b").unwrap();
+        let moderator = KeywordModerator::default().pattern(card);
+        assert!(enforce(&moderator, "call me at 555-0100").await.is_ok());
+        let err = enforce(&moderator, "card: 4111 1111 1111 1111")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            GeminiError::ContentBlocked(reason) if reason.contains(r"\d{4}")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_enforce_maps_block_to_error() {
+        let moderator = KeywordModerator::new(["secret"]);
+        assert!(enforce(&moderator, "nothing to see").await.is_ok());
+        let err = enforce(&moderator, "the secret plan").await.unwrap_err();
+        assert!(matches!(err, GeminiError::ContentBlocked(_)));
+    }
+
+    #[tokio::test]
+    async fn test_async_closure_moderator() {
+        let moderator = moderator_fn(|text| async move {
+            Ok(if text.contains('!') {
+                ModerationVerdict::Block {
+                    reason: "shouting".into(),
+                }
+            } else {
+                ModerationVerdict::Allow
+            })
+        });
+        assert_eq!(
+            moderator.moderate("hey!").await.unwrap(),
+            ModerationVerdict::Block {
+                reason: "shouting".into()
+            }
+        );
+    }
+}
@@ -0,0 +1,837 @@
+//! Multi-step prompt chains.
+//!
+//! A [`Pipeline`] is a dependency graph of named [`Step`]s. Each step's prompt is a template
+//! whose `{{name}}` placeholders are filled from pipeline inputs or the outputs of other steps;
+//! steps can also pipe other outputs in as context with [`Step::uses`]. Steps whose
+//! dependencies are satisfied run concurrently, up to [`Pipeline::max_concurrency`], each with
+//! its own retry budget and timeout.
+//!
+//! [`Pipeline::branch_if`] adds a pair of alternative steps chosen by a predicate on another
+//! step's output.
+//!
+//! With [`Pipeline::checkpoint`], step outputs are persisted keyed by step name and a hash of
+//! the step's rendered inputs. Re-running the pipeline replays unchanged steps from the store
+//! and only calls the model again from the first step whose inputs changed (or that failed).
+//!
+//! [`Pipeline::run`] stops at the first failure and returns every output in a [`PipelineRun`];
+//! [`Pipeline::execute`] runs everything it can and returns a typed [`ExecutionReport`].
+//!
+//! ```rust,no_run
+//! use gemini_oxide::pipeline::{Pipeline, Step};
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let run = Pipeline::new()
+//!     .input("topic", "ownership in Rust")
+//!     .step(Step::new("outline", "Write a five-point outline about {{topic}}."))
+//!     .step(
+//!         Step::new("draft", "Write a blog post about {{topic}} following the outline.")
+//!             .uses(["outline"])
+//!             .configure(|g| g.model("gemini-2.5-pro")),
+//!     )
+//!     .run()
+//!     .await?;
+//!
+//! println!("{}", run.get("outline").unwrap_or_default());
+//! println!("{}", run.output().unwrap_or_default());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::storage::CacheStore;
+use crate::{Configure, Gemini, GeminiError};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Default number of steps allowed to run at once.
+const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
+/// One prompt in a [`Pipeline`].
+pub struct Step {
+    name: String,
+    template: String,
+    uses: Vec<String>,
+    retries: u32,
+    timeout: Option<Duration>,
+    condition: Option<Condition>,
+    configure: Option<Configure>,
+}
+
+type Predicate = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Runs a step only if `predicate(source output) == expected`.
+#[derive(Clone)]
+struct Condition {
+    source: String,
+    predicate: Predicate,
+    expected: bool,
+}
+
+impl Step {
+    /// Create a step. `template` may reference inputs and other steps as `{{name}}`.
+    pub fn new(name: impl Into<String>, template: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            template: template.into(),
+            uses: Vec::new(),
+            retries: 0,
+            timeout: None,
+            condition: None,
+            configure: None,
+        }
+    }
+
+    /// Retry a failed attempt up to `retries` more times. Defaults to no retries.
+    #[must_use]
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Fail an attempt that takes longer than `timeout` with `GeminiError::Timeout`, measured
+    /// on the request's [`clock`](Gemini::clock). Timed-out attempts are retried like any other
+    /// failure.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Pipe the outputs of these steps to the model as context.
+    #[must_use]
+    pub fn uses<I, S>(mut self, steps: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.uses.extend(steps.into_iter().map(Into::into));
+        self
+    }
+
+    /// Customize this step's request (model, binary path, ...).
+    ///
+    /// Applied after the pipeline-wide [`Pipeline::configure`].
+    #[must_use]
+    pub fn configure(mut self, f: impl Fn(Gemini) -> Gemini + Send + Sync + 'static) -> Self {
+        self.configure = Some(Box::new(f));
+        self
+    }
+
+    /// The step's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Every input or step this step depends on, through its template, [`uses`](Self::uses)
+    /// or a branch condition.
+    pub fn dependencies(&self) -> Vec<String> {
+        let mut deps = placeholders(&self.template);
+        let condition = self.condition.as_ref().map(|c| &c.source);
+        for dep in self.uses.iter().chain(condition) {
+            if !deps.contains(dep) {
+                deps.push(dep.clone());
+            }
+        }
+        deps
+    }
+}
+
+/// The result of one executed step.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct StepOutput {
+    /// The step's name.
+    pub name: String,
+    /// The rendered prompt that was sent.
+    pub prompt: String,
+    /// The model's response.
+    pub output: String,
+    /// How long the step took, across all attempts.
+    pub elapsed: Duration,
+    /// Number of attempts made, including the successful one (zero if replayed).
+    pub attempts: u32,
+    /// Whether the output was replayed from the checkpoint store instead of the model.
+    pub replayed: bool,
+}
+
+/// The outputs of every step of a pipeline run, in completion order.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct PipelineRun {
+    pub steps: Vec<StepOutput>,
+}
+
+impl PipelineRun {
+    /// The output of the named step.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.steps
+            .iter()
+            .find(|s| s.name == name)
+            .map(|s| s.output.as_str())
+    }
+
+    /// The output of the last step.
+    pub fn output(&self) -> Option<&str> {
+        self.steps.last().map(|s| s.output.as_str())
+    }
+}
+
+/// How a step ended.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum StepStatus {
+    /// The step produced an output.
+    Succeeded,
+    /// Every attempt failed; `error` is the last error message.
+    Failed { error: String },
+    /// The step never ran because a dependency did not succeed.
+    Skipped { reason: String },
+}
+
+/// What happened to one step during [`Pipeline::execute`].
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct StepReport {
+    /// The step's name.
+    pub name: String,
+    /// How the step ended.
+    pub status: StepStatus,
+    /// Number of attempts made (zero if skipped or replayed).
+    pub attempts: u32,
+    /// Whether the output was replayed from the checkpoint store instead of the model.
+    pub replayed: bool,
+    /// How long the step took, across all attempts.
+    pub elapsed: Duration,
+    /// The rendered prompt, if the step ran.
+    pub prompt: Option<String>,
+    /// The model's response, if the step succeeded.
+    pub output: Option<String>,
+}
+
+/// The outcome of every step of a pipeline, in completion order.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ExecutionReport {
+    pub steps: Vec<StepReport>,
+    /// Wall-clock time for the whole pipeline.
+    pub elapsed: Duration,
+}
+
+impl ExecutionReport {
+    /// The report for the named step.
+    pub fn get(&self, name: &str) -> Option<&StepReport> {
+        self.steps.iter().find(|s| s.name == name)
+    }
+
+    /// Whether every step succeeded.
+    pub fn succeeded(&self) -> bool {
+        self.steps.iter().all(|s| s.status == StepStatus::Succeeded)
+    }
+
+    /// The steps that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &StepReport> {
+        self.steps
+            .iter()
+            .filter(|s| matches!(s.status, StepStatus::Failed { .. }))
+    }
+}
+
+/// A graph of prompts feeding each other.
+pub struct Pipeline {
+    inputs: BTreeMap<String, String>,
+    steps: Vec<Step>,
+    max_concurrency: usize,
+    checkpoints: Option<Arc<dyn CacheStore>>,
+    configure: Option<Configure>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self {
+            inputs: BTreeMap::new(),
+            steps: Vec::new(),
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            checkpoints: None,
+            configure: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum NodeState {
+    Pending,
+    Running,
+    Succeeded,
+    NotSucceeded,
+    NotTaken,
+}
+
+impl Pipeline {
+    /// Create an empty pipeline running up to 4 steps at once.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Provide a value for `{{name}}` placeholders.
+    #[must_use]
+    pub fn input(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.inputs.insert(name.into(), value.into());
+        self
+    }
+
+    /// Add a step. Steps may be added in any order; dependencies decide when they run.
+    #[must_use]
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Add two alternative steps after the most recently added step.
+    ///
+    /// Once that step succeeds, `predicate` is called with its output: `then_step` runs if it
+    /// returns `true`, `else_step` otherwise. The step not taken is reported as skipped, and so
+    /// is everything depending on it.
+    ///
+    /// ```rust
+    /// use gemini_oxide::pipeline::{Pipeline, Step};
+    ///
+    /// let pipeline = Pipeline::new()
+    ///     .input("diff", "...")
+    ///     .step(Step::new("triage", "Reply NEEDS_REVIEW or OK for this diff:\n{{diff}}"))
+    ///     .branch_if(
+    ///         |out| out.contains("NEEDS_REVIEW"),
+    ///         Step::new("review", "Review this diff in detail:\n{{diff}}"),
+    ///         Step::new("changelog", "Write a changelog line for:\n{{diff}}"),
+    ///     );
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pipeline has no steps yet.
+    #[must_use]
+    pub fn branch_if(
+        self,
+        predicate: impl Fn(&str) -> bool + Send + Sync + 'static,
+        then_step: Step,
+        else_step: Step,
+    ) -> Self {
+        let source = self
+            .steps
+            .last()
+            .expect("branch_if needs a preceding step")
+            .name
+            .clone();
+        let predicate: Predicate = Arc::new(predicate);
+        let branch = |mut step: Step, expected| {
+            step.condition = Some(Condition {
+                source: source.clone(),
+                predicate: predicate.clone(),
+                expected,
+            });
+            step
+        };
+        let (then_step, else_step) = (branch(then_step, true), branch(else_step, false));
+        self.step(then_step).step(else_step)
+    }
+
+    /// Maximum number of steps (and therefore CLI processes) running at once.
+    #[must_use]
+    pub fn max_concurrency(mut self, n: usize) -> Self {
+        self.max_concurrency = n.max(1);
+        self
+    }
+
+    /// Customize every step's request (model, binary path, ...).
+    #[must_use]
+    pub fn configure(mut self, f: impl Fn(Gemini) -> Gemini + Send + Sync + 'static) -> Self {
+        self.configure = Some(Box::new(f));
+        self
+    }
+
+    /// Persist successful step outputs in `store` and replay them on later runs.
+    ///
+    /// Outputs are keyed by step name and a hash of the step's request (rendered prompt,
+    /// context, file contents, model and flags), so a step is replayed only while its inputs,
+    /// including upstream outputs, are unchanged.
+    #[must_use]
+    pub fn checkpoint(mut self, store: Arc<dyn CacheStore>) -> Self {
+        self.checkpoints = Some(store);
+        self
+    }
+
+    /// Check that step names are unique, every dependency is an input or a step, and the
+    /// dependency graph has no cycles.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::ValidationFailed` describing the first problem found.
+    pub fn validate(&self) -> Result<(), GeminiError> {
+        self.topological_order().map(|_| ())
+    }
+
+    /// Run every step, stopping at the first failure.
+    ///
+    /// Only steps that ran successfully appear in the result; branches not taken are left out.
+    ///
+    /// In-flight steps are cancelled (killing their CLI processes) once a step fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::ValidationFailed` if the pipeline is invalid (see
+    /// [`validate`](Self::validate)), otherwise the first error raised by a step after its
+    /// retries are exhausted.
+    pub async fn run(&self) -> Result<PipelineRun, GeminiError> {
+        let (report, first_error) = self.schedule(true).await?;
+        if let Some(error) = first_error {
+            return Err(error);
+        }
+        let steps = report
+            .steps
+            .into_iter()
+            .filter(|s| s.status == StepStatus::Succeeded)
+            .map(|s| StepOutput {
+                name: s.name,
+                prompt: s.prompt.unwrap_or_default(),
+                output: s.output.unwrap_or_default(),
+                elapsed: s.elapsed,
+                attempts: s.attempts,
+                replayed: s.replayed,
+            })
+            .collect();
+        Ok(PipelineRun { steps })
+    }
+
+    /// Run every step that can run and report on all of them.
+    ///
+    /// A failed step does not stop independent steps; its dependents are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::ValidationFailed` if the pipeline is invalid. Step failures are
+    /// recorded in the report rather than returned.
+    pub async fn execute(&self) -> Result<ExecutionReport, GeminiError> {
+        self.schedule(false).await.map(|(report, _)| report)
+    }
+
+    /// Step indices ordered so that every step comes after its dependencies.
+    fn topological_order(&self) -> Result<Vec<usize>, GeminiError> {
+        let mut index: HashMap<&str, usize> = HashMap::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            if self.inputs.contains_key(&step.name) || index.insert(&step.name, i).is_some() {
+                return Err(GeminiError::ValidationFailed(format!(
+                    "step name `{}` is used more than once",
+                    step.name
+                )));
+            }
+        }
+
+        let mut deps: Vec<Vec<usize>> = Vec::with_capacity(self.steps.len());
+        for step in &self.steps {
+            let mut step_deps = Vec::new();
+            for dep in step.dependencies() {
+                match index.get(dep.as_str()) {
+                    Some(&i) => step_deps.push(i),
+                    None if self.inputs.contains_key(&dep) => {}
+                    None => {
+                        return Err(GeminiError::ValidationFailed(format!(
+                            "step `{}` depends on `{dep}`, which is neither an input nor a step",
+                            step.name
+                        )));
+                    }
+                }
+            }
+            deps.push(step_deps);
+        }
+
+        let mut order = Vec::with_capacity(self.steps.len());
+        let mut placed = vec![false; self.steps.len()];
+        while order.len() < self.steps.len() {
+            let before = order.len();
+            for i in 0..self.steps.len() {
+                if !placed[i] && deps[i].iter().all(|&d| placed[d]) {
+                    placed[i] = true;
+                    order.push(i);
+                }
+            }
+            if order.len() == before {
+                let stuck: Vec<&str> = (0..self.steps.len())
+                    .filter(|&i| !placed[i])
+                    .map(|i| self.steps[i].name.as_str())
+                    .collect();
+                return Err(GeminiError::ValidationFailed(format!(
+                    "dependency cycle among steps: {}",
+                    stuck.join(", ")
+                )));
+            }
+        }
+        Ok(order)
+    }
+
+    /// Drive the graph to completion. With `fail_fast`, stop at the first failed step and
+    /// return its error alongside the partial report.
+    async fn schedule(
+        &self,
+        fail_fast: bool,
+    ) -> Result<(ExecutionReport, Option<GeminiError>), GeminiError> {
+        let order = self.topological_order()?;
+        let started = Instant::now();
+        let mut values = self.inputs.clone();
+        let mut states: HashMap<&str, NodeState> = self
+            .steps
+            .iter()
+            .map(|s| (s.name.as_str(), NodeState::Pending))
+            .collect();
+        let mut report = ExecutionReport::default();
+        let mut first_error = None;
+        let mut in_flight = FuturesUnordered::new();
+
+        loop {
+            for &i in &order {
+                let step = &self.steps[i];
+                if states[step.name.as_str()] != NodeState::Pending {
+                    continue;
+                }
+                let deps = step.dependencies();
+                let dep_in =
+                    |state: NodeState| deps.iter().find(|d| states.get(d.as_str()) == Some(&state));
+                let branch_not_taken = step.condition.as_ref().is_some_and(|c| {
+                    states.get(c.source.as_str()) == Some(&NodeState::Succeeded)
+                        && (c.predicate)(&values[&c.source]) != c.expected
+                });
+                let skip = if let Some(dep) = dep_in(NodeState::NotSucceeded) {
+                    Some((
+                        NodeState::NotSucceeded,
+                        format!("dependency `{dep}` did not succeed"),
+                    ))
+                } else if let Some(dep) = dep_in(NodeState::NotTaken) {
+                    Some((
+                        NodeState::NotTaken,
+                        format!("dependency `{dep}` was not taken"),
+                    ))
+                } else {
+                    branch_not_taken.then(|| (NodeState::NotTaken, "branch not taken".to_string()))
+                };
+                if let Some((state, reason)) = skip {
+                    states.insert(&step.name, state);
+                    report.steps.push(StepReport {
+                        name: step.name.clone(),
+                        status: StepStatus::Skipped { reason },
+                        attempts: 0,
+                        replayed: false,
+                        elapsed: Duration::ZERO,
+                        prompt: None,
+                        output: None,
+                    });
+                    continue;
+                }
+                let ready = deps.iter().all(|d| {
+                    states
+                        .get(d.as_str())
+                        .is_none_or(|s| *s == NodeState::Succeeded)
+                });
+                if ready && in_flight.len() < self.max_concurrency {
+                    states.insert(&step.name, NodeState::Running);
+                    let prompt = render(&step.template, &values);
+                    let context =
+                        (!step.uses.is_empty()).then(|| used_context(&step.uses, &values));
+                    in_flight.push(self.run_step(step, prompt, context));
+                }
+            }
+
+            let Some(StepRun {
+                step,
+                prompt,
+                result,
+                attempts,
+                elapsed,
+                replayed,
+            }) = in_flight.next().await
+            else {
+                break;
+            };
+            let (status, output) = match result {
+                Ok(output) => {
+                    states.insert(&step.name, NodeState::Succeeded);
+                    values.insert(step.name.clone(), output.clone());
+                    (StepStatus::Succeeded, Some(output))
+                }
+                Err(error) => {
+                    states.insert(&step.name, NodeState::NotSucceeded);
+                    let status = StepStatus::Failed {
+                        error: error.to_string(),
+                    };
+                    first_error.get_or_insert(error);
+                    (status, None)
+                }
+            };
+            report.steps.push(StepReport {
+                name: step.name.clone(),
+                status,
+                attempts,
+                replayed,
+                elapsed,
+                prompt: Some(prompt),
+                output,
+            });
+            if fail_fast && first_error.is_some() {
+                break;
+            }
+        }
+
+        report.elapsed = started.elapsed();
+        Ok((report, first_error))
+    }
+
+    /// Build the request for one attempt of `step`.
+    fn request(&self, step: &Step, prompt: &str, context: Option<&str>) -> Gemini {
+        let mut request = Gemini::new(prompt);
+        if let Some(context) = context {
+            request = request.context(context);
+        }
+        if let Some(configure) = &self.configure {
+            request = configure(request);
+        }
+        if let Some(configure) = &step.configure {
+            request = configure(request);
+        }
+        request
+    }
+
+    /// Run one step with its retries and timeout, replaying it from the checkpoint store if
+    /// possible.
+    async fn run_step<'a>(
+        &'a self,
+        step: &'a Step,
+        prompt: String,
+        context: Option<String>,
+    ) -> StepRun<'a> {
+        let started = Instant::now();
+        let finish = |result, attempts, replayed| StepRun {
+            step,
+            prompt: prompt.clone(),
+            result,
+            attempts,
+            elapsed: started.elapsed(),
+            replayed,
+        };
+
+        let checkpoint = match &self.checkpoints {
+            Some(store) => self
+                .request(step, &prompt, context.as_deref())
+                .exact_key(&[b"pipeline-step", step.name.as_bytes()])
+                .await
+                .map(|key| (store, key)),
+            None => None,
+        };
+        if let Some((store, key)) = &checkpoint {
+            match store.get(key).await {
+                Ok(Some(output)) => return finish(Ok(output), 0, true),
+                Ok(None) => {}
+                Err(e) => return finish(Err(e), 0, false),
+            }
+        }
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let request = self.request(step, &prompt, context.as_deref());
+            let result = match step.timeout {
+                // Measured on the request's clock, so tests can drive it with a manual one.
+                Some(timeout) => {
+                    let clock = request.clock.clone();
+                    tokio::select! {
+                        result = request.text() => result,
+                        () = clock.sleep(timeout) => Err(GeminiError::Timeout(timeout)),
+                    }
+                }
+                None => request.text().await,
+            };
+            if let (Ok(output), Some((store, key))) = (&result, &checkpoint) {
+                if let Err(e) = store.put(key, output.clone()).await {
+                    return finish(Err(e), attempt, false);
+                }
+            }
+            if result.is_ok() || attempt > step.retries {
+                return finish(result, attempt, false);
+            }
+        }
+    }
+}
+
+/// The outcome of [`Pipeline::run_step`].
+struct StepRun<'a> {
+    step: &'a Step,
+    prompt: String,
+    result: Result<String, GeminiError>,
+    attempts: u32,
+    elapsed: Duration,
+    replayed: bool,
+}
+
+/// The distinct `{{name}}` placeholders in `template`, in order of appearance.
+fn placeholders(template: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("}}") else { break };
+        let name = rest[..end].trim().to_string();
+        if !name.is_empty() && !names.contains(&name) {
+            names.push(name);
+        }
+        rest = &rest[end + 2..];
+    }
+    names
+}
+
+/// Replace every `{{name}}` with its value. Unknown placeholders are left untouched.
+pub(crate) fn render(template: &str, values: &BTreeMap<String, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                match values.get(name) {
+                    Some(value) => out.push_str(value),
+                    None => out.push_str(&rest[start..start + 2 + end + 2]),
+                }
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn used_context(uses: &[String], values: &BTreeMap<String, String>) -> String {
+    uses.iter()
+        .map(|name| {
+            format!(
+                "### {name}\n{}",
+                values.get(name).map(String::as_str).unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_placeholders_and_render() {
+        let template = "Outline {{ topic }} for {{audience}}, about {{topic}}. {{missing}} {{";
+        assert_eq!(placeholders(template), ["topic", "audience", "missing"]);
+
+        let values = BTreeMap::from([
+            ("topic".to_string(), "Rust".to_string()),
+            ("audience".to_string(), "beginners".to_string()),
+        ]);
+        assert_eq!(
+            render(template, &values),
+            "Outline Rust for beginners, about Rust. {{missing}} {{"
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_unknown_names_and_cycles() {
+        let forward = Pipeline::new()
+            .step(Step::new("draft", "Expand {{outline}}"))
+            .step(Step::new("outline", "Outline it"));
+        assert!(forward.validate().is_ok());
+
+        let cycle = Pipeline::new()
+            .step(Step::new("a", "{{b}}"))
+            .step(Step::new("b", "{{a}}"));
+        assert!(matches!(
+            cycle.validate(),
+            Err(GeminiError::ValidationFailed(msg)) if msg.contains("cycle")
+        ));
+
+        let unknown = Pipeline::new().step(Step::new("a", "{{nope}}"));
+        assert!(unknown.validate().is_err());
+
+        let duplicate = Pipeline::new()
+            .step(Step::new("a", "x"))
+            .step(Step::new("a", "y"));
+        assert!(duplicate.validate().is_err());
+
+        let ok = Pipeline::new()
+            .input("topic", "Rust")
+            .step(Step::new("outline", "Outline {{topic}}"))
+            .step(Step::new("draft", "Write it").uses(["outline"]));
+        assert!(ok.validate().is_ok());
+    }
+
+    #[test]
+    fn test_branch_if_wires_conditions() {
+        let pipeline = Pipeline::new()
+            .step(Step::new("triage", "Triage"))
+            .branch_if(
+                |out| out.contains("NEEDS_REVIEW"),
+                Step::new("review", "Review"),
+                Step::new("ship", "Ship"),
+            );
+        assert!(pipeline.validate().is_ok());
+        let review = &pipeline.steps[1];
+        let ship = &pipeline.steps[2];
+        assert_eq!(review.dependencies(), ["triage"]);
+        let (yes, no) = (
+            review.condition.as_ref().unwrap(),
+            ship.condition.as_ref().unwrap(),
+        );
+        assert!(yes.expected && !no.expected);
+        assert!((yes.predicate)("NEEDS_REVIEW: yes"));
+    }
+
+    #[test]
+    fn test_topological_order_respects_dependencies() {
+        let pipeline = Pipeline::new()
+            .step(Step::new("summary", "{{left}} {{right}}"))
+            .step(Step::new("left", "L"))
+            .step(Step::new("right", "R {{left}}"));
+        assert_eq!(pipeline.topological_order().unwrap(), vec![1, 2, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_step_timeout_fires_on_the_request_clock() {
+        use crate::clock::Clock;
+        use crate::simulate::Simulator;
+        use futures_util::future::BoxFuture;
+        use std::time::SystemTime;
+
+        /// Every sleep is over at once.
+        struct ExpiredClock;
+
+        impl Clock for ExpiredClock {
+            fn now(&self) -> tokio::time::Instant {
+                tokio::time::Instant::now()
+            }
+
+            fn system_time(&self) -> SystemTime {
+                SystemTime::now()
+            }
+
+            fn sleep_until(&self, _: tokio::time::Instant) -> BoxFuture<'static, ()> {
+                Box::pin(std::future::ready(()))
+            }
+        }
+
+        let simulator = Simulator::new().latency(Duration::from_secs(3600));
+        let result = Pipeline::new()
+            .configure(move |g| g.simulator(simulator.clone()).clock(Arc::new(ExpiredClock)))
+            .step(Step::new("slow", "Wait").timeout(Duration::from_secs(60)))
+            .run()
+            .await;
+        assert!(matches!(
+            result,
+            Err(GeminiError::Timeout(limit)) if limit == Duration::from_secs(60)
+        ));
+    }
+}
@@ -0,0 +1,313 @@
+//! Heuristic extraction of the agent's plan from its messages.
+//!
+//! The CLI reports plans and "about to do X" intentions as plain prose. A [`PlanDetector`] reads
+//! the model's text line by line and recognizes:
+//!
+//! * **Plans**: a list of at least two numbered, bulleted or checkbox items introduced by a cue
+//!   such as "plan", "steps" or "I will" (checkbox lists need no cue). Each becomes a
+//!   [`StreamEvent::Plan`]; a later list is a revision of the plan.
+//! * **Intentions**: sentences such as "Now I'll run the tests" or "Next, let me update the
+//!   docs", reported as [`StreamEvent::PlanUpdate`] and matched to the closest plan step.
+//!
+//! Enable the events on a stream with [`Gemini::plan_events`](crate::Gemini::plan_events).
+
+use crate::StreamEvent;
+use serde::{Deserialize, Serialize};
+
+/// Words that introduce a plan on the line before a list.
+const PLAN_CUES: &[&str] = &[
+    "plan",
+    "steps",
+    "step-by-step",
+    "i will",
+    "i'll",
+    "i am going to",
+    "i'm going to",
+    "here's what",
+    "here is what",
+    "approach",
+    "todo",
+];
+
+/// Sentence openings that announce the agent's next action.
+const INTENT_PREFIXES: &[&str] = &[
+    "now i'll ",
+    "now i will ",
+    "now let me ",
+    "next, i'll ",
+    "next, i will ",
+    "next, let me ",
+    "next i'll ",
+    "next i will ",
+    "i'll now ",
+    "i will now ",
+    "i'm going to ",
+    "i am going to ",
+    "let me ",
+    "first, i'll ",
+    "first, let me ",
+    "finally, i'll ",
+    "finally, let me ",
+];
+
+/// Words ignored when matching an intention to a plan step.
+const STOP_WORDS: &[&str] = &[
+    "the", "a", "an", "to", "and", "of", "in", "for", "on", "with", "it", "that", "this", "then",
+    "now", "next", "will", "i'll", "let", "me", "i", "by", "all",
+];
+
+/// Where a plan step stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanStepStatus {
+    Pending,
+    InProgress,
+    Done,
+}
+
+/// One step of the agent's plan.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PlanStep {
+    pub text: String,
+    pub status: PlanStepStatus,
+}
+
+/// Parse a list item (`1.`, `2)`, `-`, `*`, `- [ ]`, `- [x]`) into a step.
+fn parse_item(line: &str) -> Option<PlanStep> {
+    let line = line.trim();
+    let rest = if let Some(rest) = line.strip_prefix(['-', '*', '+']) {
+        rest
+    } else {
+        let digits = line.chars().take_while(char::is_ascii_digit).count();
+        if digits == 0 {
+            return None;
+        }
+        line[digits..].strip_prefix(['.', ')'])?
+    };
+    if !rest.starts_with(' ') {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let (status, text) = if let Some(text) = rest.strip_prefix("[ ]") {
+        (PlanStepStatus::Pending, text)
+    } else if let Some(text) = rest
+        .strip_prefix("[x]")
+        .or_else(|| rest.strip_prefix("[X]"))
+    {
+        (PlanStepStatus::Done, text)
+    } else {
+        (PlanStepStatus::Pending, rest)
+    };
+    let text = text.trim().trim_matches('*').trim();
+    (!text.is_empty()).then(|| PlanStep {
+        text: text.to_string(),
+        status,
+    })
+}
+
+fn is_checkbox(line: &str) -> bool {
+    let line = line
+        .trim_start()
+        .trim_start_matches(['-', '*', '+'])
+        .trim_start();
+    line.starts_with("[ ]") || line.starts_with("[x]") || line.starts_with("[X]")
+}
+
+fn has_plan_cue(line: &str) -> bool {
+    let line = line.to_lowercase();
+    PLAN_CUES.iter().any(|cue| line.contains(cue))
+}
+
+/// The action announced by `line`, if it states the agent's next step.
+fn intent(line: &str) -> Option<String> {
+    let trimmed = line.trim();
+    let lower = trimmed.to_lowercase();
+    let prefix = INTENT_PREFIXES.iter().find(|p| lower.starts_with(*p))?;
+    let action = trimmed[prefix.len()..]
+        .trim()
+        .trim_end_matches(['.', ':', '!'])
+        .trim();
+    (!action.is_empty() && parse_item(trimmed).is_none()).then(|| action.to_string())
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .map(str::to_lowercase)
+        .filter(|w| w.len() > 1 && !STOP_WORDS.contains(&w.as_str()))
+        .collect()
+}
+
+/// The plan step sharing the most significant words with `action`, if any.
+fn closest_step(steps: &[PlanStep], action: &str) -> Option<usize> {
+    let action = words(action);
+    steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| {
+            let overlap = words(&step.text)
+                .iter()
+                .filter(|w| action.contains(w))
+                .count();
+            (i, overlap)
+        })
+        .filter(|&(_, overlap)| overlap > 0)
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+        .map(|(i, _)| i)
+}
+
+/// Incrementally extracts plan events from the model's text.
+#[derive(Debug, Clone, Default)]
+pub struct PlanDetector {
+    partial: String,
+    previous: Option<String>,
+    block: Vec<PlanStep>,
+    block_cued: bool,
+    block_checkbox: bool,
+    steps: Vec<PlanStep>,
+}
+
+impl PlanDetector {
+    /// A detector with no plan yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The current plan, with statuses updated from later intentions.
+    pub fn steps(&self) -> &[PlanStep] {
+        &self.steps
+    }
+
+    /// Feed more model text (a delta or a full message) and return any events it completes.
+    pub fn push(&mut self, text: &str) -> Vec<StreamEvent> {
+        self.partial.push_str(text);
+        let mut events = Vec::new();
+        while let Some(newline) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=newline).collect();
+            self.line(line.trim_end(), &mut events);
+        }
+        events
+    }
+
+    /// Mark the end of a message: the pending partial line and any open list are completed.
+    pub fn finish(&mut self) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        let rest = std::mem::take(&mut self.partial);
+        if !rest.trim().is_empty() {
+            self.line(rest.trim_end(), &mut events);
+        }
+        self.close_block(&mut events);
+        self.previous = None;
+        events
+    }
+
+    fn line(&mut self, line: &str, events: &mut Vec<StreamEvent>) {
+        if let Some(step) = parse_item(line) {
+            if self.block.is_empty() {
+                self.block_cued = self.previous.as_deref().is_some_and(has_plan_cue);
+                self.block_checkbox = true;
+            }
+            self.block_checkbox &= is_checkbox(line);
+            self.block.push(step);
+            return;
+        }
+        if line.trim().is_empty() && !self.block.is_empty() {
+            // Blank lines inside a list do not end it.
+            return;
+        }
+        self.close_block(events);
+        if let Some(action) = intent(line) {
+            let index = closest_step(&self.steps, &action);
+            if let Some(i) = index {
+                for step in &mut self.steps[..i] {
+                    if step.status == PlanStepStatus::InProgress {
+                        step.status = PlanStepStatus::Done;
+                    }
+                }
+                self.steps[i].status = PlanStepStatus::InProgress;
+            }
+            events.push(StreamEvent::PlanUpdate {
+                action,
+                index,
+                status: PlanStepStatus::InProgress,
+            });
+        }
+        if !line.trim().is_empty() {
+            self.previous = Some(line.to_string());
+        }
+    }
+
+    fn close_block(&mut self, events: &mut Vec<StreamEvent>) {
+        let block = std::mem::take(&mut self.block);
+        if block.len() >= 2 && (self.block_cued || self.block_checkbox) {
+            self.steps = block.clone();
+            events.push(StreamEvent::Plan { steps: block });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(events: &[StreamEvent]) -> Option<&Vec<PlanStep>> {
+        events.iter().find_map(|e| match e {
+            StreamEvent::Plan { steps } => Some(steps),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_cued_list_across_deltas_becomes_a_plan() {
+        let mut detector = PlanDetector::new();
+        let mut events = detector.push("Here's my plan:\n1. Read the fail");
+        events.extend(detector.push("ing test\n2. Fix the parser\n\n3) Run `cargo test`\n"));
+        assert!(events.is_empty());
+        events.extend(detector.push("Done planning.\n"));
+
+        let steps = plan(&events).expect("plan");
+        assert_eq!(
+            steps.iter().map(|s| s.text.as_str()).collect::<Vec<_>>(),
+            [
+                "Read the failing test",
+                "Fix the parser",
+                "Run `cargo test`"
+            ]
+        );
+        assert!(steps.iter().all(|s| s.status == PlanStepStatus::Pending));
+    }
+
+    #[test]
+    fn test_uncued_bullets_are_not_a_plan_but_checkboxes_are() {
+        let mut detector = PlanDetector::new();
+        let mut events = detector.push("The crate has:\n- a parser\n- a lexer\n");
+        events.extend(detector.finish());
+        assert!(plan(&events).is_none());
+
+        let mut detector = PlanDetector::new();
+        detector.push("- [x] Reproduce the bug\n- [ ] Fix it");
+        let events = detector.finish();
+        let steps = plan(&events).expect("plan");
+        assert_eq!(steps[0].status, PlanStepStatus::Done);
+        assert_eq!(steps[1].text, "Fix it");
+    }
+
+    #[test]
+    fn test_intentions_update_matching_steps() {
+        let mut detector = PlanDetector::new();
+        detector.push("I will:\n1. Update the parser\n2. Run the tests\n\n");
+        let events = detector.push("Now I'll run the unit tests.\n");
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], StreamEvent::Plan { steps } if steps.len() == 2));
+        assert!(matches!(
+            &events[1],
+            StreamEvent::PlanUpdate { action, index: Some(1), .. } if action == "run the unit tests"
+        ));
+        assert_eq!(detector.steps()[1].status, PlanStepStatus::InProgress);
+
+        let events = detector.push("Let me look around first.\n");
+        assert!(matches!(
+            &events[0],
+            StreamEvent::PlanUpdate { index: None, .. }
+        ));
+    }
+}
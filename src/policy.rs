@@ -0,0 +1,620 @@
+//! Declarative governance for the agent's tool calls.
+//!
+//! A [`ToolPolicy`] is a list of [`PolicyRule`]s, typically loaded from JSON, that is evaluated
+//! against every tool call the agent reports. Each call is described by a [`ToolContext`]
+//! (tool name, arguments, target path, working directory) and gets a [`PolicyDecision`]:
+//! allow, deny, or ask a [`ToolApprover`]. Attach a policy with
+//! [`Gemini::tool_policy`](crate::Gemini::tool_policy); denied calls stop the run with
+//! `GeminiError::PolicyDenied`.
+//!
+//! Only decisions that depend on nothing but the tool's name are enforced before anything
+//! runs: such tools are passed to the CLI as excluded (every call denied) or pre-approved
+//! (every call allowed), see [`ToolPolicy::cli_tools`]. Rules on paths, commands and
+//! `outside_cwd` can only be checked once the CLI reports the call, and in
+//! [`yolo`](crate::Gemini::yolo) mode the CLI runs it at the same moment. For those the policy
+//! is a best-effort kill switch that stops the run, not a guarantee that the call never ran.
+//!
+//! ```rust
+//! use gemini_oxide::policy::{PolicyDecision, ToolContext, ToolPolicy};
+//! use serde_json::json;
+//!
+//! let policy = ToolPolicy::from_json(r#"{
+//!     "default": "ask",
+//!     "rules": [
+//!         { "effect": "allow", "tool": "read_*" },
+//!         { "effect": "allow", "tool": "write_file", "path": "docs/*" },
+//!         { "effect": "deny", "outside_cwd": true, "reason": "stay in the workspace" },
+//!         { "effect": "deny", "tool": "run_shell_command", "command": "*rm -rf*" }
+//!     ]
+//! }"#).unwrap();
+//!
+//! let call = ToolContext::new("read_file", json!({ "file_path": "/etc/passwd" }), "/repo");
+//! assert_eq!(
+//!     policy.evaluate(&call),
+//!     PolicyDecision::Deny { reason: "stay in the workspace".into() }
+//! );
+//! ```
+
+use crate::GeminiError;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+
+/// Argument names the built-in tools use for the file or directory they act on.
+const TARGET_KEYS: &[&str] = &[
+    "file_path",
+    "absolute_path",
+    "path",
+    "dir_path",
+    "directory",
+];
+
+/// The CLI's built-in tools, which tool patterns with wildcards are expanded to.
+const BUILTIN_TOOLS: &[&str] = &[
+    "list_directory",
+    "read_file",
+    "read_many_files",
+    "write_file",
+    "replace",
+    "glob",
+    "search_file_content",
+    "run_shell_command",
+    "web_fetch",
+    "google_web_search",
+    "save_memory",
+    "write_todos",
+];
+
+/// How much the CLI may do without asking, passed as `--approval-mode`.
+///
+/// In headless runs nobody can answer the CLI's confirmations, so tools that need approval are
+/// not run. Combine a permissive mode with a [`ToolPolicy`] to keep control over what runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalMode {
+    /// Every file edit and shell command needs confirmation.
+    #[default]
+    Default,
+    /// File edits are approved automatically; shell commands still need confirmation.
+    AutoEdit,
+    /// Every tool call is approved automatically.
+    Yolo,
+}
+
+impl ApprovalMode {
+    /// The value of the CLI's `--approval-mode` flag.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApprovalMode::Default => "default",
+            ApprovalMode::AutoEdit => "auto_edit",
+            ApprovalMode::Yolo => "yolo",
+        }
+    }
+}
+
+/// The effect of a matching rule, and the policy's default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Effect {
+    Allow,
+    Deny,
+    #[default]
+    Ask,
+}
+
+/// The outcome of evaluating a tool call.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum PolicyDecision {
+    /// The call may proceed.
+    Allow,
+    /// The call must not proceed.
+    Deny { reason: String },
+    /// The call needs approval from a [`ToolApprover`].
+    Ask { reason: String },
+}
+
+/// A tool call, as seen by a policy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolContext {
+    /// The tool's name, e.g. `write_file`.
+    pub tool: String,
+    /// The tool's arguments as reported by the CLI.
+    pub args: Value,
+    /// The file or directory the call acts on, resolved against `cwd`.
+    pub target: Option<PathBuf>,
+    /// The working directory of the run.
+    pub cwd: PathBuf,
+    /// The request's [labels](crate::Gemini::label), for attributing the call in audit logs.
+    pub labels: BTreeMap<String, String>,
+}
+
+impl ToolContext {
+    /// Describe a call to `tool` with `args`, made from `cwd`.
+    pub fn new(tool: impl Into<String>, args: Value, cwd: impl Into<PathBuf>) -> Self {
+        let cwd = cwd.into();
+        let target = TARGET_KEYS
+            .iter()
+            .find_map(|key| args.get(key).and_then(Value::as_str))
+            .map(|path| normalize(&cwd.join(path)));
+        Self {
+            tool: tool.into(),
+            args,
+            target,
+            cwd,
+            labels: BTreeMap::new(),
+        }
+    }
+
+    /// The shell command, for command-running tools.
+    pub fn command(&self) -> Option<&str> {
+        self.args.get("command").and_then(Value::as_str)
+    }
+
+    /// Whether the target lies outside the working directory. `false` without a target.
+    pub fn is_outside_cwd(&self) -> bool {
+        self.target
+            .as_ref()
+            .is_some_and(|target| !target.starts_with(normalize(&self.cwd)))
+    }
+
+    /// The target relative to the working directory, or absolute if it lies outside it.
+    fn display_target(&self) -> Option<String> {
+        let target = self.target.as_ref()?;
+        let shown = target.strip_prefix(normalize(&self.cwd)).unwrap_or(target);
+        Some(shown.to_string_lossy().into_owned())
+    }
+}
+
+/// A condition on tool calls and the effect it has when every given condition holds.
+///
+/// Patterns are globs where `*` matches any run of characters (including `/`) and `?` matches
+/// one character.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PolicyRule {
+    pub effect: Effect,
+    /// Pattern for the tool name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    /// Pattern for the target path, relative to the working directory when inside it. Calls
+    /// without a target never match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Pattern for the shell command. Calls without a command never match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Match only calls whose target is (or is not) outside the working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outside_cwd: Option<bool>,
+    /// Explanation reported with deny and ask decisions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl PolicyRule {
+    /// A rule with the given effect and no conditions (it matches every call).
+    pub fn new(effect: Effect) -> Self {
+        Self {
+            effect,
+            ..Self::default()
+        }
+    }
+
+    /// Only match tools whose name matches `pattern`.
+    #[must_use]
+    pub fn tool(mut self, pattern: impl Into<String>) -> Self {
+        self.tool = Some(pattern.into());
+        self
+    }
+
+    /// Only match calls whose target path matches `pattern`.
+    #[must_use]
+    pub fn path(mut self, pattern: impl Into<String>) -> Self {
+        self.path = Some(pattern.into());
+        self
+    }
+
+    /// Only match calls whose shell command matches `pattern`.
+    #[must_use]
+    pub fn command(mut self, pattern: impl Into<String>) -> Self {
+        self.command = Some(pattern.into());
+        self
+    }
+
+    /// Only match calls whose target is outside (`true`) or inside (`false`) the working
+    /// directory.
+    #[must_use]
+    pub fn outside_cwd(mut self, outside: bool) -> Self {
+        self.outside_cwd = Some(outside);
+        self
+    }
+
+    /// Set the explanation reported with the decision.
+    #[must_use]
+    pub fn reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    /// Whether every condition of the rule holds for `call`.
+    pub fn matches(&self, call: &ToolContext) -> bool {
+        let tool = self.tool.as_deref().is_none_or(|p| glob(p, &call.tool));
+        let path = self
+            .path
+            .as_deref()
+            .is_none_or(|p| call.display_target().is_some_and(|t| glob(p, &t)));
+        let command = self
+            .command
+            .as_deref()
+            .is_none_or(|p| call.command().is_some_and(|c| glob(p, c)));
+        let outside = self
+            .outside_cwd
+            .is_none_or(|outside| call.is_outside_cwd() == outside);
+        tool && path && command && outside
+    }
+
+    /// Whether the rule depends on nothing but the tool's name.
+    fn is_unconditional(&self) -> bool {
+        self.path.is_none() && self.command.is_none() && self.outside_cwd.is_none()
+    }
+
+    /// Whether the rule can match some call of `tool`.
+    fn can_match(&self, tool: &str) -> bool {
+        self.tool.as_deref().is_none_or(|p| glob(p, tool))
+    }
+
+    /// The tools the rule's pattern names: the pattern itself, or the built-in tools it
+    /// matches if it has wildcards.
+    fn tools(&self) -> Vec<String> {
+        match self.tool.as_deref() {
+            Some(pattern) if !pattern.contains(['*', '?']) => vec![pattern.to_string()],
+            pattern => BUILTIN_TOOLS
+                .iter()
+                .filter(|tool| pattern.is_none_or(|p| glob(p, tool)))
+                .map(|tool| tool.to_string())
+                .collect(),
+        }
+    }
+
+    fn describe(&self, call: &ToolContext) -> String {
+        self.reason.clone().unwrap_or_else(|| {
+            let mut text = format!("`{}`", call.tool);
+            if let Some(target) = call.display_target() {
+                text.push_str(&format!(" on `{target}`"));
+            }
+            let verb = match self.effect {
+                Effect::Deny => "denied",
+                _ => "requires approval",
+            };
+            format!("{text} {verb} by policy")
+        })
+    }
+}
+
+/// An ordered set of rules with a default effect.
+///
+/// Every matching rule is considered: any deny wins over any ask, which wins over any allow.
+/// Calls no rule matches get the default effect, `ask` unless configured otherwise.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ToolPolicy {
+    #[serde(default)]
+    pub default: Effect,
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+}
+
+impl ToolPolicy {
+    /// An empty policy with the given default effect.
+    pub fn new(default: Effect) -> Self {
+        Self {
+            default,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Load a policy from its JSON form.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::JsonParseFailed` if `json` is not a valid policy.
+    pub fn from_json(json: &str) -> Result<Self, GeminiError> {
+        serde_json::from_str(json).map_err(GeminiError::JsonParseFailed)
+    }
+
+    /// Add a rule.
+    #[must_use]
+    pub fn rule(mut self, rule: PolicyRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Decide on `call`.
+    pub fn evaluate(&self, call: &ToolContext) -> PolicyDecision {
+        let matching: Vec<&PolicyRule> = self.rules.iter().filter(|r| r.matches(call)).collect();
+        let first = |effect| matching.iter().find(|r| r.effect == effect);
+        if let Some(rule) = first(Effect::Deny) {
+            return PolicyDecision::Deny {
+                reason: rule.describe(call),
+            };
+        }
+        if let Some(rule) = first(Effect::Ask) {
+            return PolicyDecision::Ask {
+                reason: rule.describe(call),
+            };
+        }
+        if !matching.is_empty() {
+            return PolicyDecision::Allow;
+        }
+        let fallback = PolicyRule::new(self.default);
+        match self.default {
+            Effect::Allow => PolicyDecision::Allow,
+            Effect::Deny => PolicyDecision::Deny {
+                reason: fallback.describe(call),
+            },
+            Effect::Ask => PolicyDecision::Ask {
+                reason: fallback.describe(call),
+            },
+        }
+    }
+
+    /// The tools whose every call the policy decides by name alone, as `(allowed, excluded)`.
+    ///
+    /// Excluded tools are those an unconditional deny rule matches, and with a `deny` default,
+    /// the built-in tools no allow or ask rule can match. Allowed tools are those an
+    /// unconditional allow rule matches that no deny or ask rule can match, so the CLI may run
+    /// them without confirmation.
+    pub fn cli_tools(&self) -> (Vec<String>, Vec<String>) {
+        let mut excluded: Vec<String> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.effect == Effect::Deny && rule.is_unconditional())
+            .flat_map(PolicyRule::tools)
+            .collect();
+        if self.default == Effect::Deny {
+            excluded.extend(
+                BUILTIN_TOOLS
+                    .iter()
+                    .filter(|tool| {
+                        !self
+                            .rules
+                            .iter()
+                            .any(|rule| rule.effect != Effect::Deny && rule.can_match(tool))
+                    })
+                    .map(|tool| tool.to_string()),
+            );
+        }
+        let mut allowed: Vec<String> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.effect == Effect::Allow && rule.is_unconditional())
+            .flat_map(PolicyRule::tools)
+            .filter(|tool| {
+                !self
+                    .rules
+                    .iter()
+                    .any(|rule| rule.effect != Effect::Allow && rule.can_match(tool))
+            })
+            .collect();
+        for tools in [&mut allowed, &mut excluded] {
+            tools.sort();
+            tools.dedup();
+        }
+        (allowed, excluded)
+    }
+}
+
+/// Resolves `ask` decisions, e.g. by prompting a human or consulting a ticketing system.
+pub trait ToolApprover: Send + Sync {
+    /// Whether `call` may proceed. `reason` explains why approval was needed.
+    fn approve<'a>(
+        &'a self,
+        call: &'a ToolContext,
+        reason: &'a str,
+    ) -> BoxFuture<'a, Result<bool, GeminiError>>;
+}
+
+struct FnApprover<F>(F);
+
+impl<F, Fut> ToolApprover for FnApprover<F>
+where
+    F: Fn(ToolContext, String) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<bool, GeminiError>> + Send + 'static,
+{
+    fn approve<'a>(
+        &'a self,
+        call: &'a ToolContext,
+        reason: &'a str,
+    ) -> BoxFuture<'a, Result<bool, GeminiError>> {
+        Box::pin((self.0)(call.clone(), reason.to_string()))
+    }
+}
+
+/// Wrap an async closure as a [`ToolApprover`].
+///
+/// ```rust
+/// use gemini_oxide::policy::approver_fn;
+///
+/// let approver = approver_fn(|call, _reason| async move {
+///     Ok(call.tool == "write_file" && !call.is_outside_cwd())
+/// });
+/// ```
+pub fn approver_fn<F, Fut>(f: F) -> impl ToolApprover
+where
+    F: Fn(ToolContext, String) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<bool, GeminiError>> + Send + 'static,
+{
+    FnApprover(f)
+}
+
+/// Evaluate `call`, consulting `approver` for `ask` decisions. Without an approver, `ask` is
+/// treated as deny.
+pub(crate) async fn enforce(
+    policy: &ToolPolicy,
+    approver: Option<&dyn ToolApprover>,
+    call: &ToolContext,
+) -> Result<(), GeminiError> {
+    match policy.evaluate(call) {
+        PolicyDecision::Allow => Ok(()),
+        PolicyDecision::Deny { reason } => Err(GeminiError::PolicyDenied(reason)),
+        PolicyDecision::Ask { reason } => match approver {
+            Some(approver) if approver.approve(call, &reason).await? => Ok(()),
+            Some(_) => Err(GeminiError::PolicyDenied(format!("{reason}; not approved"))),
+            None => Err(GeminiError::PolicyDenied(format!(
+                "{reason}; no approver configured"
+            ))),
+        },
+    }
+}
+
+/// Resolve `.` and `..` components without touching the file system.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any run of characters and `?` one.
+pub(crate) fn glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(tool: &str, args: Value) -> ToolContext {
+        ToolContext::new(tool, args, "/repo")
+    }
+
+    #[test]
+    fn test_glob_and_targets() {
+        assert!(glob("read_*", "read_file"));
+        assert!(glob("*rm -rf*", "cd /tmp && rm -rf build"));
+        assert!(glob("src/?.rs", "src/a.rs"));
+        assert!(!glob("docs/*", "src/docs/a.md"));
+
+        let escape = call("write_file", json!({ "file_path": "src/../../etc/hosts" }));
+        assert_eq!(escape.target, Some(PathBuf::from("/etc/hosts")));
+        assert!(escape.is_outside_cwd());
+        assert!(!call("write_file", json!({ "file_path": "src/lib.rs" })).is_outside_cwd());
+        assert!(!call("web_fetch", json!({ "url": "https://x" })).is_outside_cwd());
+    }
+
+    #[test]
+    fn test_name_only_decisions_are_passed_to_the_cli() {
+        let policy = ToolPolicy::new(Effect::Deny)
+            .rule(PolicyRule::new(Effect::Allow).tool("read_*"))
+            .rule(
+                PolicyRule::new(Effect::Allow)
+                    .tool("write_file")
+                    .path("src/*"),
+            )
+            .rule(PolicyRule::new(Effect::Allow).tool("glob"))
+            .rule(PolicyRule::new(Effect::Ask).tool("glob").outside_cwd(true))
+            .rule(PolicyRule::new(Effect::Deny).tool("mcp_deploy"));
+        let (allowed, excluded) = policy.cli_tools();
+        assert_eq!(allowed, ["read_file", "read_many_files"]);
+        assert!(excluded.contains(&"run_shell_command".to_string()));
+        assert!(excluded.contains(&"mcp_deploy".to_string()));
+        assert!(!excluded.iter().any(|t| t == "write_file" || t == "glob"));
+
+        // A rule on every tool leaves nothing to pre-approve.
+        let cautious = ToolPolicy::new(Effect::Allow)
+            .rule(PolicyRule::new(Effect::Allow).tool("read_file"))
+            .rule(PolicyRule::new(Effect::Deny).outside_cwd(true));
+        assert_eq!(cautious.cli_tools(), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_deny_overrides_ask_and_allow() {
+        let policy = ToolPolicy::new(Effect::Allow)
+            .rule(PolicyRule::new(Effect::Allow).tool("run_shell_command"))
+            .rule(PolicyRule::new(Effect::Ask).tool("run_shell_command"))
+            .rule(
+                PolicyRule::new(Effect::Deny)
+                    .command("*rm -rf*")
+                    .reason("destructive"),
+            )
+            .rule(
+                PolicyRule::new(Effect::Deny)
+                    .tool("write_file")
+                    .path("docs/*"),
+            );
+
+        let shell = |cmd: &str| call("run_shell_command", json!({ "command": cmd }));
+        assert_eq!(
+            policy.evaluate(&shell("rm -rf /")),
+            PolicyDecision::Deny {
+                reason: "destructive".into()
+            }
+        );
+        assert!(matches!(
+            policy.evaluate(&shell("ls")),
+            PolicyDecision::Ask { .. }
+        ));
+        assert_eq!(
+            policy.evaluate(&call(
+                "write_file",
+                json!({ "file_path": "/repo/docs/a.md" })
+            )),
+            PolicyDecision::Deny {
+                reason: "`write_file` on `docs/a.md` denied by policy".into()
+            }
+        );
+        assert_eq!(
+            policy.evaluate(&call("write_file", json!({ "file_path": "src/a.rs" }))),
+            PolicyDecision::Allow
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ask_needs_an_approver() {
+        let policy = ToolPolicy::from_json(r#"{ "rules": [] }"#).unwrap();
+        let read = call("read_file", json!({ "file_path": "a" }));
+        assert!(matches!(
+            enforce(&policy, None, &read).await,
+            Err(GeminiError::PolicyDenied(reason)) if reason.contains("no approver")
+        ));
+
+        let approver = approver_fn(|call, _| async move { Ok(call.tool.starts_with("read_")) });
+        assert!(enforce(&policy, Some(&approver), &read).await.is_ok());
+        let write = call("write_file", json!({ "file_path": "a" }));
+        assert!(enforce(&policy, Some(&approver), &write).await.is_err());
+    }
+}
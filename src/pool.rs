@@ -0,0 +1,310 @@
+//! Concurrency-limited execution.
+//!
+//! Every request launches its own CLI process, so running a long list of prompts with
+//! `FuturesUnordered` or `join_all` starts them all at once and quickly exhausts API quotas
+//! and local resources. A [`GeminiPool`] runs at most `max_concurrency` requests at a time;
+//! excess requests wait their turn in arrival order. The pool is cheap to clone and every
+//! clone shares the same limit, so one pool can guard a whole application.
+//!
+//! [`text_batch`](GeminiPool::text_batch) and [`json_batch`](GeminiPool::json_batch) run a
+//! whole list through the pool and yield a [`BatchItem`] per request, carrying its index in
+//! the list and how long it queued and ran. Items arrive as requests finish, or in submission
+//! order with [`BatchOrder::Submission`] when results must line up with their inputs.
+//!
+//! Use [`JobQueue`](crate::jobs::JobQueue) instead for background jobs with priorities and
+//! status polling.
+//!
+//! ```rust,no_run
+//! use futures_util::future::join_all;
+//! use gemini_oxide::pool::GeminiPool;
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() {
+//! let pool = GeminiPool::new(4);
+//! let answers = join_all(
+//!     ["What is 2+2?", "Who wrote Rust?", "What is the speed of sound?"]
+//!         .into_iter()
+//!         .map(|prompt| pool.text(Gemini::new(prompt))),
+//! )
+//! .await;
+//! # }
+//! ```
+
+use crate::{Gemini, GeminiError, GeminiJsonOutput, StreamEvent};
+use futures_util::stream::{self, Stream, StreamExt};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+/// The order in which a batch yields its results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BatchOrder {
+    /// As requests finish.
+    #[default]
+    Completion,
+    /// In the order requests were submitted. A slow request holds back the ones after it.
+    Submission,
+}
+
+/// One request's outcome in a batch.
+#[derive(Debug)]
+pub struct BatchItem<T> {
+    /// The request's position in the submitted list, from 0.
+    pub index: usize,
+    pub result: Result<T, GeminiError>,
+    /// Time spent waiting for a free slot.
+    pub queued: Duration,
+    /// Time spent running once admitted.
+    pub elapsed: Duration,
+}
+
+/// Runs requests with a cap on how many CLI processes are alive at once.
+#[derive(Debug, Clone)]
+pub struct GeminiPool {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    max_concurrency: usize,
+    permits: Arc<Semaphore>,
+    queued: AtomicUsize,
+}
+
+impl GeminiPool {
+    /// A pool running at most `max_concurrency` requests at a time (at least one).
+    pub fn new(max_concurrency: usize) -> Self {
+        let max_concurrency = max_concurrency.max(1);
+        Self {
+            shared: Arc::new(Shared {
+                max_concurrency,
+                permits: Arc::new(Semaphore::new(max_concurrency)),
+                queued: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// The most requests run at once.
+    pub fn max_concurrency(&self) -> usize {
+        self.shared.max_concurrency
+    }
+
+    /// Requests currently running.
+    pub fn running(&self) -> usize {
+        self.shared.max_concurrency - self.shared.permits.available_permits()
+    }
+
+    /// Requests waiting for a free slot.
+    pub fn queued(&self) -> usize {
+        self.shared.queued.load(Ordering::SeqCst)
+    }
+
+    /// Run `request` with [`Gemini::text`] once a slot is free.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised while running the CLI.
+    pub async fn text(&self, request: Gemini) -> Result<String, GeminiError> {
+        let _permit = self.acquire().await;
+        request.text().await
+    }
+
+    /// Run `request` with [`Gemini::json`] once a slot is free.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised while running the CLI.
+    pub async fn json(&self, request: Gemini) -> Result<GeminiJsonOutput, GeminiError> {
+        let _permit = self.acquire().await;
+        request.json().await
+    }
+
+    /// Start `request` with [`Gemini::stream`] once a slot is free. The slot is held until the
+    /// stream ends or is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised while starting the CLI.
+    pub async fn stream(
+        &self,
+        request: Gemini,
+    ) -> Result<impl Stream<Item = Result<StreamEvent, GeminiError>>, GeminiError> {
+        let permit = self.acquire().await;
+        let events = request.stream()?;
+        Ok(async_stream::stream! {
+            let _permit = permit;
+            futures_util::pin_mut!(events);
+            while let Some(event) = events.next().await {
+                yield event;
+            }
+        })
+    }
+
+    /// Run every request with [`Gemini::text`] through the pool, yielding results in `order`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use gemini_oxide::pool::{BatchOrder, GeminiPool};
+    /// # use gemini_oxide::Gemini;
+    /// # use futures_util::StreamExt;
+    /// # async fn run() {
+    /// let rows = ["Paris", "Lagos", "Lima"];
+    /// let requests = rows.iter().map(|city| Gemini::new(format!("Population of {city}?")));
+    /// let items: Vec<_> = GeminiPool::new(2)
+    ///     .text_batch(requests, BatchOrder::Submission)
+    ///     .collect()
+    ///     .await;
+    /// for (city, item) in rows.iter().zip(items) {
+    ///     println!("{city}: {:?} ({:?})", item.result, item.elapsed);
+    /// }
+    /// # }
+    /// ```
+    pub fn text_batch<I>(
+        &self,
+        requests: I,
+        order: BatchOrder,
+    ) -> impl Stream<Item = BatchItem<String>>
+    where
+        I: IntoIterator<Item = Gemini>,
+    {
+        self.batch(requests, order, Gemini::text)
+    }
+
+    /// Run every request with [`Gemini::json`] through the pool, yielding results in `order`.
+    pub fn json_batch<I>(
+        &self,
+        requests: I,
+        order: BatchOrder,
+    ) -> impl Stream<Item = BatchItem<GeminiJsonOutput>>
+    where
+        I: IntoIterator<Item = Gemini>,
+    {
+        self.batch(requests, order, Gemini::json)
+    }
+
+    fn batch<I, T, F, Fut>(
+        &self,
+        requests: I,
+        order: BatchOrder,
+        run: F,
+    ) -> impl Stream<Item = BatchItem<T>>
+    where
+        I: IntoIterator<Item = Gemini>,
+        F: Fn(Gemini) -> Fut,
+        Fut: Future<Output = Result<T, GeminiError>>,
+    {
+        let pool = self.clone();
+        let items = stream::iter(
+            requests
+                .into_iter()
+                .enumerate()
+                .map(move |(index, request)| {
+                    let pool = pool.clone();
+                    let result = run(request);
+                    async move {
+                        let submitted = Instant::now();
+                        let _permit = pool.acquire().await;
+                        let queued = submitted.elapsed();
+                        let result = result.await;
+                        BatchItem {
+                            index,
+                            result,
+                            queued,
+                            elapsed: submitted.elapsed() - queued,
+                        }
+                    }
+                }),
+        );
+        // Every request is started at once; the pool's semaphore does the limiting.
+        match order {
+            BatchOrder::Completion => items.buffer_unordered(usize::MAX).left_stream(),
+            BatchOrder::Submission => items.buffered(usize::MAX).right_stream(),
+        }
+    }
+
+    /// Wait for a free slot, counting the wait as queued.
+    async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.shared.queued.fetch_add(1, Ordering::SeqCst);
+        let _queued = Dequeue(&self.shared.queued);
+        self.shared
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the pool's semaphore is never closed")
+    }
+}
+
+/// Leaves the queue when the wait ends, including when the waiting future is dropped.
+struct Dequeue<'a>(&'a AtomicUsize);
+
+impl Drop for Dequeue<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::Simulator;
+
+    #[tokio::test]
+    async fn test_waiters_are_counted_until_admitted() {
+        let pool = GeminiPool::new(1);
+        let held = pool.acquire().await;
+        assert_eq!((pool.running(), pool.queued()), (1, 0));
+
+        let waiting = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                let _permit = pool.acquire().await;
+            }
+        });
+        while pool.queued() == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(pool.queued(), 1);
+
+        drop(held);
+        waiting.await.unwrap();
+        assert_eq!((pool.running(), pool.queued()), (0, 0));
+        assert_eq!(GeminiPool::new(0).max_concurrency(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_batch_order() {
+        let requests = || {
+            [300, 100, 200].map(|ms| {
+                Gemini::new("Hi").simulator(
+                    Simulator::new()
+                        .fallback(ms.to_string())
+                        .latency(Duration::from_millis(ms)),
+                )
+            })
+        };
+        let pool = GeminiPool::new(3);
+        let finished: Vec<_> = pool
+            .text_batch(requests(), BatchOrder::Completion)
+            .map(|item| item.index)
+            .collect()
+            .await;
+        assert_eq!(finished, [1, 2, 0]);
+
+        let items: Vec<_> = GeminiPool::new(1)
+            .text_batch(requests(), BatchOrder::Submission)
+            .collect()
+            .await;
+        let indexed: Vec<_> = items
+            .iter()
+            .map(|item| (item.index, item.result.as_deref().unwrap()))
+            .collect();
+        assert_eq!(indexed, [(0, "300"), (1, "100"), (2, "200")]);
+        assert_eq!(items[2].queued, Duration::from_millis(400));
+        assert_eq!(items[2].elapsed, Duration::from_millis(200));
+    }
+}
@@ -0,0 +1,270 @@
+//! Response post-processing.
+//!
+//! A [`ProcessorChain`] is an ordered list of [`Processor`]s applied to the model's response
+//! before it is handed back to the caller. Attach one with
+//! [`Gemini::post_process`](crate::Gemini::post_process); the same chain is used by `text()`,
+//! `json()` and `stream()`.
+
+use crate::{Configure, Gemini, GeminiError};
+use futures_util::future::BoxFuture;
+use std::sync::Arc;
+
+/// A single transformation of response text.
+///
+/// Processors are asynchronous so they can call out to other services (or the model itself,
+/// as [`Translate`] does).
+pub trait Processor: Send + Sync {
+    /// Transform `text`, returning the new text.
+    fn process(&self, text: String) -> BoxFuture<'_, Result<String, GeminiError>>;
+}
+
+/// An ordered, cheaply clonable list of processors.
+#[derive(Clone, Default)]
+pub struct ProcessorChain {
+    processors: Vec<Arc<dyn Processor>>,
+}
+
+impl ProcessorChain {
+    /// Create an empty chain, which returns text unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the chain contains no processors.
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    /// Append a processor.
+    #[must_use]
+    pub fn with(mut self, processor: impl Processor + 'static) -> Self {
+        self.processors.push(Arc::new(processor));
+        self
+    }
+
+    /// Append [`StripMarkdown`].
+    #[must_use]
+    pub fn strip_markdown(self) -> Self {
+        self.with(StripMarkdown)
+    }
+
+    /// Append [`NormalizeWhitespace`].
+    #[must_use]
+    pub fn normalize_whitespace(self) -> Self {
+        self.with(NormalizeWhitespace)
+    }
+
+    /// Append a [`Translate`] step into `language` using the default CLI settings.
+    #[must_use]
+    pub fn translate(self, language: impl Into<String>) -> Self {
+        self.with(Translate::new(language))
+    }
+
+    /// Append a synchronous closure.
+    #[must_use]
+    pub fn map(self, f: impl Fn(String) -> String + Send + Sync + 'static) -> Self {
+        self.with(FnProcessor(f))
+    }
+
+    /// Run every processor in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error raised by a processor.
+    pub async fn run(&self, mut text: String) -> Result<String, GeminiError> {
+        for processor in &self.processors {
+            text = processor.process(text).await?;
+        }
+        Ok(text)
+    }
+}
+
+struct FnProcessor<F>(F);
+
+impl<F> Processor for FnProcessor<F>
+where
+    F: Fn(String) -> String + Send + Sync,
+{
+    fn process(&self, text: String) -> BoxFuture<'_, Result<String, GeminiError>> {
+        let out = (self.0)(text);
+        Box::pin(async move { Ok(out) })
+    }
+}
+
+/// Removes Markdown formatting: code fences, heading markers, block quotes, emphasis and
+/// inline code backticks. List markers and line structure are preserved, and the lines inside
+/// a fenced code block are kept exactly as written.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StripMarkdown;
+
+impl StripMarkdown {
+    /// Strip Markdown formatting from `text`.
+    pub fn apply(text: &str) -> String {
+        // The fence character and length of the open code block, if any.
+        let mut fence: Option<(char, usize)> = None;
+        let mut out = Vec::new();
+        for line in text.lines() {
+            let trimmed = line.trim_start();
+            let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+            let run = marker.map_or(0, |c| trimmed.chars().take_while(|x| *x == c).count());
+            match (fence, marker) {
+                (None, Some(c)) if run >= 3 => fence = Some((c, run)),
+                (Some((open, len)), Some(c))
+                    if c == open && run >= len && trimmed[run..].trim().is_empty() =>
+                {
+                    fence = None;
+                }
+                (Some(_), _) => out.push(line.to_string()),
+                (None, _) => out.push(Self::strip_line(line)),
+            }
+        }
+        out.join("\n")
+    }
+
+    fn strip_line(line: &str) -> String {
+        let trimmed = line.trim_start();
+        let unheaded = trimmed.trim_start_matches('#');
+        let line = if unheaded.len() < trimmed.len() && unheaded.starts_with(' ') {
+            unheaded.trim_start()
+        } else {
+            line
+        };
+        let line = line.strip_prefix("> ").unwrap_or(line);
+        line.replace("**", "").replace("__", "").replace('`', "")
+    }
+}
+
+impl Processor for StripMarkdown {
+    fn process(&self, text: String) -> BoxFuture<'_, Result<String, GeminiError>> {
+        let out = Self::apply(&text);
+        Box::pin(async move { Ok(out) })
+    }
+}
+
+/// Trims trailing whitespace, collapses runs of spaces and tabs after a line's indentation, and
+/// squeezes more than one consecutive blank line into one. Indentation, spaces or tabs, is kept.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NormalizeWhitespace;
+
+impl NormalizeWhitespace {
+    /// Normalize the whitespace of `text`.
+    pub fn apply(text: &str) -> String {
+        let mut out: Vec<String> = Vec::new();
+        for line in text.lines() {
+            let collapsed = line.split_whitespace().collect::<Vec<_>>().join(" ");
+            let indent: String = line
+                .chars()
+                .take_while(|c| matches!(c, ' ' | '\t'))
+                .collect();
+            if collapsed.is_empty() && out.last().is_none_or(String::is_empty) {
+                continue;
+            }
+            out.push(if collapsed.is_empty() {
+                collapsed
+            } else {
+                indent + &collapsed
+            });
+        }
+        while out.last().is_some_and(String::is_empty) {
+            out.pop();
+        }
+        out.join("\n")
+    }
+}
+
+impl Processor for NormalizeWhitespace {
+    fn process(&self, text: String) -> BoxFuture<'_, Result<String, GeminiError>> {
+        let out = Self::apply(&text);
+        Box::pin(async move { Ok(out) })
+    }
+}
+
+/// Translates the response into another language with a follow-up model call.
+pub struct Translate {
+    language: String,
+    configure: Option<Configure>,
+}
+
+impl Translate {
+    /// Translate into `language` (e.g. `"German"` or `"de"`).
+    pub fn new(language: impl Into<String>) -> Self {
+        Self {
+            language: language.into(),
+            configure: None,
+        }
+    }
+
+    /// Customize the translation request (model, binary path, ...).
+    #[must_use]
+    pub fn configure(mut self, f: impl Fn(Gemini) -> Gemini + Send + Sync + 'static) -> Self {
+        self.configure = Some(Box::new(f));
+        self
+    }
+}
+
+impl Processor for Translate {
+    fn process(&self, text: String) -> BoxFuture<'_, Result<String, GeminiError>> {
+        Box::pin(async move {
+            if text.trim().is_empty() {
+                return Ok(text);
+            }
+            let mut request = Gemini::new(format!(
+                "Translate the text on standard input into {}. Preserve formatting. \
+                 Reply with the translation only.",
+                self.language
+            ))
+            .context(text);
+            if let Some(configure) = &self.configure {
+                request = configure(request);
+            }
+            request.text().await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_markdown() {
+        let md = "# Title\n\n**Bold** and `code`\n> quoted\n```rust\nfn x() {}\n```\n- item";
+        assert_eq!(
+            StripMarkdown::apply(md),
+            "Title\n\nBold and code\nquoted\nfn x() {}\n- item"
+        );
+        assert_eq!(StripMarkdown::apply("#hashtag"), "#hashtag");
+    }
+
+    #[test]
+    fn test_strip_markdown_keeps_fenced_code() {
+        let md = "Run **this**:\n```python\n# Double the input\ndef double(__x):\n    \"\"\"Returns `x * 2`.\"\"\"\n    > 0\n    return __x * 2\n```\nDone.";
+        assert_eq!(
+            StripMarkdown::apply(md),
+            "Run this:\n# Double the input\ndef double(__x):\n    \"\"\"Returns `x * 2`.\"\"\"\n    > 0\n    return __x * 2\nDone."
+        );
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        let text = "a   b\t c  \n\n\n\n  indented   line\n\n";
+        assert_eq!(NormalizeWhitespace::apply(text), "a b c\n\n  indented line");
+        assert_eq!(
+            NormalizeWhitespace::apply("fn main() {\n\tlet  x = 1;\n}"),
+            "fn main() {\n\tlet x = 1;\n}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chain_runs_in_order() {
+        let chain = ProcessorChain::new()
+            .strip_markdown()
+            .map(|t| t.to_uppercase())
+            .map(|t| format!("<{t}>"));
+        assert_eq!(chain.run("**hi**".into()).await.unwrap(), "<HI>");
+        assert_eq!(
+            ProcessorChain::new().run("same".into()).await.unwrap(),
+            "same"
+        );
+    }
+}
@@ -0,0 +1,337 @@
+//! Best-effort progress estimation for long agent runs.
+//!
+//! The CLI does not report how far along a run is, so a [`ProgressTracker`] estimates it from
+//! what the stream does show. When the agent has announced a plan (see
+//! [`Gemini::plan_events`](crate::Gemini::plan_events)), progress is the share of plan steps
+//! completed. Otherwise it compares elapsed time and tool activity against a [`RunHistory`] of
+//! previous runs. Attach a tracker with [`Gemini::progress`](crate::Gemini::progress), keep a
+//! clone, and poll [`ProgressTracker::progress`] from the UI; jobs started with
+//! [`Gemini::spawn_detached_streaming`](crate::Gemini::spawn_detached_streaming) report it in
+//! [`JobHandle::progress`](crate::jobs::JobHandle::progress).
+//!
+//! Estimates are capped below 100% until the stream's final `Result` event arrives, and never
+//! go backwards. Without a plan or history there is nothing to go by and the estimate is `None`.
+
+use crate::plan::PlanStepStatus;
+use crate::StreamEvent;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The highest estimate reported before the run has actually finished.
+const MAX_UNFINISHED: f32 = 0.95;
+
+/// Default number of past runs kept in a [`RunHistory`].
+const DEFAULT_HISTORY: usize = 50;
+
+/// The shape of one completed run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunSample {
+    /// Wall-clock time from start to the final `Result` event.
+    pub duration: Duration,
+    /// Number of tool invocations.
+    pub tool_calls: u32,
+}
+
+/// A bounded, shareable record of completed runs.
+///
+/// Clones share the same history, so one instance can serve every tracker for a given kind of
+/// task.
+#[derive(Debug, Clone)]
+pub struct RunHistory {
+    samples: Arc<Mutex<Vec<RunSample>>>,
+    capacity: usize,
+}
+
+impl Default for RunHistory {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_HISTORY)
+    }
+}
+
+impl RunHistory {
+    /// An empty history keeping the last 50 runs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An empty history keeping the last `capacity` runs.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            samples: Arc::new(Mutex::new(Vec::new())),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Record a completed run, evicting the oldest if full.
+    pub fn record(&self, sample: RunSample) {
+        let mut samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        if samples.len() >= self.capacity {
+            samples.remove(0);
+        }
+        samples.push(sample);
+    }
+
+    /// The recorded runs, oldest first.
+    pub fn samples(&self) -> Vec<RunSample> {
+        self.samples
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// The median run, or `None` if nothing has been recorded.
+    pub fn typical(&self) -> Option<RunSample> {
+        let samples = self.samples();
+        if samples.is_empty() {
+            return None;
+        }
+        let mut durations: Vec<Duration> = samples.iter().map(|s| s.duration).collect();
+        let mut tools: Vec<u32> = samples.iter().map(|s| s.tool_calls).collect();
+        durations.sort();
+        tools.sort();
+        Some(RunSample {
+            duration: durations[durations.len() / 2],
+            tool_calls: tools[tools.len() / 2],
+        })
+    }
+}
+
+#[derive(Debug)]
+struct TrackerState {
+    started: Option<Instant>,
+    tool_calls: u32,
+    /// The status of each step of the latest plan, if the agent announced one.
+    plan: Vec<PlanStepStatus>,
+    finished: bool,
+    reported: f32,
+}
+
+impl TrackerState {
+    fn new(started: Option<Instant>) -> Self {
+        Self {
+            started,
+            tool_calls: 0,
+            plan: Vec::new(),
+            finished: false,
+            reported: 0.0,
+        }
+    }
+
+    /// The share of plan steps completed, or `None` without a plan.
+    fn plan_fraction(&self) -> Option<f32> {
+        if self.plan.is_empty() {
+            return None;
+        }
+        let done = self
+            .plan
+            .iter()
+            .filter(|status| **status == PlanStepStatus::Done)
+            .count();
+        Some(done as f32 / self.plan.len() as f32)
+    }
+}
+
+/// Estimates the progress of a streamed run.
+#[derive(Debug, Clone)]
+pub struct ProgressTracker {
+    history: RunHistory,
+    state: Arc<Mutex<TrackerState>>,
+}
+
+impl Default for ProgressTracker {
+    fn default() -> Self {
+        Self::new(RunHistory::new())
+    }
+}
+
+impl ProgressTracker {
+    /// Create a tracker that compares runs against, and records them into, `history`.
+    pub fn new(history: RunHistory) -> Self {
+        Self {
+            history,
+            state: Arc::new(Mutex::new(TrackerState::new(None))),
+        }
+    }
+
+    /// The history this tracker learns from.
+    pub fn history(&self) -> &RunHistory {
+        &self.history
+    }
+
+    /// Whether the tracked run has finished.
+    pub fn is_finished(&self) -> bool {
+        self.lock().finished
+    }
+
+    /// The estimated fraction complete, from `0.0` to `1.0`.
+    ///
+    /// Returns `None` before the run starts, or while it runs without a plan or any history to
+    /// compare against.
+    pub fn progress(&self) -> Option<f32> {
+        let mut state = self.lock();
+        if state.finished {
+            return Some(1.0);
+        }
+        let started = state.started?;
+        let fraction = match state.plan_fraction() {
+            Some(fraction) => fraction,
+            None => {
+                let typical = self.history.typical()?;
+                let mut fractions = Vec::with_capacity(2);
+                if !typical.duration.is_zero() {
+                    fractions
+                        .push(started.elapsed().as_secs_f32() / typical.duration.as_secs_f32());
+                }
+                if typical.tool_calls > 0 {
+                    fractions.push(state.tool_calls as f32 / typical.tool_calls as f32);
+                }
+                if fractions.is_empty() {
+                    return None;
+                }
+                fractions.iter().sum::<f32>() / fractions.len() as f32
+            }
+        };
+        let estimate = fraction.clamp(0.0, MAX_UNFINISHED).max(state.reported);
+        state.reported = estimate;
+        Some(estimate)
+    }
+
+    /// Reset the tracker for a new run starting now.
+    pub(crate) fn begin(&self) {
+        *self.lock() = TrackerState::new(Some(Instant::now()));
+    }
+
+    /// Update the estimate with a stream event.
+    pub(crate) fn observe(&self, event: &StreamEvent) {
+        let mut state = self.lock();
+        match event {
+            StreamEvent::ToolUse { .. } => state.tool_calls += 1,
+            StreamEvent::Plan { steps } => {
+                state.plan = steps.iter().map(|step| step.status).collect();
+            }
+            StreamEvent::PlanUpdate {
+                index: Some(index),
+                status,
+                ..
+            } if *index < state.plan.len() => {
+                // Starting a step completes the one in progress before it, as in `PlanDetector`.
+                for earlier in &mut state.plan[..*index] {
+                    if *earlier == PlanStepStatus::InProgress {
+                        *earlier = PlanStepStatus::Done;
+                    }
+                }
+                state.plan[*index] = *status;
+            }
+            StreamEvent::Result { .. } if !state.finished => {
+                state.finished = true;
+                if let Some(started) = state.started {
+                    self.history.record(RunSample {
+                        duration: started.elapsed(),
+                        tool_calls: state.tool_calls,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, TrackerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool_use() -> StreamEvent {
+        StreamEvent::ToolUse {
+            tool_name: "read_file".into(),
+            parameters: serde_json::Value::Null,
+            timestamp: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_history_median_and_capacity() {
+        let history = RunHistory::with_capacity(3);
+        for (secs, tools) in [(100, 9), (10, 1), (20, 2), (30, 3)] {
+            history.record(RunSample {
+                duration: Duration::from_secs(secs),
+                tool_calls: tools,
+            });
+        }
+        assert_eq!(history.samples().len(), 3);
+        assert_eq!(
+            history.typical(),
+            Some(RunSample {
+                duration: Duration::from_secs(20),
+                tool_calls: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_progress_from_tool_calls_is_capped_and_monotonic() {
+        let history = RunHistory::new();
+        history.record(RunSample {
+            duration: Duration::from_secs(3600),
+            tool_calls: 4,
+        });
+        let tracker = ProgressTracker::new(history);
+        assert_eq!(tracker.progress(), None);
+
+        tracker.begin();
+        tracker.observe(&tool_use());
+        tracker.observe(&tool_use());
+        let halfway = tracker.progress().unwrap();
+        assert!((0.2..0.3).contains(&halfway));
+
+        for _ in 0..10 {
+            tracker.observe(&tool_use());
+        }
+        assert_eq!(tracker.progress(), Some(MAX_UNFINISHED));
+        assert!(!tracker.is_finished());
+    }
+
+    #[test]
+    fn test_finished_run_is_recorded() {
+        let tracker = ProgressTracker::default();
+        tracker.begin();
+        assert_eq!(tracker.progress(), None);
+        tracker.observe(&tool_use());
+        tracker.observe(&StreamEvent::Result {
+            status: "success".into(),
+            stats: serde_json::Value::Null.into(),
+            timestamp: String::new(),
+        });
+        assert_eq!(tracker.progress(), Some(1.0));
+        assert_eq!(tracker.history().samples()[0].tool_calls, 1);
+    }
+
+    #[test]
+    fn test_plan_steps_drive_progress_without_history() {
+        use crate::plan::PlanStep;
+
+        let tracker = ProgressTracker::default();
+        tracker.begin();
+        let step = |text: &str| PlanStep {
+            text: text.into(),
+            status: PlanStepStatus::Pending,
+        };
+        tracker.observe(&StreamEvent::Plan {
+            steps: vec![step("Read"), step("Fix"), step("Test"), step("Ship")],
+        });
+        assert_eq!(tracker.progress(), Some(0.0));
+
+        let start = |index| StreamEvent::PlanUpdate {
+            action: String::new(),
+            index: Some(index),
+            status: PlanStepStatus::InProgress,
+        };
+        tracker.observe(&start(0));
+        tracker.observe(&start(1));
+        tracker.observe(&start(2));
+        assert_eq!(tracker.progress(), Some(0.5));
+    }
+}
@@ -0,0 +1,322 @@
+//! Proxy settings for the CLI process.
+//!
+//! The CLI reaches the Gemini API through the proxy named in its environment. By default every
+//! request launches it with the [system proxy](system): the `HTTPS_PROXY`, `HTTP_PROXY`,
+//! `ALL_PROXY` and `NO_PROXY` environment variables (lowercase names take precedence), or, if
+//! none is set, the platform settings (System Settings on macOS, Internet Options on Windows).
+//! The variables are passed in both upper and lower case so every HTTP client in the CLI sees
+//! them. Override the proxy per request with [`Gemini::proxy`](crate::Gemini::proxy), or for
+//! every request of a [`GeminiClient`](crate::client::GeminiClient); a plain URL proxies all
+//! traffic.
+//!
+//! ```rust
+//! use gemini_oxide::proxy::ProxyConfig;
+//! use gemini_oxide::Gemini;
+//!
+//! let proxy = ProxyConfig::new()
+//!     .all("http://proxy.corp.example:3128")
+//!     .bypass("localhost")
+//!     .bypass(".corp.example");
+//! let req = Gemini::new("Summarize the incident").proxy(proxy);
+//! let req = Gemini::new("Summarize the incident").proxy("http://proxy.corp.example:3128");
+//! ```
+
+use std::sync::OnceLock;
+use tokio::process::Command;
+
+/// Every environment variable the proxy settings are passed in.
+const PROXY_VARS: &[&str] = &[
+    "http_proxy",
+    "HTTP_PROXY",
+    "https_proxy",
+    "HTTPS_PROXY",
+    "all_proxy",
+    "ALL_PROXY",
+    "no_proxy",
+    "NO_PROXY",
+];
+
+/// Where the CLI's HTTP(S) traffic goes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProxyConfig {
+    /// Proxy URL for plain HTTP requests.
+    pub http: Option<String>,
+    /// Proxy URL for HTTPS requests, including calls to the Gemini API.
+    pub https: Option<String>,
+    /// Hosts, domain suffixes (`.example.com`) or `*` that bypass the proxy.
+    pub no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Direct connections: no proxy at all, even if one is configured in the environment.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The proxy configured in the environment, or else in the platform settings.
+    ///
+    /// This runs `scutil` on macOS and `reg` on Windows; prefer the cached [`system`].
+    pub fn detect() -> Self {
+        let env = Self::from_lookup(|name| std::env::var(name).ok());
+        if env.is_configured() {
+            return env;
+        }
+        platform().unwrap_or(env)
+    }
+
+    /// Proxy plain HTTP requests through `url`.
+    #[must_use]
+    pub fn http(mut self, url: impl Into<String>) -> Self {
+        self.http = Some(url.into());
+        self
+    }
+
+    /// Proxy HTTPS requests through `url`.
+    #[must_use]
+    pub fn https(mut self, url: impl Into<String>) -> Self {
+        self.https = Some(url.into());
+        self
+    }
+
+    /// Proxy both HTTP and HTTPS requests through `url`.
+    #[must_use]
+    pub fn all(self, url: impl Into<String>) -> Self {
+        let url = url.into();
+        self.http(url.clone()).https(url)
+    }
+
+    /// Connect directly to `host` (a host name, a `.suffix`, an IP address or `*`).
+    #[must_use]
+    pub fn bypass(mut self, host: impl Into<String>) -> Self {
+        self.no_proxy.push(host.into());
+        self
+    }
+
+    /// Whether any proxy is set.
+    pub fn is_configured(&self) -> bool {
+        self.http.is_some() || self.https.is_some()
+    }
+
+    /// Read the standard variables through `lookup`; empty values count as unset.
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let var = |names: &[&str]| {
+            names
+                .iter()
+                .filter_map(|name| lookup(name))
+                .map(|value| value.trim().to_string())
+                .find(|value| !value.is_empty())
+        };
+        let all = var(&["all_proxy", "ALL_PROXY"]);
+        Self {
+            http: var(&["http_proxy", "HTTP_PROXY"]).or_else(|| all.clone()),
+            https: var(&["https_proxy", "HTTPS_PROXY"]).or(all),
+            no_proxy: var(&["no_proxy", "NO_PROXY"])
+                .map(|list| split_list(&list, ','))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Replace the command's proxy variables with this configuration.
+    pub(crate) fn apply(&self, cmd: &mut Command) {
+        for name in PROXY_VARS {
+            cmd.env_remove(name);
+        }
+        let mut set = |names: [&str; 2], value: &str| {
+            for name in names {
+                cmd.env(name, value);
+            }
+        };
+        if let Some(http) = &self.http {
+            set(["http_proxy", "HTTP_PROXY"], http);
+        }
+        if let Some(https) = &self.https {
+            set(["https_proxy", "HTTPS_PROXY"], https);
+        }
+        if !self.no_proxy.is_empty() {
+            set(["no_proxy", "NO_PROXY"], &self.no_proxy.join(","));
+        }
+    }
+}
+
+/// All HTTP and HTTPS traffic through the proxy at this URL, as with [`ProxyConfig::all`].
+impl From<&str> for ProxyConfig {
+    fn from(url: &str) -> Self {
+        Self::new().all(url)
+    }
+}
+
+impl From<String> for ProxyConfig {
+    fn from(url: String) -> Self {
+        Self::new().all(url)
+    }
+}
+
+/// The system proxy, detected once per process with [`ProxyConfig::detect`].
+pub fn system() -> &'static ProxyConfig {
+    static SYSTEM: OnceLock<ProxyConfig> = OnceLock::new();
+    SYSTEM.get_or_init(ProxyConfig::detect)
+}
+
+fn split_list(list: &str, separator: char) -> Vec<String> {
+    list.split(separator)
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn platform() -> Option<ProxyConfig> {
+    let output = std::process::Command::new("scutil")
+        .arg("--proxy")
+        .output()
+        .ok()?;
+    Some(parse_scutil(&String::from_utf8_lossy(&output.stdout))).filter(ProxyConfig::is_configured)
+}
+
+#[cfg(windows)]
+fn platform() -> Option<ProxyConfig> {
+    let output = std::process::Command::new("reg")
+        .args([
+            "query",
+            r"HKCU\Software\Microsoft\Windows\CurrentVersion\Internet Settings",
+        ])
+        .output()
+        .ok()?;
+    Some(parse_windows(&String::from_utf8_lossy(&output.stdout))).filter(ProxyConfig::is_configured)
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn platform() -> Option<ProxyConfig> {
+    None
+}
+
+/// Parse the output of macOS `scutil --proxy`.
+#[cfg_attr(not(any(target_os = "macos", test)), allow(dead_code))]
+fn parse_scutil(text: &str) -> ProxyConfig {
+    let mut values = std::collections::HashMap::new();
+    let mut exceptions = Vec::new();
+    let mut in_exceptions = false;
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once(" : ") else {
+            in_exceptions &= !line.trim().starts_with('}');
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if key == "ExceptionsList" {
+            in_exceptions = true;
+        } else if in_exceptions {
+            exceptions.push(value.to_string());
+        } else {
+            values.insert(key, value);
+        }
+    }
+    let proxy = |scheme: &str| {
+        if values.get(format!("{scheme}Enable").as_str()) != Some(&"1") {
+            return None;
+        }
+        let host = values.get(format!("{scheme}Proxy").as_str())?;
+        Some(match values.get(format!("{scheme}Port").as_str()) {
+            Some(port) => format!("http://{host}:{port}"),
+            None => format!("http://{host}"),
+        })
+    };
+    ProxyConfig {
+        http: proxy("HTTP"),
+        https: proxy("HTTPS"),
+        no_proxy: exceptions,
+    }
+}
+
+/// Parse `reg query` output for the Windows Internet Settings key.
+#[cfg_attr(not(any(windows, test)), allow(dead_code))]
+fn parse_windows(text: &str) -> ProxyConfig {
+    let value = |name: &str| {
+        text.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            (parts.next() == Some(name)).then(|| parts.skip(1).collect::<Vec<_>>().join(" "))
+        })
+    };
+    if value("ProxyEnable").as_deref() != Some("0x1") {
+        return ProxyConfig::new();
+    }
+    let mut config = ProxyConfig::new();
+    for entry in split_list(&value("ProxyServer").unwrap_or_default(), ';') {
+        let with_scheme = |address: &str| {
+            if address.contains("://") {
+                address.to_string()
+            } else {
+                format!("http://{address}")
+            }
+        };
+        match entry.split_once('=') {
+            Some(("http", address)) => config.http = Some(with_scheme(address)),
+            Some(("https", address)) => config.https = Some(with_scheme(address)),
+            Some(_) => {}
+            None => config = config.all(with_scheme(&entry)),
+        }
+    }
+    config.no_proxy = split_list(&value("ProxyOverride").unwrap_or_default(), ';')
+        .into_iter()
+        .map(|host| match host.as_str() {
+            "<local>" => "localhost".to_string(),
+            _ => host,
+        })
+        .collect();
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_lookup_prefers_lowercase_and_falls_back_to_all_proxy() {
+        let config = ProxyConfig::from_lookup(|name| match name {
+            "https_proxy" => Some("http://lower:1".to_string()),
+            "HTTPS_PROXY" => Some("http://upper:2".to_string()),
+            "ALL_PROXY" => Some("http://all:3".to_string()),
+            "NO_PROXY" => Some("localhost, .corp.example,,".to_string()),
+            "http_proxy" => Some("  ".to_string()),
+            _ => None,
+        });
+        assert_eq!(config.https.as_deref(), Some("http://lower:1"));
+        assert_eq!(config.http.as_deref(), Some("http://all:3"));
+        assert_eq!(config.no_proxy, vec!["localhost", ".corp.example"]);
+        assert!(!ProxyConfig::from_lookup(|_| None).is_configured());
+    }
+
+    #[test]
+    fn test_url_proxies_all_traffic() {
+        let config = ProxyConfig::from("http://proxy:3128");
+        assert_eq!(config.http.as_deref(), Some("http://proxy:3128"));
+        assert_eq!(config.https.as_deref(), Some("http://proxy:3128"));
+        assert!(config.no_proxy.is_empty());
+    }
+
+    #[test]
+    fn test_platform_settings_are_parsed() {
+        let scutil = "<dictionary> {\n  ExceptionsList : <array> {\n    0 : *.local\n    1 : 169.254/16\n  }\n  HTTPEnable : 0\n  HTTPSEnable : 1\n  HTTPSPort : 8443\n  HTTPSProxy : proxy.corp\n}\n";
+        assert_eq!(
+            parse_scutil(scutil),
+            ProxyConfig::new()
+                .https("http://proxy.corp:8443")
+                .bypass("*.local")
+                .bypass("169.254/16")
+        );
+
+        let reg = "\n    ProxyEnable    REG_DWORD    0x1\n    ProxyServer    REG_SZ    http=proxy:80;https=proxy:443\n    ProxyOverride    REG_SZ    *.corp;<local>\n";
+        assert_eq!(
+            parse_windows(reg),
+            ProxyConfig::new()
+                .http("http://proxy:80")
+                .https("http://proxy:443")
+                .bypass("*.corp")
+                .bypass("localhost")
+        );
+        assert_eq!(
+            parse_windows(&reg.replace("0x1", "0x0")),
+            ProxyConfig::new()
+        );
+    }
+}
@@ -0,0 +1,409 @@
+//! Question answering over a document set.
+//!
+//! A small retrieval-augmented pipeline: documents are split into line-aligned chunks and
+//! indexed lexically (BM25) by [`DocumentIndex`]; [`QaPipeline`] retrieves the top-k chunks for
+//! a question, pipes them to the model as numbered sources and returns an [`Answer`] whose
+//! citations point back to the originating document and line range.
+//!
+//! Retrieval is pluggable through the [`Retriever`] trait, e.g. to use an embedding store.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::qa::{DocumentIndex, QaPipeline};
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let mut index = DocumentIndex::new();
+//! index.add("handbook.md", "Vacation requests go to your manager.\nSick days need no approval.");
+//!
+//! let answer = QaPipeline::new(index).ask("Who approves vacation?").await?;
+//! for citation in &answer.citations {
+//!     println!("{} lines {}-{}", citation.doc_id, citation.start_line, citation.end_line);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Configure, Gemini, GeminiError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+/// A contiguous, line-aligned slice of an indexed document.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Chunk {
+    /// Identifier of the document the chunk came from (e.g. a path or URL).
+    pub doc_id: String,
+    /// Position of the chunk within its document.
+    pub index: usize,
+    /// First line of the chunk (1-based, inclusive).
+    pub start_line: usize,
+    /// Last line of the chunk (1-based, inclusive).
+    pub end_line: usize,
+    /// The chunk's text.
+    pub text: String,
+}
+
+/// A chunk together with its retrieval score.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ScoredChunk {
+    pub chunk: Chunk,
+    pub score: f64,
+}
+
+/// Controls how documents are split into chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkOptions {
+    /// Soft upper bound on chunk size in characters. A single longer line forms its own chunk.
+    pub max_chars: usize,
+    /// Number of trailing lines repeated at the start of the next chunk.
+    pub overlap_lines: usize,
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        Self {
+            max_chars: 1_500,
+            overlap_lines: 2,
+        }
+    }
+}
+
+/// Source of chunks relevant to a question.
+pub trait Retriever {
+    /// Return up to `k` chunks, most relevant first.
+    fn retrieve(&self, question: &str, k: usize) -> Vec<ScoredChunk>;
+}
+
+/// In-memory lexical index over document chunks, scored with BM25.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentIndex {
+    options: ChunkOptions,
+    chunks: Vec<Chunk>,
+    term_counts: Vec<HashMap<String, usize>>,
+    lengths: Vec<usize>,
+    doc_freq: HashMap<String, usize>,
+}
+
+impl DocumentIndex {
+    /// Create an empty index with default chunking.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty index with custom chunking.
+    pub fn with_options(options: ChunkOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Chunk and index a document.
+    pub fn add(&mut self, doc_id: impl Into<String>, text: &str) {
+        let doc_id = doc_id.into();
+        for chunk in chunk_lines(&doc_id, text, self.options) {
+            let mut counts = HashMap::new();
+            let mut length = 0;
+            for term in tokenize(&chunk.text) {
+                *counts.entry(term).or_insert(0) += 1;
+                length += 1;
+            }
+            for term in counts.keys() {
+                *self.doc_freq.entry(term.clone()).or_insert(0) += 1;
+            }
+            self.term_counts.push(counts);
+            self.lengths.push(length);
+            self.chunks.push(chunk);
+        }
+    }
+
+    /// Read a file and index it under its path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the file cannot be read as UTF-8.
+    pub async fn add_file(&mut self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let text = tokio::fs::read_to_string(path).await?;
+        self.add(path.display().to_string(), &text);
+        Ok(())
+    }
+
+    /// All indexed chunks.
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /// Score every chunk against `question` and return the best `k` with a positive score.
+    pub fn search(&self, question: &str, k: usize) -> Vec<ScoredChunk> {
+        if self.chunks.is_empty() {
+            return Vec::new();
+        }
+        let n = self.chunks.len() as f64;
+        let avg_len = self.lengths.iter().sum::<usize>() as f64 / n;
+        let mut terms = tokenize(question);
+        terms.sort();
+        terms.dedup();
+
+        let mut scored: Vec<ScoredChunk> = self
+            .chunks
+            .iter()
+            .enumerate()
+            .filter_map(|(i, chunk)| {
+                let len = self.lengths[i] as f64;
+                let score: f64 = terms
+                    .iter()
+                    .filter_map(|term| {
+                        let tf = *self.term_counts[i].get(term)? as f64;
+                        let df = self.doc_freq[term] as f64;
+                        let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+                        let norm = BM25_K1 * (1.0 - BM25_B + BM25_B * len / avg_len.max(1.0));
+                        Some(idf * tf * (BM25_K1 + 1.0) / (tf + norm))
+                    })
+                    .sum();
+                (score > 0.0).then(|| ScoredChunk {
+                    chunk: chunk.clone(),
+                    score,
+                })
+            })
+            .collect();
+
+        scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+        scored.truncate(k);
+        scored
+    }
+}
+
+impl Retriever for DocumentIndex {
+    fn retrieve(&self, question: &str, k: usize) -> Vec<ScoredChunk> {
+        self.search(question, k)
+    }
+}
+
+/// A reference from an answer back to the chunk that supports it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Citation {
+    /// The source label used in the prompt and answer, e.g. `S2`.
+    pub label: String,
+    pub doc_id: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// An answer with citations to the retrieved sources.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Answer {
+    /// The model's answer, containing inline `[S1]`-style markers.
+    pub text: String,
+    /// Sources actually cited in the answer, in order of first appearance.
+    pub citations: Vec<Citation>,
+    /// Every chunk that was provided to the model.
+    pub sources: Vec<ScoredChunk>,
+}
+
+/// Retrieves relevant chunks and asks the model to answer from them with citations.
+pub struct QaPipeline<R = DocumentIndex> {
+    retriever: R,
+    top_k: usize,
+    configure: Option<Configure>,
+}
+
+impl<R: Retriever> QaPipeline<R> {
+    /// Create a pipeline that retrieves the top 5 chunks per question.
+    pub fn new(retriever: R) -> Self {
+        Self {
+            retriever,
+            top_k: 5,
+            configure: None,
+        }
+    }
+
+    /// Number of chunks to retrieve per question.
+    #[must_use]
+    pub fn top_k(mut self, k: usize) -> Self {
+        self.top_k = k;
+        self
+    }
+
+    /// Customize each underlying request (model, binary path, ...).
+    #[must_use]
+    pub fn configure(mut self, f: impl Fn(Gemini) -> Gemini + Send + Sync + 'static) -> Self {
+        self.configure = Some(Box::new(f));
+        self
+    }
+
+    /// The underlying retriever.
+    pub fn retriever(&self) -> &R {
+        &self.retriever
+    }
+
+    /// Answer `question` from the indexed documents.
+    ///
+    /// If nothing relevant is retrieved the model is not called and an empty answer is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised while running the CLI.
+    pub async fn ask(&self, question: &str) -> Result<Answer, GeminiError> {
+        let sources = self.retriever.retrieve(question, self.top_k);
+        if sources.is_empty() {
+            return Ok(Answer {
+                text: String::new(),
+                citations: Vec::new(),
+                sources,
+            });
+        }
+
+        let mut request = Gemini::new(build_prompt(question)).context(format_sources(&sources));
+        if let Some(configure) = &self.configure {
+            request = configure(request);
+        }
+        let text = request.text().await?;
+        let citations = extract_citations(&text, &sources);
+
+        Ok(Answer {
+            text,
+            citations,
+            sources,
+        })
+    }
+}
+
+fn build_prompt(question: &str) -> String {
+    format!(
+        "Answer the question using only the numbered sources provided on standard input. \
+         Cite every claim with the label of its source in square brackets, e.g. [S1]. \
+         If the sources do not contain the answer, say so.\n\nQuestion: {question}"
+    )
+}
+
+fn format_sources(sources: &[ScoredChunk]) -> String {
+    sources
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            format!(
+                "[S{}] {} (lines {}-{})\n{}\n",
+                i + 1,
+                s.chunk.doc_id,
+                s.chunk.start_line,
+                s.chunk.end_line,
+                s.chunk.text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Find `[S<n>]` markers in `text` and map them back to their chunks.
+fn extract_citations(text: &str, sources: &[ScoredChunk]) -> Vec<Citation> {
+    let mut citations: Vec<Citation> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[S") {
+        rest = &rest[start + 2..];
+        let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+        if !rest[digits.len()..].starts_with(']') {
+            continue;
+        }
+        let Ok(n) = digits.parse::<usize>() else {
+            continue;
+        };
+        let label = format!("S{n}");
+        if let Some(source) = n.checked_sub(1).and_then(|i| sources.get(i)) {
+            if !citations.iter().any(|c| c.label == label) {
+                citations.push(Citation {
+                    label,
+                    doc_id: source.chunk.doc_id.clone(),
+                    start_line: source.chunk.start_line,
+                    end_line: source.chunk.end_line,
+                });
+            }
+        }
+    }
+    citations
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 1)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn chunk_lines(doc_id: &str, text: &str, options: ChunkOptions) -> Vec<Chunk> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while start < lines.len() {
+        let mut end = start;
+        let mut size = 0;
+        while end < lines.len() && (end == start || size + lines[end].len() < options.max_chars) {
+            size += lines[end].len() + 1;
+            end += 1;
+        }
+        chunks.push(Chunk {
+            doc_id: doc_id.to_string(),
+            index: chunks.len(),
+            start_line: start + 1,
+            end_line: end,
+            text: lines[start..end].join("\n"),
+        });
+        if end == lines.len() {
+            break;
+        }
+        start = end.saturating_sub(options.overlap_lines).max(start + 1);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunking_tracks_line_ranges_with_overlap() {
+        let text = "aaaa\nbbbb\ncccc\ndddd\neeee";
+        let chunks = chunk_lines(
+            "doc",
+            text,
+            ChunkOptions {
+                max_chars: 12,
+                overlap_lines: 1,
+            },
+        );
+        let ranges: Vec<_> = chunks.iter().map(|c| (c.start_line, c.end_line)).collect();
+        assert_eq!(ranges, vec![(1, 2), (2, 3), (3, 4), (4, 5)]);
+        assert_eq!(chunks[0].text, "aaaa\nbbbb");
+    }
+
+    #[test]
+    fn test_search_ranks_relevant_chunks_first() {
+        let mut index = DocumentIndex::new();
+        index.add("pets.md", "Cats sleep most of the day.");
+        index.add("cars.md", "Engines need oil changes.");
+        index.add("more_pets.md", "Dogs and cats can live together.");
+
+        let results = index.search("how long do cats sleep", 5);
+        assert_eq!(results[0].chunk.doc_id, "pets.md");
+        assert!(results.iter().all(|r| r.chunk.doc_id != "cars.md"));
+    }
+
+    #[test]
+    fn test_citations_map_to_sources() {
+        let mut index = DocumentIndex::new();
+        index.add("a.md", "alpha");
+        index.add("b.md", "beta");
+        let sources = vec![
+            index.search("alpha", 1).remove(0),
+            index.search("beta", 1).remove(0),
+        ];
+
+        let citations = extract_citations("Beta [S2], alpha [S1][S2], bogus [S9] [Sx]", &sources);
+        let labels: Vec<_> = citations.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, vec!["S2", "S1"]);
+        assert_eq!(citations[0].doc_id, "b.md");
+    }
+}
@@ -0,0 +1,215 @@
+//! Detection of refusals and non-answers.
+//!
+//! Models sometimes answer "I can't help with that" or "I don't know" instead of doing the
+//! task. Stored as data, such responses are indistinguishable from real answers. A
+//! [`RefusalDetector`] classifies a response as an [`Outcome`] so calling code can fall back to
+//! a different prompt or model. With [`Gemini::detect_refusals`](crate::Gemini::detect_refusals),
+//! `text()` and `json()` return `GeminiError::Refused` instead of the refusal.
+//!
+//! Detection is phrase-based and only looks at the opening of a response, so a long answer that
+//! mentions "I cannot" in passing is not flagged.
+//!
+//! ```rust
+//! use gemini_oxide::refusal::{Outcome, RefusalDetector};
+//!
+//! let detector = RefusalDetector::new();
+//! assert!(matches!(
+//!     detector.classify("I'm sorry, but I can't help with that request."),
+//!     Outcome::Refused { .. }
+//! ));
+//! assert_eq!(detector.classify("The capital of France is Paris."), Outcome::Answered);
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+/// Phrases that open a refusal.
+const REFUSAL_PHRASES: &[&str] = &[
+    "i can't help",
+    "i cannot help",
+    "i can't assist",
+    "i cannot assist",
+    "i can't provide",
+    "i cannot provide",
+    "i can't do that",
+    "i cannot do that",
+    "i can't comply",
+    "i cannot comply",
+    "i won't be able to",
+    "i'm unable to",
+    "i am unable to",
+    "i'm not able to",
+    "i am not able to",
+    "i must decline",
+    "i have to decline",
+    "as an ai language model",
+    "against my guidelines",
+    "i'm not allowed to",
+    "i am not allowed to",
+];
+
+/// Phrases that open a non-answer.
+const NON_ANSWER_PHRASES: &[&str] = &[
+    "i don't know",
+    "i do not know",
+    "i'm not sure",
+    "i am not sure",
+    "i don't have enough information",
+    "i do not have enough information",
+    "i don't have access to",
+    "i do not have access to",
+    "there is not enough information",
+    "there isn't enough information",
+];
+
+/// How many characters from the start of a response are searched for phrases.
+const DEFAULT_OPENING_CHARS: usize = 200;
+
+/// What kind of response the model gave.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum Outcome {
+    /// The response appears to attempt the task.
+    Answered,
+    /// The model declined the task. `reason` is the sentence that declined.
+    Refused { reason: String },
+    /// The response is empty or says the model does not know. `reason` explains which.
+    NonAnswer { reason: String },
+}
+
+impl Outcome {
+    /// Whether the response appears to attempt the task.
+    pub fn is_answered(&self) -> bool {
+        matches!(self, Outcome::Answered)
+    }
+}
+
+/// Classifies responses as answers, refusals or non-answers.
+#[derive(Debug, Clone)]
+pub struct RefusalDetector {
+    refusal_phrases: Vec<String>,
+    non_answer_phrases: Vec<String>,
+    opening_chars: usize,
+}
+
+impl Default for RefusalDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RefusalDetector {
+    /// A detector with the built-in English phrase lists.
+    pub fn new() -> Self {
+        Self {
+            refusal_phrases: REFUSAL_PHRASES.iter().map(|p| p.to_string()).collect(),
+            non_answer_phrases: NON_ANSWER_PHRASES.iter().map(|p| p.to_string()).collect(),
+            opening_chars: DEFAULT_OPENING_CHARS,
+        }
+    }
+
+    /// Add a phrase (matched case-insensitively) that marks a refusal.
+    #[must_use]
+    pub fn refusal_phrase(mut self, phrase: impl AsRef<str>) -> Self {
+        self.refusal_phrases.push(phrase.as_ref().to_lowercase());
+        self
+    }
+
+    /// Add a phrase (matched case-insensitively) that marks a non-answer.
+    #[must_use]
+    pub fn non_answer_phrase(mut self, phrase: impl AsRef<str>) -> Self {
+        self.non_answer_phrases.push(phrase.as_ref().to_lowercase());
+        self
+    }
+
+    /// How many characters from the start of a response are searched. Defaults to 200.
+    #[must_use]
+    pub fn opening_chars(mut self, chars: usize) -> Self {
+        self.opening_chars = chars;
+        self
+    }
+
+    /// Classify `text`.
+    pub fn classify(&self, text: &str) -> Outcome {
+        let text = text.trim();
+        if text.is_empty() {
+            return Outcome::NonAnswer {
+                reason: "empty response".to_string(),
+            };
+        }
+        // Curly apostrophes are common in model output.
+        let opening: String = text
+            .chars()
+            .take(self.opening_chars)
+            .collect::<String>()
+            .replace('\u{2019}', "'");
+        let lower = opening.to_lowercase();
+        let find = |phrases: &[String]| phrases.iter().filter_map(|p| lower.find(p.as_str())).min();
+        match (find(&self.refusal_phrases), find(&self.non_answer_phrases)) {
+            (Some(at), other) if other.is_none_or(|o| at <= o) => Outcome::Refused {
+                reason: sentence_at(&opening, at),
+            },
+            (_, Some(at)) => Outcome::NonAnswer {
+                reason: sentence_at(&opening, at),
+            },
+            _ => Outcome::Answered,
+        }
+    }
+}
+
+/// Classify `text` with the default [`RefusalDetector`].
+pub fn classify(text: &str) -> Outcome {
+    RefusalDetector::new().classify(text)
+}
+
+/// The sentence of `text` containing byte offset `at`.
+fn sentence_at(text: &str, at: usize) -> String {
+    let is_end = |c: char| matches!(c, '.' | '!' | '?' | '\n');
+    let start = text[..at].rfind(is_end).map_or(0, |i| i + 1);
+    let end = text[at..].find(is_end).map_or(text.len(), |i| at + i + 1);
+    text[start..end].trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refusals_are_detected_with_their_sentence() {
+        let outcome = classify("Thanks for asking. I’m unable to share personal data! Try X.");
+        assert_eq!(
+            outcome,
+            Outcome::Refused {
+                reason: "I'm unable to share personal data!".to_string()
+            }
+        );
+        assert!(!outcome.is_answered());
+    }
+
+    #[test]
+    fn test_non_answers_and_late_mentions() {
+        assert_eq!(
+            classify("  \n"),
+            Outcome::NonAnswer {
+                reason: "empty response".to_string()
+            }
+        );
+        assert!(matches!(
+            classify("I don't know which version you mean."),
+            Outcome::NonAnswer { .. }
+        ));
+        let long = format!(
+            "{} I cannot help noticing the loop.",
+            "The function sorts. ".repeat(20)
+        );
+        assert_eq!(classify(&long), Outcome::Answered);
+    }
+
+    #[test]
+    fn test_custom_phrases() {
+        let detector = RefusalDetector::new().refusal_phrase("Je ne peux pas");
+        assert!(matches!(
+            detector.classify("Je ne peux pas vous aider."),
+            Outcome::Refused { .. }
+        ));
+    }
+}
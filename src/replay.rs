@@ -0,0 +1,335 @@
+//! Replaying recorded runs to validate CLI upgrades.
+//!
+//! Before rolling out a new CLI version (or SDK release) across a fleet, record representative
+//! requests with the current one, replay them with the candidate and look at what changed.
+//! [`RecordedRun::record`] streams a request and keeps it with its [`RunTranscript`];
+//! [`RecordedRun::replay`] runs the same request again, configured for the new version, and
+//! [`compare`]s the two runs. The resulting [`RunDiff`] lists differences in the response, the
+//! final status and stats, the model, the tools called and the errors raised.
+//!
+//! Stats whose name mentions latency or duration vary between any two runs and are ignored.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::replay::RecordedRun;
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let request = Gemini::new("List the files in src").model("gemini-2.5-flash");
+//! let baseline = RecordedRun::record(request).await?;
+//! baseline.save("baseline.json")?;
+//!
+//! // Later, with the candidate CLI installed side by side:
+//! let diff = RecordedRun::load("baseline.json")?
+//!     .replay(|request| request.bin_path("/opt/gemini-next/bin/gemini"))
+//!     .await?;
+//! if !diff.is_empty() {
+//!     println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::transcript::RunTranscript;
+use crate::{wire, Gemini, GeminiError, StreamEvent};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+/// A request and the run it produced.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RecordedRun {
+    /// The prompt as given.
+    pub prompt: String,
+    /// The piped context, if any.
+    pub context: Option<String>,
+    /// The model explicitly requested, if any.
+    pub model: Option<String>,
+    /// The run.
+    pub transcript: RunTranscript,
+}
+
+impl RecordedRun {
+    /// Stream `request` to completion and record it.
+    ///
+    /// Only the prompt, piped context and model are kept for replaying; pass any other
+    /// settings to [`replay`](Self::replay) again.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error raised by [`Gemini::transcript`].
+    pub async fn record(request: Gemini) -> Result<Self, GeminiError> {
+        let prompt = request.prompt.clone();
+        let context = request.input_data.clone();
+        let model = request.model.clone();
+        Ok(Self {
+            prompt,
+            context,
+            model,
+            transcript: request.transcript().await?,
+        })
+    }
+
+    /// Run the recorded request again, after `configure` applied any settings (such as the
+    /// new CLI's [`bin_path`](Gemini::bin_path)), and compare the new run with the recorded
+    /// one.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error raised by [`Gemini::transcript`].
+    pub async fn replay(
+        &self,
+        configure: impl FnOnce(Gemini) -> Gemini,
+    ) -> Result<RunDiff, GeminiError> {
+        let mut request = Gemini::new(self.prompt.clone());
+        if let Some(context) = &self.context {
+            request = request.context(context.clone());
+        }
+        if let Some(model) = &self.model {
+            request = request.model(model);
+        }
+        let new = configure(request).transcript().await?;
+        Ok(compare(&self.transcript, &new))
+    }
+
+    /// Save the recording as JSON, tagged with its [schema version](crate::wire).
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::StorageError` if the file cannot be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), GeminiError> {
+        let path = path.as_ref();
+        let json = serde_json::to_vec_pretty(&wire::to_value(self)?)
+            .map_err(|e| GeminiError::StorageError(format!("failed to serialize run: {e}")))?;
+        std::fs::write(path, json).map_err(|e| {
+            GeminiError::StorageError(format!("failed to write {}: {e}", path.display()))
+        })
+    }
+
+    /// Load a recording saved with [`save`](Self::save), by this or an earlier crate version.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::StorageError` if the file cannot be read or is not a recording.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, GeminiError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|e| {
+            GeminiError::StorageError(format!("failed to read {}: {e}", path.display()))
+        })?;
+        wire::from_str(&json).map_err(|e| {
+            GeminiError::StorageError(format!("invalid recording {}: {e}", path.display()))
+        })
+    }
+}
+
+/// A value that differs between the recorded and the new run.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Change<T> {
+    /// The value in the recorded run.
+    pub recorded: T,
+    /// The value in the new run.
+    pub new: T,
+}
+
+impl<T: PartialEq> Change<T> {
+    fn of(recorded: T, new: T) -> Option<Self> {
+        (recorded != new).then_some(Self { recorded, new })
+    }
+}
+
+/// A stat, named by its dotted path in the final stats, that differs between the runs.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct StatChange {
+    /// The stat's path, e.g. `models.gemini-2.5-pro.tokens.total`.
+    pub path: String,
+    /// The value in the recorded run, if it had the stat.
+    pub recorded: Option<Value>,
+    /// The value in the new run, if it has the stat.
+    pub new: Option<Value>,
+}
+
+/// The differences between two runs of the same request.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct RunDiff {
+    /// The model's text, ignoring surrounding whitespace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<Change<String>>,
+    /// The model announced by the CLI.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<Change<Option<String>>>,
+    /// The status of the final `Result` event; `None` if the run did not finish.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Change<Option<String>>>,
+    /// The names of the tools called, in order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Change<Vec<String>>>,
+    /// The statuses of the tool results, in order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_results: Option<Change<Vec<String>>>,
+    /// The messages of `Error` events, in order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Change<Vec<String>>>,
+    /// The final stats that differ.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stats: Vec<StatChange>,
+}
+
+impl RunDiff {
+    /// Whether the runs behaved the same.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The differences between a recorded run and a new run of the same request.
+pub fn compare(recorded: &RunTranscript, new: &RunTranscript) -> RunDiff {
+    let collect = |run: &RunTranscript, f: fn(&StreamEvent) -> Option<String>| -> Vec<String> {
+        run.events.iter().filter_map(f).collect()
+    };
+    let tools = |e: &StreamEvent| match e {
+        StreamEvent::ToolUse { tool_name, .. } => Some(tool_name.clone()),
+        _ => None,
+    };
+    let tool_results = |e: &StreamEvent| match e {
+        StreamEvent::ToolResult { status, .. } => Some(status.clone()),
+        _ => None,
+    };
+    let errors = |e: &StreamEvent| match e {
+        StreamEvent::Error { message } => Some(message.clone()),
+        _ => None,
+    };
+    RunDiff {
+        response: Change::of(
+            recorded.text.trim().to_string(),
+            new.text.trim().to_string(),
+        ),
+        model: Change::of(
+            recorded.model().map(str::to_string),
+            new.model().map(str::to_string),
+        ),
+        status: Change::of(status(recorded), status(new)),
+        tools: Change::of(collect(recorded, tools), collect(new, tools)),
+        tool_results: Change::of(collect(recorded, tool_results), collect(new, tool_results)),
+        errors: Change::of(collect(recorded, errors), collect(new, errors)),
+        stats: compare_stats(stats(recorded), stats(new)),
+    }
+}
+
+fn result(run: &RunTranscript) -> Option<(&str, Value)> {
+    run.events.iter().rev().find_map(|e| match e {
+        StreamEvent::Result { status, stats, .. } => Some((status.as_str(), stats.to_value())),
+        _ => None,
+    })
+}
+
+fn status(run: &RunTranscript) -> Option<String> {
+    result(run).map(|(status, _)| status.to_string())
+}
+
+fn stats(run: &RunTranscript) -> Vec<(String, Value)> {
+    let mut leaves = Vec::new();
+    if let Some((_, stats)) = result(run) {
+        flatten(String::new(), &stats, &mut leaves);
+    }
+    leaves
+}
+
+/// The leaves of `value` with their dotted paths, skipping timing stats.
+fn flatten(path: String, value: &Value, leaves: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                let lower = key.to_ascii_lowercase();
+                if lower.contains("latency") || lower.contains("duration") {
+                    continue;
+                }
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                flatten(path, value, leaves);
+            }
+        }
+        leaf => leaves.push((path, leaf.clone())),
+    }
+}
+
+fn compare_stats(recorded: Vec<(String, Value)>, new: Vec<(String, Value)>) -> Vec<StatChange> {
+    let mut new: std::collections::BTreeMap<String, Value> = new.into_iter().collect();
+    let mut changes = Vec::new();
+    for (path, recorded) in recorded {
+        let new = new.remove(&path);
+        if new.as_ref() != Some(&recorded) {
+            changes.push(StatChange {
+                path,
+                recorded: Some(recorded),
+                new,
+            });
+        }
+    }
+    changes.extend(new.into_iter().map(|(path, new)| StatChange {
+        path,
+        recorded: None,
+        new: Some(new),
+    }));
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::StreamTiming;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn run(text: &str, tools: &[&str], stats: Value) -> RunTranscript {
+        let mut events = vec![StreamEvent::Message {
+            role: "model".into(),
+            content: text.into(),
+            delta: Some(true),
+            timestamp: String::new(),
+        }];
+        events.extend(tools.iter().map(|tool| StreamEvent::ToolUse {
+            tool_name: tool.to_string(),
+            parameters: json!({}),
+            timestamp: String::new(),
+        }));
+        events.push(StreamEvent::Result {
+            status: "success".into(),
+            stats: stats.into(),
+            timestamp: String::new(),
+        });
+        let timing = StreamTiming {
+            time_to_first_event: None,
+            time_to_first_text: None,
+            duration: Duration::ZERO,
+            output_tokens: 0,
+            tokens_estimated: true,
+        };
+        RunTranscript::new(events, timing)
+    }
+
+    #[test]
+    fn test_differences_are_reported() {
+        let stats =
+            json!({"models": {"pro": {"tokens": {"total": 10}, "api": {"totalLatencyMs": 5}}}});
+        let recorded = run("Done.", &["read_file"], stats.clone());
+        assert!(compare(&recorded, &run("Done.\n", &["read_file"], stats)).is_empty());
+
+        let new = run(
+            "Done!",
+            &["read_file", "write_file"],
+            json!({"models": {"pro": {"tokens": {"total": 12}, "api": {"totalLatencyMs": 9}}}, "files": {"added": 1}}),
+        );
+        let diff = compare(&recorded, &new);
+        assert_eq!(diff.response.unwrap().new, "Done!");
+        assert_eq!(
+            diff.tools.unwrap().new,
+            vec!["read_file".to_string(), "write_file".to_string()]
+        );
+        let paths: Vec<_> = diff.stats.iter().map(|s| s.path.as_str()).collect();
+        assert_eq!(paths, ["files.added", "models.pro.tokens.total"]);
+        assert_eq!(diff.status, None);
+    }
+}
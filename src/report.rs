@@ -0,0 +1,429 @@
+//! Rendering run results for delivery by email or chat.
+//!
+//! A [`Report`] is a titled list of [`ReportSection`]s, each holding a prompt, the answer,
+//! a few stats and the files the run changed. Sections are built from a
+//! [`RunTranscript`], a [`WorktreeRun`] or a pipeline [`StepReport`]; a whole
+//! [`ExecutionReport`] becomes one report. [`Report::to_text`] and [`Report::to_html`] produce
+//! the two parts of a `multipart/alternative` email. The HTML uses inline styles only, since
+//! most mail clients drop `<style>` blocks.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::report::{Report, ReportSection};
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let transcript = Gemini::new("Summarize yesterday's failed deploys").transcript().await?;
+//! let report = Report::new("Nightly deploy digest")
+//!     .section(ReportSection::from_transcript("Deploys", &transcript));
+//! let (text, html) = (report.to_text(), report.to_html());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::pipeline::{ExecutionReport, StepReport, StepStatus};
+use crate::transcript::RunTranscript;
+use crate::workspace::{FileChange, WorktreeRun};
+use crate::StreamEvent;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Tools whose `file_path` argument is reported as a changed file.
+const WRITE_TOOLS: &[&str] = &["write_file", "replace", "edit"];
+
+/// One run (or pipeline step) in a [`Report`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ReportSection {
+    pub title: String,
+    pub prompt: Option<String>,
+    pub answer: String,
+    /// A short outcome such as `success` or `failed: <error>`.
+    pub status: Option<String>,
+    /// Labelled values, e.g. `("Duration", "4.2s")`, in display order.
+    pub stats: Vec<(String, String)>,
+    pub files: Vec<FileChange>,
+}
+
+impl ReportSection {
+    /// An empty section.
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            ..Self::default()
+        }
+    }
+
+    /// A section for a streamed run: the first user message is the prompt, the model text is
+    /// the answer, and files written by tools are listed without line counts.
+    pub fn from_transcript(title: impl Into<String>, transcript: &RunTranscript) -> Self {
+        let mut section = Self::new(title).answer(&transcript.text);
+        for event in &transcript.events {
+            match event {
+                StreamEvent::Message { role, content, .. }
+                    if role == "user" && section.prompt.is_none() =>
+                {
+                    section.prompt = Some(content.clone());
+                }
+                StreamEvent::ToolUse {
+                    tool_name,
+                    parameters,
+                    ..
+                } if WRITE_TOOLS.contains(&tool_name.as_str()) => {
+                    let path = parameters.get("file_path").and_then(|p| p.as_str());
+                    if let Some(path) = path {
+                        if !section.files.iter().any(|f| f.path == path) {
+                            section.files.push(FileChange {
+                                path: path.to_string(),
+                                added: None,
+                                removed: None,
+                            });
+                        }
+                    }
+                }
+                StreamEvent::Result { status, .. } => section.status = Some(status.clone()),
+                _ => {}
+            }
+        }
+        if let Some(model) = transcript.model() {
+            section = section.stat("Model", model);
+        }
+        let timing = &transcript.timing;
+        section = section.stat("Duration", format_duration(timing.duration));
+        if let Some(first) = timing.time_to_first_text {
+            section = section.stat("First text after", format_duration(first));
+        }
+        let estimated = if timing.tokens_estimated { "~" } else { "" };
+        section = section.stat(
+            "Output tokens",
+            format!("{estimated}{}", timing.output_tokens),
+        );
+        if let Some(rate) = timing.tokens_per_second() {
+            section = section.stat("Tokens/s", format!("{rate:.1}"));
+        }
+        section
+    }
+
+    /// A section for a [`GitWorktree`](crate::workspace::GitWorktree) run, with its diff.
+    pub fn from_worktree(title: impl Into<String>, run: &WorktreeRun) -> Self {
+        let mut section = Self::new(title)
+            .answer(&run.response)
+            .stat("Branch", &run.branch)
+            .files(run.diff.files.clone());
+        if let Some(commit) = &run.commit {
+            section = section.stat("Commit", commit);
+        }
+        if !run.diff.is_empty() {
+            section = section.stat(
+                "Lines",
+                format!("+{} -{}", run.diff.lines_added(), run.diff.lines_removed()),
+            );
+        }
+        section
+    }
+
+    /// Set the prompt.
+    #[must_use]
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
+
+    /// Set the answer.
+    #[must_use]
+    pub fn answer(mut self, answer: impl Into<String>) -> Self {
+        self.answer = answer.into();
+        self
+    }
+
+    /// Set the outcome line.
+    #[must_use]
+    pub fn status(mut self, status: impl Into<String>) -> Self {
+        self.status = Some(status.into());
+        self
+    }
+
+    /// Append a stat.
+    #[must_use]
+    pub fn stat(mut self, label: impl Into<String>, value: impl Into<String>) -> Self {
+        self.stats.push((label.into(), value.into()));
+        self
+    }
+
+    /// Append changed files.
+    #[must_use]
+    pub fn files(mut self, files: impl IntoIterator<Item = FileChange>) -> Self {
+        self.files.extend(files);
+        self
+    }
+}
+
+impl From<&StepReport> for ReportSection {
+    fn from(step: &StepReport) -> Self {
+        let status = match &step.status {
+            StepStatus::Succeeded => "succeeded".to_string(),
+            StepStatus::Failed { error } => format!("failed: {error}"),
+            StepStatus::Skipped { reason } => format!("skipped: {reason}"),
+        };
+        let mut section = Self::new(&step.name)
+            .answer(step.output.clone().unwrap_or_default())
+            .status(status)
+            .stat("Duration", format_duration(step.elapsed))
+            .stat("Attempts", step.attempts.to_string());
+        section.prompt = step.prompt.clone();
+        if step.replayed {
+            section = section.stat("Replayed", "yes");
+        }
+        section
+    }
+}
+
+/// A titled collection of run results.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Report {
+    pub title: String,
+    pub sections: Vec<ReportSection>,
+}
+
+impl Report {
+    /// An empty report.
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            sections: Vec::new(),
+        }
+    }
+
+    /// One section per pipeline step, in completion order.
+    pub fn from_execution(title: impl Into<String>, execution: &ExecutionReport) -> Self {
+        Self {
+            title: title.into(),
+            sections: execution.steps.iter().map(ReportSection::from).collect(),
+        }
+    }
+
+    /// Append a section.
+    #[must_use]
+    pub fn section(mut self, section: ReportSection) -> Self {
+        self.sections.push(section);
+        self
+    }
+
+    /// Render as plain text.
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "{}\n{}\n",
+            self.title,
+            "=".repeat(self.title.chars().count())
+        );
+        for section in &self.sections {
+            out.push_str(&format!(
+                "\n{}\n{}\n",
+                section.title,
+                "-".repeat(section.title.chars().count())
+            ));
+            if let Some(status) = &section.status {
+                out.push_str(&format!("Status: {status}\n"));
+            }
+            for (label, value) in &section.stats {
+                out.push_str(&format!("{label}: {value}\n"));
+            }
+            if let Some(prompt) = &section.prompt {
+                out.push_str(&format!("\nPrompt:\n{}\n", indent(prompt)));
+            }
+            if !section.answer.is_empty() {
+                out.push_str(&format!("\nAnswer:\n{}\n", indent(&section.answer)));
+            }
+            if !section.files.is_empty() {
+                out.push_str("\nFiles changed:\n");
+                for file in &section.files {
+                    out.push_str(&format!("  {}{}\n", file.path, line_counts(file)));
+                }
+            }
+        }
+        out
+    }
+
+    /// Render as a self-contained HTML document with inline styles.
+    pub fn to_html(&self) -> String {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title></head>\n\
+             <body style=\"font-family:sans-serif;color:#1f2328;max-width:760px;margin:0 auto\">\n\
+             <h1 style=\"font-size:22px\">{title}</h1>\n",
+            title = escape(&self.title)
+        );
+        for section in &self.sections {
+            out.push_str(&format!(
+                "<h2 style=\"font-size:18px;border-bottom:1px solid #d0d7de\">{}</h2>\n",
+                escape(&section.title)
+            ));
+            if section.status.is_some() || !section.stats.is_empty() {
+                out.push_str("<table style=\"border-collapse:collapse;font-size:13px\">\n");
+                let status = section.status.iter().map(|s| ("Status", s.as_str()));
+                let stats = section.stats.iter().map(|(l, v)| (l.as_str(), v.as_str()));
+                for (label, value) in status.chain(stats) {
+                    out.push_str(&format!(
+                        "<tr><td style=\"padding:2px 12px 2px 0;color:#656d76\">{}</td>\
+                         <td style=\"padding:2px 0\">{}</td></tr>\n",
+                        escape(label),
+                        escape(value)
+                    ));
+                }
+                out.push_str("</table>\n");
+            }
+            if let Some(prompt) = &section.prompt {
+                out.push_str(&format!(
+                    "<h3 style=\"font-size:14px\">Prompt</h3>\n<pre style=\"{PRE}\">{}</pre>\n",
+                    escape(prompt)
+                ));
+            }
+            if !section.answer.is_empty() {
+                out.push_str(&format!(
+                    "<h3 style=\"font-size:14px\">Answer</h3>\n<div style=\"white-space:pre-wrap\">{}</div>\n",
+                    escape(&section.answer)
+                ));
+            }
+            if !section.files.is_empty() {
+                out.push_str("<h3 style=\"font-size:14px\">Files changed</h3>\n<ul>\n");
+                for file in &section.files {
+                    out.push_str(&format!(
+                        "<li><code>{}</code>{}</li>\n",
+                        escape(&file.path),
+                        escape(&line_counts(file))
+                    ));
+                }
+                out.push_str("</ul>\n");
+            }
+        }
+        out.push_str("</body></html>\n");
+        out
+    }
+}
+
+const PRE: &str = "background:#f6f8fa;padding:8px;white-space:pre-wrap;font-size:13px";
+
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+fn line_counts(file: &FileChange) -> String {
+    match (file.added, file.removed) {
+        (Some(added), Some(removed)) => format!(" (+{added} -{removed})"),
+        _ => String::new(),
+    }
+}
+
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("  {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::StreamTiming;
+    use serde_json::json;
+
+    fn transcript() -> RunTranscript {
+        let message = |role: &str, content: &str| StreamEvent::Message {
+            role: role.into(),
+            content: content.into(),
+            delta: None,
+            timestamp: String::new(),
+        };
+        RunTranscript {
+            events: vec![
+                message("user", "Fix <main>"),
+                StreamEvent::ToolUse {
+                    tool_name: "write_file".into(),
+                    parameters: json!({ "file_path": "src/main.rs" }),
+                    timestamp: String::new(),
+                },
+                message("model", "Fixed & tested."),
+                StreamEvent::Result {
+                    status: "success".into(),
+                    stats: json!({}).into(),
+                    timestamp: String::new(),
+                },
+            ],
+            text: "Fixed & tested.".into(),
+            timing: StreamTiming {
+                time_to_first_event: None,
+                time_to_first_text: None,
+                duration: Duration::from_millis(2_500),
+                output_tokens: 4,
+                tokens_estimated: true,
+            },
+        }
+    }
+
+    #[test]
+    fn test_transcript_section_renders_as_text() {
+        let report =
+            Report::new("Digest").section(ReportSection::from_transcript("Run", &transcript()));
+        let text = report.to_text();
+        assert!(text.starts_with("Digest\n======\n"));
+        assert!(text.contains("Status: success\nDuration: 2.5s\nOutput tokens: ~4\n"));
+        assert!(text.contains("Prompt:\n  Fix <main>\n"));
+        assert!(text.contains("Answer:\n  Fixed & tested.\n"));
+        assert!(text.contains("Files changed:\n  src/main.rs\n"));
+    }
+
+    #[test]
+    fn test_html_is_escaped() {
+        let report = Report::new("A <b> report")
+            .section(ReportSection::from_transcript("Run", &transcript()));
+        let html = report.to_html();
+        assert!(html.contains("<h1 style=\"font-size:22px\">A &lt;b&gt; report</h1>"));
+        assert!(html.contains("Fix &lt;main&gt;"));
+        assert!(html.contains("Fixed &amp; tested."));
+        assert!(html.contains("<li><code>src/main.rs</code></li>"));
+    }
+
+    #[test]
+    fn test_step_reports_become_sections() {
+        let execution = ExecutionReport {
+            steps: vec![StepReport {
+                name: "summarize".into(),
+                status: StepStatus::Failed {
+                    error: "timeout".into(),
+                },
+                attempts: 2,
+                replayed: false,
+                elapsed: Duration::from_millis(40),
+                prompt: Some("Summarize".into()),
+                output: None,
+            }],
+            elapsed: Duration::from_millis(40),
+        };
+        let report = Report::from_execution("Pipeline", &execution);
+        let section = &report.sections[0];
+        assert_eq!(section.status.as_deref(), Some("failed: timeout"));
+        assert_eq!(
+            section.stats,
+            [
+                ("Duration".to_string(), "40ms".to_string()),
+                ("Attempts".to_string(), "2".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Retrying transient failures.
+//!
+//! The CLI fails now and then for reasons that go away on their own: the API answers with
+//! 429 or "quota exceeded", a connection is reset mid-request, or the process cannot be
+//! launched because the host is briefly out of resources. Attach a [`RetryPolicy`] with
+//! [`Gemini::retry`](crate::Gemini::retry) and `text()` / `json()` run the CLI again after an
+//! exponentially growing, jittered delay. Other failures (bad output, a missing binary,
+//! timeouts, cancellation, guard and policy rejections) are returned at once.
+//!
+//! When a request still fails after retrying, the error is
+//! [`GeminiError::RetriesExhausted`], which records the number of attempts and wraps the last
+//! failure.
+//!
+//! ```rust
+//! use gemini_oxide::retry::RetryPolicy;
+//! use gemini_oxide::Gemini;
+//! use std::time::Duration;
+//!
+//! let policy = RetryPolicy::new()
+//!     .max_attempts(5)
+//!     .initial_backoff(Duration::from_millis(500))
+//!     .max_backoff(Duration::from_secs(20));
+//! let req = Gemini::new("Summarize the changelog").retry(policy);
+//! ```
+
+use crate::GeminiError;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+/// Messages in the CLI's stderr or API errors that indicate a transient failure.
+const TRANSIENT_MARKERS: &[&str] = &[
+    "429",
+    "too many requests",
+    "rate limit",
+    "quota",
+    "resource_exhausted",
+    "503",
+    "unavailable",
+    "overloaded",
+    "econnreset",
+    "connection reset",
+    "socket hang up",
+    "epipe",
+    "broken pipe",
+    "etimedout",
+];
+
+/// How often and how patiently to retry a failed request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first.
+    pub max_attempts: u32,
+    /// Delay before the first retry.
+    pub initial_backoff: Duration,
+    /// Upper bound on any single delay.
+    pub max_backoff: Duration,
+    /// Factor by which the delay grows after each retry.
+    pub multiplier: f64,
+    /// Fraction of each delay that is randomized, from `0.0` (none) to `1.0` (full jitter).
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// Three attempts, waiting about 1s and then 2s, with half of each delay randomized.
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.5,
+        }
+    }
+
+    /// Make at most `attempts` attempts in total. `1` disables retrying.
+    #[must_use]
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Wait `delay` before the first retry.
+    #[must_use]
+    pub fn initial_backoff(mut self, delay: Duration) -> Self {
+        self.initial_backoff = delay;
+        self
+    }
+
+    /// Never wait longer than `delay` between attempts.
+    #[must_use]
+    pub fn max_backoff(mut self, delay: Duration) -> Self {
+        self.max_backoff = delay;
+        self
+    }
+
+    /// Grow the delay by `factor` after each retry.
+    #[must_use]
+    pub fn multiplier(mut self, factor: f64) -> Self {
+        self.multiplier = factor.max(1.0);
+        self
+    }
+
+    /// Randomize `fraction` of each delay so clients that failed together do not retry
+    /// together. Clamped to `0.0..=1.0`.
+    #[must_use]
+    pub fn jitter(mut self, fraction: f64) -> Self {
+        self.jitter = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Whether `error` is worth retrying.
+    pub fn is_transient(error: &GeminiError) -> bool {
+        match error {
+            GeminiError::CliLaunchFailed(io) => !matches!(
+                io.kind(),
+                ErrorKind::NotFound | ErrorKind::PermissionDenied | ErrorKind::InvalidInput
+            ),
+            GeminiError::ApiError(message) | GeminiError::RuntimeError(message) => {
+                let message = message.to_lowercase();
+                TRANSIENT_MARKERS
+                    .iter()
+                    .any(|marker| message.contains(marker))
+            }
+            _ => false,
+        }
+    }
+
+    /// The delay after failed attempt number `attempt` (starting at 1), before jitter.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(64) as i32;
+        let delay = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        Duration::from_secs_f64(delay.min(self.max_backoff.as_secs_f64()))
+    }
+
+    /// The jittered delay after failed attempt number `attempt`.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let random = RandomState::new().hash_one(attempt) as f64 / u64::MAX as f64;
+        self.backoff(attempt).mul_f64(1.0 - self.jitter * random)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let policy = RetryPolicy::new()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(350));
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(350));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(350));
+
+        for attempt in 1..20 {
+            let delay = policy.delay(attempt);
+            assert!(delay <= policy.backoff(attempt));
+            assert!(delay >= policy.backoff(attempt) / 2);
+        }
+    }
+
+    #[test]
+    fn test_only_transient_errors_are_retried() {
+        let transient = [
+            GeminiError::RuntimeError("Error: 429 Too Many Requests".into()),
+            GeminiError::ApiError("Quota exceeded for quota metric".into()),
+            GeminiError::RuntimeError("read ECONNRESET".into()),
+            GeminiError::CliLaunchFailed(std::io::Error::from(ErrorKind::WouldBlock)),
+        ];
+        assert!(transient.iter().all(RetryPolicy::is_transient));
+
+        let permanent = [
+            GeminiError::RuntimeError("Invalid model name".into()),
+            GeminiError::CliLaunchFailed(std::io::Error::from(ErrorKind::NotFound)),
+            GeminiError::Timeout(Duration::from_secs(1)),
+            GeminiError::Cancelled,
+        ];
+        assert!(!permanent.iter().any(RetryPolicy::is_transient));
+    }
+}
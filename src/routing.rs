@@ -0,0 +1,216 @@
+//! Adaptive model routing.
+//!
+//! A [`ModelRouter`] inspects a request before it is launched and picks between a
+//! fast model (e.g. `gemini-2.5-flash`) and a stronger model (e.g. `gemini-2.5-pro`)
+//! according to a [`RoutingPolicy`]. Attach one with [`Gemini::router`](crate::Gemini::router).
+//!
+//! An explicit `.model(...)` on the builder always wins over the router.
+
+use serde::{Deserialize, Serialize};
+
+/// Phrases that suggest the prompt needs multi-step reasoning rather than a quick answer.
+const REASONING_MARKERS: &[&str] = &[
+    "step by step",
+    "explain why",
+    "prove",
+    "derive",
+    "analyze",
+    "analyse",
+    "compare",
+    "trade-off",
+    "tradeoff",
+    "root cause",
+    "design",
+    "architecture",
+    "refactor",
+    "debug",
+];
+
+/// Tokens that are a strong hint that the request contains source code.
+const CODE_MARKERS: &[&str] = &[
+    "```", "fn ", "def ", "class ", "impl ", "#include", "import ", "=> {", "};",
+];
+
+/// Coarse complexity bucket assigned to a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Complexity {
+    /// Short, self-contained requests that a fast model handles well.
+    Simple,
+    /// Long, code-heavy or reasoning-heavy requests.
+    Complex,
+}
+
+/// Signals extracted from a request that drive the routing decision.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptFeatures {
+    /// Number of characters in the prompt plus any inline context.
+    pub chars: usize,
+    /// Number of files piped into the request.
+    pub files: usize,
+    /// Whether the prompt or context appears to contain source code.
+    pub has_code: bool,
+    /// Whether the prompt asks for multi-step reasoning.
+    pub needs_reasoning: bool,
+}
+
+impl PromptFeatures {
+    /// Extract routing signals from a prompt, its inline context and the number of attached files.
+    pub fn analyze(prompt: &str, context: Option<&str>, files: usize) -> Self {
+        let context = context.unwrap_or_default();
+        let lower = prompt.to_lowercase();
+
+        Self {
+            chars: prompt.len() + context.len(),
+            files,
+            has_code: CODE_MARKERS
+                .iter()
+                .any(|m| prompt.contains(m) || context.contains(m)),
+            needs_reasoning: REASONING_MARKERS.iter().any(|m| lower.contains(m)),
+        }
+    }
+}
+
+/// Configuration for [`ModelRouter`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RoutingPolicy {
+    /// Model used for simple requests.
+    pub fast_model: String,
+    /// Model used for complex requests.
+    pub strong_model: String,
+    /// Requests longer than this many characters are considered complex.
+    pub max_fast_chars: usize,
+    /// Requests with more attached files than this are considered complex.
+    pub max_fast_files: usize,
+    /// Route requests containing code to the strong model.
+    pub code_is_complex: bool,
+    /// Route requests asking for reasoning to the strong model.
+    pub reasoning_is_complex: bool,
+}
+
+impl Default for RoutingPolicy {
+    fn default() -> Self {
+        Self {
+            fast_model: "gemini-2.5-flash".to_string(),
+            strong_model: "gemini-2.5-pro".to_string(),
+            max_fast_chars: 4_000,
+            max_fast_files: 1,
+            code_is_complex: true,
+            reasoning_is_complex: true,
+        }
+    }
+}
+
+/// The outcome of routing a single request.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RoutingDecision {
+    /// The model the router selected.
+    pub model: String,
+    /// The complexity bucket the request was assigned to.
+    pub complexity: Complexity,
+    /// Human-readable reasons that pushed the request into its bucket.
+    pub reasons: Vec<String>,
+}
+
+/// Picks a model for each request based on a [`RoutingPolicy`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModelRouter {
+    policy: RoutingPolicy,
+}
+
+impl ModelRouter {
+    /// Create a router with the given policy.
+    pub fn new(policy: RoutingPolicy) -> Self {
+        Self { policy }
+    }
+
+    /// The policy this router applies.
+    pub fn policy(&self) -> &RoutingPolicy {
+        &self.policy
+    }
+
+    /// Classify a request into a [`Complexity`] bucket, collecting the reasons.
+    pub fn classify(&self, features: &PromptFeatures) -> (Complexity, Vec<String>) {
+        let mut reasons = Vec::new();
+        if features.chars > self.policy.max_fast_chars {
+            reasons.push(format!(
+                "length {} exceeds {} chars",
+                features.chars, self.policy.max_fast_chars
+            ));
+        }
+        if features.files > self.policy.max_fast_files {
+            reasons.push(format!(
+                "{} files exceeds {}",
+                features.files, self.policy.max_fast_files
+            ));
+        }
+        if self.policy.code_is_complex && features.has_code {
+            reasons.push("contains code".to_string());
+        }
+        if self.policy.reasoning_is_complex && features.needs_reasoning {
+            reasons.push("requires reasoning".to_string());
+        }
+
+        let complexity = if reasons.is_empty() {
+            Complexity::Simple
+        } else {
+            Complexity::Complex
+        };
+        (complexity, reasons)
+    }
+
+    /// Route a request described by `features` to a model.
+    pub fn route(&self, features: &PromptFeatures) -> RoutingDecision {
+        let (complexity, reasons) = self.classify(features);
+        let model = match complexity {
+            Complexity::Simple => self.policy.fast_model.clone(),
+            Complexity::Complex => self.policy.strong_model.clone(),
+        };
+        RoutingDecision {
+            model,
+            complexity,
+            reasons,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_prompt_routes_to_fast_model() {
+        let router = ModelRouter::default();
+        let decision = router.route(&PromptFeatures::analyze("What is 2+2?", None, 0));
+        assert_eq!(decision.complexity, Complexity::Simple);
+        assert_eq!(decision.model, "gemini-2.5-flash");
+        assert!(decision.reasons.is_empty());
+    }
+
+    #[test]
+    fn test_code_and_reasoning_route_to_strong_model() {
+        let router = ModelRouter::default();
+        let features = PromptFeatures::analyze(
+            "Explain why this deadlocks",
+            Some("fn main() { lock(); lock(); }"),
+            0,
+        );
+        let decision = router.route(&features);
+        assert_eq!(decision.complexity, Complexity::Complex);
+        assert_eq!(decision.model, "gemini-2.5-pro");
+        assert_eq!(decision.reasons.len(), 2);
+    }
+
+    #[test]
+    fn test_policy_thresholds_are_respected() {
+        let router = ModelRouter::new(RoutingPolicy {
+            max_fast_chars: 10,
+            code_is_complex: false,
+            ..RoutingPolicy::default()
+        });
+        let features = PromptFeatures::analyze("fn main() {}", None, 0);
+        let (complexity, reasons) = router.classify(&features);
+        assert_eq!(complexity, Complexity::Complex);
+        assert_eq!(reasons, vec!["length 12 exceeds 10 chars".to_string()]);
+    }
+}
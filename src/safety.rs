@@ -0,0 +1,186 @@
+//! A seatbelt against unattended runs with too much reach.
+//!
+//! With [`yolo`](crate::Gemini::yolo) the CLI runs every tool call without asking, so a
+//! request that also includes directories it should not touch, or leaves the shell available,
+//! can change anything the process can. A [`SafetyLock`] refuses such requests before the CLI
+//! is launched, with `GeminiError::PolicyDenied`:
+//!
+//! * while the agent can edit files (`write_file` or `replace` is available), every workspace
+//!   directory — the [working directory](crate::Gemini::current_dir) and each
+//!   [included](crate::Gemini::include) one — must lie inside a directory the lock allows
+//!   writes in;
+//! * the shell (`run_shell_command`) must be [excluded](crate::Gemini::exclude_tools) or left
+//!   out of the [allowed tools](crate::Gemini::allow_tools), unless the lock allows it. A
+//!   shell narrowed to certain commands still counts as available.
+//!
+//! Requests in any other approval mode are not checked. Set the lock once on a
+//! [`GeminiClient`](crate::client::GeminiClient) so every request is covered, and lift it for a
+//! single request with [`Gemini::override_safety_lock`](crate::Gemini::override_safety_lock).
+//!
+//! ```rust
+//! use gemini_oxide::client::GeminiClient;
+//! use gemini_oxide::safety::SafetyLock;
+//!
+//! let client = GeminiClient::new().safety_lock(SafetyLock::new().allow_writes_in("/srv/sandboxes"));
+//!
+//! // Allowed: edits stay in the sandbox and the shell is excluded.
+//! let fix = client
+//!     .prompt("Fix the failing test")
+//!     .current_dir("/srv/sandboxes/42")
+//!     .yolo()
+//!     .exclude_tools(["run_shell_command"]);
+//!
+//! // Refused when run: the shell is available.
+//! let risky = client.prompt("Clean up the build").current_dir("/srv/sandboxes/42").yolo();
+//! ```
+
+use crate::policy::{normalize, ApprovalMode};
+use crate::{Gemini, GeminiError};
+use std::path::PathBuf;
+
+/// The tools that edit files.
+const WRITE_TOOLS: &[&str] = &["write_file", "replace"];
+
+/// The tool that runs shell commands.
+const SHELL_TOOL: &str = "run_shell_command";
+
+/// Which [`yolo`](crate::Gemini::yolo) requests may run. See the [module docs](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SafetyLock {
+    writable: Vec<PathBuf>,
+    allow_shell: bool,
+}
+
+impl SafetyLock {
+    /// A lock that refuses every `yolo` request that can edit files or run shell commands.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow `yolo` edits in workspaces inside `dir`. A relative `dir` is resolved against the
+    /// current directory when a request is checked. May be called more than once.
+    #[must_use]
+    pub fn allow_writes_in(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.writable.push(dir.into());
+        self
+    }
+
+    /// Allow `yolo` requests with the shell available.
+    #[must_use]
+    pub fn allow_shell(mut self) -> Self {
+        self.allow_shell = true;
+        self
+    }
+
+    /// Refuse `request` if it runs in `yolo` mode beyond what the lock allows.
+    pub(crate) fn check(&self, request: &Gemini) -> Result<(), GeminiError> {
+        if request.approval != ApprovalMode::Yolo {
+            return Ok(());
+        }
+        let refuse = |reason: String| {
+            Err(GeminiError::PolicyDenied(format!(
+                "safety lock refused a yolo request: {reason}; \
+                 use override_safety_lock() if this is intended"
+            )))
+        };
+        if !self.allow_shell && offers(request, SHELL_TOOL) {
+            return refuse(format!("{SHELL_TOOL} is available"));
+        }
+        if WRITE_TOOLS.iter().any(|tool| offers(request, tool)) {
+            let here = std::env::current_dir().unwrap_or_default();
+            let cwd = normalize(&here.join(request.current_dir.as_deref().unwrap_or(&here)));
+            let writable: Vec<PathBuf> = self
+                .writable
+                .iter()
+                .map(|dir| normalize(&here.join(dir)))
+                .collect();
+            let workspace = std::iter::once(cwd.clone()).chain(
+                request
+                    .include_dirs
+                    .iter()
+                    .map(|dir| normalize(&cwd.join(dir))),
+            );
+            for dir in workspace {
+                if !writable.iter().any(|allowed| dir.starts_with(allowed)) {
+                    return refuse(format!(
+                        "{} is outside the directories it may write in",
+                        dir.display()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the agent is offered `tool` in `request`.
+fn offers(request: &Gemini, tool: &str) -> bool {
+    let names = |entry: &String| {
+        entry == tool
+            || entry
+                .strip_prefix(tool)
+                .is_some_and(|rest| rest.starts_with('('))
+    };
+    !request.excluded_tools.iter().any(|entry| entry == tool)
+        && (request.allowed_tools.is_empty() || request.allowed_tools.iter().any(names))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock() -> SafetyLock {
+        SafetyLock::new().allow_writes_in("/srv/sandboxes")
+    }
+
+    #[test]
+    fn test_only_yolo_requests_are_checked() {
+        let request = Gemini::new("Hi").current_dir("/etc");
+        assert!(lock().check(&request).is_ok());
+        assert!(lock()
+            .check(&request.clone().approval(ApprovalMode::AutoEdit))
+            .is_ok());
+        assert!(matches!(
+            lock().check(&request.yolo()),
+            Err(GeminiError::PolicyDenied(_))
+        ));
+    }
+
+    #[test]
+    fn test_shell_must_be_unavailable_or_allowed() {
+        let request = Gemini::new("Hi").current_dir("/srv/sandboxes/1").yolo();
+        assert!(lock().check(&request).is_err());
+        assert!(lock()
+            .check(
+                &request
+                    .clone()
+                    .allow_tools(["run_shell_command(git status)"])
+            )
+            .is_err());
+        assert!(lock()
+            .check(&request.clone().allow_tools(["read_file", "write_file"]))
+            .is_ok());
+        assert!(lock()
+            .check(&request.clone().exclude_tools(["run_shell_command"]))
+            .is_ok());
+        assert!(lock().allow_shell().check(&request).is_ok());
+    }
+
+    #[test]
+    fn test_writes_must_stay_in_allowed_directories() {
+        let request = Gemini::new("Hi")
+            .current_dir("/srv/sandboxes/1")
+            .yolo()
+            .exclude_tools(["run_shell_command"]);
+        assert!(lock().check(&request.clone().include("../2/docs")).is_ok());
+        assert!(lock().check(&request.clone().include("../../etc")).is_err());
+        assert!(lock()
+            .check(&request.clone().current_dir("/srv/sandboxes-old"))
+            .is_err());
+        let read_only =
+            request
+                .include("/etc")
+                .allow_tools(["read_file", "glob", "search_file_content"]);
+        assert!(lock().check(&read_only).is_ok());
+    }
+}
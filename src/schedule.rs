@@ -0,0 +1,561 @@
+//! Recurring requests.
+//!
+//! [`Gemini::schedule`](crate::Gemini::schedule) runs a request on a cadence and hands every
+//! result to a [`RunSink`]: an async callback ([`sink_fn`]) or a Tokio channel. Cadences are
+//! five-field cron expressions evaluated in UTC (`"30 7 * * 1-5"`), the macros `@hourly`,
+//! `@daily`, `@weekly` and `@monthly`, or fixed intervals such as `"@every 15m"`.
+//!
+//! By default a run is skipped while the previous one is still in flight ([`Overlap::Skip`]),
+//! and a [`Scheduler`] can add random jitter so that many processes sharing a schedule do not
+//! hit the API at the same instant.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::schedule::{sink_fn, Scheduler};
+//! use gemini_oxide::Gemini;
+//! use std::time::Duration;
+//!
+//! # fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let handle = Scheduler::new("0 8 * * *")?
+//!     .name("daily digest")
+//!     .jitter(Duration::from_secs(60))
+//!     .start(
+//!         Gemini::new("Summarize yesterday's open incidents"),
+//!         sink_fn(|run| async move {
+//!             if let Ok(text) = run.result {
+//!                 println!("{}: {text}", run.name);
+//!             }
+//!         }),
+//!     );
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Gemini, GeminiError};
+use futures_util::future::BoxFuture;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, Notify};
+
+/// How far ahead [`Schedule::next_after`] searches for a matching cron time.
+const MAX_SEARCH_DAYS: i64 = 366 * 5;
+
+/// When a recurring request runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    Cron(Cron),
+    Every(Duration),
+}
+
+/// The allowed values of each cron field, as bit sets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day-of-month and day-of-week were both restricted; cron then matches either.
+    either_day: bool,
+}
+
+impl Schedule {
+    /// Parse a cron expression, macro or `@every <n><s|m|h|d>` interval.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::ValidationFailed` if the expression is malformed.
+    pub fn parse(expr: &str) -> Result<Self, GeminiError> {
+        let expr = expr.trim();
+        let expanded = match expr {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            _ => expr,
+        };
+        if let Some(interval) = expanded.strip_prefix("@every") {
+            return Ok(Self::every(parse_interval(interval.trim()).ok_or_else(
+                || invalid(expr, "expected an interval such as `@every 15m`"),
+            )?));
+        }
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid(expr, "expected five fields"));
+        };
+        let weekdays = parse_field(weekday, 0, 7).map_err(|e| invalid(expr, &e))?;
+        Ok(Self {
+            kind: Kind::Cron(Cron {
+                minutes: parse_field(minute, 0, 59).map_err(|e| invalid(expr, &e))?,
+                hours: parse_field(hour, 0, 23).map_err(|e| invalid(expr, &e))?,
+                days: parse_field(day, 1, 31).map_err(|e| invalid(expr, &e))?,
+                months: parse_field(month, 1, 12).map_err(|e| invalid(expr, &e))?,
+                // 7 is another name for Sunday.
+                weekdays: (weekdays | (weekdays >> 7)) & 0x7f,
+                either_day: day != "*" && weekday != "*",
+            }),
+        })
+    }
+
+    /// Run every `interval`, starting one interval after the scheduler starts.
+    pub fn every(interval: Duration) -> Self {
+        Self {
+            kind: Kind::Every(interval.max(Duration::from_millis(1))),
+        }
+    }
+
+    /// The first time strictly after `after` at which the schedule fires, or `None` if a cron
+    /// expression never matches (e.g. February 30th).
+    pub fn next_after(&self, after: SystemTime) -> Option<SystemTime> {
+        let cron = match &self.kind {
+            Kind::Every(interval) => return Some(after + *interval),
+            Kind::Cron(cron) => cron,
+        };
+        let secs = after.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+        let start_minute = secs.div_euclid(60) + 1;
+        let first_day = start_minute.div_euclid(24 * 60);
+        for day in first_day..first_day + MAX_SEARCH_DAYS {
+            if !cron.matches_day(day) {
+                continue;
+            }
+            let from = if day == first_day {
+                start_minute.rem_euclid(24 * 60)
+            } else {
+                0
+            };
+            for minute_of_day in from..24 * 60 {
+                if bit(cron.hours, minute_of_day / 60) && bit(cron.minutes, minute_of_day % 60) {
+                    let minute = day * 24 * 60 + minute_of_day;
+                    return Some(UNIX_EPOCH + Duration::from_secs(minute as u64 * 60));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = GeminiError;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        Self::parse(expr)
+    }
+}
+
+impl Cron {
+    fn matches_day(&self, days_since_epoch: i64) -> bool {
+        let (_, month, day) = civil_from_days(days_since_epoch);
+        if !bit(self.months, month) {
+            return false;
+        }
+        // 1970-01-01 was a Thursday.
+        let weekday = (days_since_epoch + 4).rem_euclid(7);
+        let by_date = bit(self.days, day);
+        let by_weekday = bit(self.weekdays, weekday);
+        if self.either_day {
+            by_date || by_weekday
+        } else {
+            by_date && by_weekday
+        }
+    }
+}
+
+fn bit(set: u64, value: i64) -> bool {
+    set & (1 << value) != 0
+}
+
+fn invalid(expr: &str, reason: &str) -> GeminiError {
+    GeminiError::ValidationFailed(format!("invalid schedule `{expr}`: {reason}"))
+}
+
+/// Parse one cron field (`*`, `5`, `1-5`, `*/15`, `0-30/10`, comma lists) into a bit set.
+fn parse_field(field: &str, min: i64, max: i64) -> Result<u64, String> {
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<i64>()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("bad step in `{part}`"))?,
+            ),
+            None => (part, 1),
+        };
+        let number = |s: &str| {
+            s.parse::<i64>()
+                .ok()
+                .filter(|n| (min..=max).contains(n))
+                .ok_or_else(|| format!("`{s}` is not in {min}-{max}"))
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (number(start)?, number(end)?),
+                None if step > 1 => (number(range)?, max),
+                None => (number(range)?, number(range)?),
+            },
+        };
+        if start > end {
+            return Err(format!("empty range `{range}`"));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+fn parse_interval(text: &str) -> Option<Duration> {
+    let split = text.find(|c: char| !c.is_ascii_digit())?;
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number.parse().ok().filter(|n| *n > 0)?;
+    let secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(number * secs))
+}
+
+/// Convert days since 1970-01-01 into a (year, month, day) date in the proleptic Gregorian
+/// calendar.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// One scheduled execution and its result.
+#[derive(Debug)]
+pub struct ScheduledRun {
+    /// The scheduler's [`name`](Scheduler::name).
+    pub name: String,
+    /// The time the schedule fired, before jitter.
+    pub scheduled_for: SystemTime,
+    /// The time the request started.
+    pub started_at: SystemTime,
+    /// The request's text response.
+    pub result: Result<String, GeminiError>,
+}
+
+/// Receives the result of every scheduled run.
+pub trait RunSink: Send + Sync {
+    fn deliver(&self, run: ScheduledRun) -> BoxFuture<'_, ()>;
+}
+
+/// Results are sent to the channel; they are dropped once the receiver is closed.
+impl RunSink for mpsc::Sender<ScheduledRun> {
+    fn deliver(&self, run: ScheduledRun) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let _ = self.send(run).await;
+        })
+    }
+}
+
+/// Results are sent to the channel; they are dropped once the receiver is closed.
+impl RunSink for mpsc::UnboundedSender<ScheduledRun> {
+    fn deliver(&self, run: ScheduledRun) -> BoxFuture<'_, ()> {
+        let _ = self.send(run);
+        Box::pin(async {})
+    }
+}
+
+struct FnSink<F>(F);
+
+impl<F, Fut> RunSink for FnSink<F>
+where
+    F: Fn(ScheduledRun) -> Fut + Send + Sync,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    fn deliver(&self, run: ScheduledRun) -> BoxFuture<'_, ()> {
+        Box::pin((self.0)(run))
+    }
+}
+
+/// Wrap an async closure as a [`RunSink`].
+pub fn sink_fn<F, Fut>(f: F) -> impl RunSink
+where
+    F: Fn(ScheduledRun) -> Fut + Send + Sync,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    FnSink(f)
+}
+
+/// What to do when a run is due while the previous one is still in flight.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overlap {
+    /// Skip the new run.
+    #[default]
+    Skip,
+    /// Start the new run alongside the previous one.
+    Allow,
+}
+
+/// Configures and starts a recurring request.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    schedule: Schedule,
+    name: String,
+    jitter: Duration,
+    overlap: Overlap,
+}
+
+impl Scheduler {
+    /// A scheduler for `expr` (see [`Schedule::parse`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::ValidationFailed` if the expression is malformed.
+    pub fn new(expr: &str) -> Result<Self, GeminiError> {
+        Ok(Self::from_schedule(Schedule::parse(expr)?))
+    }
+
+    /// A scheduler for an already parsed schedule.
+    pub fn from_schedule(schedule: Schedule) -> Self {
+        Self {
+            schedule,
+            name: "scheduled".to_string(),
+            jitter: Duration::ZERO,
+            overlap: Overlap::Skip,
+        }
+    }
+
+    /// Name reported in every [`ScheduledRun`].
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Delay each run by a random amount up to `jitter`. Defaults to none.
+    #[must_use]
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the overlap policy. Defaults to [`Overlap::Skip`].
+    #[must_use]
+    pub fn overlap(mut self, overlap: Overlap) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Start running `request` on the schedule, delivering each result to `sink`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    pub fn start(self, request: Gemini, sink: impl RunSink + 'static) -> ScheduleHandle {
+        let state = Arc::new(ScheduleState::default());
+        let task_state = state.clone();
+        let sink: Arc<dyn RunSink> = Arc::new(sink);
+        let clock = request.clock.clone();
+        tokio::spawn(async move {
+            let running = Arc::new(AtomicBool::new(false));
+            loop {
+                let now = clock.system_time();
+                let Some(next) = self.schedule.next_after(now) else {
+                    break;
+                };
+                let delay =
+                    next.duration_since(now).unwrap_or_default() + random_up_to(self.jitter);
+                tokio::select! {
+                    () = clock.sleep(delay) => {}
+                    () = task_state.cancel.notified() => break,
+                }
+                if self.overlap == Overlap::Skip && running.swap(true, Ordering::SeqCst) {
+                    task_state.skipped.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                task_state.started.fetch_add(1, Ordering::Relaxed);
+                let (request, sink, running) = (request.clone(), sink.clone(), running.clone());
+                let name = self.name.clone();
+                let started_at = clock.system_time();
+                tokio::spawn(async move {
+                    let result = request.text().await;
+                    running.store(false, Ordering::SeqCst);
+                    sink.deliver(ScheduledRun {
+                        name,
+                        scheduled_for: next,
+                        started_at,
+                        result,
+                    })
+                    .await;
+                });
+            }
+        });
+        ScheduleHandle { state }
+    }
+}
+
+/// Start running `request` on the schedule `expr` with the default [`Scheduler`] settings.
+///
+/// # Errors
+///
+/// Returns `GeminiError::ValidationFailed` if the expression is malformed.
+///
+/// # Panics
+///
+/// Panics if called outside a Tokio runtime.
+pub fn schedule(
+    expr: &str,
+    request: Gemini,
+    sink: impl RunSink + 'static,
+) -> Result<ScheduleHandle, GeminiError> {
+    Ok(Scheduler::new(expr)?.start(request, sink))
+}
+
+fn random_up_to(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    let random = RandomState::new().build_hasher().finish();
+    let max_nanos = u64::try_from(max.as_nanos()).unwrap_or(u64::MAX);
+    Duration::from_nanos(random % max_nanos)
+}
+
+#[derive(Default)]
+struct ScheduleState {
+    cancel: Notify,
+    started: AtomicU64,
+    skipped: AtomicU64,
+}
+
+/// A handle to a running schedule. Dropping it does not stop the schedule.
+#[derive(Clone)]
+pub struct ScheduleHandle {
+    state: Arc<ScheduleState>,
+}
+
+impl ScheduleHandle {
+    /// Stop scheduling new runs. A run already in flight still completes and is delivered.
+    pub fn cancel(&self) {
+        self.state.cancel.notify_one();
+    }
+
+    /// Number of runs started so far.
+    pub fn runs_started(&self) -> u64 {
+        self.state.started.load(Ordering::Relaxed)
+    }
+
+    /// Number of runs skipped because the previous one was still in flight.
+    pub fn runs_skipped(&self) -> u64 {
+        self.state.skipped.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for ScheduleHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScheduleHandle")
+            .field("runs_started", &self.runs_started())
+            .field("runs_skipped", &self.runs_skipped())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::Simulator;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        // 2024-02-28T23:59:30Z, a Wednesday.
+        let now = at(1_709_164_770);
+        let next = |expr: &str| Schedule::parse(expr).unwrap().next_after(now).unwrap();
+
+        assert_eq!(next("* * * * *"), at(1_709_164_800));
+        // Leap day, then Friday March 1st.
+        assert_eq!(next("30 7 * * *"), at(1_709_164_800 + 7 * 3600 + 1800));
+        assert_eq!(next("0 9 * * 5"), at(1_709_164_800 + 86_400 + 9 * 3600));
+        assert_eq!(next("@monthly"), at(1_709_164_800 + 86_400));
+        assert_eq!(next("*/20 0 * * 1-5"), at(1_709_164_800));
+        // Day-of-month OR day-of-week: the 15th or any Monday (March 4th).
+        assert_eq!(next("0 0 15 * 1"), at(1_709_164_800 + 4 * 86_400));
+        assert_eq!(Schedule::parse("0 0 30 2 *").unwrap().next_after(now), None);
+        assert_eq!(
+            Schedule::parse("@every 15m").unwrap().next_after(now),
+            Some(now + Duration::from_secs(900))
+        );
+    }
+
+    #[test]
+    fn test_invalid_expressions_are_rejected() {
+        for expr in [
+            "* * * *",
+            "60 * * * *",
+            "5-1 * * * *",
+            "*/0 * * * *",
+            "@every",
+            "@every 3w",
+        ] {
+            assert!(
+                matches!(Schedule::parse(expr), Err(GeminiError::ValidationFailed(_))),
+                "{expr}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_runs_are_delivered_until_cancelled() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let handle = Scheduler::from_schedule(Schedule::every(Duration::from_millis(20)))
+            .name("probe")
+            .start(Gemini::new("hi").bin_path("/nonexistent/gemini"), tx);
+
+        let run = rx.recv().await.unwrap();
+        assert_eq!(run.name, "probe");
+        assert!(run.started_at >= run.scheduled_for);
+        assert!(matches!(run.result, Err(GeminiError::CliLaunchFailed(_))));
+
+        handle.cancel();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let started = handle.runs_started();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(handle.runs_started(), started);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_daily_runs_follow_paused_time() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let request = Gemini::new("hi").simulator(Simulator::new().fallback("done"));
+        let handle = Scheduler::new("@daily").unwrap().start(request, tx);
+
+        let mut due = Vec::new();
+        for _ in 0..3 {
+            let run = rx.recv().await.unwrap();
+            assert_eq!(run.result.unwrap(), "done");
+            due.push(run.scheduled_for.duration_since(UNIX_EPOCH).unwrap());
+        }
+        handle.cancel();
+        assert_eq!(due[0].as_secs() % 86_400, 0);
+        assert_eq!(due[1] - due[0], Duration::from_secs(86_400));
+        assert_eq!(due[2] - due[1], Duration::from_secs(86_400));
+    }
+}
@@ -0,0 +1,322 @@
+//! Lightweight JSON Schema support.
+//!
+//! The [`JsonSchema`] trait describes the shape of a Rust type as a JSON Schema document so it
+//! can be embedded in prompts, and [`validate`] checks a model's reply against such a schema
+//! before it is deserialized. Only the subset of JSON Schema that models reliably follow is
+//! supported: `type`, `properties`, `required`, `additionalProperties`, `items`, `enum` and `anyOf`.
+//!
+//! Implementations are provided for primitives and common containers. Structs with named
+//! fields and enums with unit variants derive the trait, honouring serde's `rename`,
+//! `rename_all`, `default` and `skip` attributes; `Option` fields are not required:
+//!
+//! ```rust
+//! use gemini_oxide::schema::JsonSchema;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, JsonSchema)]
+//! struct Person {
+//!     name: String,
+//!     age: Option<u32>,
+//! }
+//!
+//! let schema = Person::json_schema();
+//! assert_eq!(schema["required"], serde_json::json!(["name"]));
+//! ```
+//!
+//! Other shapes implement it by hand, usually with the [`object`] helper:
+//!
+//! ```rust
+//! use gemini_oxide::schema::{self, JsonSchema, Value};
+//!
+//! struct Range(u32, u32);
+//!
+//! impl JsonSchema for Range {
+//!     fn json_schema() -> Value {
+//!         schema::object(
+//!             [("start", u32::json_schema()), ("end", u32::json_schema())],
+//!             &["start", "end"],
+//!         )
+//!     }
+//! }
+//! ```
+
+pub use gemini_oxide_derive::JsonSchema;
+pub use serde_json::Value;
+
+use serde_json::{json, Map};
+use std::collections::{BTreeMap, HashMap};
+
+/// Types that can describe themselves as a JSON Schema.
+pub trait JsonSchema {
+    /// The JSON Schema for this type.
+    fn json_schema() -> Value;
+}
+
+macro_rules! impl_schema {
+    ($kind:literal => $($t:ty),+) => {
+        $(impl JsonSchema for $t {
+            fn json_schema() -> Value {
+                json!({ "type": $kind })
+            }
+        })+
+    };
+}
+
+impl_schema!("string" => String, char);
+impl_schema!("boolean" => bool);
+impl_schema!("integer" => i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+impl_schema!("number" => f32, f64);
+
+impl JsonSchema for Value {
+    fn json_schema() -> Value {
+        json!({})
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    fn json_schema() -> Value {
+        json!({ "anyOf": [T::json_schema(), { "type": "null" }] })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Vec<T> {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for HashMap<String, T> {
+    fn json_schema() -> Value {
+        json!({ "type": "object", "additionalProperties": T::json_schema() })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for BTreeMap<String, T> {
+    fn json_schema() -> Value {
+        json!({ "type": "object", "additionalProperties": T::json_schema() })
+    }
+}
+
+/// Build an object schema from `(name, schema)` pairs and a list of required property names.
+///
+/// Unknown properties are rejected, which keeps model replies tight.
+pub fn object<'a>(
+    properties: impl IntoIterator<Item = (&'a str, Value)>,
+    required: &[&str],
+) -> Value {
+    let properties: Map<String, Value> = properties
+        .into_iter()
+        .map(|(name, schema)| (name.to_string(), schema))
+        .collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// Build a string schema restricted to the given values.
+pub fn string_enum(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+/// Check `value` against `schema`.
+///
+/// Returns every violation found, each prefixed with a JSON path such as `$.items[2].name`.
+///
+/// # Errors
+///
+/// Returns the list of violations if the value does not conform.
+pub fn validate(schema: &Value, value: &Value) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    validate_at(schema, value, "$", &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    let actual = type_name(value);
+    actual == expected || (expected == "number" && actual == "integer")
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    if let Some(any_of) = schema.get("anyOf").and_then(Value::as_array) {
+        let matched = any_of.iter().any(|s| {
+            let mut scratch = Vec::new();
+            validate_at(s, value, path, &mut scratch);
+            scratch.is_empty()
+        });
+        if !matched {
+            errors.push(format!("{path}: does not match any allowed schema"));
+        }
+        return;
+    }
+
+    match schema.get("type") {
+        Some(Value::String(expected)) if !type_matches(expected, value) => {
+            errors.push(format!(
+                "{path}: expected {expected}, found {}",
+                type_name(value)
+            ));
+            return;
+        }
+        Some(Value::Array(options))
+            if !options
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|t| type_matches(t, value)) =>
+        {
+            errors.push(format!(
+                "{path}: expected one of {}, found {}",
+                Value::Array(options.clone()),
+                type_name(value)
+            ));
+            return;
+        }
+        _ => {}
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            errors.push(format!(
+                "{path}: {value} is not one of {}",
+                Value::Array(allowed.clone())
+            ));
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            if let Some(required) = schema.get("required").and_then(Value::as_array) {
+                for name in required.iter().filter_map(Value::as_str) {
+                    if !map.contains_key(name) {
+                        errors.push(format!("{path}: missing required property `{name}`"));
+                    }
+                }
+            }
+            for (key, child) in map {
+                let child_path = format!("{path}.{key}");
+                match properties.and_then(|p| p.get(key)) {
+                    Some(child_schema) => validate_at(child_schema, child, &child_path, errors),
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            errors.push(format!("{path}: unexpected property `{key}`"));
+                        }
+                        Some(extra @ Value::Object(_)) => {
+                            validate_at(extra, child, &child_path, errors);
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_at(item_schema, item, &format!("{path}[{i}]"), errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn person_schema() -> Value {
+        object(
+            [
+                ("name", String::json_schema()),
+                ("age", Option::<u32>::json_schema()),
+                ("tags", Vec::<String>::json_schema()),
+            ],
+            &["name", "tags"],
+        )
+    }
+
+    #[test]
+    fn test_valid_value_passes() {
+        let value = json!({ "name": "Ada", "age": null, "tags": ["math"] });
+        assert!(validate(&person_schema(), &value).is_ok());
+    }
+
+    #[test]
+    fn test_violations_are_reported_with_paths() {
+        let value = json!({ "age": "old", "tags": [1], "extra": true });
+        let errors = validate(&person_schema(), &value).unwrap_err();
+        assert!(errors.contains(&"$: missing required property `name`".to_string()));
+        assert!(errors.contains(&"$.age: does not match any allowed schema".to_string()));
+        assert!(errors.contains(&"$.tags[0]: expected string, found integer".to_string()));
+        assert!(errors.contains(&"$: unexpected property `extra`".to_string()));
+    }
+
+    #[test]
+    fn test_derive_follows_serde_attributes() {
+        use serde::Deserialize;
+
+        #[derive(Deserialize, JsonSchema)]
+        #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+        #[allow(dead_code)]
+        enum Level {
+            Low,
+            VeryHigh,
+        }
+
+        #[derive(Deserialize, JsonSchema)]
+        #[serde(rename_all = "camelCase")]
+        #[allow(dead_code)]
+        struct Alert {
+            level: Level,
+            #[serde(rename = "msg")]
+            message: String,
+            #[serde(default)]
+            retry_count: u32,
+            owner: Option<String>,
+            #[serde(skip)]
+            seen: bool,
+        }
+
+        assert_eq!(
+            Alert::json_schema(),
+            object(
+                [
+                    ("level", string_enum(&["LOW", "VERY_HIGH"])),
+                    ("msg", String::json_schema()),
+                    ("retryCount", u32::json_schema()),
+                    ("owner", Option::<String>::json_schema()),
+                ],
+                &["level", "msg"],
+            )
+        );
+    }
+
+    #[test]
+    fn test_enum_and_number_widening() {
+        assert!(validate(&string_enum(&["a", "b"]), &json!("c")).is_err());
+        assert!(validate(&f64::json_schema(), &json!(3)).is_ok());
+        assert!(validate(&u32::json_schema(), &json!(3.5)).is_err());
+    }
+}
@@ -0,0 +1,506 @@
+//! Multi-turn conversations.
+//!
+//! Every [`Gemini`] request is stateless. A [`GeminiSession`] keeps the conversation so far and
+//! pipes it to the model with each new message, so follow-ups like "and in Python?" are answered
+//! in context. The history is plain data ([`Turn`]s) that can be stored between requests, e.g.
+//! per chat thread, and restored with [`GeminiSession::with_history`].
+//!
+//! Long conversations are trimmed from the oldest turn to stay within
+//! [`max_history_chars`](GeminiSession::max_history_chars).
+//!
+//! Each turn records the tokens it used ([`TurnUsage`]), so the cost of a conversation can be
+//! attributed to the user messages that caused it; see [`GeminiSession::usage_by_turn`]. As the
+//! history is resent with every message, later turns use more prompt tokens, of which the
+//! CLI may serve part from its cache.
+//!
+//! With [`store`](GeminiSession::store), every turn and its usage are saved to a
+//! [`ConversationStore`] as they happen, and [`load`](GeminiSession::load) picks the conversation
+//! up again later, e.g. in another process.
+//!
+//! Alternatively, the CLI can keep the conversation itself: take the [`SessionId`] from a
+//! stream's `Init` event with [`StreamEvent::session_id`] and continue it with
+//! [`Gemini::resume`], which loads the full prior context, tool calls included.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::session::GeminiSession;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let mut session = GeminiSession::new().configure(|request| request.model("gemini-2.5-flash"));
+//! session.send("Write a function that reverses a string in Rust.").await?;
+//! let follow_up = session.send("Now make it handle grapheme clusters.").await?;
+//! println!("{}", follow_up.response);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::storage::{self, Conversation, ConversationStore, Role, StoredMessage, UsageRecord};
+#[cfg(doc)]
+use crate::StreamEvent;
+use crate::{Configure, Gemini, GeminiError, GeminiJsonOutput};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// A conversation saved by the CLI, to be continued with [`Gemini::resume`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct SessionId(String);
+
+impl SessionId {
+    /// The session with this ID, as reported in a stream's `Init` event.
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    /// The most recent session in the project.
+    pub fn latest() -> Self {
+        Self::new("latest")
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for SessionId {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl From<&str> for SessionId {
+    fn from(id: &str) -> Self {
+        Self::new(id)
+    }
+}
+
+/// One exchange in a conversation.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Turn {
+    /// The user's message.
+    pub user: String,
+    /// The model's reply.
+    pub model: String,
+    /// The tokens the exchange used, if the CLI reported them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TurnUsage>,
+}
+
+/// Tokens used by one turn of a conversation, summed over the models that served it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TurnUsage {
+    /// Tokens sent to the model: the message, the history and any context.
+    pub prompt_tokens: u64,
+    /// The part of `prompt_tokens` served from the CLI's cache.
+    pub cached_tokens: u64,
+    /// Tokens of the model's reply.
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+}
+
+impl TurnUsage {
+    /// The usage in the output's stats, or `None` if it has none.
+    pub fn from_output(output: &GeminiJsonOutput) -> Option<Self> {
+        let stats = output.stats.as_ref()?;
+        let mut usage = Self::default();
+        for model in stats.models.values() {
+            usage.add(Self::from_tokens(&model.tokens));
+        }
+        Some(usage)
+    }
+
+    /// The usage in one model's token counts.
+    fn from_tokens(tokens: &HashMap<String, u64>) -> Self {
+        let count = |key: &str| tokens.get(key).copied().unwrap_or(0);
+        let (prompt, output) = (count("prompt"), count("candidates"));
+        Self {
+            prompt_tokens: prompt,
+            cached_tokens: count("cached"),
+            output_tokens: output,
+            total_tokens: tokens.get("total").copied().unwrap_or(prompt + output),
+        }
+    }
+
+    fn add(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.cached_tokens += other.cached_tokens;
+        self.output_tokens += other.output_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+
+    /// Prompt tokens not served from the cache.
+    pub fn fresh_prompt_tokens(&self) -> u64 {
+        self.prompt_tokens.saturating_sub(self.cached_tokens)
+    }
+}
+
+/// A conversation with the model that remembers previous turns.
+pub struct GeminiSession {
+    history: Vec<Turn>,
+    max_history_chars: usize,
+    configure: Option<Configure>,
+    store: Option<SessionStore>,
+}
+
+/// Where a session saves its turns.
+struct SessionStore {
+    store: Arc<dyn ConversationStore>,
+    conversation_id: String,
+    /// The `seq` of the next saved message, once known.
+    next_seq: Option<u64>,
+}
+
+impl Default for GeminiSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GeminiSession {
+    /// An empty conversation keeping up to 100,000 characters of history.
+    pub fn new() -> Self {
+        Self {
+            history: Vec::new(),
+            max_history_chars: 100_000,
+            configure: None,
+            store: None,
+        }
+    }
+
+    /// Continue a conversation from previously saved turns.
+    #[must_use]
+    pub fn with_history(mut self, turns: Vec<Turn>) -> Self {
+        self.history = turns;
+        self
+    }
+
+    /// Maximum characters of history sent with each message; the oldest turns are dropped
+    /// first. The latest turn is always kept.
+    #[must_use]
+    pub fn max_history_chars(mut self, chars: usize) -> Self {
+        self.max_history_chars = chars;
+        self
+    }
+
+    /// Customize each underlying request (model, binary path, ...).
+    #[must_use]
+    pub fn configure(mut self, f: impl Fn(Gemini) -> Gemini + Send + Sync + 'static) -> Self {
+        self.configure = Some(Box::new(f));
+        self
+    }
+
+    /// Save every turn sent from now on, with its usage, as conversation `conversation_id` in
+    /// `store`. Call [`load`](Self::load) to continue a conversation saved earlier.
+    ///
+    /// Each turn is stored as a user and a model message; the usage is recorded per model and
+    /// attributed to the model message.
+    #[must_use]
+    pub fn store(
+        mut self,
+        store: Arc<dyn ConversationStore>,
+        conversation_id: impl Into<String>,
+    ) -> Self {
+        self.store = Some(SessionStore {
+            store,
+            conversation_id: conversation_id.into(),
+            next_seq: None,
+        });
+        self
+    }
+
+    /// Replace the history with the turns saved in the [`store`](Self::store), including their
+    /// usage. A conversation that has not been saved yet loads as empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`GeminiError::ValidationFailed`] if no store is set, or any error raised by
+    /// the store.
+    pub async fn load(&mut self) -> Result<(), GeminiError> {
+        let Some(saved) = &mut self.store else {
+            return Err(GeminiError::ValidationFailed(
+                "GeminiSession::load requires a store; set one with GeminiSession::store".into(),
+            ));
+        };
+        let id = saved.conversation_id.as_str();
+        let messages = saved.store.messages(id).await?;
+        let mut usage: HashMap<u64, TurnUsage> = HashMap::new();
+        for record in saved.store.usage(id).await? {
+            if let Some(seq) = record.seq {
+                usage.entry(seq).or_default().add(TurnUsage {
+                    prompt_tokens: record.prompt_tokens,
+                    cached_tokens: record.cached_tokens,
+                    output_tokens: record.output_tokens,
+                    total_tokens: record.total_tokens,
+                });
+            }
+        }
+
+        let mut history = Vec::new();
+        let mut user = None;
+        for message in &messages {
+            match message.role {
+                Role::User => user = Some(message.content.clone()),
+                Role::Model => {
+                    if let Some(user) = user.take() {
+                        history.push(Turn {
+                            user,
+                            model: message.content.clone(),
+                            usage: usage.get(&message.seq).copied(),
+                        });
+                    }
+                }
+                Role::System | Role::Tool => {}
+            }
+        }
+        saved.next_seq = Some(messages.last().map_or(0, |m| m.seq + 1));
+        self.history = history;
+        Ok(())
+    }
+
+    /// The conversation so far, oldest turn first.
+    pub fn turns(&self) -> &[Turn] {
+        &self.history
+    }
+
+    /// The tokens used by each turn, in the order of [`turns`](Self::turns). `None` for turns
+    /// the CLI reported no stats for.
+    pub fn usage_by_turn(&self) -> Vec<Option<TurnUsage>> {
+        self.history.iter().map(|turn| turn.usage).collect()
+    }
+
+    /// Forget the conversation.
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Send `message` and record the exchange.
+    ///
+    /// A failed request leaves the history unchanged, so the message can be retried.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised while running the CLI or saving the turn to the
+    /// [`store`](Self::store).
+    pub async fn send(
+        &mut self,
+        message: impl Into<String>,
+    ) -> Result<GeminiJsonOutput, GeminiError> {
+        let message = message.into();
+        let output = self.request(&message).json().await?;
+        let turn = Turn {
+            user: message,
+            model: output.response.clone(),
+            usage: TurnUsage::from_output(&output),
+        };
+        if let Some(saved) = &mut self.store {
+            saved.save(&turn, &output).await?;
+        }
+        self.history.push(turn);
+        Ok(output)
+    }
+
+    /// The request for `message` given the current history.
+    fn request(&self, message: &str) -> Gemini {
+        let history = self.recent_history();
+        let mut request = if history.is_empty() {
+            Gemini::new(message)
+        } else {
+            Gemini::new(format!(
+                "The conversation so far is provided on standard input. Continue it by replying \
+                 to the user's next message.\n\nUser: {message}"
+            ))
+            .context(format_history(history))
+        };
+        if let Some(configure) = &self.configure {
+            request = configure(request);
+        }
+        request
+    }
+
+    /// The most recent turns that fit within `max_history_chars`.
+    fn recent_history(&self) -> &[Turn] {
+        let mut chars = 0;
+        let mut start = self.history.len();
+        while start > 0 {
+            let turn = &self.history[start - 1];
+            chars += turn.user.len() + turn.model.len();
+            if chars > self.max_history_chars && start < self.history.len() {
+                break;
+            }
+            start -= 1;
+        }
+        &self.history[start..]
+    }
+}
+
+impl SessionStore {
+    /// Append `turn` to the conversation, creating it on first use.
+    async fn save(&mut self, turn: &Turn, output: &GeminiJsonOutput) -> Result<(), GeminiError> {
+        let id = self.conversation_id.as_str();
+        let seq = match self.next_seq {
+            Some(seq) => seq,
+            None => {
+                if self.store.get_conversation(id).await?.is_none() {
+                    self.store.put_conversation(&Conversation::new(id)).await?;
+                }
+                let messages = self.store.messages(id).await?;
+                messages.last().map_or(0, |m| m.seq + 1)
+            }
+        };
+
+        let created_at = storage::now_millis();
+        for (offset, role, content) in [(0, Role::User, &turn.user), (1, Role::Model, &turn.model)]
+        {
+            let message = StoredMessage {
+                conversation_id: id.to_string(),
+                seq: seq + offset,
+                role,
+                content: content.clone(),
+                created_at,
+            };
+            self.store.append_message(&message).await?;
+        }
+        let models = output.stats.iter().flat_map(|stats| &stats.models);
+        for (model, stats) in models {
+            let usage = TurnUsage::from_tokens(&stats.tokens);
+            let record = UsageRecord {
+                conversation_id: id.to_string(),
+                seq: Some(seq + 1),
+                model: model.clone(),
+                prompt_tokens: usage.prompt_tokens,
+                cached_tokens: usage.cached_tokens,
+                output_tokens: usage.output_tokens,
+                total_tokens: usage.total_tokens,
+                created_at,
+            };
+            self.store.record_usage(&record).await?;
+        }
+        self.next_seq = Some(seq + 2);
+        Ok(())
+    }
+}
+
+fn format_history(turns: &[Turn]) -> String {
+    turns
+        .iter()
+        .map(|turn| format!("User: {}\n\nAssistant: {}\n", turn.user, turn.model))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::Simulator;
+
+    fn turn(user: &str, model: &str) -> Turn {
+        Turn {
+            user: user.to_string(),
+            model: model.to_string(),
+            usage: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_recorded_per_turn() {
+        let mut session = GeminiSession::new()
+            .with_history(vec![turn("Name a colour", "Blue")])
+            .configure(|request| request.simulator(Simulator::new()));
+        session.send("Another one").await.unwrap();
+        session.send("And one more").await.unwrap();
+
+        let usage = session.usage_by_turn();
+        assert_eq!(usage.len(), 3);
+        assert_eq!(usage[0], None);
+        let second = usage[1].unwrap();
+        assert!(second.output_tokens > 0);
+        assert_eq!(
+            second.total_tokens,
+            second.prompt_tokens + second.output_tokens
+        );
+        assert!(usage[2].is_some());
+
+        let output: GeminiJsonOutput = serde_json::from_str(
+            r#"{"response":"ok","stats":{"models":{"m":{"api":{},"tokens":{"prompt":100,"cached":60,"candidates":5,"total":105}}},
+                "tools":{"totalCalls":0,"totalSuccess":0,"totalFail":0},"files":{"totalLinesAdded":0,"totalLinesRemoved":0}}}"#,
+        )
+        .unwrap();
+        let usage = TurnUsage::from_output(&output).unwrap();
+        assert_eq!((usage.cached_tokens, usage.fresh_prompt_tokens()), (60, 40));
+    }
+
+    #[test]
+    fn test_history_is_piped_with_follow_ups() {
+        let session = GeminiSession::new();
+        assert!(
+            !format!("{:?}", session.request("hi").build_command("json"))
+                .contains("conversation so far")
+        );
+
+        let session = session.with_history(vec![turn("Name a colour", "Blue")]);
+        let request = session.request("Another one");
+        assert_eq!(
+            request.input_data.as_deref(),
+            Some("User: Name a colour\n\nAssistant: Blue\n")
+        );
+        assert!(request.prompt.ends_with("User: Another one"));
+    }
+
+    #[test]
+    fn test_oldest_turns_are_trimmed_first() {
+        let session = GeminiSession::new()
+            .max_history_chars(10)
+            .with_history(vec![turn("aaaa", "bbbb"), turn("cccc", "dddd")]);
+        assert_eq!(session.recent_history(), &[turn("cccc", "dddd")]);
+
+        // The latest turn is kept even if it alone exceeds the limit.
+        let session = session.max_history_chars(1);
+        assert_eq!(session.recent_history().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_turns_are_saved_and_reloaded() {
+        use crate::storage::InMemoryConversationStore;
+
+        let store = Arc::new(InMemoryConversationStore::new());
+        let mut session = GeminiSession::new()
+            .store(store.clone(), "thread-1")
+            .configure(|request| request.simulator(Simulator::new()));
+        session.send("Name a colour").await.unwrap();
+        session.send("Another one").await.unwrap();
+
+        assert!(store.get_conversation("thread-1").await.unwrap().is_some());
+        let messages = store.messages("thread-1").await.unwrap();
+        let seqs: Vec<u64> = messages.iter().map(|m| m.seq).collect();
+        assert_eq!(seqs, [0, 1, 2, 3]);
+        assert_eq!(messages[2].content, "Another one");
+        let usage = store.usage("thread-1").await.unwrap();
+        assert!(!usage.is_empty());
+        assert!(usage.iter().all(|u| matches!(u.seq, Some(1 | 3))));
+
+        let mut reloaded = GeminiSession::new()
+            .store(store.clone(), "thread-1")
+            .configure(|request| request.simulator(Simulator::new()));
+        reloaded.load().await.unwrap();
+        assert_eq!(reloaded.turns(), session.turns());
+        assert!(reloaded.usage_by_turn().iter().all(Option::is_some));
+
+        // New turns continue after the saved ones.
+        reloaded.send("And one more").await.unwrap();
+        let messages = store.messages("thread-1").await.unwrap();
+        assert_eq!(messages.last().map(|m| m.seq), Some(5));
+
+        let mut unsaved = GeminiSession::new();
+        assert!(matches!(
+            unsaved.load().await,
+            Err(GeminiError::ValidationFailed(_))
+        ));
+    }
+}
@@ -0,0 +1,21 @@
+//! CLI settings passed for a single run.
+//!
+//! Options without a command-line flag are written to a temporary settings file that the CLI
+//! loads with the highest priority, as if set by the system administrator.
+
+use serde_json::Value;
+
+/// The environment variable naming the settings file the CLI loads with the highest priority.
+pub(crate) const ENV: &str = "GEMINI_CLI_SYSTEM_SETTINGS_PATH";
+
+/// Merge `overlay` into `base`, recursing into objects; other values in `overlay` win.
+pub(crate) fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
@@ -0,0 +1,242 @@
+//! Simulated responses for offline development.
+//!
+//! A [`Simulator`] answers requests without launching the CLI, so applications can be built
+//! and demoed on machines with no `gemini` binary or API key. Attach one with
+//! [`Gemini::simulator`](crate::Gemini::simulator), or start every request from
+//! [`GeminiClient::simulated`](crate::client::GeminiClient::simulated).
+//!
+//! Responses are canned or computed from the prompt by rules, tried in the order they were
+//! added, with a fallback for everything else. They arrive after a configurable latency, and
+//! `stream()` delivers them as the CLI would: an `Init` event, the text in `Message` deltas
+//! spaced by a per-chunk delay, and a `Result` with token counts estimated at about four
+//! characters per token.
+//!
+//! `text()` and `json()` go through the SDK's usual processing (cache, post-processors,
+//! schema checks, moderation). `stream()` yields the simulated events directly, without
+//! tool policies, moderation, metrics or checkpoints.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::client::GeminiClient;
+//! use gemini_oxide::simulate::Simulator;
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let client = GeminiClient::new().simulator(
+//!     Simulator::new()
+//!         .respond_when("weather", "Sunny, 24°C.")
+//!         .rule(|prompt| prompt.starts_with("Translate").then(|| "Bonjour".to_string()))
+//!         .latency(Duration::from_millis(300)),
+//! );
+//! let forecast = client.prompt("What's the weather in Lyon?").text().await?;
+//! assert_eq!(forecast, "Sunny, 24°C.");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::compression::estimate_tokens;
+use crate::{GeminiError, StreamEvent};
+use futures_util::stream::Stream;
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The model name reported by simulated responses unless the request sets one.
+pub const DEFAULT_MODEL: &str = "simulated";
+
+type Rule = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// Produces responses in place of the CLI.
+#[derive(Clone)]
+pub struct Simulator {
+    rules: Vec<Rule>,
+    fallback: String,
+    latency: Duration,
+    chunk_delay: Duration,
+    chunk_chars: usize,
+}
+
+impl fmt::Debug for Simulator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Simulator")
+            .field("rules", &self.rules.len())
+            .field("fallback", &self.fallback)
+            .field("latency", &self.latency)
+            .field("chunk_delay", &self.chunk_delay)
+            .field("chunk_chars", &self.chunk_chars)
+            .finish()
+    }
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulator {
+    /// A simulator that answers every prompt with a placeholder, without delay.
+    pub fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+            fallback: "This is a simulated response.".to_string(),
+            latency: Duration::ZERO,
+            chunk_delay: Duration::ZERO,
+            chunk_chars: 16,
+        }
+    }
+
+    /// Answer prompts containing `needle` with `response`.
+    #[must_use]
+    pub fn respond_when(self, needle: impl Into<String>, response: impl Into<String>) -> Self {
+        let (needle, response) = (needle.into(), response.into());
+        self.rule(move |prompt| prompt.contains(&needle).then(|| response.clone()))
+    }
+
+    /// Answer with `rule`'s response for every prompt it returns `Some` for.
+    #[must_use]
+    pub fn rule(mut self, rule: impl Fn(&str) -> Option<String> + Send + Sync + 'static) -> Self {
+        self.rules.push(Arc::new(rule));
+        self
+    }
+
+    /// The response to prompts no rule matches.
+    #[must_use]
+    pub fn fallback(mut self, response: impl Into<String>) -> Self {
+        self.fallback = response.into();
+        self
+    }
+
+    /// Wait this long before the response (or, when streaming, its first event).
+    #[must_use]
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// When streaming, send the text in chunks of `chars` characters, `delay` apart.
+    #[must_use]
+    pub fn chunks(mut self, chars: usize, delay: Duration) -> Self {
+        self.chunk_chars = chars.max(1);
+        self.chunk_delay = delay;
+        self
+    }
+
+    /// The response to `prompt`.
+    pub fn response(&self, prompt: &str) -> String {
+        self.rules
+            .iter()
+            .find_map(|rule| rule(prompt))
+            .unwrap_or_else(|| self.fallback.clone())
+    }
+
+    /// What the CLI would print for `prompt` in `format` (`text` or `json`), after the
+    /// configured latency.
+    pub(crate) async fn output(&self, prompt: &str, model: Option<&str>, format: &str) -> Vec<u8> {
+        tokio::time::sleep(self.latency).await;
+        let response = self.response(prompt);
+        if format != "json" {
+            return response.into_bytes();
+        }
+        let model = model.unwrap_or(DEFAULT_MODEL);
+        json!({
+            "response": response,
+            "stats": {
+                "models": { model: { "api": { "totalRequests": 1 }, "tokens": tokens(prompt, &response) } },
+                "tools": { "totalCalls": 0, "totalSuccess": 0, "totalFail": 0 },
+                "files": { "totalLinesAdded": 0, "totalLinesRemoved": 0 },
+            },
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    /// The events the CLI would stream for `prompt`, paced by the configured delays.
+    pub(crate) fn stream(
+        &self,
+        prompt: &str,
+        model: Option<&str>,
+    ) -> impl Stream<Item = Result<StreamEvent, GeminiError>> {
+        let simulator = self.clone();
+        let prompt = prompt.to_string();
+        let model = model.unwrap_or(DEFAULT_MODEL).to_string();
+        async_stream::try_stream! {
+            tokio::time::sleep(simulator.latency).await;
+            yield StreamEvent::Init {
+                session_id: "simulated".to_string(),
+                model: model.clone(),
+                timestamp: String::new(),
+            };
+            let response = simulator.response(&prompt);
+            let chars: Vec<char> = response.chars().collect();
+            for (i, chunk) in chars.chunks(simulator.chunk_chars).enumerate() {
+                if i > 0 {
+                    tokio::time::sleep(simulator.chunk_delay).await;
+                }
+                yield StreamEvent::Message {
+                    role: "model".to_string(),
+                    content: chunk.iter().collect(),
+                    delta: Some(true),
+                    timestamp: String::new(),
+                };
+            }
+            yield StreamEvent::Result {
+                status: "success".to_string(),
+                stats: json!({ "models": { model: { "tokens": tokens(&prompt, &response) } } }).into(),
+                timestamp: String::new(),
+            };
+        }
+    }
+}
+
+/// Estimated token counts in the CLI's per-model stats form.
+fn tokens(prompt: &str, response: &str) -> Value {
+    let (prompt, candidates) = (estimate_tokens(prompt), estimate_tokens(response));
+    json!({ "prompt": prompt, "candidates": candidates, "total": prompt + candidates })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let simulator = Simulator::new()
+            .respond_when("weather", "Sunny")
+            .rule(|p| p.contains("weather").then(|| "Rainy".to_string()))
+            .fallback("Unknown");
+        assert_eq!(simulator.response("weather in Lyon?"), "Sunny");
+        assert_eq!(simulator.response("stocks?"), "Unknown");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stream_is_chunked_and_paced() {
+        let simulator = Simulator::new()
+            .fallback("abcdefgh")
+            .latency(Duration::from_secs(2))
+            .chunks(3, Duration::from_secs(1));
+        let start = tokio::time::Instant::now();
+        let events: Vec<_> = simulator.stream("Hi", None).collect().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(4));
+
+        let deltas: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                Ok(StreamEvent::Message { content, .. }) => Some(content.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(deltas, ["abc", "def", "gh"]);
+        assert!(matches!(events[0], Ok(StreamEvent::Init { .. })));
+        match events.last() {
+            Some(Ok(StreamEvent::Result { stats, .. })) => {
+                assert_eq!(
+                    stats.to_value()["models"][DEFAULT_MODEL]["tokens"]["candidates"],
+                    2
+                );
+            }
+            other => panic!("expected a result, got {other:?}"),
+        }
+    }
+}
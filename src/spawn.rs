@@ -0,0 +1,154 @@
+//! Control over how the CLI process is launched.
+//!
+//! [`SpawnOptions`] covers common hardening safely: running the CLI as an unprivileged user and
+//! group, and in its own process group so signals sent to the host's group (e.g. Ctrl-C in a
+//! terminal) do not reach it. Attach them with
+//! [`Gemini::spawn_options`](crate::Gemini::spawn_options).
+//!
+//! For anything else — entering namespaces, installing a seccomp filter, setting
+//! `PR_SET_NO_NEW_PRIVS` — register a hook with the `unsafe`
+//! [`Gemini::pre_exec`](crate::Gemini::pre_exec) (Unix only). Hooks run in the child after
+//! `fork` and before `exec`, after the options above have been applied.
+//!
+//! A CLI in its own process group is also stopped as a group: when the request is dropped,
+//! times out or is cancelled, every process in the group — including tool subprocesses the
+//! agent started — is killed, not just the CLI. This is opt-in because a separate group no
+//! longer receives the terminal's Ctrl-C, so a host killed by it leaves the CLI running.
+//!
+//! ```rust
+//! use gemini_oxide::spawn::SpawnOptions;
+//! use gemini_oxide::Gemini;
+//!
+//! let req = Gemini::new("Summarize the logs")
+//!     .spawn_options(SpawnOptions::new().user(65534).group(65534).new_process_group());
+//! ```
+
+use tokio::process::Command;
+
+/// A closure run in the child process between `fork` and `exec`.
+#[cfg(unix)]
+pub(crate) type PreExecHook = std::sync::Arc<dyn Fn() -> std::io::Result<()> + Send + Sync>;
+
+/// Safe process-level settings for the CLI. Ignored on platforms other than Unix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpawnOptions {
+    /// Run the CLI as this user id.
+    pub uid: Option<u32>,
+    /// Run the CLI with this group id.
+    pub gid: Option<u32>,
+    /// Put the CLI in a new process group of its own.
+    pub new_process_group: bool,
+}
+
+impl SpawnOptions {
+    /// Options that change nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the CLI as user `uid`. The host process needs the privilege to switch users.
+    #[must_use]
+    pub fn user(mut self, uid: u32) -> Self {
+        self.uid = Some(uid);
+        self
+    }
+
+    /// Run the CLI with group `gid`. The host process needs the privilege to switch groups.
+    #[must_use]
+    pub fn group(mut self, gid: u32) -> Self {
+        self.gid = Some(gid);
+        self
+    }
+
+    /// Start the CLI in a new process group, which is killed as a whole when the CLI is.
+    #[must_use]
+    pub fn new_process_group(mut self) -> Self {
+        self.new_process_group = true;
+        self
+    }
+
+    pub(crate) fn apply(&self, cmd: &mut Command) {
+        #[cfg(unix)]
+        {
+            // The group is set first so it is still permitted after dropping the user.
+            if let Some(gid) = self.gid {
+                cmd.gid(gid);
+            }
+            if let Some(uid) = self.uid {
+                cmd.uid(uid);
+            }
+            if self.new_process_group {
+                cmd.process_group(0);
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = cmd;
+    }
+}
+
+/// Register `hooks` to run in the child before `exec`.
+///
+/// # Safety
+///
+/// Every hook must be safe to run between `fork` and `exec`; see
+/// [`Gemini::pre_exec`](crate::Gemini::pre_exec).
+#[cfg(unix)]
+pub(crate) unsafe fn apply_pre_exec(cmd: &mut Command, hooks: &[PreExecHook]) {
+    for hook in hooks {
+        let hook = hook.clone();
+        // SAFETY: upheld by the caller of `Gemini::pre_exec`.
+        unsafe {
+            cmd.pre_exec(move || hook());
+        }
+    }
+}
+
+/// The CLI's process group, killed with it so tool subprocesses are not orphaned.
+///
+/// Only tracks a group when the CLI leads one ([`SpawnOptions::new_process_group`]).
+#[derive(Debug)]
+pub(crate) struct ProcessGroup {
+    pgid: Option<u32>,
+    kill_on_drop: bool,
+}
+
+impl ProcessGroup {
+    pub(crate) fn of(
+        child: &tokio::process::Child,
+        options: &SpawnOptions,
+        kill_on_drop: bool,
+    ) -> Self {
+        Self {
+            pgid: child
+                .id()
+                .filter(|_| cfg!(unix) && options.new_process_group),
+            kill_on_drop,
+        }
+    }
+
+    /// Kill every process in the group. Call before killing (and reaping) the CLI itself, whose
+    /// pid names the group.
+    pub(crate) fn kill(&mut self) {
+        #[cfg(unix)]
+        if let Some(pgid) = self.pgid.take() {
+            // SAFETY: `killpg` only sends a signal; a group that has already gone is reported
+            // as an error, which there is nothing to do about.
+            unsafe {
+                libc::killpg(pgid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+
+    /// Stop tracking the group once the CLI has exited on its own.
+    pub(crate) fn release(&mut self) {
+        self.pgid = None;
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if self.kill_on_drop {
+            self.kill();
+        }
+    }
+}
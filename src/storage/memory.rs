@@ -0,0 +1,252 @@
+//! In-memory store implementations, used as defaults and in tests.
+
+use super::{
+    CacheStore, Conversation, ConversationStore, StoredMessage, ToolTranscript, UsageRecord,
+};
+use crate::GeminiError;
+use futures_util::future::BoxFuture;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct ConversationData {
+    conversation: Option<Conversation>,
+    messages: Vec<StoredMessage>,
+    tool_calls: Vec<ToolTranscript>,
+    usage: Vec<UsageRecord>,
+}
+
+/// A [`ConversationStore`] that keeps everything in process memory.
+#[derive(Default)]
+pub struct InMemoryConversationStore {
+    data: Mutex<HashMap<String, ConversationData>>,
+}
+
+impl InMemoryConversationStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with<T>(&self, id: &str, f: impl FnOnce(&mut ConversationData) -> T) -> T {
+        let mut data = self.data.lock().unwrap_or_else(|e| e.into_inner());
+        f(data.entry(id.to_string()).or_default())
+    }
+
+    fn read<T>(&self, id: &str, f: impl FnOnce(&ConversationData) -> T) -> Option<T> {
+        let data = self.data.lock().unwrap_or_else(|e| e.into_inner());
+        data.get(id).map(f)
+    }
+}
+
+impl ConversationStore for InMemoryConversationStore {
+    fn put_conversation<'a>(
+        &'a self,
+        conversation: &'a Conversation,
+    ) -> BoxFuture<'a, Result<(), GeminiError>> {
+        self.with(&conversation.id, |d| {
+            d.conversation = Some(conversation.clone());
+        });
+        Box::pin(async { Ok(()) })
+    }
+
+    fn get_conversation<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Conversation>, GeminiError>> {
+        let conversation = self.read(id, |d| d.conversation.clone()).flatten();
+        Box::pin(async move { Ok(conversation) })
+    }
+
+    fn list_conversations(&self) -> BoxFuture<'_, Result<Vec<Conversation>, GeminiError>> {
+        let mut all: Vec<Conversation> = {
+            let data = self.data.lock().unwrap_or_else(|e| e.into_inner());
+            data.values()
+                .filter_map(|d| d.conversation.clone())
+                .collect()
+        };
+        all.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        Box::pin(async move { Ok(all) })
+    }
+
+    fn delete_conversation<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), GeminiError>> {
+        self.data
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(id);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn append_message<'a>(
+        &'a self,
+        message: &'a StoredMessage,
+    ) -> BoxFuture<'a, Result<(), GeminiError>> {
+        self.with(&message.conversation_id, |d| {
+            d.messages.push(message.clone());
+            d.messages.sort_by_key(|m| m.seq);
+        });
+        Box::pin(async { Ok(()) })
+    }
+
+    fn messages<'a>(
+        &'a self,
+        conversation_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<StoredMessage>, GeminiError>> {
+        let messages = self
+            .read(conversation_id, |d| d.messages.clone())
+            .unwrap_or_default();
+        Box::pin(async move { Ok(messages) })
+    }
+
+    fn record_tool_call<'a>(
+        &'a self,
+        call: &'a ToolTranscript,
+    ) -> BoxFuture<'a, Result<(), GeminiError>> {
+        self.with(&call.conversation_id, |d| d.tool_calls.push(call.clone()));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn tool_calls<'a>(
+        &'a self,
+        conversation_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<ToolTranscript>, GeminiError>> {
+        let calls = self
+            .read(conversation_id, |d| d.tool_calls.clone())
+            .unwrap_or_default();
+        Box::pin(async move { Ok(calls) })
+    }
+
+    fn record_usage<'a>(
+        &'a self,
+        usage: &'a UsageRecord,
+    ) -> BoxFuture<'a, Result<(), GeminiError>> {
+        self.with(&usage.conversation_id, |d| d.usage.push(usage.clone()));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn usage<'a>(
+        &'a self,
+        conversation_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<UsageRecord>, GeminiError>> {
+        let usage = self
+            .read(conversation_id, |d| d.usage.clone())
+            .unwrap_or_default();
+        Box::pin(async move { Ok(usage) })
+    }
+}
+
+/// A [`CacheStore`] backed by a `HashMap`, with an optional time-to-live.
+#[derive(Default)]
+pub struct InMemoryCacheStore {
+    ttl: Option<Duration>,
+    entries: Mutex<HashMap<String, (Instant, String)>>,
+}
+
+impl InMemoryCacheStore {
+    /// Create a cache whose entries never expire.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a cache whose entries expire `ttl` after being written.
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            ttl: Some(ttl),
+            ..Self::default()
+        }
+    }
+
+    fn is_fresh(&self, written: Instant) -> bool {
+        self.ttl.is_none_or(|ttl| written.elapsed() < ttl)
+    }
+}
+
+impl CacheStore for InMemoryCacheStore {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<String>, GeminiError>> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let value = match entries.get(key) {
+            Some((written, value)) if self.is_fresh(*written) => Some(value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        };
+        Box::pin(async move { Ok(value) })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, value: String) -> BoxFuture<'a, Result<(), GeminiError>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key.to_string(), (Instant::now(), value));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), GeminiError>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, GeminiError>> {
+        let mut keys: Vec<String> = {
+            let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            entries
+                .iter()
+                .filter(|(_, (written, _))| self.is_fresh(*written))
+                .map(|(k, _)| k.clone())
+                .collect()
+        };
+        keys.sort();
+        Box::pin(async move { Ok(keys) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Role;
+
+    #[tokio::test]
+    async fn test_conversation_round_trip() {
+        let store = InMemoryConversationStore::new();
+        store
+            .put_conversation(&Conversation::new("c1"))
+            .await
+            .unwrap();
+        for (seq, content) in [(1, "second"), (0, "first")] {
+            let message = StoredMessage {
+                conversation_id: "c1".into(),
+                seq,
+                role: Role::User,
+                content: content.into(),
+                created_at: 0,
+            };
+            store.append_message(&message).await.unwrap();
+        }
+
+        let messages = store.messages("c1").await.unwrap();
+        assert_eq!(messages[0].content, "first");
+        assert_eq!(store.list_conversations().await.unwrap().len(), 1);
+
+        store.delete_conversation("c1").await.unwrap();
+        assert!(store.get_conversation("c1").await.unwrap().is_none());
+        assert!(store.messages("c1").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cache_ttl_expiry() {
+        let cache = InMemoryCacheStore::with_ttl(Duration::from_millis(20));
+        cache.put("k", "v".into()).await.unwrap();
+        assert_eq!(cache.get("k").await.unwrap().as_deref(), Some("v"));
+        assert_eq!(cache.list().await.unwrap(), vec!["k".to_string()]);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(cache.get("k").await.unwrap().is_none());
+        assert!(cache.list().await.unwrap().is_empty());
+    }
+}
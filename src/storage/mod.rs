@@ -0,0 +1,210 @@
+//! Pluggable persistence for conversations and cached responses.
+//!
+//! [`ConversationStore`] is the extension point for persisting conversations, their messages,
+//! tool transcripts and token usage. [`CacheStore`] is a string key/value store used by the
+//! response cache (see [`Gemini::cache`](crate::Gemini::cache)). Backends implement the traits
+//! with boxed futures so stores can be used as trait objects (`Arc<dyn CacheStore>`), which makes
+//! it straightforward to back them with Redis, DynamoDB or similar.
+//!
+//! The [`memory`] module provides in-process defaults. With the `sqlx` feature enabled,
+//! [`sql::PgStore`] and [`sql::SqliteStore`] persist conversations to Postgres and SQLite
+//! through `sqlx`.
+
+pub mod memory;
+#[cfg(feature = "sqlx")]
+pub mod sql;
+
+pub use memory::{InMemoryCacheStore, InMemoryConversationStore};
+
+use crate::GeminiError;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Milliseconds since the Unix epoch, used for all persisted timestamps.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Top-level record for a conversation.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Conversation {
+    pub id: String,
+    pub title: Option<String>,
+    pub model: Option<String>,
+    /// Creation time in Unix milliseconds.
+    pub created_at: u64,
+}
+
+impl Conversation {
+    /// Create a conversation record stamped with the current time.
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            title: None,
+            model: None,
+            created_at: now_millis(),
+        }
+    }
+}
+
+/// The author of a stored message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    System,
+    User,
+    Model,
+    Tool,
+}
+
+impl Role {
+    /// The lowercase name used when persisting the role.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Model => "model",
+            Role::Tool => "tool",
+        }
+    }
+
+    /// Parse a persisted role name.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "system" => Some(Role::System),
+            "user" => Some(Role::User),
+            "model" => Some(Role::Model),
+            "tool" => Some(Role::Tool),
+            _ => None,
+        }
+    }
+}
+
+/// A single message within a conversation.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StoredMessage {
+    pub conversation_id: String,
+    /// Position of the message within the conversation, starting at 0.
+    pub seq: u64,
+    pub role: Role,
+    pub content: String,
+    pub created_at: u64,
+}
+
+/// A tool invocation made by the agent and its outcome.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ToolTranscript {
+    pub conversation_id: String,
+    pub tool_name: String,
+    pub parameters: serde_json::Value,
+    pub status: String,
+    pub output: String,
+    pub created_at: u64,
+}
+
+/// Token usage attributed to a conversation.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct UsageRecord {
+    pub conversation_id: String,
+    /// The message whose reply used the tokens, if known.
+    #[serde(default)]
+    pub seq: Option<u64>,
+    pub model: String,
+    pub prompt_tokens: u64,
+    /// The part of `prompt_tokens` served from the CLI's cache.
+    #[serde(default)]
+    pub cached_tokens: u64,
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+    pub created_at: u64,
+}
+
+/// Persistent storage for conversations.
+pub trait ConversationStore: Send + Sync {
+    /// Insert or replace a conversation record.
+    fn put_conversation<'a>(
+        &'a self,
+        conversation: &'a Conversation,
+    ) -> BoxFuture<'a, Result<(), GeminiError>>;
+
+    /// Fetch a conversation by id.
+    fn get_conversation<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<Conversation>, GeminiError>>;
+
+    /// List all conversations, oldest first.
+    fn list_conversations(&self) -> BoxFuture<'_, Result<Vec<Conversation>, GeminiError>>;
+
+    /// Delete a conversation and everything recorded for it.
+    fn delete_conversation<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), GeminiError>>;
+
+    /// Append a message to its conversation.
+    fn append_message<'a>(
+        &'a self,
+        message: &'a StoredMessage,
+    ) -> BoxFuture<'a, Result<(), GeminiError>>;
+
+    /// All messages of a conversation, ordered by `seq`.
+    fn messages<'a>(
+        &'a self,
+        conversation_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<StoredMessage>, GeminiError>>;
+
+    /// Record a tool invocation.
+    fn record_tool_call<'a>(
+        &'a self,
+        call: &'a ToolTranscript,
+    ) -> BoxFuture<'a, Result<(), GeminiError>>;
+
+    /// All tool invocations of a conversation, oldest first.
+    fn tool_calls<'a>(
+        &'a self,
+        conversation_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<ToolTranscript>, GeminiError>>;
+
+    /// Record token usage.
+    fn record_usage<'a>(&'a self, usage: &'a UsageRecord)
+        -> BoxFuture<'a, Result<(), GeminiError>>;
+
+    /// All usage records of a conversation, oldest first.
+    fn usage<'a>(
+        &'a self,
+        conversation_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<UsageRecord>, GeminiError>>;
+}
+
+/// A string key/value store for cached responses.
+pub trait CacheStore: Send + Sync {
+    /// Fetch the value stored under `key`, if present and not expired.
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<String>, GeminiError>>;
+
+    /// Store `value` under `key`, replacing any previous value.
+    fn put<'a>(&'a self, key: &'a str, value: String) -> BoxFuture<'a, Result<(), GeminiError>>;
+
+    /// Remove the value stored under `key`, if any.
+    fn remove<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), GeminiError>>;
+
+    /// List all live keys.
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, GeminiError>>;
+}
+
+/// Derive a stable cache key from a sequence of request components.
+///
+/// Uses SHA-256 with length prefixes, so keys are identical across processes, platforms and
+/// compiler versions, and requests sharing a backend cannot be made to collide.
+pub(crate) fn cache_key<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    let digest = hasher.finalize();
+    let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+    format!("gemini:{hex}")
+}
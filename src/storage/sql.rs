@@ -0,0 +1,479 @@
+//! [`ConversationStore`]s for Postgres and SQLite, built on `sqlx`. Enable the `sqlx` feature
+//! to use them.
+//!
+//! [`PgStore`] and [`SqliteStore`] wrap a `sqlx` connection pool. Call `migrate` once at startup
+//! to create the tables, or apply [`schema`] with your own migration tooling. Deleting a
+//! conversation removes its messages, tool calls and usage in a single transaction.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::storage::sql::SqliteStore;
+//! use gemini_oxide::storage::{Conversation, ConversationStore};
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let pool = sqlx::SqlitePool::connect("sqlite://conversations.db?mode=rwc")
+//!     .await
+//!     .map_err(|e| gemini_oxide::GeminiError::StorageError(e.to_string()))?;
+//! let store = SqliteStore::new(pool);
+//! store.migrate().await?;
+//! store.put_conversation(&Conversation::new("thread-1")).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use super::{Conversation, ConversationStore, Role, StoredMessage, ToolTranscript, UsageRecord};
+use crate::GeminiError;
+use futures_util::future::BoxFuture;
+
+/// The database a [`schema`] is generated for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqlDialect {
+    Postgres,
+    Sqlite,
+}
+
+/// DDL creating the conversation tables for `dialect`.
+pub fn schema(dialect: SqlDialect) -> Vec<String> {
+    let serial = match dialect {
+        SqlDialect::Postgres => "BIGSERIAL PRIMARY KEY",
+        SqlDialect::Sqlite => "INTEGER PRIMARY KEY AUTOINCREMENT",
+    };
+    vec![
+        "CREATE TABLE IF NOT EXISTS gemini_conversations (
+            id TEXT PRIMARY KEY,
+            title TEXT,
+            model TEXT,
+            created_at BIGINT NOT NULL
+        )"
+        .to_string(),
+        "CREATE TABLE IF NOT EXISTS gemini_messages (
+            conversation_id TEXT NOT NULL REFERENCES gemini_conversations(id),
+            seq BIGINT NOT NULL,
+            role TEXT NOT NULL,
+            content TEXT NOT NULL,
+            created_at BIGINT NOT NULL,
+            PRIMARY KEY (conversation_id, seq)
+        )"
+        .to_string(),
+        format!(
+            "CREATE TABLE IF NOT EXISTS gemini_tool_calls (
+            id {serial},
+            conversation_id TEXT NOT NULL REFERENCES gemini_conversations(id),
+            tool_name TEXT NOT NULL,
+            parameters TEXT NOT NULL,
+            status TEXT NOT NULL,
+            output TEXT NOT NULL,
+            created_at BIGINT NOT NULL
+        )"
+        ),
+        format!(
+            "CREATE TABLE IF NOT EXISTS gemini_usage (
+            id {serial},
+            conversation_id TEXT NOT NULL REFERENCES gemini_conversations(id),
+            seq BIGINT,
+            model TEXT NOT NULL,
+            prompt_tokens BIGINT NOT NULL,
+            cached_tokens BIGINT NOT NULL DEFAULT 0,
+            output_tokens BIGINT NOT NULL,
+            total_tokens BIGINT NOT NULL,
+            created_at BIGINT NOT NULL
+        )"
+        ),
+    ]
+}
+
+/// A [`ConversationStore`] persisting to Postgres.
+#[derive(Debug, Clone)]
+pub struct PgStore {
+    pool: sqlx::PgPool,
+}
+
+/// A [`ConversationStore`] persisting to SQLite.
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    pool: sqlx::SqlitePool,
+}
+
+fn storage_error(e: sqlx::Error) -> GeminiError {
+    GeminiError::StorageError(e.to_string())
+}
+
+/// Stored as `BIGINT`, which both databases read back as `i64`.
+fn int(n: u64) -> i64 {
+    n as i64
+}
+
+type ConversationRow = (String, Option<String>, Option<String>, i64);
+type MessageRow = (i64, String, String, i64);
+type ToolCallRow = (String, String, String, String, i64);
+type UsageRow = (Option<i64>, String, i64, i64, i64, i64, i64);
+
+fn conversation(row: ConversationRow) -> Conversation {
+    let (id, title, model, created_at) = row;
+    Conversation {
+        id,
+        title,
+        model,
+        created_at: created_at as u64,
+    }
+}
+
+fn message(conversation_id: &str, row: MessageRow) -> Result<StoredMessage, GeminiError> {
+    let (seq, role, content, created_at) = row;
+    Ok(StoredMessage {
+        conversation_id: conversation_id.to_string(),
+        seq: seq as u64,
+        role: Role::parse(&role)
+            .ok_or_else(|| GeminiError::StorageError(format!("unknown role `{role}`")))?,
+        content,
+        created_at: created_at as u64,
+    })
+}
+
+fn tool_call(conversation_id: &str, row: ToolCallRow) -> Result<ToolTranscript, GeminiError> {
+    let (tool_name, parameters, status, output, created_at) = row;
+    Ok(ToolTranscript {
+        conversation_id: conversation_id.to_string(),
+        tool_name,
+        parameters: serde_json::from_str(&parameters)
+            .map_err(|e| GeminiError::StorageError(e.to_string()))?,
+        status,
+        output,
+        created_at: created_at as u64,
+    })
+}
+
+fn usage(conversation_id: &str, row: UsageRow) -> UsageRecord {
+    let (seq, model, prompt_tokens, cached_tokens, output_tokens, total_tokens, created_at) = row;
+    UsageRecord {
+        conversation_id: conversation_id.to_string(),
+        seq: seq.map(|seq| seq as u64),
+        model,
+        prompt_tokens: prompt_tokens as u64,
+        cached_tokens: cached_tokens as u64,
+        output_tokens: output_tokens as u64,
+        total_tokens: total_tokens as u64,
+        created_at: created_at as u64,
+    }
+}
+
+/// Implements the store for a pool type. Both drivers accept `$n` placeholders, so the SQL is
+/// shared; only the pool and the DDL differ.
+macro_rules! sqlx_store {
+    ($store:ident, $pool:ty, $dialect:expr) => {
+        impl $store {
+            /// A store using the tables in `pool`'s database.
+            pub fn new(pool: $pool) -> Self {
+                Self { pool }
+            }
+
+            /// The underlying pool.
+            pub fn pool(&self) -> &$pool {
+                &self.pool
+            }
+
+            /// Create the tables if they do not exist yet.
+            ///
+            /// # Errors
+            ///
+            /// Returns [`GeminiError::StorageError`] if a statement fails.
+            pub async fn migrate(&self) -> Result<(), GeminiError> {
+                for statement in schema($dialect) {
+                    sqlx::query(&statement)
+                        .execute(&self.pool)
+                        .await
+                        .map_err(storage_error)?;
+                }
+                Ok(())
+            }
+        }
+
+        impl ConversationStore for $store {
+            fn put_conversation<'a>(
+                &'a self,
+                conversation: &'a Conversation,
+            ) -> BoxFuture<'a, Result<(), GeminiError>> {
+                Box::pin(async move {
+                    sqlx::query(
+                        "INSERT INTO gemini_conversations (id, title, model, created_at) \
+                         VALUES ($1, $2, $3, $4) \
+                         ON CONFLICT (id) DO UPDATE \
+                         SET title = excluded.title, model = excluded.model",
+                    )
+                    .bind(&conversation.id)
+                    .bind(&conversation.title)
+                    .bind(&conversation.model)
+                    .bind(int(conversation.created_at))
+                    .execute(&self.pool)
+                    .await
+                    .map_err(storage_error)?;
+                    Ok(())
+                })
+            }
+
+            fn get_conversation<'a>(
+                &'a self,
+                id: &'a str,
+            ) -> BoxFuture<'a, Result<Option<Conversation>, GeminiError>> {
+                Box::pin(async move {
+                    let row: Option<ConversationRow> = sqlx::query_as(
+                        "SELECT id, title, model, created_at FROM gemini_conversations \
+                         WHERE id = $1",
+                    )
+                    .bind(id)
+                    .fetch_optional(&self.pool)
+                    .await
+                    .map_err(storage_error)?;
+                    Ok(row.map(conversation))
+                })
+            }
+
+            fn list_conversations(
+                &self,
+            ) -> BoxFuture<'_, Result<Vec<Conversation>, GeminiError>> {
+                Box::pin(async move {
+                    let rows: Vec<ConversationRow> = sqlx::query_as(
+                        "SELECT id, title, model, created_at FROM gemini_conversations \
+                         ORDER BY created_at, id",
+                    )
+                    .fetch_all(&self.pool)
+                    .await
+                    .map_err(storage_error)?;
+                    Ok(rows.into_iter().map(conversation).collect())
+                })
+            }
+
+            fn delete_conversation<'a>(
+                &'a self,
+                id: &'a str,
+            ) -> BoxFuture<'a, Result<(), GeminiError>> {
+                Box::pin(async move {
+                    let mut tx = self.pool.begin().await.map_err(storage_error)?;
+                    for statement in [
+                        "DELETE FROM gemini_messages WHERE conversation_id = $1",
+                        "DELETE FROM gemini_tool_calls WHERE conversation_id = $1",
+                        "DELETE FROM gemini_usage WHERE conversation_id = $1",
+                        "DELETE FROM gemini_conversations WHERE id = $1",
+                    ] {
+                        sqlx::query(statement)
+                            .bind(id)
+                            .execute(&mut *tx)
+                            .await
+                            .map_err(storage_error)?;
+                    }
+                    tx.commit().await.map_err(storage_error)
+                })
+            }
+
+            fn append_message<'a>(
+                &'a self,
+                message: &'a StoredMessage,
+            ) -> BoxFuture<'a, Result<(), GeminiError>> {
+                Box::pin(async move {
+                    sqlx::query(
+                        "INSERT INTO gemini_messages \
+                         (conversation_id, seq, role, content, created_at) \
+                         VALUES ($1, $2, $3, $4, $5)",
+                    )
+                    .bind(&message.conversation_id)
+                    .bind(int(message.seq))
+                    .bind(message.role.as_str())
+                    .bind(&message.content)
+                    .bind(int(message.created_at))
+                    .execute(&self.pool)
+                    .await
+                    .map_err(storage_error)?;
+                    Ok(())
+                })
+            }
+
+            fn messages<'a>(
+                &'a self,
+                conversation_id: &'a str,
+            ) -> BoxFuture<'a, Result<Vec<StoredMessage>, GeminiError>> {
+                Box::pin(async move {
+                    let rows: Vec<MessageRow> = sqlx::query_as(
+                        "SELECT seq, role, content, created_at FROM gemini_messages \
+                         WHERE conversation_id = $1 ORDER BY seq",
+                    )
+                    .bind(conversation_id)
+                    .fetch_all(&self.pool)
+                    .await
+                    .map_err(storage_error)?;
+                    rows.into_iter()
+                        .map(|row| message(conversation_id, row))
+                        .collect()
+                })
+            }
+
+            fn record_tool_call<'a>(
+                &'a self,
+                call: &'a ToolTranscript,
+            ) -> BoxFuture<'a, Result<(), GeminiError>> {
+                Box::pin(async move {
+                    sqlx::query(
+                        "INSERT INTO gemini_tool_calls \
+                         (conversation_id, tool_name, parameters, status, output, created_at) \
+                         VALUES ($1, $2, $3, $4, $5, $6)",
+                    )
+                    .bind(&call.conversation_id)
+                    .bind(&call.tool_name)
+                    .bind(call.parameters.to_string())
+                    .bind(&call.status)
+                    .bind(&call.output)
+                    .bind(int(call.created_at))
+                    .execute(&self.pool)
+                    .await
+                    .map_err(storage_error)?;
+                    Ok(())
+                })
+            }
+
+            fn tool_calls<'a>(
+                &'a self,
+                conversation_id: &'a str,
+            ) -> BoxFuture<'a, Result<Vec<ToolTranscript>, GeminiError>> {
+                Box::pin(async move {
+                    let rows: Vec<ToolCallRow> = sqlx::query_as(
+                        "SELECT tool_name, parameters, status, output, created_at \
+                         FROM gemini_tool_calls WHERE conversation_id = $1 ORDER BY id",
+                    )
+                    .bind(conversation_id)
+                    .fetch_all(&self.pool)
+                    .await
+                    .map_err(storage_error)?;
+                    rows.into_iter()
+                        .map(|row| tool_call(conversation_id, row))
+                        .collect()
+                })
+            }
+
+            fn record_usage<'a>(
+                &'a self,
+                record: &'a UsageRecord,
+            ) -> BoxFuture<'a, Result<(), GeminiError>> {
+                Box::pin(async move {
+                    sqlx::query(
+                        "INSERT INTO gemini_usage (conversation_id, seq, model, prompt_tokens, \
+                         cached_tokens, output_tokens, total_tokens, created_at) \
+                         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                    )
+                    .bind(&record.conversation_id)
+                    .bind(record.seq.map(int))
+                    .bind(&record.model)
+                    .bind(int(record.prompt_tokens))
+                    .bind(int(record.cached_tokens))
+                    .bind(int(record.output_tokens))
+                    .bind(int(record.total_tokens))
+                    .bind(int(record.created_at))
+                    .execute(&self.pool)
+                    .await
+                    .map_err(storage_error)?;
+                    Ok(())
+                })
+            }
+
+            fn usage<'a>(
+                &'a self,
+                conversation_id: &'a str,
+            ) -> BoxFuture<'a, Result<Vec<UsageRecord>, GeminiError>> {
+                Box::pin(async move {
+                    let rows: Vec<UsageRow> = sqlx::query_as(
+                        "SELECT seq, model, prompt_tokens, cached_tokens, output_tokens, \
+                         total_tokens, created_at \
+                         FROM gemini_usage WHERE conversation_id = $1 ORDER BY id",
+                    )
+                    .bind(conversation_id)
+                    .fetch_all(&self.pool)
+                    .await
+                    .map_err(storage_error)?;
+                    Ok(rows
+                        .into_iter()
+                        .map(|row| usage(conversation_id, row))
+                        .collect())
+                })
+            }
+        }
+    };
+}
+
+sqlx_store!(PgStore, sqlx::PgPool, SqlDialect::Postgres);
+sqlx_store!(SqliteStore, sqlx::SqlitePool, SqlDialect::Sqlite);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn store() -> SqliteStore {
+        // Every connection to `:memory:` opens a new database, so keep to one.
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let store = SqliteStore::new(pool);
+        store.migrate().await.unwrap();
+        store
+    }
+
+    async fn seed(store: &SqliteStore) {
+        store
+            .put_conversation(&Conversation::new("c1"))
+            .await
+            .unwrap();
+        let message = StoredMessage {
+            conversation_id: "c1".into(),
+            seq: 0,
+            role: Role::User,
+            content: "it's $1, isn't it?".into(),
+            created_at: 10,
+        };
+        store.append_message(&message).await.unwrap();
+        let record = UsageRecord {
+            conversation_id: "c1".into(),
+            seq: Some(1),
+            model: "gemini-2.5-pro".into(),
+            prompt_tokens: 100,
+            cached_tokens: 60,
+            output_tokens: 5,
+            total_tokens: 105,
+            created_at: 10,
+        };
+        store.record_usage(&record).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_round_trip() {
+        let store = store().await;
+        seed(&store).await;
+
+        let conversation = store.get_conversation("c1").await.unwrap().unwrap();
+        assert_eq!(conversation.title, None);
+        let messages = store.messages("c1").await.unwrap();
+        assert_eq!(messages[0].content, "it's $1, isn't it?");
+        assert_eq!(messages[0].role, Role::User);
+        let usage = store.usage("c1").await.unwrap();
+        assert_eq!((usage[0].seq, usage[0].cached_tokens), (Some(1), 60));
+
+        store.delete_conversation("c1").await.unwrap();
+        assert!(store.list_conversations().await.unwrap().is_empty());
+        assert!(store.messages("c1").await.unwrap().is_empty());
+        assert!(store.usage("c1").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_delete_removes_nothing() {
+        let store = store().await;
+        seed(&store).await;
+        sqlx::query(
+            "CREATE TRIGGER keep_conversations BEFORE DELETE ON gemini_conversations \
+             BEGIN SELECT RAISE(ABORT, 'kept'); END",
+        )
+        .execute(store.pool())
+        .await
+        .unwrap();
+
+        assert!(store.delete_conversation("c1").await.is_err());
+        assert_eq!(store.messages("c1").await.unwrap().len(), 1);
+        assert_eq!(store.usage("c1").await.unwrap().len(), 1);
+    }
+}
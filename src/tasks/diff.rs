@@ -0,0 +1,202 @@
+use super::Extractor;
+use crate::schema::JsonSchema;
+use crate::{Gemini, GeminiError};
+use serde::{Deserialize, Serialize};
+
+/// Default character budget for both sides of one comparison request.
+const DEFAULT_CHUNK_CHARS: usize = 24_000;
+
+/// Unchanged lines shown around the changed region.
+const CONTEXT_LINES: usize = 3;
+
+/// How a piece of meaning changed between the two versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// One difference in meaning or behavior between two texts.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, JsonSchema, Serialize)]
+pub struct SemanticChange {
+    pub kind: ChangeKind,
+    /// What the change is about, e.g. "session timeout" or "data retention period".
+    pub topic: String,
+    /// The old meaning, for removed and modified items.
+    pub before: Option<String>,
+    /// The new meaning, for added and modified items.
+    pub after: Option<String>,
+    /// The practical consequence of the change.
+    pub impact: String,
+}
+
+/// Compare two texts by meaning using the default [`SemanticDiff`].
+///
+/// Rewording, reformatting and reordering that do not change meaning are ignored.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+/// let changes = gemini_oxide::tasks::semantic_diff(
+///     "Sessions expire after 30 minutes of inactivity.",
+///     "Idle sessions are closed after one hour.",
+/// )
+/// .await?;
+/// for change in &changes {
+///     println!("{:?} {}: {}", change.kind, change.topic, change.impact);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns `GeminiError::ValidationFailed` if the model never produced a well-formed list of
+/// changes, or any error raised while running the CLI.
+pub async fn semantic_diff(
+    a: impl AsRef<str>,
+    b: impl AsRef<str>,
+) -> Result<Vec<SemanticChange>, GeminiError> {
+    SemanticDiff::new().run(a, b).await
+}
+
+/// Configurable semantic comparison of two versions of a text, e.g. a config file or a policy.
+///
+/// Identical texts yield no changes without calling the model. Lines shared at the start and
+/// end of both versions are trimmed down to a little context; if the remaining changed region
+/// still exceeds the character budget, both sides are split into the same number of line-aligned
+/// sections that are compared pairwise.
+pub struct SemanticDiff {
+    chunk_chars: usize,
+    extractor: Extractor<Vec<SemanticChange>>,
+}
+
+impl Default for SemanticDiff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SemanticDiff {
+    /// Create a comparer with a 24 000-character budget per request.
+    pub fn new() -> Self {
+        Self {
+            chunk_chars: DEFAULT_CHUNK_CHARS,
+            extractor: Extractor::new().instructions(
+                "The input contains two versions of a document, BEFORE and AFTER. List the \
+                 differences in meaning or behavior between them: rules, values, permissions, \
+                 obligations or defaults that were added, removed or modified. Ignore rewording, \
+                 formatting and reordering that do not change meaning. Return an empty list if \
+                 the meaning is unchanged.",
+            ),
+        }
+    }
+
+    /// Character budget for both sides of one comparison request.
+    #[must_use]
+    pub fn chunk_chars(mut self, chars: usize) -> Self {
+        self.chunk_chars = chars.max(1);
+        self
+    }
+
+    /// Customize each underlying request (model, binary path, ...).
+    #[must_use]
+    pub fn configure(mut self, f: impl Fn(Gemini) -> Gemini + Send + Sync + 'static) -> Self {
+        self.extractor = self.extractor.configure(f);
+        self
+    }
+
+    /// Compare `a` (before) with `b` (after).
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::ValidationFailed` if the model never produced a well-formed list
+    /// of changes, or any error raised while running the CLI.
+    pub async fn run(
+        &self,
+        a: impl AsRef<str>,
+        b: impl AsRef<str>,
+    ) -> Result<Vec<SemanticChange>, GeminiError> {
+        let mut changes = Vec::new();
+        for (before, after) in sections(a.as_ref(), b.as_ref(), self.chunk_chars) {
+            changes.extend(self.extractor.run(format_input(&before, &after)).await?);
+        }
+        Ok(changes)
+    }
+}
+
+/// Pairs of (before, after) sections to compare, empty if the texts are identical.
+fn sections(a: &str, b: &str, max_chars: usize) -> Vec<(String, String)> {
+    if a == b {
+        return Vec::new();
+    }
+    let a: Vec<&str> = a.lines().collect();
+    let b: Vec<&str> = b.lines().collect();
+
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let start = prefix.saturating_sub(CONTEXT_LINES);
+    let a = &a[start..(a.len() - suffix + CONTEXT_LINES).min(a.len())];
+    let b = &b[start..(b.len() - suffix + CONTEXT_LINES).min(b.len())];
+
+    let total: usize = a.iter().chain(b).map(|l| l.len() + 1).sum();
+    let parts = total
+        .div_ceil(max_chars)
+        .clamp(1, a.len().max(b.len()).max(1));
+    (0..parts)
+        .map(|i| (slice(a, i, parts), slice(b, i, parts)))
+        .collect()
+}
+
+/// The `i`-th of `parts` roughly equal, line-aligned slices of `lines`, joined.
+fn slice(lines: &[&str], i: usize, parts: usize) -> String {
+    let from = lines.len() * i / parts;
+    let to = lines.len() * (i + 1) / parts;
+    lines[from..to].join("\n")
+}
+
+fn format_input(before: &str, after: &str) -> String {
+    format!("## BEFORE\n{before}\n\n## AFTER\n{after}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identical_texts_need_no_comparison() {
+        assert!(sections("a\nb", "a\nb", 100).is_empty());
+    }
+
+    #[test]
+    fn test_sections_trim_shared_lines_to_context() {
+        let a = "1\n2\n3\n4\n5\nold\n6\n7\n8\n9\n10";
+        let b = "1\n2\n3\n4\n5\nnew\n6\n7\n8\n9\n10";
+        assert_eq!(
+            sections(a, b, 1_000),
+            vec![(
+                "3\n4\n5\nold\n6\n7\n8".to_string(),
+                "3\n4\n5\nnew\n6\n7\n8".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_long_changes_are_split_pairwise() {
+        let a: Vec<String> = (0..40).map(|i| format!("a{i}")).collect();
+        let b: Vec<String> = (0..20).map(|i| format!("b{i}")).collect();
+        let pairs = sections(&a.join("\n"), &b.join("\n"), 100);
+        assert!(pairs.len() > 1);
+        assert!(pairs[0].0.starts_with("a0\n") && pairs[0].1.starts_with("b0\n"));
+        let rejoined: Vec<&str> = pairs.iter().map(|(x, _)| x.as_str()).collect();
+        assert_eq!(rejoined.join("\n"), a.join("\n"));
+    }
+}
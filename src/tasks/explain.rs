@@ -0,0 +1,292 @@
+use super::Extractor;
+use crate::schema::JsonSchema;
+use crate::{Gemini, GeminiError};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Lines of source shown on each side of a referenced line.
+const DEFAULT_RADIUS: usize = 5;
+
+/// Context files not referenced by the error are included in full up to this many lines.
+const MAX_UNREFERENCED_LINES: usize = 200;
+
+/// A file/line reference in an explanation.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, JsonSchema, Serialize)]
+pub struct SourceRef {
+    pub file: String,
+    pub line: u32,
+    /// Why this location matters.
+    pub note: String,
+}
+
+/// A concrete change that should resolve the error.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, JsonSchema, Serialize)]
+pub struct SuggestedFix {
+    /// What to change, in one or two sentences.
+    pub description: String,
+    /// The file to edit, if the fix is local to one place.
+    pub file: Option<String>,
+    /// The line to edit.
+    pub line: Option<u32>,
+    /// Replacement code or a unified diff, if the model proposed one.
+    pub patch: Option<String>,
+}
+
+/// A structured explanation of a compiler error or backtrace.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, JsonSchema, Serialize)]
+pub struct ErrorExplanation {
+    /// One-sentence summary of what went wrong.
+    pub summary: String,
+    /// The underlying cause, in plain language.
+    pub cause: String,
+    /// Suggested fixes, most likely first.
+    pub fixes: Vec<SuggestedFix>,
+    /// Source locations relevant to the error.
+    pub references: Vec<SourceRef>,
+}
+
+/// Explain a Rust compiler error or panic backtrace using the default [`ErrorExplainer`].
+///
+/// Source locations mentioned in `error` (`--> src/main.rs:10:5`, `at ./src/lib.rs:42:9`) are
+/// matched against `context_files`, and the surrounding lines are sent along with the error.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+/// let error = "error[E0382]: borrow of moved value: `v`\n  --> src/main.rs:4:20";
+/// let explanation = gemini_oxide::tasks::explain_error(error, ["src/main.rs"]).await?;
+/// for fix in &explanation.fixes {
+///     println!("{}", fix.description);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns `GeminiError::ValidationFailed` if the model never produced a well-formed
+/// explanation, or any error raised while running the CLI.
+pub async fn explain_error<I, P>(
+    error: impl Into<String>,
+    context_files: I,
+) -> Result<ErrorExplanation, GeminiError>
+where
+    I: IntoIterator<Item = P>,
+    P: Into<PathBuf>,
+{
+    ErrorExplainer::new().files(context_files).run(error).await
+}
+
+/// Configurable error explanation for developer tooling.
+pub struct ErrorExplainer {
+    files: Vec<PathBuf>,
+    radius: usize,
+    extractor: Extractor<ErrorExplanation>,
+}
+
+impl Default for ErrorExplainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ErrorExplainer {
+    /// Create an explainer with no context files and five lines of context per location.
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            radius: DEFAULT_RADIUS,
+            extractor: Extractor::new().instructions(
+                "The input is a Rust compiler error or panic backtrace followed by source \
+                 snippets. Explain the root cause for an experienced Rust developer, suggest \
+                 concrete fixes (most likely first) and reference the relevant file/line \
+                 locations. Use file paths and line numbers exactly as they appear in the input.",
+            ),
+        }
+    }
+
+    /// Add source files the error may refer to.
+    #[must_use]
+    pub fn files<I, P>(mut self, files: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.files.extend(files.into_iter().map(Into::into));
+        self
+    }
+
+    /// Lines of source shown on each side of a referenced line.
+    #[must_use]
+    pub fn radius(mut self, lines: usize) -> Self {
+        self.radius = lines;
+        self
+    }
+
+    /// Customize each underlying request (model, binary path, ...).
+    #[must_use]
+    pub fn configure(mut self, f: impl Fn(Gemini) -> Gemini + Send + Sync + 'static) -> Self {
+        self.extractor = self.extractor.configure(f);
+        self
+    }
+
+    /// Explain `error`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::ValidationFailed` if the model never produced a well-formed
+    /// explanation, or any error raised while running the CLI. Unreadable context files are
+    /// skipped.
+    pub async fn run(&self, error: impl Into<String>) -> Result<ErrorExplanation, GeminiError> {
+        let error = error.into();
+        let mut sources = Vec::new();
+        for path in &self.files {
+            if let Ok(text) = tokio::fs::read_to_string(path).await {
+                sources.push((path.clone(), text));
+            }
+        }
+        self.extractor
+            .run(format_input(&error, &sources, self.radius))
+            .await
+    }
+}
+
+/// A `file:line` location mentioned in an error or backtrace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Location {
+    pub(super) file: String,
+    pub(super) line: usize,
+}
+
+/// Every distinct `*.rs:line[:col]` location in `text`, in order of appearance.
+pub(super) fn locations(text: &str) -> Vec<Location> {
+    let mut found: Vec<Location> = Vec::new();
+    for token in text.split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | ',' | '`')) {
+        let Some(idx) = token.find(".rs:") else {
+            continue;
+        };
+        let file = token[..idx + 3].trim_start_matches("./");
+        let line: String = token[idx + 4..]
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect();
+        let Ok(line) = line.parse() else { continue };
+        let location = Location {
+            file: file.to_string(),
+            line,
+        };
+        if !file.is_empty() && !found.contains(&location) {
+            found.push(location);
+        }
+    }
+    found
+}
+
+/// Whether `path` is the file referred to as `reference` (which may be relative).
+///
+/// One may be a suffix of the other, but only at a `/`, so `lib.rs` does not match
+/// `src/stdlib.rs`.
+pub(super) fn same_file(path: &Path, reference: &str) -> bool {
+    let path = path.to_string_lossy();
+    let path = path.trim_start_matches("./");
+    let reference = reference.trim_start_matches("./");
+    let ends_at_separator = |long: &str, short: &str| {
+        long.strip_suffix(short)
+            .is_some_and(|prefix| prefix.ends_with('/'))
+    };
+    path == reference || ends_at_separator(path, reference) || ends_at_separator(reference, path)
+}
+
+/// Numbered lines `line - radius ..= line + radius` of `text`, marking `line` with `>`.
+pub(super) fn snippet(text: &str, line: usize, radius: usize) -> String {
+    numbered(text, line.saturating_sub(radius), line + radius, Some(line))
+}
+
+/// Lines `first ..= last` (1-based) of `text` with line numbers.
+fn numbered(text: &str, first: usize, last: usize, mark: Option<usize>) -> String {
+    let first = first.max(1);
+    text.lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l))
+        .skip(first - 1)
+        .take((last + 1).saturating_sub(first))
+        .map(|(n, l)| format!("{} {n:>5} | {l}", if Some(n) == mark { ">" } else { " " }))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_input(error: &str, sources: &[(PathBuf, String)], radius: usize) -> String {
+    let mut input = format!("## Error\n{}\n", error.trim_end());
+    let locations = locations(error);
+    for (path, text) in sources {
+        let referenced: Vec<&Location> = locations
+            .iter()
+            .filter(|loc| same_file(path, &loc.file))
+            .collect();
+        if referenced.is_empty() {
+            input.push_str(&format!("\n## {} (not referenced)\n", path.display()));
+            input.push_str(&numbered(text, 1, MAX_UNREFERENCED_LINES, None));
+            input.push('\n');
+        }
+        for loc in referenced {
+            input.push_str(&format!("\n## {}:{}\n", path.display(), loc.line));
+            input.push_str(&snippet(text, loc.line, radius));
+            input.push('\n');
+        }
+    }
+    input
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUSTC: &str = "error[E0382]: borrow of moved value: `v`\n --> src/main.rs:4:20\n  |\n\
+                         thread 'main' panicked at ./src/lib.rs:12:5:\n   1: app::run\n             at ./src/main.rs:4:20";
+
+    #[test]
+    fn test_locations_from_rustc_and_backtraces() {
+        assert_eq!(
+            locations(RUSTC),
+            vec![
+                Location {
+                    file: "src/main.rs".into(),
+                    line: 4
+                },
+                Location {
+                    file: "src/lib.rs".into(),
+                    line: 12
+                },
+            ]
+        );
+        assert!(same_file(Path::new("/repo/src/main.rs"), "src/main.rs"));
+        assert!(!same_file(Path::new("/repo/src/lib.rs"), "src/main.rs"));
+        assert!(same_file(Path::new("./main.rs"), "src/main.rs"));
+        assert!(!same_file(Path::new("lib.rs"), "src/stdlib.rs"));
+        assert!(!same_file(Path::new("/repo/src/mylib.rs"), "lib.rs"));
+    }
+
+    #[test]
+    fn test_snippet_marks_the_line() {
+        let text = "a\nb\nc\nd\ne";
+        assert_eq!(snippet(text, 2, 1), "      1 | a\n>     2 | b\n      3 | c");
+        assert_eq!(snippet(text, 5, 10).lines().count(), 5);
+    }
+
+    #[test]
+    fn test_input_contains_error_and_snippets() {
+        let sources = vec![
+            (
+                PathBuf::from("src/main.rs"),
+                "fn main() {\n    let v = vec![1];\n    drop(v);\n    println!(\"{:?}\", v);\n}"
+                    .to_string(),
+            ),
+            (PathBuf::from("Cargo.toml"), "[package]".to_string()),
+        ];
+        let input = format_input(RUSTC, &sources, 1);
+        assert!(input.starts_with("## Error\nerror[E0382]"));
+        assert!(input.contains("## src/main.rs:4\n      3 |     drop(v);\n>     4 |"));
+        assert!(input.contains("## Cargo.toml (not referenced)\n      1 | [package]"));
+    }
+}
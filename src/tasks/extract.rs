@@ -0,0 +1,244 @@
+use crate::schema::{self, JsonSchema};
+use crate::{Configure, Gemini, GeminiError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::marker::PhantomData;
+
+/// Extract a value of type `T` from free-form text using the default [`Extractor`] settings.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use gemini_oxide::schema::JsonSchema;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize, JsonSchema)]
+/// struct Invoice {
+///     number: String,
+///     total: f64,
+/// }
+///
+/// # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+/// let invoice: Invoice = gemini_oxide::tasks::extract("Invoice #42, total due $19.99").await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns `GeminiError::ValidationFailed` if no attempt produced a conforming value, or any
+/// error raised while running the CLI.
+pub async fn extract<T>(text: impl Into<String>) -> Result<T, GeminiError>
+where
+    T: DeserializeOwned + JsonSchema,
+{
+    Extractor::new().run(text).await
+}
+
+type Validator<T> = Box<dyn Fn(&T) -> Result<(), String> + Send + Sync>;
+
+/// Configurable structured extraction.
+///
+/// Combines the JSON Schema of `T`, optional few-shot examples and a strict "JSON only"
+/// instruction into a prompt. The reply is parsed, validated against the schema, deserialized
+/// and checked by an optional custom validator. Rejected replies are fed back to the model with
+/// the reason, up to `max_attempts` times.
+pub struct Extractor<T> {
+    configure: Option<Configure>,
+    instructions: Option<String>,
+    examples: Vec<(String, Value)>,
+    max_attempts: usize,
+    validator: Option<Validator<T>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for Extractor<T>
+where
+    T: DeserializeOwned + JsonSchema,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Extractor<T>
+where
+    T: DeserializeOwned + JsonSchema,
+{
+    /// Create an extractor with three attempts and no examples.
+    pub fn new() -> Self {
+        Self {
+            configure: None,
+            instructions: None,
+            examples: Vec::new(),
+            max_attempts: 3,
+            validator: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Customize each underlying request (model, binary path, ...).
+    #[must_use]
+    pub fn configure(mut self, f: impl Fn(Gemini) -> Gemini + Send + Sync + 'static) -> Self {
+        self.configure = Some(Box::new(f));
+        self
+    }
+
+    /// Add task-specific guidance, e.g. "Dates must be ISO-8601".
+    #[must_use]
+    pub fn instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.instructions = Some(instructions.into());
+        self
+    }
+
+    /// Add a few-shot example pairing an input with the expected output.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `output` cannot be serialized to JSON.
+    #[must_use]
+    pub fn example<E: Serialize>(mut self, input: impl Into<String>, output: &E) -> Self {
+        let output = serde_json::to_value(output).expect("example output must serialize to JSON");
+        self.examples.push((input.into(), output));
+        self
+    }
+
+    /// Maximum number of requests made before giving up. Values below 1 are treated as 1.
+    #[must_use]
+    pub fn max_attempts(mut self, attempts: usize) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Reject otherwise well-formed values, e.g. to enforce cross-field invariants.
+    ///
+    /// The error message is shown to the model on the next attempt.
+    #[must_use]
+    pub fn validate(
+        mut self,
+        f: impl Fn(&T) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.validator = Some(Box::new(f));
+        self
+    }
+
+    /// Run the extraction against `text`, which is piped to the CLI as context.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::ValidationFailed` if every attempt was rejected, or any error
+    /// raised while running the CLI.
+    pub async fn run(&self, text: impl Into<String>) -> Result<T, GeminiError> {
+        let text = text.into();
+        let schema = T::json_schema();
+        let mut feedback = None;
+        let mut last_error = String::new();
+
+        for _ in 0..self.max_attempts {
+            let mut request =
+                Gemini::new(self.build_prompt(&schema, feedback.as_deref())).context(text.clone());
+            if let Some(configure) = &self.configure {
+                request = configure(request);
+            }
+
+            let reply = request.json().await?.response;
+            match self.check(&schema, &reply) {
+                Ok(value) => return Ok(value),
+                Err(reason) => {
+                    feedback = Some(format!(
+                        "Your previous answer was rejected: {reason}\nPrevious answer:\n{reply}"
+                    ));
+                    last_error = reason;
+                }
+            }
+        }
+
+        Err(GeminiError::ValidationFailed(format!(
+            "no valid output after {} attempt(s): {last_error}",
+            self.max_attempts
+        )))
+    }
+
+    fn build_prompt(&self, schema: &Value, feedback: Option<&str>) -> String {
+        let mut prompt = String::from(
+            "Extract structured data from the input provided on standard input.\n\
+             Respond with a single JSON value that conforms to the JSON Schema below. \
+             Do not wrap it in Markdown and do not add any commentary.\n",
+        );
+        if let Some(instructions) = &self.instructions {
+            prompt.push_str(&format!("\n{instructions}\n"));
+        }
+        prompt.push_str(&format!("\nJSON Schema:\n{schema}\n"));
+        for (input, output) in &self.examples {
+            prompt.push_str(&format!(
+                "\nExample input:\n{input}\nExample output:\n{output}\n"
+            ));
+        }
+        if let Some(feedback) = feedback {
+            prompt.push_str(&format!("\n{feedback}\n"));
+        }
+        prompt
+    }
+
+    fn check(&self, schema: &Value, reply: &str) -> Result<T, String> {
+        let value = super::parse_json_payload(reply)
+            .map_err(|e| format!("reply is not valid JSON ({e})"))?;
+        schema::validate(schema, &value).map_err(|errors| errors.join("; "))?;
+        let parsed: T = serde_json::from_value(value)
+            .map_err(|e| format!("reply does not match the type ({e})"))?;
+        if let Some(validator) = &self.validator {
+            validator(&parsed)?;
+        }
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, JsonSchema, PartialEq)]
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    #[test]
+    fn test_prompt_contains_schema_examples_and_feedback() {
+        let extractor = Extractor::<Point>::new()
+            .instructions("Coordinates are integers.")
+            .example("one two", &serde_json::json!({ "x": 1, "y": 2 }));
+        let prompt = extractor.build_prompt(&Point::json_schema(), Some("try again"));
+        assert!(prompt.contains("\"required\":[\"x\",\"y\"]"));
+        assert!(prompt.contains("Coordinates are integers."));
+        assert!(prompt.contains("Example input:\none two"));
+        assert!(prompt.ends_with("try again\n"));
+    }
+
+    #[test]
+    fn test_check_applies_schema_and_custom_validator() {
+        let extractor = Extractor::<Point>::new().validate(|p| {
+            if p.x < 0 {
+                Err("x must be positive".into())
+            } else {
+                Ok(())
+            }
+        });
+        let schema = Point::json_schema();
+
+        assert_eq!(
+            extractor.check(&schema, "```json\n{\"x\": 1, \"y\": 2}\n```"),
+            Ok(Point { x: 1, y: 2 })
+        );
+        assert!(extractor
+            .check(&schema, "{\"x\": 1}")
+            .unwrap_err()
+            .contains("missing required property `y`"));
+        assert_eq!(
+            extractor.check(&schema, "{\"x\": -1, \"y\": 0}"),
+            Err("x must be positive".to_string())
+        );
+    }
+}
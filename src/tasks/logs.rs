@@ -0,0 +1,244 @@
+use super::Extractor;
+use crate::schema::JsonSchema;
+use crate::{Gemini, GeminiError};
+use futures_util::stream::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+/// Default number of lines per window.
+const DEFAULT_WINDOW: usize = 400;
+
+/// Default number of lines repeated from the end of one window at the start of the next.
+const DEFAULT_OVERLAP: usize = 20;
+
+/// The model's reply for one window.
+#[derive(Debug, Deserialize, JsonSchema)]
+struct Digest {
+    window_summary: String,
+    incident_summary: String,
+}
+
+/// The summary of one window of log lines.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct WindowSummary {
+    /// 0-based position of the window.
+    pub index: usize,
+    /// First log line covered (1-based, inclusive), including overlap.
+    pub first_line: usize,
+    /// Last log line covered (1-based, inclusive).
+    pub last_line: usize,
+    /// What happened in this window.
+    pub summary: String,
+    /// The rolling incident summary after this window.
+    pub incident: String,
+}
+
+/// Summarizes a large or growing log in sliding windows while maintaining a rolling incident
+/// summary.
+///
+/// Lines are buffered with [`feed`](Self::feed); each full window is sent to the model along
+/// with the current incident summary, which the model updates. Consecutive windows overlap so
+/// events at a boundary keep their context.
+///
+/// ```rust,no_run
+/// use gemini_oxide::tasks::LogSummarizer;
+///
+/// # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+/// let mut summarizer = LogSummarizer::new().window(200);
+/// for line in std::fs::read_to_string("app.log").unwrap().lines() {
+///     if let Some(window) = summarizer.feed(line).await? {
+///         println!("lines {}-{}: {}", window.first_line, window.last_line, window.summary);
+///     }
+/// }
+/// summarizer.flush().await?;
+/// println!("{}", summarizer.incident_summary());
+/// # Ok(())
+/// # }
+/// ```
+pub struct LogSummarizer {
+    window: usize,
+    overlap: usize,
+    extractor: Extractor<Digest>,
+    buffer: Vec<String>,
+    /// Line number of `buffer[0]`.
+    buffer_start: usize,
+    /// Lines in `buffer` already covered by a previous window.
+    carried: usize,
+    windows: usize,
+    incident: String,
+}
+
+impl Default for LogSummarizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogSummarizer {
+    /// Create a summarizer with 400-line windows overlapping by 20 lines.
+    pub fn new() -> Self {
+        Self {
+            window: DEFAULT_WINDOW,
+            overlap: DEFAULT_OVERLAP,
+            extractor: Extractor::new().instructions(
+                "The input contains the current incident summary and a new window of log lines. \
+                 `window_summary`: what happened in these lines (errors, anomalies, state \
+                 changes, with timestamps where available). `incident_summary`: the current \
+                 incident summary updated with anything significant from this window; keep it \
+                 concise, drop resolved noise, and keep it unchanged if nothing relevant happened.",
+            ),
+            buffer: Vec::new(),
+            buffer_start: 1,
+            carried: 0,
+            windows: 0,
+            incident: String::new(),
+        }
+    }
+
+    /// Number of lines per window, including overlap. Values below 1 are treated as 1.
+    #[must_use]
+    pub fn window(mut self, lines: usize) -> Self {
+        self.window = lines.max(1);
+        self
+    }
+
+    /// Number of lines repeated at the start of the next window. Clamped below the window size.
+    #[must_use]
+    pub fn overlap(mut self, lines: usize) -> Self {
+        self.overlap = lines;
+        self
+    }
+
+    /// Customize each underlying request (model, binary path, ...).
+    #[must_use]
+    pub fn configure(mut self, f: impl Fn(Gemini) -> Gemini + Send + Sync + 'static) -> Self {
+        self.extractor = self.extractor.configure(f);
+        self
+    }
+
+    /// The rolling incident summary so far (empty until the first window is summarized).
+    pub fn incident_summary(&self) -> &str {
+        &self.incident
+    }
+
+    /// Add a log line, summarizing a window once enough lines have accumulated.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised while summarizing. The window is kept and retried on the next
+    /// call.
+    pub async fn feed(
+        &mut self,
+        line: impl Into<String>,
+    ) -> Result<Option<WindowSummary>, GeminiError> {
+        self.buffer.push(line.into());
+        if self.buffer.len() < self.window {
+            return Ok(None);
+        }
+        self.summarize().await.map(Some)
+    }
+
+    /// Summarize any lines not yet covered by a window.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised while summarizing.
+    pub async fn flush(&mut self) -> Result<Option<WindowSummary>, GeminiError> {
+        if self.buffer.len() <= self.carried {
+            return Ok(None);
+        }
+        self.summarize().await.map(Some)
+    }
+
+    /// Consume a stream of log lines and return the final incident summary.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error raised while summarizing.
+    pub async fn run<S>(&mut self, lines: S) -> Result<String, GeminiError>
+    where
+        S: Stream<Item = String>,
+    {
+        futures_util::pin_mut!(lines);
+        while let Some(line) = lines.next().await {
+            self.feed(line).await?;
+        }
+        self.flush().await?;
+        Ok(self.incident.clone())
+    }
+
+    async fn summarize(&mut self) -> Result<WindowSummary, GeminiError> {
+        let first_line = self.buffer_start;
+        let last_line = self.buffer_start + self.buffer.len() - 1;
+        let digest = self
+            .extractor
+            .run(self.window_input(first_line, last_line))
+            .await?;
+
+        let summary = WindowSummary {
+            index: self.windows,
+            first_line,
+            last_line,
+            summary: digest.window_summary,
+            incident: digest.incident_summary,
+        };
+        self.windows += 1;
+        self.incident = summary.incident.clone();
+
+        let keep = self.overlap.min(self.window - 1).min(self.buffer.len());
+        self.buffer.drain(..self.buffer.len() - keep);
+        self.buffer_start = last_line + 1 - keep;
+        self.carried = keep;
+        Ok(summary)
+    }
+
+    fn window_input(&self, first_line: usize, last_line: usize) -> String {
+        let incident = if self.incident.is_empty() {
+            "(none yet)"
+        } else {
+            &self.incident
+        };
+        let mut input = format!(
+            "## Current incident summary\n{incident}\n\n## Log lines {first_line}-{last_line}"
+        );
+        if self.carried > 0 {
+            input.push_str(&format!(
+                " (the first {} already seen in the previous window)",
+                self.carried
+            ));
+        }
+        input.push('\n');
+        for line in &self.buffer {
+            input.push_str(line);
+            input.push('\n');
+        }
+        input
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_input_marks_overlap() {
+        let mut summarizer = LogSummarizer::new().window(3).overlap(1);
+        summarizer.buffer = vec!["c".into(), "d".into(), "e".into()];
+        summarizer.buffer_start = 3;
+        summarizer.carried = 1;
+        summarizer.incident = "db is down".into();
+        assert_eq!(
+            summarizer.window_input(3, 5),
+            "## Current incident summary\ndb is down\n\n\
+             ## Log lines 3-5 (the first 1 already seen in the previous window)\nc\nd\ne\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_feed_buffers_until_window_is_full() {
+        let mut summarizer = LogSummarizer::new().window(3);
+        assert_eq!(summarizer.feed("one").await.unwrap(), None);
+        assert_eq!(summarizer.feed("two").await.unwrap(), None);
+        assert_eq!(summarizer.buffer.len(), 2);
+        assert_eq!(summarizer.incident_summary(), "");
+    }
+}
@@ -0,0 +1,74 @@
+//! Ready-made, higher-level tasks built on top of the [`Gemini`](crate::Gemini) builder.
+//!
+//! Each task bundles the prompt engineering, output parsing and validation that a common
+//! use case needs into a single call.
+
+mod diff;
+mod explain;
+mod extract;
+mod logs;
+mod release;
+mod triage;
+
+pub use diff::{semantic_diff, ChangeKind, SemanticChange, SemanticDiff};
+pub use explain::{explain_error, ErrorExplainer, ErrorExplanation, SourceRef, SuggestedFix};
+pub use extract::{extract, Extractor};
+pub use logs::{LogSummarizer, WindowSummary};
+pub use release::{release_notes, CommitInfo, ReleaseNote, ReleaseNotes, ReleaseNotesGenerator};
+pub use triage::{
+    parse_test_failures, triage_test_failures, FailureGroup, Hypothesis, TestFailure, TestTriage,
+    TriageReport,
+};
+
+use serde_json::Value;
+
+/// Parse a JSON payload out of a model reply.
+///
+/// Models often wrap JSON in Markdown fences or add a sentence around it even when told not
+/// to, so this strips fences and falls back to the outermost `{...}` or `[...]` span.
+pub(crate) fn parse_json_payload(reply: &str) -> Result<Value, serde_json::Error> {
+    let trimmed = strip_code_fence(reply);
+    serde_json::from_str(trimmed).or_else(|err| {
+        let start = trimmed.find(['{', '[']);
+        let end = trimmed.rfind(['}', ']']);
+        match (start, end) {
+            (Some(start), Some(end)) if start < end => serde_json::from_str(&trimmed[start..=end]),
+            _ => Err(err),
+        }
+    })
+}
+
+/// Remove a surrounding Markdown code fence (with optional language tag), if present.
+pub(crate) fn strip_code_fence(text: &str) -> &str {
+    let trimmed = text.trim();
+    let Some(rest) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    let Some(body) = rest.strip_suffix("```") else {
+        return trimmed;
+    };
+    // Drop the info string (e.g. `json`) on the opening fence line.
+    match body.find('\n') {
+        Some(newline) => body[newline + 1..].trim(),
+        None => body.trim(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_json_payload_handles_fences_and_chatter() {
+        assert_eq!(
+            parse_json_payload("```json\n{\"a\": 1}\n```").unwrap(),
+            json!({ "a": 1 })
+        );
+        assert_eq!(
+            parse_json_payload("Sure! Here it is: [1, 2] Hope that helps.").unwrap(),
+            json!([1, 2])
+        );
+        assert!(parse_json_payload("no json here").is_err());
+    }
+}
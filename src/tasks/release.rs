@@ -0,0 +1,333 @@
+use super::Extractor;
+use crate::schema::JsonSchema;
+use crate::{Gemini, GeminiError};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// Default character budget for the commits sent in one request.
+const DEFAULT_CHUNK_CHARS: usize = 24_000;
+
+/// Each commit's diff is truncated to this many characters.
+const DEFAULT_DIFF_CHARS: usize = 4_000;
+
+/// Separates commits in the `git log` output.
+const RECORD: char = '\u{1e}';
+
+/// Separates the fields of a commit in the `git log` output.
+const FIELD: char = '\u{1f}';
+
+/// A commit in the release range.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CommitInfo {
+    pub hash: String,
+    pub subject: String,
+    /// The message body, without the subject line.
+    pub body: String,
+    /// The commit's diff, possibly truncated.
+    pub diff: String,
+}
+
+/// One entry in the release notes.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, JsonSchema, Serialize)]
+pub struct ReleaseNote {
+    /// A user-facing description of the change.
+    pub summary: String,
+    /// Hashes (full or abbreviated) of the commits behind the change.
+    pub commits: Vec<String>,
+}
+
+/// Release notes grouped by kind of change.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, JsonSchema, Serialize)]
+pub struct ReleaseNotes {
+    pub features: Vec<ReleaseNote>,
+    pub fixes: Vec<ReleaseNote>,
+    /// Changes that require users to update their code or configuration.
+    pub breaking_changes: Vec<ReleaseNote>,
+}
+
+impl ReleaseNotes {
+    /// Whether there are no entries at all.
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty() && self.fixes.is_empty() && self.breaking_changes.is_empty()
+    }
+
+    /// Render the notes as Markdown, omitting empty sections.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        for (title, notes) in [
+            ("Breaking changes", &self.breaking_changes),
+            ("Features", &self.features),
+            ("Fixes", &self.fixes),
+        ] {
+            if notes.is_empty() {
+                continue;
+            }
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("## {title}\n\n"));
+            for note in notes {
+                out.push_str(&format!("- {}\n", note.summary));
+            }
+        }
+        out
+    }
+
+    fn merge(&mut self, other: ReleaseNotes) {
+        self.features.extend(other.features);
+        self.fixes.extend(other.fixes);
+        self.breaking_changes.extend(other.breaking_changes);
+    }
+}
+
+/// Generate release notes for the commits in `from_tag..to_tag` of the git repository at `repo`,
+/// using the default [`ReleaseNotesGenerator`].
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+/// let notes = gemini_oxide::tasks::release_notes(".", "v0.1.0", "v0.2.0").await?;
+/// println!("{}", notes.to_markdown());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns `GeminiError::RuntimeError` if git fails (e.g. an unknown tag), or any error raised
+/// while generating the notes.
+pub async fn release_notes(
+    repo: impl Into<PathBuf>,
+    from_tag: &str,
+    to_tag: &str,
+) -> Result<ReleaseNotes, GeminiError> {
+    ReleaseNotesGenerator::new(repo).run(from_tag, to_tag).await
+}
+
+/// Configurable release-notes generation from git history.
+///
+/// Commits are sent in chunks that fit a character budget; the notes produced for each chunk
+/// are concatenated in history order.
+pub struct ReleaseNotesGenerator {
+    repo: PathBuf,
+    git: PathBuf,
+    chunk_chars: usize,
+    diff_chars: usize,
+    extractor: Extractor<ReleaseNotes>,
+}
+
+impl ReleaseNotesGenerator {
+    /// Generate notes for the git repository at `repo`.
+    pub fn new(repo: impl Into<PathBuf>) -> Self {
+        Self {
+            repo: repo.into(),
+            git: PathBuf::from("git"),
+            chunk_chars: DEFAULT_CHUNK_CHARS,
+            diff_chars: DEFAULT_DIFF_CHARS,
+            extractor: Extractor::new().instructions(
+                "The input is a list of git commits with their messages and diffs. Write release \
+                 notes for users of the project: group user-visible changes into features, fixes \
+                 and breaking changes, merge commits that describe the same change into one \
+                 entry, skip purely internal changes (refactors, CI, tests), and list the commit \
+                 hashes behind each entry.",
+            ),
+        }
+    }
+
+    /// Set the path to the `git` binary. Defaults to `"git"` on the PATH.
+    #[must_use]
+    pub fn git_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.git = path.into();
+        self
+    }
+
+    /// Character budget for the commits sent in one request.
+    #[must_use]
+    pub fn chunk_chars(mut self, chars: usize) -> Self {
+        self.chunk_chars = chars;
+        self
+    }
+
+    /// Truncate each commit's diff to this many characters. `0` sends messages only.
+    #[must_use]
+    pub fn diff_chars(mut self, chars: usize) -> Self {
+        self.diff_chars = chars;
+        self
+    }
+
+    /// Customize each underlying request (model, binary path, ...).
+    #[must_use]
+    pub fn configure(mut self, f: impl Fn(Gemini) -> Gemini + Send + Sync + 'static) -> Self {
+        self.extractor = self.extractor.configure(f);
+        self
+    }
+
+    /// The commits in `from..to`, oldest first, with truncated diffs.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::RuntimeError` if git cannot be started or fails.
+    pub async fn commits(&self, from: &str, to: &str) -> Result<Vec<CommitInfo>, GeminiError> {
+        let output = Command::new(&self.git)
+            .arg("log")
+            .arg("--reverse")
+            .arg("--no-color")
+            .arg(format!("--format={RECORD}%H{FIELD}%s{FIELD}%b{FIELD}"))
+            .arg(if self.diff_chars > 0 {
+                "--patch"
+            } else {
+                "--no-patch"
+            })
+            .arg(format!("{from}..{to}"))
+            .arg("--")
+            .current_dir(&self.repo)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|e| GeminiError::RuntimeError(format!("failed to run git: {e}")))?;
+
+        if !output.status.success() {
+            return Err(GeminiError::RuntimeError(format!(
+                "git log failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(parse_log(
+            &String::from_utf8_lossy(&output.stdout),
+            self.diff_chars,
+        ))
+    }
+
+    /// Generate release notes for `from..to`.
+    ///
+    /// An empty range yields empty notes without calling the model.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::RuntimeError` if git fails, `GeminiError::ValidationFailed` if the
+    /// model never produced well-formed notes, or any error raised while running the CLI.
+    pub async fn run(&self, from: &str, to: &str) -> Result<ReleaseNotes, GeminiError> {
+        let commits = self.commits(from, to).await?;
+        let mut notes = ReleaseNotes::default();
+        for chunk in chunks(&commits, self.chunk_chars) {
+            notes.merge(self.extractor.run(chunk).await?);
+        }
+        Ok(notes)
+    }
+}
+
+/// Parse `git log` output produced with the record/field separators used by
+/// [`ReleaseNotesGenerator::commits`].
+fn parse_log(stdout: &str, diff_chars: usize) -> Vec<CommitInfo> {
+    stdout
+        .split(RECORD)
+        .filter_map(|record| {
+            let mut fields = record.splitn(4, FIELD);
+            let hash = fields.next()?.trim();
+            if hash.is_empty() {
+                return None;
+            }
+            let subject = fields.next()?.trim();
+            let body = fields.next()?.trim();
+            let diff = fields.next().unwrap_or_default().trim();
+            Some(CommitInfo {
+                hash: hash.to_string(),
+                subject: subject.to_string(),
+                body: body.to_string(),
+                diff: truncate(diff, diff_chars),
+            })
+        })
+        .collect()
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if max_chars == 0 {
+        return String::new();
+    }
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}\n[diff truncated]", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+fn format_commit(commit: &CommitInfo) -> String {
+    let mut out = format!("## {} {}\n", commit.hash, commit.subject);
+    if !commit.body.is_empty() {
+        out.push_str(&commit.body);
+        out.push('\n');
+    }
+    if !commit.diff.is_empty() {
+        out.push_str(&format!("```diff\n{}\n```\n", commit.diff));
+    }
+    out
+}
+
+/// Group formatted commits into chunks of at most `max_chars`. A commit larger than the budget
+/// gets a chunk of its own.
+fn chunks(commits: &[CommitInfo], max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for commit in commits {
+        let text = format_commit(commit);
+        if !current.is_empty() && current.len() + text.len() > max_chars {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(&text);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str =
+        "\u{1e}aaa\u{1f}Add login\u{1f}Closes #1\n\u{1f}\n\ndiff --git a/x b/x\n+hello\n\
+                       \u{1e}bbb\u{1f}Fix crash\u{1f}\u{1f}\n";
+
+    #[test]
+    fn test_parse_log_splits_commits() {
+        let commits = parse_log(LOG, 10);
+        assert_eq!(commits.len(), 2);
+        assert_eq!(commits[0].hash, "aaa");
+        assert_eq!(commits[0].subject, "Add login");
+        assert_eq!(commits[0].body, "Closes #1");
+        assert_eq!(commits[0].diff, "diff --git\n[diff truncated]");
+        assert_eq!(commits[1].subject, "Fix crash");
+        assert_eq!(commits[1].diff, "");
+    }
+
+    #[test]
+    fn test_chunks_respect_budget() {
+        let commits = parse_log(LOG, 0);
+        assert_eq!(chunks(&commits, 1_000).len(), 1);
+        let split = chunks(&commits, 10);
+        assert_eq!(split.len(), 2);
+        assert!(split[1].starts_with("## bbb Fix crash"));
+    }
+
+    #[test]
+    fn test_markdown_skips_empty_sections() {
+        let notes = ReleaseNotes {
+            fixes: vec![ReleaseNote {
+                summary: "Fix crash on empty input".into(),
+                commits: vec!["bbb".into()],
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            notes.to_markdown(),
+            "## Fixes\n\n- Fix crash on empty input\n"
+        );
+    }
+}
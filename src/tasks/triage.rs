@@ -0,0 +1,398 @@
+use super::explain::{locations, snippet};
+use super::Extractor;
+use crate::schema::JsonSchema;
+use crate::{Gemini, GeminiError};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Lines of source shown on each side of a failure location.
+const DEFAULT_RADIUS: usize = 8;
+
+/// A single failed test parsed from test-runner output.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TestFailure {
+    /// The test's full name, e.g. `tests::parses_dates` or `suite::Class::method`.
+    pub name: String,
+    /// The captured failure output (panic message, assertion diff, ...).
+    pub message: String,
+}
+
+/// Failures that share the same first line of output, modulo numbers.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FailureGroup {
+    /// The normalized first line shared by the group.
+    pub signature: String,
+    /// The failing tests.
+    pub tests: Vec<String>,
+    /// The full output of the first failure in the group.
+    pub sample: String,
+}
+
+/// A structured guess about why a group of tests fails.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, JsonSchema, Serialize)]
+pub struct Hypothesis {
+    /// The tests this hypothesis explains.
+    pub tests: Vec<String>,
+    /// The file most likely to contain the bug, if identifiable.
+    pub suspect_file: Option<String>,
+    /// The likely cause, in one or two sentences.
+    pub likely_cause: String,
+    /// What a developer should do next to confirm or fix it.
+    pub next_step: String,
+}
+
+/// The result of [`triage_test_failures`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TriageReport {
+    /// Failures grouped by their output, largest group first.
+    pub groups: Vec<FailureGroup>,
+    /// The model's hypotheses, roughly one per group.
+    pub hypotheses: Vec<Hypothesis>,
+}
+
+/// Triage the failures in `cargo test` or JUnit XML output using the default [`TestTriage`].
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+/// let output = std::fs::read_to_string("target/test-output.txt").unwrap();
+/// let report = gemini_oxide::tasks::triage_test_failures(output).await?;
+/// for hypothesis in &report.hypotheses {
+///     println!("{:?}: {}", hypothesis.suspect_file, hypothesis.likely_cause);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns `GeminiError::ValidationFailed` if the model never produced well-formed
+/// hypotheses, or any error raised while running the CLI.
+pub async fn triage_test_failures(output: impl Into<String>) -> Result<TriageReport, GeminiError> {
+    TestTriage::new().run(output).await
+}
+
+/// Configurable test-failure triage for CI bots.
+///
+/// Failures are parsed from the output, grouped, and sent to the model together with the test
+/// sources around each panic location (resolved relative to [`root`](Self::root)).
+pub struct TestTriage {
+    root: PathBuf,
+    radius: usize,
+    extractor: Extractor<Vec<Hypothesis>>,
+}
+
+impl Default for TestTriage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestTriage {
+    /// Create a triage that resolves source paths against the current directory.
+    pub fn new() -> Self {
+        Self {
+            root: PathBuf::from("."),
+            radius: DEFAULT_RADIUS,
+            extractor: Extractor::new().instructions(
+                "The input lists groups of failing tests with their output, followed by the \
+                 relevant source. Return a JSON array with one hypothesis per distinct root cause: \
+                 the tests it explains, the file most likely to contain the bug, the likely \
+                 cause and the next step a developer should take.",
+            ),
+        }
+    }
+
+    /// The directory source paths in the output are relative to, usually the workspace root.
+    #[must_use]
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = root.into();
+        self
+    }
+
+    /// Lines of source shown on each side of a failure location.
+    #[must_use]
+    pub fn radius(mut self, lines: usize) -> Self {
+        self.radius = lines;
+        self
+    }
+
+    /// Customize each underlying request (model, binary path, ...).
+    #[must_use]
+    pub fn configure(mut self, f: impl Fn(Gemini) -> Gemini + Send + Sync + 'static) -> Self {
+        self.extractor = self.extractor.configure(f);
+        self
+    }
+
+    /// Triage `output`. If no failures are found the model is not called.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::ValidationFailed` if the model never produced well-formed
+    /// hypotheses, or any error raised while running the CLI.
+    pub async fn run(&self, output: impl Into<String>) -> Result<TriageReport, GeminiError> {
+        let groups = group_failures(&parse_test_failures(&output.into()));
+        if groups.is_empty() {
+            return Ok(TriageReport::default());
+        }
+
+        let mut input = String::new();
+        for (i, group) in groups.iter().enumerate() {
+            input.push_str(&format!(
+                "## Group {} ({} test(s)): {}\n{}\n\n{}\n\n",
+                i + 1,
+                group.tests.len(),
+                group.signature,
+                group.tests.join("\n"),
+                group.sample.trim_end()
+            ));
+        }
+        let samples = groups
+            .iter()
+            .map(|g| g.sample.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        for loc in locations(&samples) {
+            if let Ok(text) = tokio::fs::read_to_string(self.root.join(&loc.file)).await {
+                input.push_str(&format!("## {}:{}\n", loc.file, loc.line));
+                input.push_str(&snippet(&text, loc.line, self.radius));
+                input.push_str("\n\n");
+            }
+        }
+
+        let hypotheses = self.extractor.run(input).await?;
+        Ok(TriageReport { groups, hypotheses })
+    }
+}
+
+/// Parse failed tests from `cargo test` (libtest) output or JUnit XML.
+pub fn parse_test_failures(output: &str) -> Vec<TestFailure> {
+    if output.contains("<testcase") {
+        parse_junit(output)
+    } else {
+        parse_libtest(output)
+    }
+}
+
+fn parse_libtest(output: &str) -> Vec<TestFailure> {
+    let mut failures: Vec<TestFailure> = Vec::new();
+    let mut current: Option<TestFailure> = None;
+
+    for line in output.lines() {
+        let header = line
+            .strip_prefix("---- ")
+            .and_then(|l| l.strip_suffix(" stdout ----"));
+        if let Some(name) = header {
+            failures.extend(current.take());
+            current = Some(TestFailure {
+                name: name.to_string(),
+                message: String::new(),
+            });
+        } else if line == "failures:" || line.starts_with("test result:") {
+            failures.extend(current.take());
+        } else if let Some(failure) = &mut current {
+            if !line.starts_with("note: run with `RUST_BACKTRACE") {
+                failure.message.push_str(line);
+                failure.message.push('\n');
+            }
+        }
+    }
+    failures.extend(current);
+    for failure in &mut failures {
+        failure.message = failure.message.trim().to_string();
+    }
+
+    // Tests that failed without captured output (e.g. `should_panic` or timeouts).
+    for line in output.lines() {
+        let name = line
+            .strip_prefix("test ")
+            .and_then(|l| l.strip_suffix(" ... FAILED"));
+        if let Some(name) = name {
+            if !failures.iter().any(|f| f.name == name) {
+                failures.push(TestFailure {
+                    name: name.to_string(),
+                    message: String::new(),
+                });
+            }
+        }
+    }
+    failures
+}
+
+fn parse_junit(xml: &str) -> Vec<TestFailure> {
+    let mut failures = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<testcase") {
+        rest = &rest[start..];
+        let open_end = rest.find('>').unwrap_or(rest.len());
+        let open = &rest[..open_end];
+        let (case, next) = if open.ends_with('/') {
+            (open, &rest[open_end..])
+        } else {
+            let end = rest.find("</testcase>").unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        rest = next.get(1..).unwrap_or_default();
+
+        let problem = case.find("<failure").or_else(|| case.find("<error"));
+        let Some(problem) = problem else { continue };
+        let name = attribute(open, "name").unwrap_or_default();
+        let name = match attribute(open, "classname") {
+            Some(class) if !class.is_empty() => format!("{class}::{name}"),
+            _ => name,
+        };
+
+        let element = &case[problem..];
+        let tag_end = element.find('>').unwrap_or(element.len());
+        let mut message = attribute(&element[..tag_end], "message").unwrap_or_default();
+        if !element[..tag_end].ends_with('/') {
+            let body = &element[(tag_end + 1).min(element.len())..];
+            let body = body.split("</").next().unwrap_or_default().trim();
+            if !body.is_empty() {
+                if !message.is_empty() {
+                    message.push('\n');
+                }
+                message.push_str(&unescape(body));
+            }
+        }
+        failures.push(TestFailure { name, message });
+    }
+    failures
+}
+
+/// The unescaped value of `name="..."` in an XML start tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let needle = format!(" {name}=\"");
+    let start = tag.find(&needle)? + needle.len();
+    let end = tag[start..].find('"')? + start;
+    Some(unescape(&tag[start..end]))
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Group failures by the first meaningful line of output with digit runs normalized.
+fn group_failures(failures: &[TestFailure]) -> Vec<FailureGroup> {
+    let mut groups: Vec<FailureGroup> = Vec::new();
+    for failure in failures {
+        let signature = signature(&failure.message);
+        match groups.iter_mut().find(|g| g.signature == signature) {
+            Some(group) => group.tests.push(failure.name.clone()),
+            None => groups.push(FailureGroup {
+                signature,
+                tests: vec![failure.name.clone()],
+                sample: failure.message.clone(),
+            }),
+        }
+    }
+    groups.sort_by_key(|g| std::cmp::Reverse(g.tests.len()));
+    groups
+}
+
+fn signature(message: &str) -> String {
+    // The "panicked at" line names the test's own location, so the reason is on the next line.
+    let line = message
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with("thread '"))
+        .unwrap_or("(no output)");
+    let mut out = String::with_capacity(line.len());
+    let mut in_digits = false;
+    for c in line.chars() {
+        if c.is_ascii_digit() {
+            if !in_digits {
+                out.push('N');
+            }
+            in_digits = true;
+        } else {
+            out.push(c);
+            in_digits = false;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARGO: &str = "running 3 tests
+test tests::ok ... ok
+test tests::a ... FAILED
+test tests::b ... FAILED
+test tests::c ... FAILED
+
+failures:
+
+---- tests::a stdout ----
+
+thread 'tests::a' panicked at src/lib.rs:10:9:
+index out of bounds: the len is 3 but the index is 7
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+---- tests::b stdout ----
+
+thread 'tests::b' panicked at src/lib.rs:20:9:
+index out of bounds: the len is 0 but the index is 12
+
+failures:
+    tests::a
+    tests::b
+    tests::c
+
+test result: FAILED. 1 passed; 3 failed";
+
+    const JUNIT: &str = r#"<testsuite name="s">
+  <testcase classname="api" name="login_works"/>
+  <testcase classname="api" name="logout_fails"><failure message="expected 200 &amp; got 500">stack &lt;here&gt;</failure></testcase>
+  <testcase name="errors"><error message="boom"/></testcase>
+</testsuite>"#;
+
+    #[test]
+    fn test_parse_libtest_output() {
+        let failures = parse_test_failures(CARGO);
+        let names: Vec<&str> = failures.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["tests::a", "tests::b", "tests::c"]);
+        assert!(failures[0]
+            .message
+            .starts_with("thread 'tests::a' panicked"));
+        assert!(!failures[0].message.contains("RUST_BACKTRACE"));
+        assert!(failures[2].message.is_empty());
+    }
+
+    #[test]
+    fn test_parse_junit_output() {
+        let failures = parse_test_failures(JUNIT);
+        assert_eq!(
+            failures,
+            vec![
+                TestFailure {
+                    name: "api::logout_fails".into(),
+                    message: "expected 200 & got 500\nstack <here>".into(),
+                },
+                TestFailure {
+                    name: "errors".into(),
+                    message: "boom".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_groups_by_normalized_message() {
+        let groups = group_failures(&parse_test_failures(CARGO));
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            groups[0].signature,
+            "index out of bounds: the len is N but the index is N"
+        );
+        assert_eq!(groups[0].tests, ["tests::a", "tests::b"]);
+        assert_eq!(groups[1].signature, "(no output)");
+    }
+}
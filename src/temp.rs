@@ -0,0 +1,61 @@
+//! Temporary files handed to the CLI.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A file in its own directory under the system temp dir, both deleted when dropped.
+///
+/// On unix, the directory and the file are only accessible to the current user.
+#[derive(Debug)]
+pub(crate) struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Write `contents` to a new file named `contents{suffix}` in a new, uniquely named
+    /// directory.
+    pub(crate) fn create(suffix: &str, contents: &[u8]) -> io::Result<Self> {
+        Self::create_named(&format!("contents{suffix}"), contents)
+    }
+
+    /// Write `contents` to a new file named `name` in a new, uniquely named directory.
+    pub(crate) fn create_named(name: &str, contents: &[u8]) -> io::Result<Self> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "gemini-oxide-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&dir)?;
+        let file = Self {
+            path: dir.join(name),
+        };
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        io::Write::write_all(&mut options.open(&file.path)?, contents)?;
+        Ok(file)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The directory holding only this file.
+    pub(crate) fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or(&self.path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        let _ = std::fs::remove_dir(self.dir());
+    }
+}
@@ -0,0 +1,350 @@
+//! Fault injection for testing resilience paths.
+//!
+//! A [`FaultyCli`] installs a stand-in `gemini` executable that answers every request with a
+//! fixed response, shaped for whichever output format the request asks for, and fails in the
+//! ways the real CLI does: a slow first byte, output cut off mid-stream, warnings on stderr, a
+//! non-zero exit after partial output, and lines of stream output that are not valid JSON.
+//! Point a request at it with [`Gemini::bin_path`](crate::Gemini::bin_path) and the SDK runs
+//! its real process handling against the failure.
+//!
+//! The executable is a shell script, so this module is only available on unix.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::testing::FaultyCli;
+//! use gemini_oxide::{Gemini, GeminiError};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let cli = FaultyCli::new()
+//!     .response("Partial answer that never finishes")
+//!     .slow_first_byte(Duration::from_millis(500))
+//!     .truncate_after(20)
+//!     .exit_with(1, "Error: connection reset")
+//!     .install()?;
+//!
+//! let result = Gemini::new("Hi").bin_path(cli.path()).text().await;
+//! assert!(matches!(result, Err(GeminiError::RuntimeError(_))));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::temp::TempFile;
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::Duration;
+
+/// The line [`FaultyCli::malformed_line`] inserts: a stream event cut off mid-object.
+pub const MALFORMED_LINE: &str = r#"{"type":"message","role":"model","content":"unterm"#;
+
+/// Ends the heredocs holding the canned output; never a line of the output itself.
+const DELIMITER: &str = "GEMINI_OXIDE_FAULTY_CLI_EOF";
+
+/// A stand-in CLI that fails in configurable ways.
+#[derive(Debug, Clone)]
+pub struct FaultyCli {
+    response: String,
+    first_byte_delay: Duration,
+    truncate_after: Option<usize>,
+    stderr: Vec<String>,
+    exit_code: i32,
+    stderr_trailer: Option<String>,
+    malformed_lines: BTreeSet<usize>,
+}
+
+impl Default for FaultyCli {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FaultyCli {
+    /// A CLI that answers with a placeholder and injects no faults.
+    pub fn new() -> Self {
+        Self {
+            response: "This is a response from a faulty CLI.".to_string(),
+            first_byte_delay: Duration::ZERO,
+            truncate_after: None,
+            stderr: Vec::new(),
+            exit_code: 0,
+            stderr_trailer: None,
+            malformed_lines: BTreeSet::new(),
+        }
+    }
+
+    /// The model's answer to every request. Streams deliver it one word per `Message` delta.
+    #[must_use]
+    pub fn response(mut self, response: impl Into<String>) -> Self {
+        self.response = response.into();
+        self
+    }
+
+    /// Wait this long before writing anything to stdout.
+    #[must_use]
+    pub fn slow_first_byte(mut self, delay: Duration) -> Self {
+        self.first_byte_delay = delay;
+        self
+    }
+
+    /// Stop writing stdout after `bytes` bytes, possibly in the middle of a line.
+    #[must_use]
+    pub fn truncate_after(mut self, bytes: usize) -> Self {
+        self.truncate_after = Some(bytes);
+        self
+    }
+
+    /// Write `line` to stderr before any output, as the CLI does with warnings. May be called
+    /// more than once.
+    #[must_use]
+    pub fn stderr_noise(mut self, line: impl Into<String>) -> Self {
+        self.stderr.push(line.into());
+        self
+    }
+
+    /// Write `message` to stderr after the output, then exit with `code`. Combined with
+    /// [`truncate_after`](Self::truncate_after), the CLI fails after partial output.
+    #[must_use]
+    pub fn exit_with(mut self, code: i32, message: impl Into<String>) -> Self {
+        self.exit_code = code;
+        self.stderr_trailer = Some(message.into());
+        self
+    }
+
+    /// In stream output, write [`MALFORMED_LINE`] before the event at `index` (the `Init`
+    /// event is 0). Indexes past the last event put it at the end. May be called more than
+    /// once.
+    #[must_use]
+    pub fn malformed_line(mut self, index: usize) -> Self {
+        self.malformed_lines.insert(index);
+        self
+    }
+
+    /// Write the CLI to a new temporary executable, deleted when the returned handle is
+    /// dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns any I/O error from creating the file.
+    pub fn install(&self) -> io::Result<InstalledCli> {
+        let file = TempFile::create(".sh", self.script().as_bytes())?;
+        std::fs::set_permissions(file.path(), std::fs::Permissions::from_mode(0o700))?;
+        Ok(InstalledCli { file })
+    }
+
+    /// The shell script printing the canned output for the requested `--output-format`.
+    fn script(&self) -> String {
+        let mut script = String::from(
+            "#!/bin/sh\nformat=text\nprevious=\nfor arg do\n    \
+             [ \"$previous\" = --output-format ] && format=$arg\n    \
+             previous=$arg\ndone\n",
+        );
+        for line in &self.stderr {
+            script.push_str(&format!("printf '%s\\n' {} >&2\n", quote(line)));
+        }
+        if !self.first_byte_delay.is_zero() {
+            script.push_str(&format!("sleep {}\n", self.first_byte_delay.as_secs_f64()));
+        }
+        let truncate = match self.truncate_after {
+            Some(bytes) => format!(" | head -c {bytes}"),
+            None => String::new(),
+        };
+        script.push_str("case \"$format\" in\n");
+        for (format, output) in [
+            ("stream-json", self.stream_output()),
+            ("json", self.json_output()),
+            ("*", format!("{}\n", self.response)),
+        ] {
+            script.push_str(&format!(
+                "{format})\n    cat <<'{DELIMITER}'{truncate}\n{output}{DELIMITER}\n    ;;\n"
+            ));
+        }
+        script.push_str("esac\n");
+        if let Some(message) = &self.stderr_trailer {
+            script.push_str(&format!("printf '%s\\n' {} >&2\n", quote(message)));
+        }
+        script.push_str(&format!("exit {}\n", self.exit_code));
+        script
+    }
+
+    fn json_output(&self) -> String {
+        let output = json!({
+            "response": self.response,
+            "stats": {
+                "models": {},
+                "tools": { "totalCalls": 0, "totalSuccess": 0, "totalFail": 0 },
+                "files": { "totalLinesAdded": 0, "totalLinesRemoved": 0 },
+            },
+        });
+        format!("{output}\n")
+    }
+
+    fn stream_output(&self) -> String {
+        let mut events = vec![json!({
+            "type": "init",
+            "session_id": "faulty-session",
+            "model": "faulty-model",
+            "timestamp": "",
+        })];
+        events.extend(self.response.split_inclusive(' ').map(|word| {
+            json!({
+                "type": "message",
+                "role": "model",
+                "content": word,
+                "delta": true,
+                "timestamp": "",
+            })
+        }));
+        events.push(json!({ "type": "result", "status": "success", "stats": {}, "timestamp": "" }));
+
+        let mut output = String::new();
+        let count = events.len();
+        for (i, event) in events.into_iter().enumerate() {
+            if self.malformed_lines.contains(&i) {
+                output.push_str(MALFORMED_LINE);
+                output.push('\n');
+            }
+            output.push_str(&event.to_string());
+            output.push('\n');
+        }
+        if self.malformed_lines.range(count..).next().is_some() {
+            output.push_str(MALFORMED_LINE);
+            output.push('\n');
+        }
+        output
+    }
+}
+
+/// A [`FaultyCli`] written to disk.
+#[derive(Debug)]
+pub struct InstalledCli {
+    file: TempFile,
+}
+
+impl InstalledCli {
+    /// The executable, for [`Gemini::bin_path`](crate::Gemini::bin_path).
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+}
+
+/// `text` as a single-quoted shell word.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Gemini, GeminiError, StreamEvent};
+    use futures_util::StreamExt;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_clean_cli_answers_every_format() {
+        let cli = FaultyCli::new()
+            .response("It's fine")
+            .stderr_noise("[WARN] Skipping unreadable directory")
+            .install()
+            .unwrap();
+        let text = Gemini::new("Hi").bin_path(cli.path()).text().await.unwrap();
+        assert_eq!(text, "It's fine");
+        let json = Gemini::new("Hi").bin_path(cli.path()).json().await.unwrap();
+        assert_eq!(json.response, "It's fine");
+
+        let stream = Gemini::new("Hi").bin_path(cli.path()).stream().unwrap();
+        let events: Vec<_> = stream.collect().await;
+        let deltas: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                Ok(StreamEvent::Message { content, .. }) => Some(content.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(deltas, ["It's ", "fine"]);
+        assert!(matches!(
+            events.last(),
+            Some(Ok(StreamEvent::Result { .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_slow_first_byte_delays_output() {
+        let cli = FaultyCli::new()
+            .slow_first_byte(Duration::from_millis(300))
+            .install()
+            .unwrap();
+        let start = Instant::now();
+        Gemini::new("Hi").bin_path(cli.path()).text().await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn test_truncated_json_fails_to_parse() {
+        let cli = FaultyCli::new().truncate_after(10).install().unwrap();
+        let result = Gemini::new("Hi").bin_path(cli.path()).json().await;
+        assert!(matches!(result, Err(GeminiError::JsonParseFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn test_exit_after_partial_output_reports_stderr() {
+        let cli = FaultyCli::new()
+            .truncate_after(5)
+            .exit_with(1, "Error: connection reset")
+            .install()
+            .unwrap();
+        match Gemini::new("Hi").bin_path(cli.path()).text().await {
+            Err(GeminiError::RuntimeError(message)) => {
+                assert!(message.contains("connection reset"));
+            }
+            other => panic!("expected a runtime error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_reports_stderr_of_failed_cli() {
+        let cli = FaultyCli::new()
+            .response("Half an answer")
+            .exit_with(2, "Error: quota exceeded")
+            .install()
+            .unwrap();
+        let stream = Gemini::new("Hi").bin_path(cli.path()).stream().unwrap();
+        let events: Vec<_> = stream.collect().await;
+        assert!(matches!(
+            events[events.len() - 2],
+            Ok(StreamEvent::Result { .. })
+        ));
+        match events.last() {
+            Some(Err(GeminiError::RuntimeError(stderr))) => {
+                assert!(stderr.contains("quota exceeded"));
+            }
+            other => panic!("expected a runtime error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_survives_stderr_flood() {
+        // Far more than a pipe buffer, written before any output.
+        let mut cli = FaultyCli::new();
+        for _ in 0..512 {
+            cli = cli.stderr_noise("[DEBUG] ".repeat(64));
+        }
+        let cli = cli.install().unwrap();
+        let stream = Gemini::new("Hi").bin_path(cli.path()).stream().unwrap();
+        let events: Vec<_> = tokio::time::timeout(Duration::from_secs(10), stream.collect())
+            .await
+            .expect("stream stalled on a full stderr pipe");
+        assert!(events.iter().all(Result::is_ok));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_line_interrupts_stream() {
+        let cli = FaultyCli::new().malformed_line(1).install().unwrap();
+        let stream = Gemini::new("Hi").bin_path(cli.path()).stream().unwrap();
+        let events: Vec<_> = stream.collect().await;
+        assert!(matches!(events[0], Ok(StreamEvent::Init { .. })));
+        assert!(matches!(events[1], Err(GeminiError::JsonParseFailed(_))));
+        assert_eq!(events.len(), 2);
+    }
+}
@@ -0,0 +1,455 @@
+//! Collected stream runs and their timing.
+//!
+//! [`Gemini::transcript`](crate::Gemini::transcript) drives a stream to completion and returns
+//! a [`RunTranscript`]: every event, the concatenated model text, and a [`StreamTiming`] with
+//! time-to-first-event, time-to-first-text, total duration and throughput. The same timings are
+//! reported to the [metrics sink](crate::metrics) of every stream.
+//!
+//! For long runs, [`Gemini::transcript_checkpoint`](crate::Gemini::transcript_checkpoint)
+//! appends the events and the latest timing to a JSON Lines file as the stream progresses, so
+//! a crash of the host process does not lose the record of what the agent already did.
+//! [`RunTranscript::recover`] (also reachable as [`Transcript::recover`]) reads such a file
+//! back.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::transcript::Transcript;
+//!
+//! let transcript = Transcript::recover("run.jsonl").unwrap();
+//! if !transcript.is_complete() {
+//!     println!("the run was interrupted after {} events", transcript.events.len());
+//! }
+//! ```
+
+use crate::metrics::{self, MetricsSink};
+use crate::{GeminiError, StreamEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Latency and throughput of a single stream.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct StreamTiming {
+    /// Time from launching the CLI to the first event.
+    pub time_to_first_event: Option<Duration>,
+    /// Time from launching the CLI to the first non-empty model message.
+    pub time_to_first_text: Option<Duration>,
+    /// Time from launching the CLI to the end of the stream.
+    pub duration: Duration,
+    /// Output tokens, as reported in the final `Result` stats or estimated from the text.
+    pub output_tokens: u64,
+    /// Whether `output_tokens` is an estimate (about four characters per token).
+    pub tokens_estimated: bool,
+}
+
+impl StreamTiming {
+    /// Output tokens per second, measured from the first text to the end of the stream.
+    ///
+    /// Falls back to the whole duration when the text arrived in a single event.
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        if self.output_tokens == 0 {
+            return None;
+        }
+        let generating = self
+            .time_to_first_text
+            .map(|ttft| self.duration.saturating_sub(ttft))
+            .filter(|d| !d.is_zero())
+            .unwrap_or(self.duration);
+        (!generating.is_zero()).then(|| self.output_tokens as f64 / generating.as_secs_f64())
+    }
+}
+
+/// Measures a stream as its events arrive.
+#[derive(Debug)]
+pub(crate) struct StreamTimer {
+    start: Instant,
+    first_event: Option<Duration>,
+    first_text: Option<Duration>,
+    text_chars: usize,
+    reported_tokens: Option<u64>,
+    model: Option<String>,
+}
+
+impl StreamTimer {
+    /// Start timing now.
+    pub(crate) fn start() -> Self {
+        Self {
+            start: Instant::now(),
+            first_event: None,
+            first_text: None,
+            text_chars: 0,
+            reported_tokens: None,
+            model: None,
+        }
+    }
+
+    /// Record the arrival of `event`.
+    pub(crate) fn observe(&mut self, event: &StreamEvent) {
+        let elapsed = self.start.elapsed();
+        self.first_event.get_or_insert(elapsed);
+        match event {
+            StreamEvent::Init { model, .. } => self.model = Some(model.clone()),
+            StreamEvent::Message { role, content, .. } if is_model_text(role, content) => {
+                self.first_text.get_or_insert(elapsed);
+                self.text_chars += content.chars().count();
+            }
+            StreamEvent::Result { stats, .. } => {
+                self.reported_tokens = output_tokens(&stats.to_value())
+            }
+            _ => {}
+        }
+    }
+
+    /// The model announced by the `Init` event, if any.
+    pub(crate) fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    /// Stop timing and summarize.
+    pub(crate) fn finish(&self) -> StreamTiming {
+        let (output_tokens, tokens_estimated) = match self.reported_tokens {
+            Some(tokens) => (tokens, false),
+            None => (self.text_chars.div_ceil(4) as u64, true),
+        };
+        StreamTiming {
+            time_to_first_event: self.first_event,
+            time_to_first_text: self.first_text,
+            duration: self.start.elapsed(),
+            output_tokens,
+            tokens_estimated,
+        }
+    }
+
+    /// Stop timing and report the summary to `sink`.
+    pub(crate) fn emit(&self, sink: &dyn MetricsSink, labels: &BTreeMap<String, String>) {
+        let timing = self.finish();
+        let labels = metrics::with_labels(&[("model", self.model().unwrap_or("unknown"))], labels);
+        if let Some(ttfe) = timing.time_to_first_event {
+            sink.observe(
+                metrics::STREAM_TIME_TO_FIRST_EVENT_MS,
+                millis(ttfe),
+                &labels,
+            );
+        }
+        if let Some(ttft) = timing.time_to_first_text {
+            sink.observe(metrics::STREAM_TIME_TO_FIRST_TEXT_MS, millis(ttft), &labels);
+        }
+        sink.observe(
+            metrics::STREAM_DURATION_MS,
+            millis(timing.duration),
+            &labels,
+        );
+        if let Some(tps) = timing.tokens_per_second() {
+            sink.observe(metrics::STREAM_TOKENS_PER_SECOND, tps, &labels);
+        }
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn is_model_text(role: &str, content: &str) -> bool {
+    role != "user" && !content.is_empty()
+}
+
+/// Output tokens from a `Result` event's stats.
+///
+/// Understands both the flat `output_tokens` field and per-model `tokens.candidates` counts.
+fn output_tokens(stats: &serde_json::Value) -> Option<u64> {
+    if let Some(tokens) = stats.get("output_tokens").and_then(|v| v.as_u64()) {
+        return Some(tokens);
+    }
+    let models = stats.get("models")?.as_object()?;
+    let counts: Vec<u64> = models
+        .values()
+        .filter_map(|m| m.get("tokens")?.get("candidates")?.as_u64())
+        .collect();
+    (!counts.is_empty()).then(|| counts.iter().sum())
+}
+
+/// Everything observed during one streamed run.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RunTranscript {
+    /// Every event, in arrival order.
+    pub events: Vec<StreamEvent>,
+    /// The model's text: all non-user message content concatenated.
+    pub text: String,
+    /// Latency and throughput of the run.
+    pub timing: StreamTiming,
+}
+
+/// Another name for [`RunTranscript`], so a checkpoint can be read back with
+/// `Transcript::recover(path)`.
+pub type Transcript = RunTranscript;
+
+impl RunTranscript {
+    pub(crate) fn new(events: Vec<StreamEvent>, timing: StreamTiming) -> Self {
+        let text = events
+            .iter()
+            .filter_map(|e| match e {
+                StreamEvent::Message { role, content, .. } if role != "user" => {
+                    Some(content.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+        Self {
+            events,
+            text,
+            timing,
+        }
+    }
+
+    /// The session ID announced by the CLI, if any.
+    pub fn session_id(&self) -> Option<&str> {
+        self.events.iter().find_map(|e| match e {
+            StreamEvent::Init { session_id, .. } => Some(session_id.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The model announced by the CLI, if any.
+    pub fn model(&self) -> Option<&str> {
+        self.events.iter().find_map(|e| match e {
+            StreamEvent::Init { model, .. } => Some(model.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Whether the run reached its final `Result` event.
+    pub fn is_complete(&self) -> bool {
+        self.events
+            .iter()
+            .any(|e| matches!(e, StreamEvent::Result { .. }))
+    }
+
+    /// Read back a transcript written by
+    /// [`Gemini::transcript_checkpoint`](crate::Gemini::transcript_checkpoint).
+    ///
+    /// The timing is the last one flushed. A final line cut short by a crash is ignored; use
+    /// [`is_complete`](Self::is_complete) to tell whether the run finished.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::StorageError` if the file cannot be read or a line other than the
+    /// last is not a checkpoint record.
+    pub fn recover(path: impl AsRef<Path>) -> Result<Self, GeminiError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            GeminiError::StorageError(format!("failed to read {}: {e}", path.display()))
+        })?;
+        let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+        let mut events = Vec::new();
+        let mut timing = None;
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str::<Record>(line) {
+                Ok(Record::Event { event }) => events.push(event),
+                Ok(Record::Timing { timing: t }) => timing = Some(t),
+                Err(_) if i + 1 == lines.len() && !text.ends_with('\n') => {}
+                Err(e) => {
+                    return Err(GeminiError::StorageError(format!(
+                        "{}:{}: invalid checkpoint record: {e}",
+                        path.display(),
+                        i + 1
+                    )))
+                }
+            }
+        }
+        let timing = timing.unwrap_or_else(|| {
+            let mut timer = StreamTimer::start();
+            events.iter().for_each(|e| timer.observe(e));
+            StreamTiming {
+                time_to_first_event: None,
+                time_to_first_text: None,
+                duration: Duration::ZERO,
+                ..timer.finish()
+            }
+        });
+        Ok(Self::new(events, timing))
+    }
+}
+
+/// One line of a checkpoint file.
+#[derive(Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum Record {
+    Event { event: StreamEvent },
+    Timing { timing: StreamTiming },
+}
+
+/// Appends a stream's events to a checkpoint file, flushing every `interval` and when dropped.
+pub(crate) struct Checkpointer {
+    path: PathBuf,
+    file: File,
+    pending: Vec<u8>,
+    timing: Option<StreamTiming>,
+    interval: Duration,
+    last_flush: Instant,
+}
+
+impl Checkpointer {
+    /// Create (or truncate) the checkpoint file at `path`.
+    pub(crate) fn create(path: &Path, interval: Duration) -> Result<Self, GeminiError> {
+        let file = File::create(path).map_err(|e| checkpoint_error(path, e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            pending: Vec::new(),
+            timing: None,
+            interval,
+            last_flush: Instant::now(),
+        })
+    }
+
+    /// Record `event`, as measured so far by `timer`.
+    pub(crate) fn record(
+        &mut self,
+        event: &StreamEvent,
+        timer: &StreamTimer,
+    ) -> Result<(), GeminiError> {
+        self.push(serde_json::json!({ "record": "event", "event": event }));
+        self.timing = Some(timer.finish());
+        if self.last_flush.elapsed() >= self.interval {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Flush everything with the final timing from `timer`.
+    pub(crate) fn finish(&mut self, timer: &StreamTimer) -> Result<(), GeminiError> {
+        self.timing = Some(timer.finish());
+        self.flush()
+    }
+
+    /// Write pending events followed by the latest timing.
+    pub(crate) fn flush(&mut self) -> Result<(), GeminiError> {
+        if let Some(timing) = self.timing.take() {
+            self.push(serde_json::json!({ "record": "timing", "timing": timing }));
+        }
+        self.last_flush = Instant::now();
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let pending = std::mem::take(&mut self.pending);
+        self.file
+            .write_all(&pending)
+            .map_err(|e| checkpoint_error(&self.path, e))
+    }
+
+    fn push(&mut self, record: serde_json::Value) {
+        self.pending
+            .extend_from_slice(record.to_string().as_bytes());
+        self.pending.push(b'\n');
+    }
+}
+
+impl Drop for Checkpointer {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+fn checkpoint_error(path: &Path, e: std::io::Error) -> GeminiError {
+    GeminiError::StorageError(format!(
+        "failed to write transcript checkpoint {}: {e}",
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::InMemoryMetrics;
+    use serde_json::json;
+
+    fn message(role: &str, content: &str) -> StreamEvent {
+        StreamEvent::Message {
+            role: role.into(),
+            content: content.into(),
+            delta: Some(true),
+            timestamp: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_output_tokens_from_stats() {
+        assert_eq!(output_tokens(&json!({"output_tokens": 12})), Some(12));
+        assert_eq!(
+            output_tokens(&json!({"models": {
+                "a": {"tokens": {"candidates": 3}},
+                "b": {"tokens": {"candidates": 4}}
+            }})),
+            Some(7)
+        );
+        assert_eq!(output_tokens(&json!({})), None);
+    }
+
+    #[test]
+    fn test_timer_records_first_text_and_estimates_tokens() {
+        let mut timer = StreamTimer::start();
+        timer.observe(&message("user", "question"));
+        timer.observe(&message("model", "12345678"));
+        let timing = timer.finish();
+        assert!(timing.time_to_first_event.is_some());
+        assert!(timing.time_to_first_text >= timing.time_to_first_event);
+        assert_eq!(timing.output_tokens, 2);
+        assert!(timing.tokens_estimated);
+
+        let sink = InMemoryMetrics::new();
+        timer.emit(&sink, &BTreeMap::new());
+        assert_eq!(sink.observations(metrics::STREAM_DURATION_MS).len(), 1);
+        assert_eq!(
+            sink.series()[0].1,
+            vec![("model".to_string(), "unknown".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_transcript_concatenates_model_text() {
+        let transcript = RunTranscript::new(
+            vec![
+                message("user", "hi"),
+                message("model", "Hel"),
+                message("model", "lo"),
+            ],
+            StreamTimer::start().finish(),
+        );
+        assert_eq!(transcript.text, "Hello");
+        assert_eq!(transcript.session_id(), None);
+    }
+
+    #[test]
+    fn test_checkpoint_survives_a_torn_final_line() {
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.jsonl", std::process::id()));
+        let mut timer = StreamTimer::start();
+        let mut checkpoint = Checkpointer::create(&path, Duration::from_secs(3600)).unwrap();
+        for text in ["Hel", "lo"] {
+            let event = message("model", text);
+            timer.observe(&event);
+            checkpoint.record(&event, &timer).unwrap();
+        }
+        // Nothing is written before the interval elapses; dropping flushes.
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        drop(checkpoint);
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"{\"record\":\"event\",\"ev").unwrap();
+        let transcript = RunTranscript::recover(&path).unwrap();
+        assert_eq!(transcript.text, "Hello");
+        assert_eq!(transcript.timing.output_tokens, 2);
+        assert!(!transcript.is_complete());
+
+        file.write_all(b"\n{}\n").unwrap();
+        assert!(matches!(
+            RunTranscript::recover(&path),
+            Err(GeminiError::StorageError(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+//! Truncation of piped input that exceeds a token budget.
+//!
+//! Chopping the end off an oversized input usually loses what matters most: the latest log
+//! lines, the end of a conversation, the file the question is about. A [`Truncation`] caps the
+//! piped [`context`](crate::Gemini::context) and [`file`](crate::Gemini::file)s together at
+//! `max_tokens` with a [`TruncationStrategy`] that decides what to keep. Cut-out text is
+//! replaced by a marker saying how much was omitted, so the model knows the input is partial.
+//!
+//! Inputs are ordered as they are piped: the context first, then the files in the order they
+//! were added. Later inputs count as more recent. Tokens are estimated at four characters each.
+//!
+//! ```rust
+//! use gemini_oxide::truncation::Truncation;
+//! use gemini_oxide::Gemini;
+//!
+//! let req = Gemini::new("Why does the build fail?")
+//!     .file("Cargo.toml")
+//!     .file("build.log")
+//!     .truncate_input(Truncation::prioritize(8_000, "*.toml"));
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+/// What to keep when input must be truncated.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "strategy", content = "pattern", rename_all = "snake_case")]
+pub enum TruncationStrategy {
+    /// Keep the most recent content: the end of the input.
+    KeepLast,
+    /// Keep the beginning and the end, dropping the middle.
+    KeepFirstAndLast,
+    /// Keep files whose path matches the glob pattern (`*` and `?` wildcards) from their start,
+    /// then fill the rest of the budget with the most recent other content.
+    Prioritize(String),
+}
+
+/// A token budget for piped input and how to meet it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Truncation {
+    /// The most tokens of input to send.
+    pub max_tokens: u64,
+    /// What to keep when the input is larger.
+    pub strategy: TruncationStrategy,
+}
+
+/// How many characters to keep from the start and the end of one input.
+#[derive(Debug, Clone, Copy, Default)]
+struct Keep {
+    head: usize,
+    tail: usize,
+}
+
+impl Truncation {
+    /// Keep the last `max_tokens` tokens of input.
+    pub fn keep_last(max_tokens: u64) -> Self {
+        Self {
+            max_tokens,
+            strategy: TruncationStrategy::KeepLast,
+        }
+    }
+
+    /// Keep the first and last `max_tokens / 2` tokens of input.
+    pub fn keep_first_and_last(max_tokens: u64) -> Self {
+        Self {
+            max_tokens,
+            strategy: TruncationStrategy::KeepFirstAndLast,
+        }
+    }
+
+    /// Keep files matching `pattern` first, then the most recent other input.
+    pub fn prioritize(max_tokens: u64, pattern: impl Into<String>) -> Self {
+        Self {
+            max_tokens,
+            strategy: TruncationStrategy::Prioritize(pattern.into()),
+        }
+    }
+
+    /// Truncate `inputs`, given as `(source, text)` pairs in piping order, to the budget.
+    ///
+    /// Inputs that fit are returned unchanged; inputs with nothing kept are dropped.
+    pub fn apply(&self, inputs: Vec<(String, String)>) -> Vec<(String, String)> {
+        let lengths: Vec<usize> = inputs
+            .iter()
+            .map(|(_, text)| text.chars().count())
+            .collect();
+        let budget = usize::try_from(self.max_tokens.saturating_mul(4)).unwrap_or(usize::MAX);
+        if lengths.iter().sum::<usize>() <= budget {
+            return inputs;
+        }
+
+        let mut keep = vec![Keep::default(); inputs.len()];
+        match &self.strategy {
+            TruncationStrategy::KeepLast => {
+                take_tails(&mut keep, &lengths, 0..inputs.len(), budget);
+            }
+            TruncationStrategy::KeepFirstAndLast => {
+                let mut remaining = budget / 2;
+                for (keep, &len) in keep.iter_mut().zip(&lengths) {
+                    keep.head = len.min(remaining);
+                    remaining -= keep.head;
+                }
+                take_tails(
+                    &mut keep,
+                    &lengths,
+                    0..inputs.len(),
+                    budget - budget / 2 + remaining,
+                );
+            }
+            TruncationStrategy::Prioritize(pattern) => {
+                let mut remaining = budget;
+                let (preferred, others): (Vec<usize>, Vec<usize>) =
+                    (0..inputs.len()).partition(|&i| crate::policy::glob(pattern, &inputs[i].0));
+                for i in preferred {
+                    keep[i].head = lengths[i].min(remaining);
+                    remaining -= keep[i].head;
+                }
+                take_tails(&mut keep, &lengths, others.into_iter(), remaining);
+            }
+        }
+
+        inputs
+            .into_iter()
+            .zip(keep.iter().zip(&lengths))
+            .filter(|(_, (keep, _))| keep.head + keep.tail > 0)
+            .map(|((source, text), (keep, &len))| (source, cut(&text, len, *keep)))
+            .collect()
+    }
+}
+
+/// Give the inputs in `order` the end of their text, most recent first, up to `budget`
+/// characters in total on top of what they already keep.
+fn take_tails(
+    keep: &mut [Keep],
+    lengths: &[usize],
+    order: impl DoubleEndedIterator<Item = usize>,
+    mut budget: usize,
+) {
+    for i in order.rev() {
+        keep[i].tail = (lengths[i] - keep[i].head).min(budget);
+        budget -= keep[i].tail;
+    }
+}
+
+/// `text` with everything but `keep` replaced by an omission marker.
+fn cut(text: &str, len: usize, keep: Keep) -> String {
+    if keep.head + keep.tail >= len {
+        return text.to_string();
+    }
+    let head: String = text.chars().take(keep.head).collect();
+    let tail: String = text.chars().skip(len - keep.tail).collect();
+    let omitted = len - keep.head - keep.tail;
+    let mut cut = head;
+    if !cut.is_empty() && !cut.ends_with('\n') {
+        cut.push('\n');
+    }
+    cut.push_str(&format!("[... {omitted} characters omitted ...]"));
+    if !tail.is_empty() {
+        if !tail.starts_with('\n') {
+            cut.push('\n');
+        }
+        cut.push_str(&tail);
+    }
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs() -> Vec<(String, String)> {
+        vec![
+            ("context".into(), "a".repeat(40)),
+            ("src/lib.rs".into(), "b".repeat(40)),
+            ("build.log".into(), "c".repeat(40)),
+        ]
+    }
+
+    #[test]
+    fn test_strategies_keep_the_right_content() {
+        assert_eq!(Truncation::keep_last(100).apply(inputs()), inputs());
+
+        let kept = Truncation::keep_last(15).apply(inputs());
+        assert_eq!(kept.len(), 2);
+        assert_eq!(
+            kept[0].1,
+            "[... 20 characters omitted ...]\n".to_string() + &"b".repeat(20)
+        );
+        assert_eq!(kept[1].1, "c".repeat(40));
+
+        let kept = Truncation::keep_first_and_last(10).apply(inputs());
+        assert_eq!(kept.len(), 2);
+        assert!(kept[0].1.starts_with(&"a".repeat(20)));
+        assert!(kept[1].1.ends_with(&"c".repeat(20)));
+
+        let kept = Truncation::prioritize(15, "*.rs").apply(inputs());
+        let sources: Vec<_> = kept.iter().map(|(source, _)| source.as_str()).collect();
+        assert_eq!(sources, ["src/lib.rs", "build.log"]);
+        assert_eq!(kept[0].1, "b".repeat(40));
+        assert!(kept[1].1.ends_with(&"c".repeat(20)));
+    }
+}
@@ -0,0 +1,357 @@
+//! Post-run verification of an agent's changes.
+//!
+//! A [`Verifier`] inspects the working directory after a run (build it, run its tests, lint it)
+//! and passes or fails it. [`VerifiedRun`] runs a request, applies every verifier, and on failure
+//! optionally sends the verifiers' output back to the agent in a repair turn, up to a limit.
+//!
+//! [`CommandVerifier`] covers the common "run this command, exit code 0 passes" case;
+//! [`verifier_fn`] wraps an async closure for anything else.
+//!
+//! ```rust,no_run
+//! use gemini_oxide::verify::{CommandVerifier, VerifiedRun};
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let report = VerifiedRun::new(Gemini::new("Make the failing test pass").yolo())
+//!     .verifier(CommandVerifier::cargo_test())
+//!     .repair_attempts(2)
+//!     .run()
+//!     .await?;
+//! if !report.accepted() {
+//!     eprintln!("{}", report.attempts.last().unwrap().failure_output());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Gemini, GeminiError};
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::process::Command;
+
+/// Verifier output beyond this many characters is cut from the front.
+const MAX_OUTPUT_CHARS: usize = 8_000;
+
+/// The outcome of one verifier on one attempt.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Verdict {
+    /// The verifier's name.
+    pub name: String,
+    pub passed: bool,
+    /// What the verifier reported, e.g. compiler or test output. Kept short.
+    pub output: String,
+}
+
+impl Verdict {
+    /// A passing verdict.
+    pub fn pass(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            passed: true,
+            output: String::new(),
+        }
+    }
+
+    /// A failing verdict with the output explaining why.
+    pub fn fail(name: impl Into<String>, output: impl AsRef<str>) -> Self {
+        Self {
+            name: name.into(),
+            passed: false,
+            output: crate::tail_chars(output.as_ref(), MAX_OUTPUT_CHARS),
+        }
+    }
+}
+
+/// Decides whether the changes in a working directory are acceptable.
+pub trait Verifier: Send + Sync {
+    /// Check the run's working directory `dir`.
+    ///
+    /// An `Err` is an infrastructure problem (the check could not run) and aborts the
+    /// [`VerifiedRun`]; a failing check is an `Ok` verdict with `passed == false`.
+    fn verify<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<Verdict, GeminiError>>;
+}
+
+/// Runs a command in the working directory; exit code 0 passes.
+#[derive(Debug, Clone)]
+pub struct CommandVerifier {
+    name: String,
+    program: PathBuf,
+    args: Vec<String>,
+}
+
+impl CommandVerifier {
+    /// Run `program` with `args`. The verifier is named after the command line.
+    pub fn new<I, S>(program: impl Into<PathBuf>, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let program = program.into();
+        let args: Vec<String> = args.into_iter().map(Into::into).collect();
+        let name = std::iter::once(program.to_string_lossy().into_owned())
+            .chain(args.iter().cloned())
+            .collect::<Vec<_>>()
+            .join(" ");
+        Self {
+            name,
+            program,
+            args,
+        }
+    }
+
+    /// `cargo build`.
+    pub fn cargo_build() -> Self {
+        Self::new("cargo", ["build"])
+    }
+
+    /// `cargo test`.
+    pub fn cargo_test() -> Self {
+        Self::new("cargo", ["test"])
+    }
+
+    /// Rename the verifier.
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+}
+
+impl Verifier for CommandVerifier {
+    fn verify<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<Verdict, GeminiError>> {
+        Box::pin(async move {
+            let output = Command::new(&self.program)
+                .args(&self.args)
+                .current_dir(dir)
+                .stdin(Stdio::null())
+                .kill_on_drop(true)
+                .output()
+                .await
+                .map_err(|e| {
+                    GeminiError::RuntimeError(format!("failed to run `{}`: {e}", self.name))
+                })?;
+            if output.status.success() {
+                return Ok(Verdict::pass(&self.name));
+            }
+            let text = format!(
+                "{}{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            );
+            Ok(Verdict::fail(&self.name, text.trim_end()))
+        })
+    }
+}
+
+struct FnVerifier<F> {
+    name: String,
+    f: F,
+}
+
+impl<F, Fut> Verifier for FnVerifier<F>
+where
+    F: Fn(PathBuf) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Result<(), String>, GeminiError>> + Send + 'static,
+{
+    fn verify<'a>(&'a self, dir: &'a Path) -> BoxFuture<'a, Result<Verdict, GeminiError>> {
+        let check = (self.f)(dir.to_path_buf());
+        Box::pin(async move {
+            Ok(match check.await? {
+                Ok(()) => Verdict::pass(&self.name),
+                Err(output) => Verdict::fail(&self.name, output),
+            })
+        })
+    }
+}
+
+/// Wrap an async closure as a [`Verifier`]. The closure receives the working directory and
+/// returns `Ok(Err(output))` to fail the run.
+///
+/// ```rust
+/// use gemini_oxide::verify::verifier_fn;
+///
+/// let verifier = verifier_fn("changelog updated", |dir| async move {
+///     let changelog = tokio::fs::read_to_string(dir.join("CHANGELOG.md")).await;
+///     Ok(match changelog {
+///         Ok(text) if text.contains("Unreleased") => Ok(()),
+///         _ => Err("CHANGELOG.md has no Unreleased section".to_string()),
+///     })
+/// });
+/// ```
+pub fn verifier_fn<F, Fut>(name: impl Into<String>, f: F) -> impl Verifier
+where
+    F: Fn(PathBuf) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Result<(), String>, GeminiError>> + Send + 'static,
+{
+    FnVerifier {
+        name: name.into(),
+        f,
+    }
+}
+
+/// One agent turn and its verification.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Attempt {
+    /// The agent's text response.
+    pub response: String,
+    /// One verdict per verifier, in registration order.
+    pub verdicts: Vec<Verdict>,
+}
+
+impl Attempt {
+    /// Whether every verifier passed.
+    pub fn passed(&self) -> bool {
+        self.verdicts.iter().all(|v| v.passed)
+    }
+
+    /// The output of every failing verifier, headed by its name.
+    pub fn failure_output(&self) -> String {
+        self.verdicts
+            .iter()
+            .filter(|v| !v.passed)
+            .map(|v| format!("## {}\n{}", v.name, v.output))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// The result of a [`VerifiedRun`]: the initial attempt followed by any repair attempts.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VerificationReport {
+    pub attempts: Vec<Attempt>,
+}
+
+impl VerificationReport {
+    /// Whether the last attempt passed every verifier.
+    pub fn accepted(&self) -> bool {
+        self.attempts.last().is_some_and(Attempt::passed)
+    }
+
+    /// The response of the last attempt.
+    pub fn response(&self) -> &str {
+        self.attempts.last().map_or("", |a| a.response.as_str())
+    }
+}
+
+/// Runs a request and verifies the result, with optional repair turns.
+pub struct VerifiedRun {
+    request: Gemini,
+    verifiers: Vec<Arc<dyn Verifier>>,
+    repair_attempts: usize,
+}
+
+impl VerifiedRun {
+    /// Verify the outcome of `request`. Verifiers run in the request's
+    /// [`current_dir`](Gemini::current_dir), or the process's working directory.
+    pub fn new(request: Gemini) -> Self {
+        Self {
+            request,
+            verifiers: Vec::new(),
+            repair_attempts: 0,
+        }
+    }
+
+    /// Add a verifier. All verifiers run after every attempt, in order.
+    #[must_use]
+    pub fn verifier(mut self, verifier: impl Verifier + 'static) -> Self {
+        self.verifiers.push(Arc::new(verifier));
+        self
+    }
+
+    /// On failure, send the verifier output back to the agent and retry up to `attempts` times.
+    /// Defaults to 0 (verify only).
+    #[must_use]
+    pub fn repair_attempts(mut self, attempts: usize) -> Self {
+        self.repair_attempts = attempts;
+        self
+    }
+
+    /// Run the request, verify it, and repair as configured.
+    ///
+    /// A run that still fails verification is not an error; check
+    /// [`VerificationReport::accepted`].
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by the request or by a verifier that could not run.
+    pub async fn run(self) -> Result<VerificationReport, GeminiError> {
+        let dir = match &self.request.current_dir {
+            Some(dir) => dir.clone(),
+            None => std::env::current_dir()
+                .map_err(|e| GeminiError::RuntimeError(format!("no working directory: {e}")))?,
+        };
+        let mut attempts: Vec<Attempt> = Vec::new();
+        loop {
+            let request = match attempts.last() {
+                None => self.request.clone(),
+                Some(failed) => Gemini {
+                    prompt: repair_prompt(&self.request.prompt, failed),
+                    ..self.request.clone()
+                },
+            };
+            let response = request.text().await?;
+            let mut verdicts = Vec::with_capacity(self.verifiers.len());
+            for verifier in &self.verifiers {
+                verdicts.push(verifier.verify(&dir).await?);
+            }
+            attempts.push(Attempt { response, verdicts });
+            if attempts.last().is_some_and(Attempt::passed) || attempts.len() > self.repair_attempts
+            {
+                return Ok(VerificationReport { attempts });
+            }
+        }
+    }
+}
+
+fn repair_prompt(task: &str, failed: &Attempt) -> String {
+    format!(
+        "You were given this task:\n\n{task}\n\nYour changes are in the working directory, but \
+         they failed verification:\n\n{}\n\nFix the problems so that every check passes. Do not \
+         undo the intended change.",
+        failed.failure_output()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_command_verifier_reports_output() {
+        let dir = std::env::temp_dir();
+        let pass = CommandVerifier::new("sh", ["-c", "exit 0"]);
+        assert!(pass.verify(&dir).await.unwrap().passed);
+
+        let fail = CommandVerifier::new("sh", ["-c", "echo broken >&2; exit 3"]).name("check");
+        assert_eq!(
+            fail.verify(&dir).await.unwrap(),
+            Verdict::fail("check", "broken")
+        );
+
+        let missing = CommandVerifier::new("/nonexistent/tool", Vec::<String>::new());
+        assert!(matches!(
+            missing.verify(&dir).await,
+            Err(GeminiError::RuntimeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_repair_prompt_includes_failures() {
+        let attempt = Attempt {
+            response: String::new(),
+            verdicts: vec![
+                Verdict::pass("cargo build"),
+                Verdict::fail("cargo test", "test a ... FAILED"),
+            ],
+        };
+        assert!(!attempt.passed());
+        let prompt = repair_prompt("Add a feature", &attempt);
+        assert!(prompt.contains("Add a feature"));
+        assert!(prompt.contains("## cargo test\ntest a ... FAILED"));
+        assert!(!prompt.contains("## cargo build"));
+    }
+}
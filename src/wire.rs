@@ -0,0 +1,190 @@
+//! Versioned persistence of the SDK's wire types.
+//!
+//! Transcripts, recorded runs and cached responses outlive the crate version that wrote them.
+//! Types implementing [`Versioned`] are written by [`to_string`] with a top-level
+//! `schema_version` and read back by [`from_str`], which first migrates older shapes to the
+//! current one, one version at a time. Documents without a `schema_version` were written before
+//! versioning and count as version 0.
+//!
+//! | Version | Changes |
+//! |---|---|
+//! | 0 | Unversioned. Events may use the legacy CLI names handled by [`compat`](crate::compat). |
+//! | 1 | Events use the current [`StreamEvent`] names; transcripts always carry `text` and `timing`. |
+//!
+//! ```rust
+//! use gemini_oxide::transcript::RunTranscript;
+//! use gemini_oxide::wire;
+//!
+//! let old = r#"{"events": [{"type": "content", "role": "model", "text": "Hi"}]}"#;
+//! let transcript: RunTranscript = wire::from_str(old).unwrap();
+//! assert_eq!(transcript.text, "Hi");
+//! assert!(wire::to_string(&transcript).unwrap().contains("\"schema_version\":1"));
+//! ```
+
+use crate::replay::RecordedRun;
+use crate::transcript::RunTranscript;
+use crate::{compat, GeminiError, GeminiJsonOutput, StreamEvent};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// The schema version written by this crate version.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The field carrying a document's schema version.
+const VERSION_FIELD: &str = "schema_version";
+
+/// A type persisted with a schema version.
+pub trait Versioned: Serialize + DeserializeOwned {
+    /// Bring `value`, written with schema version `from`, to [`SCHEMA_VERSION`].
+    fn migrate(value: Value, from: u32) -> Value;
+}
+
+impl Versioned for StreamEvent {
+    fn migrate(value: Value, from: u32) -> Value {
+        if from < 1 {
+            compat::upgrade_event(value)
+        } else {
+            value
+        }
+    }
+}
+
+impl Versioned for RunTranscript {
+    fn migrate(mut value: Value, from: u32) -> Value {
+        if from < 1 {
+            let Some(fields) = value.as_object_mut() else {
+                return value;
+            };
+            let events: Vec<Value> = match fields.remove("events") {
+                Some(Value::Array(events)) => events
+                    .into_iter()
+                    .map(|event| StreamEvent::migrate(event, from))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            if !fields.contains_key("text") {
+                let text: String = events
+                    .iter()
+                    .filter(|e| e["type"] == "message" && e["role"] != "user")
+                    .filter_map(|e| e["content"].as_str())
+                    .collect();
+                fields.insert("text".into(), text.into());
+            }
+            fields.entry("timing").or_insert_with(|| {
+                json!({
+                    "time_to_first_event": null,
+                    "time_to_first_text": null,
+                    "duration": { "secs": 0, "nanos": 0 },
+                    "output_tokens": 0,
+                    "tokens_estimated": true,
+                })
+            });
+            fields.insert("events".into(), Value::Array(events));
+        }
+        value
+    }
+}
+
+impl Versioned for GeminiJsonOutput {
+    fn migrate(value: Value, _from: u32) -> Value {
+        // Every field added before versioning has a default.
+        value
+    }
+}
+
+impl Versioned for RecordedRun {
+    fn migrate(mut value: Value, from: u32) -> Value {
+        if let Some(transcript) = value.get_mut("transcript") {
+            *transcript = RunTranscript::migrate(transcript.take(), from);
+        }
+        value
+    }
+}
+
+/// Serialize `value` as JSON tagged with the current schema version.
+///
+/// # Errors
+///
+/// Returns `GeminiError::StorageError` if `value` cannot be serialized.
+pub fn to_string<T: Versioned>(value: &T) -> Result<String, GeminiError> {
+    to_value(value).map(|value| value.to_string())
+}
+
+/// Serialize `value` as a JSON value tagged with the current schema version.
+///
+/// # Errors
+///
+/// Returns `GeminiError::StorageError` if `value` cannot be serialized or is not an object.
+pub fn to_value<T: Versioned>(value: &T) -> Result<Value, GeminiError> {
+    let mut value = serde_json::to_value(value)
+        .map_err(|e| GeminiError::StorageError(format!("failed to serialize: {e}")))?;
+    match value.as_object_mut() {
+        Some(fields) => {
+            fields.insert(VERSION_FIELD.into(), SCHEMA_VERSION.into());
+            Ok(value)
+        }
+        None => Err(GeminiError::StorageError(
+            "only JSON objects can carry a schema version".into(),
+        )),
+    }
+}
+
+/// Deserialize JSON written by [`to_string`] of this or an earlier crate version.
+///
+/// # Errors
+///
+/// Returns `GeminiError::StorageError` if `json` is not valid JSON, was written by a newer
+/// crate version, or does not match `T` after migration.
+pub fn from_str<T: Versioned>(json: &str) -> Result<T, GeminiError> {
+    let value = serde_json::from_str(json)
+        .map_err(|e| GeminiError::StorageError(format!("invalid JSON: {e}")))?;
+    from_value(value)
+}
+
+/// Deserialize a JSON value written by [`to_value`] of this or an earlier crate version.
+///
+/// # Errors
+///
+/// See [`from_str`].
+pub fn from_value<T: Versioned>(mut value: Value) -> Result<T, GeminiError> {
+    let version = match value.as_object_mut().and_then(|o| o.remove(VERSION_FIELD)) {
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| GeminiError::StorageError(format!("invalid {VERSION_FIELD}")))?,
+        None => 0,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(GeminiError::StorageError(format!(
+            "schema version {version} was written by a newer crate version (this one reads up to \
+             {SCHEMA_VERSION})"
+        )));
+    }
+    serde_json::from_value(T::migrate(value, version)).map_err(|e| {
+        GeminiError::StorageError(format!("schema version {version} did not migrate: {e}"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_documents_round_trip_and_newer_ones_are_rejected() {
+        let event = StreamEvent::Error {
+            message: "boom".into(),
+        };
+        let json = to_string(&event).unwrap();
+        assert!(matches!(
+            from_str::<StreamEvent>(&json).unwrap(),
+            StreamEvent::Error { message } if message == "boom"
+        ));
+
+        let newer = json.replace("\"schema_version\":1", "\"schema_version\":99");
+        assert!(matches!(
+            from_str::<StreamEvent>(&newer),
+            Err(GeminiError::StorageError(_))
+        ));
+    }
+}
@@ -0,0 +1,431 @@
+//! Isolated working directories for agent runs.
+//!
+//! [`scratch`] creates a fresh, uniquely named directory under the system temp dir; [`Scratch`]
+//! additionally seeds it from a template directory or a git clone and chooses what happens to it
+//! afterwards ([`Cleanup`]). Point a request at it with [`Workspace::apply`] (which sets the
+//! CLI's working directory), so parallel jobs that edit files never step on each other.
+//!
+//! For code changes in an existing repository, [`run_in_worktree`] (or [`Worktree`] for more
+//! control) gives each run its own git worktree and branch, commits what the agent changed and
+//! reports a [`DiffSummary`].
+//!
+//! ```rust,no_run
+//! use gemini_oxide::workspace::{Cleanup, Scratch};
+//! use gemini_oxide::Gemini;
+//!
+//! # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+//! let workspace = Scratch::new()
+//!     .git_clone("https://github.com/hghalebi/gemini-rs")
+//!     .cleanup(Cleanup::Archive("runs".into()))
+//!     .create()
+//!     .await?;
+//!
+//! let summary = workspace
+//!     .apply(Gemini::new("Run the tests and fix what fails").yolo())
+//!     .text()
+//!     .await?;
+//! let archived = workspace.finish().await?;
+//! println!("{summary}\nworkspace kept at {archived:?}");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Gemini, GeminiError};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::process::Command;
+
+mod worktree;
+
+pub use worktree::{run_in_worktree, DiffSummary, FileChange, GitWorktree, Worktree, WorktreeRun};
+
+/// What happens to a workspace when it is finished or dropped.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Cleanup {
+    /// Delete the directory.
+    #[default]
+    Delete,
+    /// Leave the directory where it is.
+    Keep,
+    /// Move the directory into the given archive directory, keeping its name.
+    Archive(PathBuf),
+}
+
+/// Create an empty scratch workspace that is deleted when finished or dropped.
+///
+/// # Errors
+///
+/// Returns `GeminiError::RuntimeError` if the directory cannot be created.
+pub async fn scratch() -> Result<Workspace, GeminiError> {
+    Scratch::new().create().await
+}
+
+#[derive(Debug, Clone)]
+enum Seed {
+    Template(PathBuf),
+    GitClone(String),
+}
+
+/// Options for creating a [`Workspace`].
+#[derive(Debug, Clone, Default)]
+pub struct Scratch {
+    root: Option<PathBuf>,
+    prefix: Option<String>,
+    seed: Option<Seed>,
+    git: Option<PathBuf>,
+    cleanup: Cleanup,
+}
+
+impl Scratch {
+    /// An empty workspace under the system temp dir, deleted afterwards.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create workspaces under `dir` instead of the system temp dir.
+    #[must_use]
+    pub fn root(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.root = Some(dir.into());
+        self
+    }
+
+    /// Prefix for the directory name. Defaults to `"gemini-scratch"`.
+    #[must_use]
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Seed the workspace with a recursive copy of `dir`.
+    #[must_use]
+    pub fn template(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.seed = Some(Seed::Template(dir.into()));
+        self
+    }
+
+    /// Seed the workspace with a shallow `git clone` of `source` (a URL or local path).
+    #[must_use]
+    pub fn git_clone(mut self, source: impl Into<String>) -> Self {
+        self.seed = Some(Seed::GitClone(source.into()));
+        self
+    }
+
+    /// Set the path to the `git` binary used by [`git_clone`](Self::git_clone). Defaults to
+    /// `"git"` on the PATH.
+    #[must_use]
+    pub fn git_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.git = Some(path.into());
+        self
+    }
+
+    /// What to do with the workspace afterwards.
+    #[must_use]
+    pub fn cleanup(mut self, cleanup: Cleanup) -> Self {
+        self.cleanup = cleanup;
+        self
+    }
+
+    /// Create and seed the workspace.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::RuntimeError` if the directory cannot be created, the template
+    /// cannot be copied, or `git clone` fails. A partially created workspace is removed.
+    pub async fn create(self) -> Result<Workspace, GeminiError> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let name = format!(
+            "{}-{}-{}-{n}",
+            self.prefix.as_deref().unwrap_or("gemini-scratch"),
+            std::process::id(),
+            crate::storage::now_millis()
+        );
+        let root = self.root.unwrap_or_else(std::env::temp_dir);
+        tokio::fs::create_dir_all(&root)
+            .await
+            .map_err(|e| io_error("create", &root, e))?;
+        let path = root.join(name);
+
+        let seeded = match &self.seed {
+            None => tokio::fs::create_dir(&path)
+                .await
+                .map_err(|e| io_error("create", &path, e)),
+            Some(Seed::Template(template)) => copy_dir(template, &path).await,
+            Some(Seed::GitClone(source)) => {
+                let git = self.git.unwrap_or_else(|| PathBuf::from("git"));
+                clone(&git, source, &path).await
+            }
+        };
+        // The workspace owns the directory from here on, so a failed seed is cleaned up on drop.
+        let mut workspace = Workspace {
+            path,
+            cleanup: Cleanup::Delete,
+            finished: false,
+        };
+        seeded?;
+        workspace.cleanup = self.cleanup;
+        Ok(workspace)
+    }
+}
+
+/// A per-run working directory, cleaned up according to its [`Cleanup`] policy.
+///
+/// Call [`finish`](Self::finish) to clean up asynchronously and observe errors; otherwise
+/// cleanup happens best-effort when the workspace is dropped.
+#[derive(Debug)]
+pub struct Workspace {
+    path: PathBuf,
+    cleanup: Cleanup,
+    finished: bool,
+}
+
+impl Workspace {
+    /// The workspace directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Run `request` with the workspace as the CLI's working directory.
+    pub fn apply(&self, request: Gemini) -> Gemini {
+        request.current_dir(&self.path)
+    }
+
+    /// Clean up now. Returns the directory's final location if it was kept or archived.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::RuntimeError` if the directory cannot be removed or archived.
+    pub async fn finish(mut self) -> Result<Option<PathBuf>, GeminiError> {
+        self.finished = true;
+        match &self.cleanup {
+            Cleanup::Delete => tokio::fs::remove_dir_all(&self.path)
+                .await
+                .map(|()| None)
+                .map_err(|e| io_error("remove", &self.path, e)),
+            Cleanup::Keep => Ok(Some(self.path.clone())),
+            Cleanup::Archive(dir) => {
+                let target = archive_path(dir, &self.path);
+                tokio::fs::create_dir_all(dir)
+                    .await
+                    .map_err(|e| io_error("create", dir, e))?;
+                match tokio::fs::rename(&self.path, &target).await {
+                    Ok(()) => Ok(Some(target)),
+                    // Renaming fails across file systems; fall back to copy and delete.
+                    Err(_) => {
+                        copy_dir(&self.path, &target).await?;
+                        tokio::fs::remove_dir_all(&self.path)
+                            .await
+                            .map_err(|e| io_error("remove", &self.path, e))?;
+                        Ok(Some(target))
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        match &self.cleanup {
+            Cleanup::Delete => {
+                let _ = std::fs::remove_dir_all(&self.path);
+            }
+            Cleanup::Keep => {}
+            Cleanup::Archive(dir) => {
+                let _ = std::fs::create_dir_all(dir);
+                let _ = std::fs::rename(&self.path, archive_path(dir, &self.path));
+            }
+        }
+    }
+}
+
+fn archive_path(dir: &Path, path: &Path) -> PathBuf {
+    dir.join(path.file_name().unwrap_or_default())
+}
+
+fn io_error(action: &str, path: &Path, e: std::io::Error) -> GeminiError {
+    GeminiError::RuntimeError(format!(
+        "failed to {action} workspace `{}`: {e}",
+        path.display()
+    ))
+}
+
+/// Recursively copy `from` into the new directory `to`. Symlinks are copied as symlinks, so a
+/// link to an ancestor directory cannot make the copy recurse forever.
+async fn copy_dir(from: &Path, to: &Path) -> Result<(), GeminiError> {
+    let mut pending = vec![(from.to_path_buf(), to.to_path_buf())];
+    while let Some((src, dst)) = pending.pop() {
+        tokio::fs::create_dir(&dst)
+            .await
+            .map_err(|e| io_error("create", &dst, e))?;
+        let mut entries = tokio::fs::read_dir(&src)
+            .await
+            .map_err(|e| io_error("read", &src, e))?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| io_error("read", &src, e))?
+        {
+            let source = entry.path();
+            let target = dst.join(entry.file_name());
+            let metadata = tokio::fs::symlink_metadata(&source)
+                .await
+                .map_err(|e| io_error("read", &source, e))?;
+            if metadata.is_symlink() {
+                copy_link(&source, &target).await?;
+            } else if metadata.is_dir() {
+                pending.push((source, target));
+            } else {
+                tokio::fs::copy(&source, &target)
+                    .await
+                    .map_err(|e| io_error("copy into", &target, e))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Create a symlink at `target` pointing where the symlink `source` points.
+async fn copy_link(source: &Path, target: &Path) -> Result<(), GeminiError> {
+    let link = tokio::fs::read_link(source)
+        .await
+        .map_err(|e| io_error("read", source, e))?;
+    #[cfg(unix)]
+    let created = tokio::fs::symlink(&link, target).await;
+    #[cfg(windows)]
+    let created = match tokio::fs::metadata(source).await {
+        Ok(metadata) if metadata.is_dir() => tokio::fs::symlink_dir(&link, target).await,
+        _ => tokio::fs::symlink_file(&link, target).await,
+    };
+    created.map_err(|e| io_error("copy into", target, e))
+}
+
+async fn clone(git: &Path, source: &str, path: &Path) -> Result<(), GeminiError> {
+    let output = Command::new(git)
+        .arg("clone")
+        .arg("--depth=1")
+        .arg("--quiet")
+        // A source starting with `-` must not be read as an option such as `--upload-pack`.
+        .arg("--")
+        .arg(source)
+        .arg(path)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| GeminiError::RuntimeError(format!("failed to run git: {e}")))?;
+    if !output.status.success() {
+        return Err(GeminiError::RuntimeError(format!(
+            "git clone failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scratch_is_unique_and_deleted() {
+        let a = scratch().await.unwrap();
+        let b = scratch().await.unwrap();
+        assert_ne!(a.path(), b.path());
+        assert!(a.path().is_dir());
+
+        let path = a.path().to_path_buf();
+        assert_eq!(a.finish().await.unwrap(), None);
+        assert!(!path.exists());
+
+        let path = b.path().to_path_buf();
+        drop(b);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_template_is_copied_and_archived() {
+        let root = std::env::temp_dir().join(format!("gemini-ws-test-{}", std::process::id()));
+        let template = root.join("template");
+        std::fs::create_dir_all(template.join("src")).unwrap();
+        std::fs::write(template.join("src/lib.rs"), "fn a() {}").unwrap();
+
+        let workspace = Scratch::new()
+            .root(root.join("runs"))
+            .template(&template)
+            .cleanup(Cleanup::Archive(root.join("archive")))
+            .create()
+            .await
+            .unwrap();
+        let copied = std::fs::read_to_string(workspace.path().join("src/lib.rs")).unwrap();
+        assert_eq!(copied, "fn a() {}");
+
+        let archived = workspace.finish().await.unwrap().unwrap();
+        assert!(archived.starts_with(root.join("archive")));
+        assert!(archived.join("src/lib.rs").is_file());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_seed_leaves_nothing_behind() {
+        let root = std::env::temp_dir().join(format!("gemini-ws-fail-{}", std::process::id()));
+        let err = Scratch::new()
+            .root(&root)
+            .template(root.join("missing"))
+            .create()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, GeminiError::RuntimeError(_)));
+        assert_eq!(std::fs::read_dir(&root).unwrap().count(), 0);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlinks_are_copied_as_links() {
+        let root = std::env::temp_dir().join(format!("gemini-ws-link-{}", std::process::id()));
+        let template = root.join("template");
+        std::fs::create_dir_all(template.join("src")).unwrap();
+        std::os::unix::fs::symlink("..", template.join("src/up")).unwrap();
+
+        let workspace = Scratch::new()
+            .root(root.join("runs"))
+            .template(&template)
+            .create()
+            .await
+            .unwrap();
+        let link = workspace.path().join("src/up");
+        assert_eq!(std::fs::read_link(&link).unwrap(), PathBuf::from(".."));
+        workspace.finish().await.unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_clone_source_is_never_an_option() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join(format!("gemini-ws-opt-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let args = root.join("args");
+        let git = root.join("git");
+        std::fs::write(
+            &git,
+            format!("#!/bin/sh\nprintf '%s\\n' \"$@\" > '{}'\n", args.display()),
+        )
+        .unwrap();
+        std::fs::set_permissions(&git, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let source = "--upload-pack=touch /tmp/injected";
+        clone(&git, source, &root.join("clone")).await.unwrap();
+        let args = std::fs::read_to_string(&args).unwrap();
+        let args: Vec<&str> = args.lines().collect();
+        let separator = args.iter().position(|a| *a == "--").unwrap();
+        assert_eq!(args[separator + 1], source);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
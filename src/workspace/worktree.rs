@@ -0,0 +1,509 @@
+use crate::{Gemini, GeminiError};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::process::Command;
+
+/// A file changed on a worktree's branch.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FileChange {
+    pub path: String,
+    /// Lines added, `None` for binary files.
+    pub added: Option<u64>,
+    /// Lines removed, `None` for binary files.
+    pub removed: Option<u64>,
+}
+
+/// What an agent run changed, relative to the worktree's base commit.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DiffSummary {
+    pub files: Vec<FileChange>,
+    /// `git diff --stat` output.
+    pub stat: String,
+}
+
+impl DiffSummary {
+    /// Whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Total lines added across text files.
+    pub fn lines_added(&self) -> u64 {
+        self.files.iter().filter_map(|f| f.added).sum()
+    }
+
+    /// Total lines removed across text files.
+    pub fn lines_removed(&self) -> u64 {
+        self.files.iter().filter_map(|f| f.removed).sum()
+    }
+}
+
+/// The outcome of a run in a [`GitWorktree`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct WorktreeRun {
+    /// The branch holding the run's changes.
+    pub branch: String,
+    /// The commit the branch started from.
+    pub base: String,
+    /// The commit recording the changes, if there were any.
+    pub commit: Option<String>,
+    /// What changed.
+    pub diff: DiffSummary,
+    /// The agent's text response.
+    pub response: String,
+}
+
+/// Run `request` in a fresh worktree of the git repository at `repo`, on a new branch from
+/// `HEAD`, and commit whatever the agent changed there.
+///
+/// Several runs can modify the same repository concurrently; each gets its own branch, and the
+/// repository's own working tree is never touched.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use gemini_oxide::Gemini;
+/// # async fn run() -> Result<(), gemini_oxide::GeminiError> {
+/// let run = gemini_oxide::workspace::run_in_worktree(
+///     ".",
+///     Gemini::new("Add doc comments to src/lib.rs").yolo(),
+/// )
+/// .await?;
+/// println!("{}: {} files changed", run.branch, run.diff.files.len());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Returns `GeminiError::RuntimeError` if a git command fails, or any error raised by the
+/// request. The worktree is removed in every case; the branch is kept.
+pub async fn run_in_worktree(
+    repo: impl Into<PathBuf>,
+    request: Gemini,
+) -> Result<WorktreeRun, GeminiError> {
+    let worktree = Worktree::new(repo).create().await?;
+    let response = worktree.apply(request).text().await?;
+    let mut run = worktree.finish().await?;
+    run.response = response;
+    Ok(run)
+}
+
+/// Options for creating a [`GitWorktree`].
+#[derive(Debug, Clone)]
+pub struct Worktree {
+    repo: PathBuf,
+    base: String,
+    branch: Option<String>,
+    root: Option<PathBuf>,
+    git: PathBuf,
+    author: Option<(String, String)>,
+    message: Option<String>,
+}
+
+impl Worktree {
+    /// A worktree of the repository at `repo`, branching from `HEAD`.
+    pub fn new(repo: impl Into<PathBuf>) -> Self {
+        Self {
+            repo: repo.into(),
+            base: "HEAD".to_string(),
+            branch: None,
+            root: None,
+            git: PathBuf::from("git"),
+            author: None,
+            message: None,
+        }
+    }
+
+    /// Branch from `rev` instead of `HEAD`.
+    #[must_use]
+    pub fn base(mut self, rev: impl Into<String>) -> Self {
+        self.base = rev.into();
+        self
+    }
+
+    /// Name of the branch to create. Defaults to a unique `gemini/run-...` name.
+    #[must_use]
+    pub fn branch(mut self, name: impl Into<String>) -> Self {
+        self.branch = Some(name.into());
+        self
+    }
+
+    /// Create worktrees under `dir` instead of the system temp dir.
+    #[must_use]
+    pub fn root(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.root = Some(dir.into());
+        self
+    }
+
+    /// Set the path to the `git` binary. Defaults to `"git"` on the PATH.
+    #[must_use]
+    pub fn git_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.git = path.into();
+        self
+    }
+
+    /// Commit as this author instead of the repository's configured identity.
+    #[must_use]
+    pub fn author(mut self, name: impl Into<String>, email: impl Into<String>) -> Self {
+        self.author = Some((name.into(), email.into()));
+        self
+    }
+
+    /// Commit message for the run's changes. Defaults to `"Agent run on <branch>"`.
+    #[must_use]
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Create the branch and check it out in a new worktree.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::RuntimeError` if `repo` is not a git repository, the base does not
+    /// exist, the branch already exists, or the worktree cannot be created.
+    pub async fn create(self) -> Result<GitWorktree, GeminiError> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let branch = self.branch.unwrap_or_else(|| {
+            format!(
+                "gemini/run-{}-{}-{n}",
+                std::process::id(),
+                crate::storage::now_millis()
+            )
+        });
+        let base = git(
+            &self.git,
+            &self.repo,
+            &["rev-parse", "--verify", &self.base],
+        )
+        .await?
+        .trim()
+        .to_string();
+        let root = self.root.unwrap_or_else(std::env::temp_dir);
+        tokio::fs::create_dir_all(&root)
+            .await
+            .map_err(|e| super::io_error("create", &root, e))?;
+        // git resolves relative paths against the repository, not our working directory.
+        let root = std::path::absolute(&root).map_err(|e| super::io_error("resolve", &root, e))?;
+        let path = root.join(branch.replace('/', "-"));
+        let path_arg = path.to_string_lossy();
+        git(
+            &self.git,
+            &self.repo,
+            &[
+                "worktree", "add", "--quiet", "-b", &branch, &path_arg, &base,
+            ],
+        )
+        .await?;
+
+        let message = self
+            .message
+            .unwrap_or_else(|| format!("Agent run on {branch}"));
+        Ok(GitWorktree {
+            repo: self.repo,
+            path,
+            branch,
+            base,
+            git: self.git,
+            author: self.author,
+            message,
+            finished: false,
+        })
+    }
+}
+
+/// A git worktree on its own branch, for one agent run.
+///
+/// Call [`finish`](Self::finish) to commit the run's changes and remove the worktree. If the
+/// worktree is dropped instead, it is removed without committing; the branch is always kept.
+#[derive(Debug)]
+pub struct GitWorktree {
+    repo: PathBuf,
+    path: PathBuf,
+    branch: String,
+    base: String,
+    git: PathBuf,
+    author: Option<(String, String)>,
+    message: String,
+    finished: bool,
+}
+
+impl GitWorktree {
+    /// The worktree directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The branch checked out in the worktree.
+    pub fn branch(&self) -> &str {
+        &self.branch
+    }
+
+    /// The commit the branch started from.
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    /// Run `request` with the worktree as the CLI's working directory.
+    pub fn apply(&self, request: Gemini) -> Gemini {
+        request.current_dir(&self.path)
+    }
+
+    /// Commit every change in the worktree to its branch, summarize the diff against the base
+    /// and remove the worktree.
+    ///
+    /// The returned [`WorktreeRun::response`] is empty; [`run_in_worktree`] fills it in.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GeminiError::RuntimeError` if a git command fails. The worktree is removed on
+    /// drop in that case.
+    pub async fn finish(mut self) -> Result<WorktreeRun, GeminiError> {
+        self.git_here(&["add", "--all"]).await?;
+        let status = self.git_here(&["status", "--porcelain"]).await?;
+        let commit = if status.trim().is_empty() {
+            None
+        } else {
+            let mut args = Vec::new();
+            if let Some((name, email)) = &self.author {
+                args.extend([
+                    "-c".to_string(),
+                    format!("user.name={name}"),
+                    "-c".to_string(),
+                    format!("user.email={email}"),
+                ]);
+            }
+            args.extend(["commit", "--quiet", "--no-verify", "-m"].map(String::from));
+            args.push(self.message.clone());
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            self.git_here(&args).await?;
+            Some(
+                self.git_here(&["rev-parse", "HEAD"])
+                    .await?
+                    .trim()
+                    .to_string(),
+            )
+        };
+
+        let range = format!("{}..{}", self.base, self.branch);
+        let numstat = self.git_here(&["diff", "--numstat", &range]).await?;
+        let stat = self.git_here(&["diff", "--stat", &range]).await?;
+        let diff = DiffSummary {
+            files: parse_numstat(&numstat),
+            stat: stat.trim_end().to_string(),
+        };
+
+        let path = self.path.to_string_lossy().into_owned();
+        git(
+            &self.git,
+            &self.repo,
+            &["worktree", "remove", "--force", &path],
+        )
+        .await?;
+        self.finished = true;
+        Ok(WorktreeRun {
+            branch: self.branch.clone(),
+            base: self.base.clone(),
+            commit,
+            diff,
+            response: String::new(),
+        })
+    }
+
+    async fn git_here(&self, args: &[&str]) -> Result<String, GeminiError> {
+        git(&self.git, &self.path, args).await
+    }
+}
+
+impl Drop for GitWorktree {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let path = self.path.to_string_lossy().into_owned();
+        let args = ["worktree", "remove", "--force", &path];
+        // Drop may run on a runtime thread, so hand the removal to the runtime rather than
+        // wait for git there. Without a runtime there is nothing to stall.
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                let (git, repo, args) =
+                    (self.git.clone(), self.repo.clone(), args.map(String::from));
+                runtime.spawn(async move {
+                    let _ = Command::new(&git)
+                        .args(&args)
+                        .current_dir(&repo)
+                        .stdin(Stdio::null())
+                        .output()
+                        .await;
+                });
+            }
+            Err(_) => {
+                let _ = std::process::Command::new(&self.git)
+                    .args(args)
+                    .current_dir(&self.repo)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status();
+            }
+        }
+    }
+}
+
+async fn git(git: &Path, dir: &Path, args: &[&str]) -> Result<String, GeminiError> {
+    let output = Command::new(git)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| GeminiError::RuntimeError(format!("failed to run git: {e}")))?;
+    if !output.status.success() {
+        return Err(GeminiError::RuntimeError(format!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parse `git diff --numstat` output (`added<TAB>removed<TAB>path`, `-` for binary files).
+fn parse_numstat(output: &str) -> Vec<FileChange> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let added = fields.next()?.parse().ok();
+            let removed = fields.next()?.parse().ok();
+            Some(FileChange {
+                path: fields.next()?.to_string(),
+                added,
+                removed,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_numstat() {
+        let files = parse_numstat("3\t1\tsrc/lib.rs\n-\t-\tlogo.png\n");
+        assert_eq!(
+            files,
+            vec![
+                FileChange {
+                    path: "src/lib.rs".into(),
+                    added: Some(3),
+                    removed: Some(1)
+                },
+                FileChange {
+                    path: "logo.png".into(),
+                    added: None,
+                    removed: None
+                },
+            ]
+        );
+        let diff = DiffSummary {
+            files,
+            stat: String::new(),
+        };
+        assert_eq!((diff.lines_added(), diff.lines_removed()), (3, 1));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_worktrees_commit_to_separate_branches() {
+        let root = std::env::temp_dir().join(format!("gemini-wt-test-{}", std::process::id()));
+        let repo = root.join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        let git_path = PathBuf::from("git");
+        for args in [
+            &["init", "--quiet"][..],
+            &[
+                "-c",
+                "user.name=t",
+                "-c",
+                "user.email=t@t",
+                "commit",
+                "--quiet",
+                "--allow-empty",
+                "-m",
+                "init",
+            ],
+        ] {
+            git(&git_path, &repo, args).await.unwrap();
+        }
+
+        let options = Worktree::new(&repo)
+            .root(root.join("trees"))
+            .author("t", "t@t");
+        let a = options.clone().create().await.unwrap();
+        let b = options.clone().create().await.unwrap();
+        assert_ne!(a.branch(), b.branch());
+        std::fs::write(a.path().join("a.txt"), "one\ntwo\n").unwrap();
+
+        let (a, b) = (a.finish().await.unwrap(), b.finish().await.unwrap());
+        assert!(a.commit.is_some());
+        assert_eq!(a.diff.files[0].path, "a.txt");
+        assert_eq!(a.diff.lines_added(), 2);
+        assert!(b.commit.is_none() && b.diff.is_empty());
+        assert!(!root.join("trees").join(a.branch.replace('/', "-")).exists());
+
+        let branches = git(&git_path, &repo, &["branch", "--list", "gemini/*"])
+            .await
+            .unwrap();
+        assert_eq!(branches.lines().count(), 2);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dropped_worktree_is_removed_in_the_background() {
+        let root = std::env::temp_dir().join(format!("gemini-wt-drop-{}", std::process::id()));
+        let repo = root.join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        let git_path = PathBuf::from("git");
+        git(&git_path, &repo, &["init", "--quiet"]).await.unwrap();
+        git(
+            &git_path,
+            &repo,
+            &[
+                "-c",
+                "user.name=t",
+                "-c",
+                "user.email=t@t",
+                "commit",
+                "--quiet",
+                "--allow-empty",
+                "-m",
+                "init",
+            ],
+        )
+        .await
+        .unwrap();
+
+        let worktree = Worktree::new(&repo)
+            .root(root.join("trees"))
+            .create()
+            .await
+            .unwrap();
+        let path = worktree.path().to_path_buf();
+        drop(worktree);
+        for _ in 0..100 {
+            if !path.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
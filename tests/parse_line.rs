@@ -0,0 +1,102 @@
+//! Property tests for `StreamEvent::parse_line`: arbitrary CLI output must never panic the SDK.
+
+use gemini_oxide::StreamEvent;
+use proptest::prelude::*;
+use serde_json::{json, Value};
+
+/// Arbitrary JSON values, nested a few levels deep.
+fn any_json() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::from),
+        any::<i64>().prop_map(Value::from),
+        any::<f64>().prop_map(Value::from),
+        ".*".prop_map(Value::from),
+    ];
+    leaf.prop_recursive(4, 32, 8, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(Value::from),
+            prop::collection::hash_map(".*", inner, 0..8)
+                .prop_map(|map| Value::Object(map.into_iter().collect())),
+        ]
+    })
+}
+
+/// Objects that look like events: a known or legacy `type` plus arbitrary fields.
+fn event_like() -> impl Strategy<Value = Value> {
+    let kind = prop_oneof![
+        Just("init"),
+        Just("message"),
+        Just("content"),
+        Just("thought"),
+        Just("tool_use"),
+        Just("tool_call"),
+        Just("tool_result"),
+        Just("result"),
+        Just("error"),
+        Just("heartbeat"),
+    ];
+    let field = prop_oneof![
+        Just("role"),
+        Just("content"),
+        Just("text"),
+        Just("delta"),
+        Just("subject"),
+        Just("timestamp"),
+        Just("name"),
+        Just("args"),
+        Just("tool_name"),
+        Just("parameters"),
+        Just("status"),
+        Just("stats"),
+        Just("message"),
+    ];
+    (kind, prop::collection::vec((field, any_json()), 0..8)).prop_map(|(kind, fields)| {
+        let mut event = json!({ "type": kind });
+        for (name, value) in fields {
+            event[name] = value;
+        }
+        event
+    })
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_text_never_panics(line in ".*") {
+        let _ = StreamEvent::parse_line(&line);
+    }
+
+    #[test]
+    fn arbitrary_json_never_panics(value in any_json()) {
+        let _ = StreamEvent::parse_line(&value.to_string());
+    }
+
+    #[test]
+    fn event_like_objects_never_panic(event in event_like()) {
+        let _ = StreamEvent::parse_line(&event.to_string());
+    }
+
+    #[test]
+    fn truncated_events_never_panic(event in event_like(), cut in any::<prop::sample::Index>()) {
+        let line = event.to_string();
+        let end = cut.index(line.len() + 1);
+        if line.is_char_boundary(end) {
+            let _ = StreamEvent::parse_line(&line[..end]);
+        }
+    }
+
+    #[test]
+    fn messages_round_trip(content in ".*", delta in any::<Option<bool>>()) {
+        let event = StreamEvent::Message {
+            role: "model".into(),
+            content: content.clone(),
+            delta,
+            timestamp: String::new(),
+        };
+        let line = serde_json::to_string(&event).unwrap();
+        match StreamEvent::parse_line(&line) {
+            Ok(Some(StreamEvent::Message { content: parsed, .. })) => prop_assert_eq!(parsed, content),
+            other => prop_assert!(false, "expected a message, got {:?}", other),
+        }
+    }
+}
@@ -1,7 +1,29 @@
 use futures_util::StreamExt;
-use gemini_oxide::{Gemini, StreamEvent};
+use gemini_oxide::billing::{Aggregation, Pricing, UsageLedger};
+use gemini_oxide::citations::{self, CitationStatus};
+use gemini_oxide::cli::GeminiCli;
+use gemini_oxide::client::GeminiClient;
+use gemini_oxide::compression::Compression;
+use gemini_oxide::jobs::{JobQueue, JobStatus, Priority};
+use gemini_oxide::language::Language;
+use gemini_oxide::metrics::{self, InMemoryMetrics};
+use gemini_oxide::moderation::KeywordModerator;
+use gemini_oxide::pipeline::{Pipeline, Step, StepStatus};
+use gemini_oxide::pool::GeminiPool;
+use gemini_oxide::progress::{ProgressTracker, RunHistory};
+use gemini_oxide::proxy::ProxyConfig;
+use gemini_oxide::replay::RecordedRun;
+use gemini_oxide::safety::SafetyLock;
+use gemini_oxide::schema::{self, JsonSchema};
+use gemini_oxide::session::GeminiSession;
+use gemini_oxide::spawn::SpawnOptions;
+use gemini_oxide::storage::{CacheStore, InMemoryCacheStore};
+use gemini_oxide::tasks::Extractor;
+use gemini_oxide::{Gemini, GeminiError, StreamEvent};
 use std::env;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 fn get_mock_path() -> PathBuf {
     let current_dir = env::current_dir().unwrap();
@@ -47,3 +69,932 @@ async fn test_stream_contract() {
         assert!(matches!(last, StreamEvent::Result { .. }));
     }
 }
+
+#[derive(Debug, serde::Deserialize, JsonSchema, PartialEq)]
+struct Point {
+    x: i64,
+    y: i64,
+}
+
+#[tokio::test]
+async fn test_extract_contract() {
+    let mock_path = get_mock_path();
+
+    let point: Point = Extractor::new()
+        .configure(move |g| g.bin_path(mock_path.clone()))
+        .run("x is one and y is two")
+        .await
+        .expect("Failed to extract");
+
+    assert_eq!(point, Point { x: 1, y: 2 });
+}
+
+#[tokio::test]
+async fn test_cache_serves_repeated_requests() {
+    let cache = Arc::new(InMemoryCacheStore::new());
+
+    let first = Gemini::new("test prompt")
+        .bin_path(get_mock_path())
+        .cache(cache.clone())
+        .json()
+        .await
+        .expect("Failed to execute json command");
+
+    // The binary does not exist, so this can only succeed from the cache.
+    let second = Gemini::new("test prompt")
+        .bin_path("/nonexistent/gemini")
+        .cache(cache.clone())
+        .json()
+        .await
+        .expect("Expected a cache hit");
+
+    assert_eq!(first.response, second.response);
+    assert_eq!(cache.list().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_moderator_blocks_stream() {
+    let mock_path = get_mock_path();
+
+    let stream = Gemini::new("test prompt")
+        .bin_path(mock_path)
+        .moderator(KeywordModerator::new(["hello"]))
+        .stream()
+        .expect("Failed to start stream");
+    let mut stream = Box::pin(stream);
+
+    assert!(matches!(
+        stream.next().await,
+        Some(Ok(StreamEvent::Init { .. }))
+    ));
+    assert!(matches!(
+        stream.next().await,
+        Some(Err(GeminiError::ContentBlocked(_)))
+    ));
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_transcript_reports_timing_and_metrics() {
+    let mock_path = get_mock_path();
+    let sink = Arc::new(InMemoryMetrics::new());
+
+    let transcript = Gemini::new("test prompt")
+        .bin_path(mock_path)
+        .metrics(sink.clone())
+        .transcript()
+        .await
+        .expect("Transcript failed");
+
+    assert_eq!(transcript.text, "Hello");
+    assert_eq!(transcript.events.len(), 3);
+    let timing = &transcript.timing;
+    assert!(timing.time_to_first_event.is_some());
+    assert!(timing.time_to_first_text >= timing.time_to_first_event);
+    assert!(timing.duration >= timing.time_to_first_text.unwrap());
+    assert!(timing.output_tokens > 0);
+
+    assert_eq!(sink.observations(metrics::STREAM_DURATION_MS).len(), 1);
+    assert_eq!(
+        sink.observations(metrics::STREAM_TIME_TO_FIRST_TEXT_MS)
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_labels_reach_metrics_and_output() {
+    let mock_path = get_mock_path();
+    let sink = Arc::new(InMemoryMetrics::new());
+
+    Gemini::new("test prompt")
+        .bin_path(&mock_path)
+        .metrics(sink.clone())
+        .label("team", "payments")
+        .label("model", "ignored")
+        .transcript()
+        .await
+        .expect("Transcript failed");
+    let (_, labels) = &sink.series()[0];
+    assert!(labels.contains(&("team".to_string(), "payments".to_string())));
+    assert_eq!(labels.iter().filter(|(k, _)| k == "model").count(), 1);
+    assert!(!labels.contains(&("model".to_string(), "ignored".to_string())));
+
+    let output = Gemini::new("test prompt")
+        .bin_path(mock_path)
+        .label("team", "payments")
+        .json()
+        .await
+        .expect("JSON failed");
+    assert_eq!(output.labels["team"], "payments");
+}
+
+#[tokio::test]
+async fn test_language_retry_keeps_output_annotations() {
+    let output = Gemini::new("answer_in_german")
+        .bin_path(get_mock_path())
+        .respond_in(Language::De)
+        .max_output_tokens(100)
+        .label("team", "search")
+        .json()
+        .await
+        .expect("JSON failed");
+    assert!(output.response.starts_with("Die Schwerkraft"));
+    assert_eq!(output.labels["team"], "search");
+    // The first, English answer used up the limit; the German one did not.
+    assert!(!output.truncated);
+}
+
+#[tokio::test]
+async fn test_usage_ledger_records_runs_but_not_cache_hits() {
+    let mock_path = get_mock_path();
+    let ledger = Arc::new(UsageLedger::new());
+    let cache = Arc::new(InMemoryCacheStore::new());
+    let request = Gemini::new("report_usage")
+        .bin_path(mock_path)
+        .cache(cache)
+        .label("team", "payments")
+        .usage_ledger(ledger.clone());
+
+    request.clone().json().await.expect("JSON failed");
+    request.json().await.expect("Cached JSON failed");
+
+    let events = ledger.events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].model, "gemini-2.5-pro");
+    assert_eq!(
+        (
+            events[0].prompt_tokens,
+            events[0].output_tokens,
+            events[0].total_tokens
+        ),
+        (120, 30, 150)
+    );
+    assert_eq!(events[0].labels["team"], "payments");
+
+    let report = ledger.report(
+        &Aggregation::new(Duration::from_secs(60))
+            .by_label("team")
+            .pricing(Pricing::new().model("gemini-2.5-pro", 1_000.0, 10_000.0)),
+    );
+    assert_eq!(report.rows[0].label.as_deref(), Some("payments"));
+    assert!((report.total_cost() - 0.42).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn test_usage_ledger_records_text_and_stream_runs() {
+    let ledger = Arc::new(UsageLedger::new());
+    let request = Gemini::new("report_usage")
+        .bin_path(get_mock_path())
+        .label("team", "payments")
+        .usage_ledger(ledger.clone());
+
+    let text = request.clone().text().await.expect("Text failed");
+    assert_eq!(text, "ok");
+
+    let stream = request.stream().expect("Stream failed");
+    let mut stream = Box::pin(stream);
+    while let Some(event) = stream.next().await {
+        event.expect("Stream event failed");
+    }
+
+    let events = ledger.events();
+    assert_eq!(events.len(), 2);
+    for event in &events {
+        assert_eq!((event.prompt_tokens, event.total_tokens), (120, 150));
+        assert_eq!(event.labels["team"], "payments");
+    }
+}
+
+#[tokio::test]
+async fn test_heartbeat_during_silence() {
+    let mock_path = get_mock_path();
+
+    let stream = Gemini::new("slow_tool")
+        .bin_path(mock_path)
+        .heartbeat(Duration::from_millis(200))
+        .stream()
+        .expect("Failed to start stream");
+    let events: Vec<StreamEvent> = stream.map(|e| e.expect("Stream error")).collect().await;
+
+    let heartbeats: Vec<&StreamEvent> = events.iter().filter(|e| e.is_synthetic()).collect();
+    assert!(!heartbeats.is_empty());
+    assert!(matches!(events.first(), Some(StreamEvent::Init { .. })));
+    assert!(matches!(events.last(), Some(StreamEvent::Result { .. })));
+    assert!(matches!(
+        heartbeats.last(),
+        Some(StreamEvent::Heartbeat { idle_ms }) if *idle_ms >= 200
+    ));
+}
+
+#[tokio::test]
+async fn test_spawn_detached_job_completes() {
+    let job = Gemini::new("test prompt")
+        .bin_path(get_mock_path())
+        .spawn_detached();
+    let poller = job.clone();
+
+    let output = job.await_result().await.expect("Job failed");
+    assert_eq!(output.response, "Mock response");
+    assert_eq!(poller.status(), JobStatus::Succeeded);
+}
+
+#[tokio::test]
+async fn test_job_queue_reports_wait_time_by_priority() {
+    let sink = Arc::new(InMemoryMetrics::new());
+    let queue = JobQueue::new(1).metrics(sink.clone());
+    let batch = queue.submit(
+        Gemini::new("test prompt").bin_path(get_mock_path()),
+        Priority::Batch,
+    );
+    let interactive = queue.submit(
+        Gemini::new("test prompt").bin_path(get_mock_path()),
+        Priority::Interactive,
+    );
+    assert_eq!(interactive.status(), JobStatus::Queued);
+
+    assert!(interactive.await_result().await.is_ok());
+    assert!(batch.await_result().await.is_ok());
+    assert_eq!(sink.observations(metrics::QUEUE_WAIT_MS).len(), 2);
+    assert!(sink
+        .series()
+        .iter()
+        .any(|(name, labels)| name == metrics::QUEUE_WAIT_MS
+            && labels == &[("priority".to_string(), "interactive".to_string())]));
+    assert_eq!(queue.running(), 0);
+}
+
+#[tokio::test]
+async fn test_progress_tracker_follows_stream() {
+    let history = RunHistory::new();
+    let tracker = ProgressTracker::new(history.clone());
+
+    let stream = Gemini::new("test prompt")
+        .bin_path(get_mock_path())
+        .progress(tracker.clone())
+        .stream()
+        .expect("Failed to start stream");
+    let _: Vec<_> = stream.collect().await;
+
+    assert_eq!(tracker.progress(), Some(1.0));
+    assert_eq!(history.samples().len(), 1);
+}
+
+#[tokio::test]
+async fn test_pipeline_feeds_outputs_forward() {
+    let mock_path = get_mock_path();
+
+    let run = Pipeline::new()
+        .configure(move |g| g.bin_path(mock_path.clone()))
+        .input("topic", "testing")
+        .step(Step::new("outline", "Outline {{topic}}"))
+        .step(Step::new("draft", "Expand: {{outline}}").uses(["outline"]))
+        .run()
+        .await
+        .expect("Pipeline failed");
+
+    assert_eq!(run.steps.len(), 2);
+    assert_eq!(run.steps[0].prompt, "Outline testing");
+    // The mock prints the same payload in every output format.
+    let outline = run.get("outline").expect("outline output");
+    assert!(outline.contains("Mock response"));
+    assert_eq!(run.steps[1].prompt, format!("Expand: {outline}"));
+    assert_eq!(run.output(), Some(outline));
+}
+
+#[tokio::test]
+async fn test_pipeline_branch_runs_one_side() {
+    let mock_path = get_mock_path();
+
+    let report = Pipeline::new()
+        .configure(move |g| g.bin_path(mock_path.clone()))
+        .step(Step::new("triage", "Triage this"))
+        .branch_if(
+            |out| out.contains("Mock response"),
+            Step::new("review", "Review it"),
+            Step::new("ship", "Ship it"),
+        )
+        .step(Step::new("announce", "Announce {{ship}}"))
+        .execute()
+        .await
+        .expect("Pipeline is valid");
+
+    assert_eq!(report.get("review").unwrap().status, StepStatus::Succeeded);
+    assert_eq!(
+        report.get("ship").unwrap().status,
+        StepStatus::Skipped {
+            reason: "branch not taken".into()
+        }
+    );
+    assert!(matches!(
+        report.get("announce").unwrap().status,
+        StepStatus::Skipped { .. }
+    ));
+    assert_eq!(report.failures().count(), 0);
+}
+
+#[tokio::test]
+async fn test_pipeline_replays_checkpointed_steps() {
+    let mock_path = get_mock_path();
+    let store = Arc::new(InMemoryCacheStore::new());
+    let pipeline = |topic: &str| {
+        let mock_path = mock_path.clone();
+        Pipeline::new()
+            .configure(move |g| g.bin_path(mock_path.clone()))
+            .checkpoint(store.clone())
+            .input("topic", topic)
+            .step(Step::new("fixed", "Say hello"))
+            .step(Step::new("outline", "Outline {{topic}}"))
+            .step(Step::new("draft", "Draft {{outline}}"))
+    };
+
+    let first = pipeline("rust").run().await.expect("Pipeline failed");
+    assert!(first.steps.iter().all(|s| !s.replayed && s.attempts == 1));
+
+    let replay = pipeline("rust").run().await.expect("Pipeline failed");
+    assert!(replay.steps.iter().all(|s| s.replayed && s.attempts == 0));
+    assert_eq!(replay.get("draft"), first.get("draft"));
+
+    // Changing an input only invalidates the steps that depend on it. The mock returns the
+    // same outline for any topic, so the draft's inputs are unchanged and it replays too.
+    let changed = pipeline("go").run().await.expect("Pipeline failed");
+    let replayed: Vec<(&str, bool)> = changed
+        .steps
+        .iter()
+        .map(|s| (s.name.as_str(), s.replayed))
+        .collect();
+    assert!(replayed.contains(&("fixed", true)));
+    assert!(replayed.contains(&("outline", false)));
+    assert!(replayed.contains(&("draft", true)));
+}
+
+#[tokio::test]
+async fn test_scratch_workspace_is_cli_working_dir() {
+    let workspace = gemini_oxide::workspace::scratch()
+        .await
+        .expect("Failed to create workspace");
+    let path = workspace.path().to_path_buf();
+
+    let output = workspace
+        .apply(Gemini::new("print_cwd").bin_path(get_mock_path()))
+        .json()
+        .await
+        .expect("Request failed");
+    assert_eq!(
+        std::fs::canonicalize(&output.response).unwrap(),
+        std::fs::canonicalize(&path).unwrap()
+    );
+
+    workspace.finish().await.expect("Cleanup failed");
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn test_verified_run_repairs_until_checks_pass() {
+    use gemini_oxide::verify::{verifier_fn, VerifiedRun};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let checks = Arc::new(AtomicUsize::new(0));
+    let counter = checks.clone();
+    let report = VerifiedRun::new(Gemini::new("Fix the build").bin_path(get_mock_path()))
+        .verifier(verifier_fn("flaky", move |_dir| {
+            let n = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                Ok(if n == 0 {
+                    Err("error[E0308]: mismatched types".to_string())
+                } else {
+                    Ok(())
+                })
+            }
+        }))
+        .repair_attempts(3)
+        .run()
+        .await
+        .expect("Run failed");
+
+    assert!(report.accepted());
+    assert_eq!(report.attempts.len(), 2);
+    assert_eq!(checks.load(Ordering::SeqCst), 2);
+    assert!(!report.attempts[0].passed());
+}
+
+#[tokio::test]
+async fn test_plan_events_follow_messages() {
+    let stream = Gemini::new("make_plan")
+        .bin_path(get_mock_path())
+        .plan_events()
+        .stream()
+        .expect("Failed to start stream");
+    let events: Vec<StreamEvent> = stream.map(|e| e.expect("Stream error")).collect().await;
+
+    let plan = events
+        .iter()
+        .position(|e| matches!(e, StreamEvent::Plan { steps } if steps.len() == 2));
+    let update = events.iter().position(|e| {
+        matches!(e, StreamEvent::PlanUpdate { index: Some(1), action, .. } if action == "run the tests")
+    });
+    assert!(matches!(plan, Some(i) if matches!(events[i - 1], StreamEvent::Message { .. })));
+    assert_eq!(update, plan.map(|i| i + 1));
+}
+
+#[tokio::test]
+async fn test_transcript_checkpoint_can_be_recovered() {
+    let path = env::temp_dir().join(format!("transcript-{}.jsonl", std::process::id()));
+    let transcript = Gemini::new("Hi")
+        .bin_path(get_mock_path())
+        .transcript_checkpoint(&path, Duration::ZERO)
+        .transcript()
+        .await
+        .expect("Stream failed");
+
+    let recovered =
+        gemini_oxide::transcript::RunTranscript::recover(&path).expect("Recovery failed");
+    assert_eq!(recovered.text, transcript.text);
+    assert_eq!(recovered.events.len(), transcript.events.len());
+    assert!(recovered.is_complete());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_session_records_turns() {
+    let mut session = GeminiSession::new().configure(|request| request.bin_path(get_mock_path()));
+    session
+        .send("first question")
+        .await
+        .expect("First turn failed");
+    let output = session.send("follow up").await.expect("Follow-up failed");
+
+    assert_eq!(output.response, "Mock response");
+    let turns = session.turns();
+    assert_eq!(turns.len(), 2);
+    assert_eq!(turns[1].user, "follow up");
+    assert_eq!(turns[1].model, "Mock response");
+
+    let mut failing = GeminiSession::new().configure(|request| request.bin_path(get_mock_path()));
+    assert!(failing.send("crash_it").await.is_err());
+    assert!(failing.turns().is_empty());
+}
+
+#[tokio::test]
+async fn test_job_queue_state_reports_processes_and_cache() {
+    let queue = JobQueue::new(2);
+    let job = queue.submit(
+        Gemini::new("slow_tool")
+            .bin_path(get_mock_path())
+            .model("mock-model"),
+        Priority::Interactive,
+    );
+    let mut processes = Vec::new();
+    for _ in 0..50 {
+        processes = queue.state().processes;
+        if !processes.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(processes.len(), 1);
+    assert!(processes[0].pid.is_some());
+    assert_eq!(processes[0].model.as_deref(), Some("mock-model"));
+    assert!(job.await_result().await.is_ok());
+    assert!(queue.state().processes.is_empty());
+
+    let cache: Arc<dyn CacheStore> = Arc::new(InMemoryCacheStore::new());
+    for _ in 0..2 {
+        let request = Gemini::new("test prompt")
+            .bin_path(get_mock_path())
+            .cache(cache.clone());
+        assert!(queue
+            .submit(request, Priority::Batch)
+            .await_result()
+            .await
+            .is_ok());
+    }
+    let state = queue.state();
+    assert_eq!((state.cache.hits, state.cache.misses), (1, 1));
+    assert_eq!((state.running, state.queued_batch), (0, 0));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_spawn_options_and_hooks_apply() {
+    // SAFETY: the hook does nothing in the child.
+    let request = unsafe {
+        Gemini::new("test prompt")
+            .bin_path(get_mock_path())
+            .spawn_options(SpawnOptions::new().new_process_group())
+            .pre_exec(|| Ok(()))
+    };
+    let output = request.json().await.expect("Hardened launch failed");
+    assert_eq!(output.response, "Mock response");
+}
+
+#[tokio::test]
+async fn test_proxy_is_passed_to_cli() {
+    let proxy = ProxyConfig::new()
+        .https("http://proxy.test:3128")
+        .bypass("localhost")
+        .bypass(".internal");
+    let output = Gemini::new("print_proxy")
+        .bin_path(get_mock_path())
+        .proxy(proxy)
+        .json()
+        .await
+        .expect("Request failed");
+    assert_eq!(
+        output.response,
+        "http://proxy.test:3128|http://proxy.test:3128|localhost,.internal"
+    );
+
+    let direct = Gemini::new("print_proxy")
+        .bin_path(get_mock_path())
+        .proxy(ProxyConfig::new())
+        .json()
+        .await
+        .expect("Request failed");
+    assert_eq!(direct.response, "||");
+
+    let client = GeminiClient::new()
+        .bin_path(get_mock_path())
+        .proxy("http://proxy.test:8080");
+    let output = client
+        .prompt("print_proxy")
+        .json()
+        .await
+        .expect("Request failed");
+    assert_eq!(
+        output.response,
+        "http://proxy.test:8080|http://proxy.test:8080|"
+    );
+}
+
+#[tokio::test]
+async fn test_safety_lock_refuses_risky_yolo_requests() {
+    let sandbox = env::temp_dir();
+    let client = GeminiClient::new()
+        .bin_path(get_mock_path())
+        .safety_lock(SafetyLock::new().allow_writes_in(&sandbox));
+
+    let result = client
+        .prompt("Hi")
+        .current_dir(&sandbox)
+        .yolo()
+        .text()
+        .await;
+    assert!(matches!(result, Err(GeminiError::PolicyDenied(_))));
+    let result = client.prompt("Hi").current_dir(&sandbox).yolo().stream();
+    assert!(matches!(result, Err(GeminiError::PolicyDenied(_))));
+
+    client
+        .prompt("Hi")
+        .current_dir(&sandbox)
+        .yolo()
+        .exclude_tools(["run_shell_command"])
+        .text()
+        .await
+        .expect("request inside the sandbox was refused");
+    client
+        .prompt("Hi")
+        .yolo()
+        .override_safety_lock()
+        .text()
+        .await
+        .expect("overridden request was refused");
+}
+
+#[tokio::test]
+async fn test_pool_limits_concurrent_requests() {
+    let pool = GeminiPool::new(2);
+    let requests: Vec<_> = (0..3)
+        .map(|_| {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                pool.json(Gemini::new("slow_tool").bin_path(get_mock_path()))
+                    .await
+            })
+        })
+        .collect();
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!((pool.running(), pool.queued()), (2, 1));
+
+    for request in requests {
+        assert_eq!(request.await.unwrap().unwrap().response, "Mock response");
+    }
+    assert_eq!((pool.running(), pool.queued()), (0, 0));
+}
+
+#[tokio::test]
+async fn test_stream_until_stops_at_predicate() {
+    let text = Gemini::new("make_plan")
+        .bin_path(get_mock_path())
+        .stream_until(|text| text.contains("plan:"))
+        .await
+        .expect("stream failed");
+    assert!(text.starts_with("Here is my plan:"));
+    assert!(!text.contains("Hello"));
+
+    let text = Gemini::new("make_plan")
+        .bin_path(get_mock_path())
+        .stream_until(|_| false)
+        .await
+        .expect("stream failed");
+    assert!(text.ends_with("Now I will run the tests.Hello"));
+}
+
+#[tokio::test]
+async fn test_candidates_come_from_one_request() {
+    let candidates = Gemini::new("n_best")
+        .bin_path(get_mock_path())
+        .candidates(2)
+        .await
+        .expect("candidates failed");
+    let texts: Vec<_> = candidates.iter().map(|c| c.text.as_str()).collect();
+    assert_eq!(texts, ["Long", "Short"]);
+    assert_eq!(candidates[0].score, Some(0.8));
+}
+
+#[tokio::test]
+async fn test_json_as_distinguishes_envelope_and_payload_errors() {
+    #[derive(serde::Deserialize)]
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    let point: Point = Gemini::new("JSON Schema")
+        .bin_path(get_mock_path())
+        .json_as()
+        .await
+        .expect("payload should parse");
+    assert_eq!((point.x, point.y), (1, 2));
+
+    let result = Gemini::new("JSON Schema")
+        .bin_path(get_mock_path())
+        .json_as::<Vec<String>>()
+        .await;
+    assert!(
+        matches!(result, Err(GeminiError::ResponseParseFailed { response, .. }) if response.contains("\"x\": 1"))
+    );
+
+    let result = Gemini::new("bad_json")
+        .bin_path(get_mock_path())
+        .json_as::<Point>()
+        .await;
+    assert!(matches!(result, Err(GeminiError::JsonParseFailed(_))));
+}
+
+#[tokio::test]
+async fn test_cited_quotes_are_verified() {
+    let fetcher = citations::fetcher_fn(|url| async move {
+        assert_eq!(url, "https://example.com/page");
+        Ok("<p>A <em>mock   quote</em> from the page.</p>".to_string())
+    });
+    let output = Gemini::new("cite_sources")
+        .bin_path(get_mock_path())
+        .verify_citations(fetcher)
+        .json()
+        .await
+        .expect("request failed");
+    assert_eq!(output.citations.len(), 1);
+    assert_eq!(output.citations[0].quote.as_deref(), Some("mock quote"));
+    assert_eq!(output.citations[0].status, CitationStatus::Verified);
+}
+
+#[tokio::test]
+async fn test_response_schema_is_sent_and_enforced() {
+    #[derive(serde::Deserialize)]
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    impl JsonSchema for Point {
+        fn json_schema() -> serde_json::Value {
+            schema::object(
+                [("x", i64::json_schema()), ("y", i64::json_schema())],
+                &["x", "y"],
+            )
+        }
+    }
+
+    // The mock answers with a point when the prompt carries a JSON Schema.
+    let point: Point = Gemini::new("Where is it?")
+        .bin_path(get_mock_path())
+        .response_type::<Point>()
+        .json_as()
+        .await
+        .expect("response should conform");
+    assert_eq!((point.x, point.y), (1, 2));
+
+    let result = Gemini::new("Where is it?")
+        .bin_path(get_mock_path())
+        .response_schema(serde_json::json!({"type": "object", "required": ["z"]}))
+        .text()
+        .await;
+    assert!(matches!(result, Err(GeminiError::ValidationFailed(e)) if e.contains('z')));
+}
+
+#[tokio::test]
+async fn test_system_instruction_is_passed_as_file() {
+    let output = Gemini::new("print_system")
+        .bin_path(get_mock_path())
+        .system("Answer like a pirate.")
+        .json()
+        .await
+        .expect("request failed");
+    let (path, contents) = output.response.split_once('|').unwrap();
+    assert_eq!(contents, "Answer like a pirate.");
+    assert!(
+        !std::path::Path::new(path).exists(),
+        "system file was not removed"
+    );
+}
+
+#[tokio::test]
+async fn test_memory_is_passed_as_included_gemini_md() {
+    let dir = std::env::temp_dir().join(format!("gemini-memory-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let conventions = dir.join("CONVENTIONS.md");
+    std::fs::write(&conventions, "Prefer iterators.").unwrap();
+
+    let output = Gemini::new("print_memory")
+        .bin_path(get_mock_path())
+        .memory("Use tabs.")
+        .memory_file(&conventions)
+        .json()
+        .await
+        .expect("request failed");
+    let (include_dir, memory) = output.response.split_once('|').unwrap();
+    assert_eq!(memory, "Use tabs.  Prefer iterators.");
+    assert!(
+        !std::path::Path::new(include_dir).exists(),
+        "memory directory was not removed"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_framed_files_are_piped_as_tagged_code_blocks() {
+    let dir = std::env::temp_dir().join(format!("gemini-framing-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("lib.rs");
+    std::fs::write(&source, "fn main() {}\n").unwrap();
+
+    let output = Gemini::new("print_stdin")
+        .bin_path(get_mock_path())
+        .context("Fails on CI.")
+        .file(&source)
+        .frame_files()
+        .json()
+        .await
+        .expect("request failed");
+    assert_eq!(
+        output.response,
+        format!(
+            "Fails on CI.|File: {}|```rust|fn main() {{}}|```|",
+            source.display()
+        )
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_large_context_is_passed_as_file_reference() {
+    let context = "x".repeat(100);
+    let output = Gemini::new("print_context_file")
+        .bin_path(get_mock_path())
+        .context(context.clone())
+        .context_file_over(10)
+        .json()
+        .await
+        .expect("request failed");
+    let (path, contents) = output.response.split_once('|').unwrap();
+    assert_eq!(contents, context);
+    assert!(
+        !std::path::Path::new(path).exists(),
+        "context file was not removed"
+    );
+
+    let output = Gemini::new("print_context_file")
+        .bin_path(get_mock_path())
+        .context(context)
+        .context_file_over(10)
+        .sensitive_context()
+        .json()
+        .await
+        .expect("request failed");
+    assert_eq!(output.response, "piped");
+}
+
+#[tokio::test]
+async fn test_sampling_parameters_are_passed_as_settings() {
+    let output = Gemini::new("print_settings")
+        .bin_path(get_mock_path())
+        .temperature(0.2)
+        .top_k(3)
+        .json()
+        .await
+        .expect("request failed");
+    let settings: serde_json::Value = serde_json::from_str(&output.response).unwrap();
+    assert_eq!(
+        settings["modelConfigs"]["overrides"][0]["modelConfig"]["generateContentConfig"],
+        serde_json::json!({"temperature": 0.2f32, "topK": 3})
+    );
+}
+
+#[tokio::test]
+async fn test_output_limit_flags_truncated_responses() {
+    let output = Gemini::new("print_settings")
+        .bin_path(get_mock_path())
+        .max_output_tokens(1_000)
+        .json()
+        .await
+        .expect("request failed");
+    assert!(!output.truncated);
+    assert!(output.response.contains(r#""maxOutputTokens":1000"#));
+
+    // "Mock response" is about four tokens long.
+    let output = Gemini::new("Hello")
+        .bin_path(get_mock_path())
+        .max_output_tokens(4)
+        .json()
+        .await
+        .expect("request failed");
+    assert!(output.truncated);
+}
+
+#[tokio::test]
+async fn test_compression_reports_saved_tokens() {
+    let log = "ERROR    disk full\n".repeat(50) + "==========\n\n\n";
+    let output = Gemini::new("Summarize")
+        .bin_path(get_mock_path())
+        .context(log.clone())
+        .compress(Compression::new())
+        .json()
+        .await
+        .expect("request failed");
+    let report = output.compression.expect("no compression report");
+    assert!(!report.condensed);
+    assert_eq!(report.compressed_tokens, 3 + 4);
+    assert!(report.saved_tokens() > 200);
+
+    // Long context is condensed by the model first.
+    let output = Gemini::new("Summarize")
+        .bin_path(get_mock_path())
+        .context("x ".repeat(5_000))
+        .compress(Compression::new().condense_over(1_000))
+        .json()
+        .await
+        .expect("request failed");
+    assert!(output.compression.is_some_and(|report| report.condensed));
+}
+
+#[tokio::test]
+async fn test_replay_reports_changed_tool_behavior() {
+    let recorded = RecordedRun::record(Gemini::new("Hello").bin_path(get_mock_path()))
+        .await
+        .expect("record failed");
+    let path = env::temp_dir().join(format!("gemini-replay-{}.json", std::process::id()));
+    recorded.save(&path).unwrap();
+    let recorded = RecordedRun::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let same = recorded
+        .replay(|request| request.bin_path(get_mock_path()))
+        .await
+        .expect("replay failed");
+    assert!(same.is_empty(), "unexpected differences: {same:?}");
+
+    let changed = recorded
+        .replay(|_| Gemini::new("Hello use_tool").bin_path(get_mock_path()))
+        .await
+        .expect("replay failed");
+    assert_eq!(changed.tools.unwrap().new, vec!["write_file".to_string()]);
+}
+
+#[tokio::test]
+async fn test_extension_management() {
+    let cli = GeminiCli::new().bin_path(get_mock_path());
+    let extensions = cli.list_extensions().await.expect("list failed");
+    assert_eq!(extensions.len(), 1);
+    assert_eq!(extensions[0].name, "security");
+    assert_eq!(
+        extensions[0].path.as_deref(),
+        Some("/mock/extensions/security")
+    );
+
+    let report = cli.install_extension("./db").await.expect("install failed");
+    assert!(report.contains("\"./db\" installed"));
+    match cli.uninstall_extension("missing").await {
+        Err(GeminiError::RuntimeError(stderr)) => assert!(stderr.contains("not found")),
+        other => panic!("expected a runtime error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_capabilities_report_the_cli_version() {
+    let capabilities = gemini_oxide::capabilities::detect(get_mock_path()).await;
+    let cli = capabilities.cli.expect("CLI not detected");
+    assert_eq!(cli.version.to_string(), "0.9.2");
+    assert!(cli.stream_json);
+}